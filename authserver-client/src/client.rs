@@ -0,0 +1,738 @@
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use crate::models::scim::ListParams;
+use crate::models::{
+    AddIdentityForm, ClientCredentials, ClientMetadata, ClientSummary, DeletedUser,
+    DeviceAuthorizationRequest, DeviceAuthorizationResponse, IntrospectionResponse, LinkStarted,
+    ListResponse, LoginIdentity, LoginRequest, NewClient, PatchRequest, ProviderMetadata,
+    RegistrationResponse, ScimGroup, ScimUser, Session, SignInResponse, SignupRequest,
+    TokenReference, TokenRequest, TokenResponse, User, UserGrant, UserInfo,
+};
+use crate::Error;
+
+/// How long before its expiry a token is renewed rather than sent.
+const RENEWAL_MARGIN_SECONDS: i64 = 30;
+/// The version of the API the client speaks, the prefix of its paths.
+const API_VERSION: &str = "/v1";
+
+/// How the client gets a new token once its token expires.
+#[derive(Clone)]
+enum Renewal {
+    /// Signs out, then in again with the user's password. Only kept when the
+    /// client was built `with_password_renewal`.
+    Password { username: String, password: String },
+    /// Repeats a client credentials grant, or redeems a refresh token.
+    Grant(Box<TokenRequest>),
+}
+
+#[derive(Default)]
+struct Auth {
+    token: Option<String>,
+    renewal: Option<Renewal>,
+}
+
+/// A client of one realm of the server. Its clones share the token.
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    auth: Arc<RwLock<Auth>>,
+    password_renewal: bool,
+}
+
+/// The `exp` claim of a JWT, read without verifying it.
+fn expiry(token: &str) -> Option<i64> {
+    let payload = token.split('.').nth(1)?;
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+    serde_json::from_slice::<serde_json::Value>(&payload)
+        .ok()?
+        .get("exp")?
+        .as_i64()
+}
+
+fn expires_soon(token: &str) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    expiry(token).is_some_and(|exp| exp - now < RENEWAL_MARGIN_SECONDS)
+}
+
+/// The response, or the error it carries.
+async fn check(response: Response) -> Result<Response, Error> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(Error::from_response(response).await)
+    }
+}
+
+async fn json<T: DeserializeOwned>(response: Response) -> Result<T, Error> {
+    Ok(response.json().await?)
+}
+
+impl Client {
+    /// A client of the server at `base_url`, such as `https://auth.example.com`.
+    pub fn new(base_url: &str) -> Self {
+        Client {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            auth: Arc::default(),
+            password_renewal: false,
+        }
+    }
+
+    /// Keeps the password given to `login` or `signup` in memory, to sign in
+    /// again when the token expires. Without it, the user's token is not
+    /// renewed and the password is forgotten once signed in.
+    pub fn with_password_renewal(mut self) -> Self {
+        self.password_renewal = true;
+        self
+    }
+
+    /// Sends the requests with `http`, to set a user agent or timeouts.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// Talks to the realm `name` through its `/realms/{name}` prefix,
+    /// rather than the realm the server picks from the host.
+    pub fn with_realm(mut self, name: &str) -> Self {
+        self.base_url = format!("{}/realms/{}", self.base_url, name);
+        self
+    }
+
+    /// Sends a token obtained elsewhere, which is not renewed.
+    pub fn with_token(self, token: &str) -> Self {
+        self.store(Some(token.to_string()), None);
+        self
+    }
+
+    /// The token sent with the requests, if signed in.
+    pub fn token(&self) -> Option<String> {
+        self.auth.read().unwrap().token.clone()
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}{}", self.base_url, API_VERSION, path)
+    }
+
+    /// Well-known URIs are not versioned (RFC 8615).
+    fn well_known_url(&self, path: &str) -> String {
+        format!("{}/.well-known/{}", self.base_url, path)
+    }
+
+    fn store(&self, token: Option<String>, renewal: Option<Renewal>) {
+        *self.auth.write().unwrap() = Auth { token, renewal };
+    }
+
+    /// Stores the user's token, and their password only when it renews it.
+    fn store_password(&self, token: String, username: &str, password: &str) {
+        let renewal = self.password_renewal.then(|| Renewal::Password {
+            username: username.to_string(),
+            password: password.to_string(),
+        });
+        self.store(Some(token), renewal);
+    }
+
+    fn is_renewable(&self) -> bool {
+        self.auth.read().unwrap().renewal.is_some()
+    }
+
+    async fn renew(&self) -> Result<String, Error> {
+        let renewal = self.auth.read().unwrap().renewal.clone();
+        match renewal {
+            Some(Renewal::Password { username, password }) => {
+                // The session being replaced may have moments left: end it
+                // rather than leave one more session behind each renewal.
+                if let Some(token) = self.token() {
+                    let logout = self.http.post(self.url("/logout")).bearer_auth(token);
+                    logout.send().await.ok();
+                }
+                self.login(&username, &password).await
+            }
+            Some(Renewal::Grant(request)) => {
+                Ok(self.sign_in_with_grant(*request).await?.access_token)
+            }
+            None => Err(Error::NotSignedIn),
+        }
+    }
+
+    /// The token to send: renewed first when it is about to expire.
+    async fn fresh_token(&self) -> Result<String, Error> {
+        let (token, renewable) = {
+            let auth = self.auth.read().unwrap();
+            (auth.token.clone(), auth.renewal.is_some())
+        };
+        match token {
+            Some(token) if !(renewable && expires_soon(&token)) => Ok(token),
+            _ => self.renew().await,
+        }
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        check(request.send().await?).await
+    }
+
+    /// Sends the request with the token, and once more with a new token
+    /// when the server no longer accepts it.
+    async fn send_authorized(
+        &self,
+        request: impl Fn(&reqwest::Client) -> RequestBuilder,
+    ) -> Result<Response, Error> {
+        let token = self.fresh_token().await?;
+        let response = request(&self.http).bearer_auth(&token).send().await?;
+        if response.status() == StatusCode::UNAUTHORIZED && self.is_renewable() {
+            let token = self.renew().await?;
+            return self.send(request(&self.http).bearer_auth(&token)).await;
+        }
+        check(response).await
+    }
+
+    pub async fn health(&self) -> Result<(), Error> {
+        self.send(self.http.get(self.url("/health"))).await?;
+        Ok(())
+    }
+
+    /// `POST /signup`: creates the user and signs them in.
+    pub async fn signup(
+        &self,
+        username: &str,
+        password: &str,
+        email: &str,
+    ) -> Result<String, Error> {
+        let request = self.http.post(self.url("/signup")).json(&SignupRequest {
+            username: username.to_string(),
+            email: email.to_string(),
+            password: password.to_string(),
+        });
+        let token = json::<SignInResponse>(self.send(request).await?)
+            .await?
+            .access_token;
+        self.store_password(token.clone(), username, password);
+        Ok(token)
+    }
+
+    /// `POST /login`: signs the user in, and, `with_password_renewal`, in
+    /// again whenever the token expires.
+    pub async fn login(&self, username: &str, password: &str) -> Result<String, Error> {
+        let request = self.http.post(self.url("/login")).json(&LoginRequest {
+            username: username.to_string(),
+            password: password.to_string(),
+        });
+        let token = json::<SignInResponse>(self.send(request).await?)
+            .await?
+            .access_token;
+        self.store_password(token.clone(), username, password);
+        Ok(token)
+    }
+
+    /// `POST /logout`: ends the session, and forgets the token and password.
+    pub async fn logout(&self) -> Result<(), Error> {
+        self.send_authorized(|http| http.post(self.url("/logout")))
+            .await?;
+        self.store(None, None);
+        Ok(())
+    }
+
+    pub async fn me(&self) -> Result<User, Error> {
+        json(
+            self.send_authorized(|http| http.get(self.url("/me")))
+                .await?,
+        )
+        .await
+    }
+
+    /// `DELETE /me`: deletes the user, returning their id.
+    pub async fn delete_me(&self) -> Result<Uuid, Error> {
+        let deleted: DeletedUser = json(
+            self.send_authorized(|http| http.delete(self.url("/me")))
+                .await?,
+        )
+        .await?;
+        self.store(None, None);
+        Ok(deleted.id)
+    }
+
+    pub async fn sessions(&self) -> Result<Vec<Session>, Error> {
+        json(
+            self.send_authorized(|http| http.get(self.url("/me/sessions")))
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn revoke_session(&self, id: Uuid) -> Result<(), Error> {
+        self.send_authorized(|http| http.delete(self.url(&format!("/me/sessions/{}", id))))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn identities(&self) -> Result<Vec<LoginIdentity>, Error> {
+        json(
+            self.send_authorized(|http| http.get(self.url("/me/identities")))
+                .await?,
+        )
+        .await
+    }
+
+    /// `POST /me/identities/{provider}`: sets the password, or returns where
+    /// to sign in to the provider for its account to be linked.
+    pub async fn add_identity(
+        &self,
+        provider: &str,
+        form: &AddIdentityForm,
+    ) -> Result<Option<LinkStarted>, Error> {
+        let response = self
+            .send_authorized(|http| {
+                http.post(self.url(&format!("/me/identities/{}", provider)))
+                    .form(form)
+            })
+            .await?;
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(None),
+            _ => json(response).await.map(Some),
+        }
+    }
+
+    pub async fn remove_identity(&self, provider: &str) -> Result<(), Error> {
+        self.send_authorized(|http| http.delete(self.url(&format!("/me/identities/{}", provider))))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn grants(&self) -> Result<Vec<UserGrant>, Error> {
+        json(
+            self.send_authorized(|http| http.get(self.url("/me/grants")))
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn revoke_grant(&self, client_id: Uuid) -> Result<(), Error> {
+        self.send_authorized(|http| http.delete(self.url(&format!("/me/grants/{}", client_id))))
+            .await?;
+        Ok(())
+    }
+
+    /// `POST /token`, leaving the client's own token alone.
+    pub async fn token_grant(&self, request: &TokenRequest) -> Result<TokenResponse, Error> {
+        json(
+            self.send(self.http.post(self.url("/token")).form(request))
+                .await?,
+        )
+        .await
+    }
+
+    /// Redeems a grant at `POST /token` and sends the access token from then
+    /// on, renewed with the refresh token or, for client credentials, by
+    /// repeating the grant.
+    pub async fn sign_in_with_grant(&self, request: TokenRequest) -> Result<TokenResponse, Error> {
+        let response = self.token_grant(&request).await?;
+        let renewal = match &response.refresh_token {
+            _ if request.grant_type == "client_credentials" => {
+                Some(Renewal::Grant(Box::new(request)))
+            }
+            Some(refresh_token) => Some(Renewal::Grant(Box::new(TokenRequest {
+                grant_type: "refresh_token".to_string(),
+                client_id: request.client_id,
+                client_secret: request.client_secret,
+                refresh_token: Some(refresh_token.clone()),
+                ..TokenRequest::default()
+            }))),
+            None => None,
+        };
+        self.store(Some(response.access_token.clone()), renewal);
+        Ok(response)
+    }
+
+    /// Signs a confidential client in with the client credentials grant.
+    pub async fn client_credentials(
+        &self,
+        client_id: &str,
+        client_secret: &str,
+        scope: Option<&str>,
+    ) -> Result<TokenResponse, Error> {
+        self.sign_in_with_grant(TokenRequest {
+            grant_type: "client_credentials".to_string(),
+            client_id: Some(client_id.to_string()),
+            client_secret: Some(client_secret.to_string()),
+            scope: scope.map(str::to_string),
+            ..TokenRequest::default()
+        })
+        .await
+    }
+
+    pub async fn device_authorization(
+        &self,
+        request: &DeviceAuthorizationRequest,
+    ) -> Result<DeviceAuthorizationResponse, Error> {
+        let request = self
+            .http
+            .post(self.url("/device_authorization"))
+            .form(request);
+        json(self.send(request).await?).await
+    }
+
+    pub async fn introspect(&self, token: &TokenReference) -> Result<IntrospectionResponse, Error> {
+        let request = self.http.post(self.url("/introspect")).form(token);
+        json(self.send(request).await?).await
+    }
+
+    pub async fn revoke(&self, token: &TokenReference) -> Result<(), Error> {
+        self.send(self.http.post(self.url("/revoke")).form(token))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn userinfo(&self) -> Result<UserInfo, Error> {
+        json(
+            self.send_authorized(|http| http.get(self.url("/userinfo")))
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn discovery(&self) -> Result<ProviderMetadata, Error> {
+        let request = self.http.get(self.well_known_url("openid-configuration"));
+        json(self.send(request).await?).await
+    }
+
+    /// `POST /register` (RFC 7591).
+    pub async fn register(&self, metadata: &ClientMetadata) -> Result<RegistrationResponse, Error> {
+        json(
+            self.send(self.http.post(self.url("/register")).json(metadata))
+                .await?,
+        )
+        .await
+    }
+
+    /// `GET /register/{client_id}` with the registration access token (RFC 7592).
+    pub async fn registration(
+        &self,
+        client_id: Uuid,
+        registration_token: &str,
+    ) -> Result<RegistrationResponse, Error> {
+        let request = self
+            .http
+            .get(self.url(&format!("/register/{}", client_id)))
+            .bearer_auth(registration_token);
+        json(self.send(request).await?).await
+    }
+
+    pub async fn update_registration(
+        &self,
+        client_id: Uuid,
+        registration_token: &str,
+        metadata: &ClientMetadata,
+    ) -> Result<RegistrationResponse, Error> {
+        let request = self
+            .http
+            .put(self.url(&format!("/register/{}", client_id)))
+            .bearer_auth(registration_token)
+            .json(metadata);
+        json(self.send(request).await?).await
+    }
+
+    pub async fn delete_registration(
+        &self,
+        client_id: Uuid,
+        registration_token: &str,
+    ) -> Result<(), Error> {
+        let request = self
+            .http
+            .delete(self.url(&format!("/register/{}", client_id)))
+            .bearer_auth(registration_token);
+        self.send(request).await?;
+        Ok(())
+    }
+
+    pub async fn clients(&self) -> Result<Vec<ClientSummary>, Error> {
+        json(
+            self.send_authorized(|http| http.get(self.url("/admin/clients")))
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn create_client(&self, client: &NewClient) -> Result<ClientCredentials, Error> {
+        json(
+            self.send_authorized(|http| http.post(self.url("/admin/clients")).json(client))
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn approve_client(&self, client_id: Uuid) -> Result<(), Error> {
+        self.send_authorized(|http| {
+            http.post(self.url(&format!("/admin/clients/{}/approve", client_id)))
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn rotate_client_secret(&self, client_id: Uuid) -> Result<ClientCredentials, Error> {
+        json(
+            self.send_authorized(|http| {
+                http.post(self.url(&format!("/admin/clients/{}/secret", client_id)))
+            })
+            .await?,
+        )
+        .await
+    }
+
+    pub async fn scim_users(&self, params: &ListParams) -> Result<ListResponse<ScimUser>, Error> {
+        json(
+            self.send_authorized(|http| http.get(self.url("/scim/v2/Users")).query(params))
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn scim_user(&self, id: &str) -> Result<ScimUser, Error> {
+        json(
+            self.send_authorized(|http| http.get(self.url(&format!("/scim/v2/Users/{}", id))))
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn scim_create_user(&self, user: &ScimUser) -> Result<ScimUser, Error> {
+        json(
+            self.send_authorized(|http| http.post(self.url("/scim/v2/Users")).json(user))
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn scim_replace_user(&self, id: &str, user: &ScimUser) -> Result<ScimUser, Error> {
+        json(
+            self.send_authorized(|http| {
+                http.put(self.url(&format!("/scim/v2/Users/{}", id)))
+                    .json(user)
+            })
+            .await?,
+        )
+        .await
+    }
+
+    pub async fn scim_patch_user(&self, id: &str, patch: &PatchRequest) -> Result<ScimUser, Error> {
+        json(
+            self.send_authorized(|http| {
+                http.patch(self.url(&format!("/scim/v2/Users/{}", id)))
+                    .json(patch)
+            })
+            .await?,
+        )
+        .await
+    }
+
+    pub async fn scim_delete_user(&self, id: &str) -> Result<(), Error> {
+        self.send_authorized(|http| http.delete(self.url(&format!("/scim/v2/Users/{}", id))))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn scim_groups(&self, params: &ListParams) -> Result<ListResponse<ScimGroup>, Error> {
+        json(
+            self.send_authorized(|http| http.get(self.url("/scim/v2/Groups")).query(params))
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn scim_group(&self, id: &str) -> Result<ScimGroup, Error> {
+        json(
+            self.send_authorized(|http| http.get(self.url(&format!("/scim/v2/Groups/{}", id))))
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn scim_create_group(&self, group: &ScimGroup) -> Result<ScimGroup, Error> {
+        json(
+            self.send_authorized(|http| http.post(self.url("/scim/v2/Groups")).json(group))
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn scim_replace_group(
+        &self,
+        id: &str,
+        group: &ScimGroup,
+    ) -> Result<ScimGroup, Error> {
+        json(
+            self.send_authorized(|http| {
+                http.put(self.url(&format!("/scim/v2/Groups/{}", id)))
+                    .json(group)
+            })
+            .await?,
+        )
+        .await
+    }
+
+    pub async fn scim_patch_group(
+        &self,
+        id: &str,
+        patch: &PatchRequest,
+    ) -> Result<ScimGroup, Error> {
+        json(
+            self.send_authorized(|http| {
+                http.patch(self.url(&format!("/scim/v2/Groups/{}", id)))
+                    .json(patch)
+            })
+            .await?,
+        )
+        .await
+    }
+
+    pub async fn scim_delete_group(&self, id: &str) -> Result<(), Error> {
+        self.send_authorized(|http| http.delete(self.url(&format!("/scim/v2/Groups/{}", id))))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    use super::*;
+
+    fn jwt(exp: i64) -> String {
+        let payload =
+            base64::encode_config(json!({ "exp": exp }).to_string(), base64::URL_SAFE_NO_PAD);
+        format!("e30.{}.signature", payload)
+    }
+
+    /// Serves `/login`, handing out `first` then `second`, a `/me` only
+    /// accepting `second` and a `/logout` counting the sessions ended.
+    async fn spawn_server(logins: Arc<AtomicUsize>, logouts: Arc<AtomicUsize>) -> SocketAddr {
+        let login = warp::post()
+            .and(warp::path!("v1" / "login"))
+            .and(warp::body::json())
+            .map(move |request: LoginRequest| {
+                assert_eq!("jane", request.username);
+                let token = match logins.fetch_add(1, Ordering::SeqCst) {
+                    0 => "first",
+                    _ => "second",
+                };
+                warp::reply::json(
+                    &json!({"access_token": token, "token_type": "Bearer", "expires_in": 3600}),
+                )
+            });
+        let me = warp::get()
+            .and(warp::path!("v1" / "me"))
+            .and(warp::header::<String>("authorization"))
+            .map(|authorization: String| {
+                if authorization == "Bearer second" {
+                    let user = json!({"id": Uuid::nil(), "username": "jane", "email": "jane@example.com",
+                        "full_name": null, "bio": null, "image": null});
+                    warp::reply::with_status(warp::reply::json(&user), StatusCode::OK)
+                } else {
+                    let error = json!({"type": "urn:authserver:problem:invalid_token",
+                        "title": "Invalid Token", "status": 401, "detail": "The token has expired.",
+                        "code": "invalid_token", "request_id": "42"});
+                    warp::reply::with_status(warp::reply::json(&error), StatusCode::UNAUTHORIZED)
+                }
+            });
+        let logout = warp::post()
+            .and(warp::path!("v1" / "logout"))
+            .and(warp::header::<String>("authorization"))
+            .map(move |authorization: String| {
+                assert_eq!("Bearer first", authorization);
+                logouts.fetch_add(1, Ordering::SeqCst);
+                StatusCode::NO_CONTENT
+            });
+        let token = warp::post().and(warp::path!("v1" / "token")).map(|| {
+            let error = json!({"error": "invalid_grant", "error_description": "The code expired."});
+            warp::reply::with_status(warp::reply::json(&error), StatusCode::BAD_REQUEST)
+        });
+        let (addr, server) =
+            warp::serve(login.or(me).or(logout).or(token)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        addr
+    }
+
+    #[test]
+    fn renews_tokens_about_to_expire() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        assert_eq!(Some(now), expiry(&jwt(now)));
+        assert!(expires_soon(&jwt(now + 10)));
+        assert!(!expires_soon(&jwt(now + 3600)));
+        assert!(!expires_soon("opaque"));
+    }
+
+    #[tokio::test]
+    async fn signs_in_again_when_the_token_is_refused() {
+        let logins = Arc::new(AtomicUsize::new(0));
+        let logouts = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_server(logins.clone(), logouts.clone()).await;
+        let client = Client::new(&format!("http://{}", addr)).with_password_renewal();
+
+        assert_eq!("first", client.login("jane", "password").await.unwrap());
+        let user = client.me().await.unwrap();
+
+        assert_eq!(Some("jane".to_string()), user.username);
+        assert_eq!(2, logins.load(Ordering::SeqCst));
+        assert_eq!(1, logouts.load(Ordering::SeqCst));
+        assert_eq!(Some("second".to_string()), client.token());
+    }
+
+    #[tokio::test]
+    async fn forgets_the_password_by_default() {
+        let logins = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_server(logins.clone(), Arc::default()).await;
+        let client = Client::new(&format!("http://{}", addr));
+
+        assert_eq!("first", client.login("jane", "password").await.unwrap());
+        assert!(matches!(client.me().await, Err(Error::Api(problem)) if problem.status == 401));
+        assert_eq!(1, logins.load(Ordering::SeqCst));
+        assert_eq!(Some("first".to_string()), client.token());
+    }
+
+    #[tokio::test]
+    async fn maps_the_errors_the_server_answers_with() {
+        let addr = spawn_server(Arc::default(), Arc::default()).await;
+        let url = format!("http://{}", addr);
+
+        match Client::new(&url).with_token("stale").me().await {
+            Err(Error::Api(problem)) => {
+                assert_eq!(401, problem.status);
+                assert_eq!("invalid_token", problem.code);
+                assert_eq!("42", problem.request_id);
+            }
+            other => panic!("expected an API error, got {:?}", other.map(|_| ())),
+        }
+        let grant = TokenRequest {
+            grant_type: "authorization_code".to_string(),
+            ..TokenRequest::default()
+        };
+        match Client::new(&url).token_grant(&grant).await {
+            Err(Error::OAuth { status, error, .. }) => {
+                assert_eq!(400, status);
+                assert_eq!("invalid_grant", error);
+            }
+            other => panic!("expected an OAuth error, got {:?}", other.map(|_| ())),
+        }
+        assert!(matches!(
+            Client::new(&url).me().await,
+            Err(Error::NotSignedIn)
+        ));
+    }
+}
@@ -0,0 +1,72 @@
+use reqwest::Response;
+
+use crate::models::error::{OAuthErrorResponse, Problem, ScimErrorResponse};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The problem details the account and admin endpoints answer with.
+    #[error("{} {}: {}", .0.status, .0.code, .0.detail.as_deref().unwrap_or(&.0.title))]
+    Api(Box<Problem>),
+    /// The errors of the OAuth endpoints (RFC 6749 section 5.2).
+    #[error("{status} {error}: {description}")]
+    OAuth {
+        status: u16,
+        error: String,
+        description: String,
+    },
+    /// The errors of the SCIM endpoints (RFC 7644 section 3.12).
+    #[error("{status} {detail}")]
+    Scim {
+        status: u16,
+        scim_type: Option<String>,
+        detail: String,
+    },
+    /// An answer none of the API's bodies describes.
+    #[error("{status} unexpected response: {body}")]
+    Unexpected { status: u16, body: String },
+    #[error("not signed in")]
+    NotSignedIn,
+}
+
+impl Error {
+    /// The HTTP status the server answered with.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::Http(e) => e.status().map(|status| status.as_u16()),
+            Error::Api(problem) => Some(problem.status),
+            Error::OAuth { status, .. }
+            | Error::Scim { status, .. }
+            | Error::Unexpected { status, .. } => Some(*status),
+            Error::NotSignedIn => None,
+        }
+    }
+
+    /// The error of an answer that is not a success.
+    pub(crate) async fn from_response(response: Response) -> Self {
+        let status = response.status().as_u16();
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(e) => return Error::Http(e),
+        };
+        if let Ok(e) = serde_json::from_str::<OAuthErrorResponse>(&body) {
+            return Error::OAuth {
+                status,
+                error: e.error,
+                description: e.error_description,
+            };
+        }
+        if let Ok(e) = serde_json::from_str::<ScimErrorResponse>(&body) {
+            return Error::Scim {
+                status,
+                scim_type: e.scim_type,
+                detail: e.detail,
+            };
+        }
+        match serde_json::from_str::<Problem>(&body) {
+            Ok(problem) => Error::Api(Box::new(problem)),
+            Err(_) => Error::Unexpected { status, body },
+        }
+    }
+}
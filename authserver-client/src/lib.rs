@@ -0,0 +1,23 @@
+//! A client for the authserver API, and the request and response models it
+//! shares with the server.
+//!
+//! A [`Client`] signs in with a password or an OAuth grant, sends the token
+//! with every request and gets a new one when it expires: by signing in
+//! again with the password, redeeming the refresh token or repeating a
+//! client credentials grant. Errors are turned back into the [`Error`] the
+//! server answered with. Without the default `client` feature, only the
+//! [`models`] are built.
+//!
+//! The pages browsers are sent to (`/authorize`, `/device`, `/login/{provider}`,
+//! `/saml/...`) and the reverse proxy's `/auth/verify` are not covered.
+
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "client")]
+mod error;
+pub mod models;
+
+#[cfg(feature = "client")]
+pub use client::Client;
+#[cfg(feature = "client")]
+pub use error::Error;
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+/// The `type` of a problem is this prefix followed by its `code`.
+pub const PROBLEM_TYPE_PREFIX: &str = "urn:authserver:problem:";
+
+/// An error of the API, as `application/problem+json` (RFC 7807).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Problem {
+    /// `urn:authserver:problem:{code}`, which does not change between releases.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// What went wrong, such as `invalid_token` or `validation_failed`.
+    pub code: String,
+    /// The `X-Request-Id` of the request, to find it in the server's logs.
+    #[serde(default)]
+    pub request_id: String,
+    /// The fields of the request that are not valid.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// Why a field of the request is not valid.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    /// Such as `email` or `length`.
+    pub code: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// The body of the OAuth endpoints' errors (RFC 6749 section 5.2).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthErrorResponse {
+    pub error: String,
+    #[serde(default)]
+    pub error_description: String,
+}
+
+/// The body of the SCIM endpoints' errors (RFC 7644 section 3.12).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimErrorResponse {
+    pub schemas: Vec<String>,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scim_type: Option<String>,
+    pub detail: String,
+}
@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// One of the ways a user signs in, as `GET /me/identities` lists them:
+/// their password or directory account, and the upstream accounts linked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginIdentity {
+    pub provider: String,
+    pub subject: String,
+    /// Unknown for passwords and directory accounts.
+    pub linked_at: Option<DateTime<Utc>>,
+}
+
+/// Form of `POST /me/identities/{provider}`. Linking needs the user to
+/// prove who they are again: with their password when they have one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AddIdentityForm {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// The password to set, when adding the `password` identity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_hint: Option<String>,
+}
+
+/// Where the user signs in to the provider to link their account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkStarted {
+    pub authorization_url: String,
+}
@@ -0,0 +1,23 @@
+//! The bodies of the API's requests and responses. The server serializes
+//! the same types, so the two cannot drift apart.
+
+pub mod error;
+pub mod federation;
+pub mod oauth;
+pub mod oidc;
+pub mod registration;
+pub mod scim;
+pub mod session;
+pub mod user;
+
+pub use error::{FieldError, Problem};
+pub use federation::{AddIdentityForm, LinkStarted, LoginIdentity};
+pub use oauth::{
+    ClientCredentials, ClientSummary, DeviceAuthorizationRequest, DeviceAuthorizationResponse,
+    IntrospectionResponse, NewClient, TokenReference, TokenRequest, TokenResponse, UserGrant,
+};
+pub use oidc::{ProviderMetadata, UserInfo};
+pub use registration::{ClientMetadata, RegistrationResponse};
+pub use scim::{ListResponse, PatchOperation, PatchRequest, ScimGroup, ScimUser};
+pub use session::{LoginRequest, Session, SignInResponse};
+pub use user::{DeletedUser, SignupRequest, User};
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const TOKEN_ENDPOINT_AUTH_METHODS: [&str; 3] =
+    ["none", "client_secret_basic", "client_secret_post"];
+
+fn default_grant_types() -> Vec<String> {
+    vec!["authorization_code".to_string()]
+}
+
+fn default_auth_method() -> String {
+    "client_secret_basic".to_string()
+}
+
+/// Client metadata of RFC 7591 section 2, as sent to `POST /register` and
+/// `PUT /register/{client_id}`. Other metadata is accepted and ignored.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClientMetadata {
+    #[serde(default)]
+    pub client_name: Option<String>,
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    #[serde(default = "default_grant_types")]
+    pub grant_types: Vec<String>,
+    #[serde(default)]
+    pub response_types: Option<Vec<String>>,
+    #[serde(default = "default_auth_method")]
+    pub token_endpoint_auth_method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+/// Client information response (RFC 7591 section 3.2.1, RFC 7592 section 3).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationResponse {
+    pub client_id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    pub client_id_issued_at: i64,
+    /// Secrets do not expire.
+    pub client_secret_expires_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration_access_token: Option<String>,
+    pub registration_client_uri: String,
+    /// `pending` until an admin approves the client, `active` afterwards.
+    pub status: String,
+    #[serde(flatten)]
+    pub metadata: ClientMetadata,
+}
@@ -0,0 +1,217 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+pub const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const PATCH_OP_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+pub const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+
+/// Some identity providers send booleans as strings: `"active": "False"`.
+fn lenient_bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::Bool(b) => Ok(b),
+        Value::String(s) if s.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(s) if s.eq_ignore_ascii_case("false") => Ok(false),
+        other => Err(serde::de::Error::custom(format!(
+            "expected a boolean, found {}",
+            other
+        ))),
+    }
+}
+
+fn active_by_default() -> bool {
+    true
+}
+
+/// The `User` resource of RFC 7643 section 4.1, as far as users map onto it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUser {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub user_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<ScimName>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub emails: Vec<ScimEmail>,
+    #[serde(default = "active_by_default", deserialize_with = "lenient_bool")]
+    pub active: bool,
+    /// Write-only: the server never returns it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimName {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScimEmail {
+    pub value: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primary: Option<bool>,
+}
+
+impl ScimUser {
+    /// The primary email, or the first one.
+    pub fn email(&self) -> Option<&str> {
+        self.emails
+            .iter()
+            .find(|email| email.primary == Some(true))
+            .or_else(|| self.emails.first())
+            .map(|email| email.value.as_str())
+    }
+    /// The user's full name. Clients change `displayName`, `name.formatted`
+    /// or the given and family names, leaving the others as they were: the
+    /// first that differs from the `current` full name wins.
+    pub fn full_name(&self, current: Option<&str>) -> Option<String> {
+        let name = self.name.as_ref();
+        let parts: Vec<&str> = name
+            .into_iter()
+            .flat_map(|n| [n.given_name.as_deref(), n.family_name.as_deref()])
+            .flatten()
+            .collect();
+        let candidates = [
+            self.display_name.clone(),
+            name.and_then(|n| n.formatted.clone()),
+            Some(parts.join(" ")).filter(|joined| !joined.is_empty()),
+        ];
+        let candidates: Vec<String> = candidates.into_iter().flatten().collect();
+        candidates
+            .iter()
+            .find(|candidate| Some(candidate.as_str()) != current)
+            .or_else(|| candidates.first())
+            .cloned()
+    }
+}
+
+/// The `Group` resource of RFC 7643 section 4.2.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroup {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub display_name: String,
+    #[serde(default)]
+    pub members: Vec<ScimMember>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScimMember {
+    /// The member's user id.
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meta {
+    pub resource_type: String,
+    pub location: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListResponse<T> {
+    pub schemas: Vec<String>,
+    pub total_results: i64,
+    pub start_index: i64,
+    pub items_per_page: i64,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
+
+impl<T> ListResponse<T> {
+    pub fn new(total_results: i64, start_index: i64, resources: Vec<T>) -> Self {
+        ListResponse {
+            schemas: vec![LIST_RESPONSE_SCHEMA.to_string()],
+            total_results,
+            start_index,
+            items_per_page: resources.len() as i64,
+            resources,
+        }
+    }
+}
+
+/// Query of the list endpoints (RFC 7644 section 3.4.2).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// 1-based.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_index: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchRequest {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(rename = "Operations")]
+    pub operations: Vec<PatchOperation>,
+}
+
+impl PatchRequest {
+    pub fn new(operations: Vec<PatchOperation>) -> Self {
+        PatchRequest {
+            schemas: vec![PATCH_OP_SCHEMA.to_string()],
+            operations,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchOperation {
+    pub op: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+}
+
+#[test]
+fn test_full_name() {
+    let user: ScimUser = serde_json::from_value(serde_json::json!({
+        "userName": "jane",
+        "displayName": "Jane Doe",
+        "name": {"formatted": "Jane Roe", "givenName": "Jane", "familyName": "Roe"},
+        "active": "false"
+    }))
+    .unwrap();
+    assert!(!user.active);
+    assert_eq!(Some("Jane Doe".to_string()), user.full_name(None));
+    assert_eq!(
+        Some("Jane Roe".to_string()),
+        user.full_name(Some("Jane Doe"))
+    );
+}
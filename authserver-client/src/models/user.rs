@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A user's profile, as `GET /me` returns it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub id: Uuid,
+    pub username: Option<String>,
+    pub email: String,
+    pub full_name: Option<String>,
+    pub bio: Option<String>,
+    pub image: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// The JSON body of `POST /signup`, instead of the Basic header and a form.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignupRequest {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
+
+/// What `DELETE /me` answers with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeletedUser {
+    pub id: Uuid,
+}
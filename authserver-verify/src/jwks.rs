@@ -0,0 +1,148 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::DecodingKey;
+use serde::Deserialize;
+
+use crate::VerifyError;
+
+/// How long after a refresh a token signed with an unknown key does not
+/// make the cache refresh again.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+}
+
+type Keys = Vec<(Option<String>, DecodingKey)>;
+
+/// The RSA keys of a JWKS, by key id.
+fn rsa_keys(set: JwkSet) -> Keys {
+    set.keys
+        .into_iter()
+        .filter(|jwk| jwk.kty == "RSA")
+        .filter_map(|jwk| {
+            let key = DecodingKey::from_rsa_components(jwk.n.as_deref()?, jwk.e.as_deref()?);
+            Some((jwk.kid, key.ok()?))
+        })
+        .collect()
+}
+
+struct Inner {
+    url: String,
+    client: reqwest::Client,
+    keys: RwLock<Keys>,
+    refreshed_at: Mutex<Option<Instant>>,
+}
+
+/// The signing keys published at a JWKS URL, such as authserver's `/jwks`,
+/// refreshed in the background and whenever a token names a key it does
+/// not know yet.
+#[derive(Clone)]
+pub struct JwksCache(Arc<Inner>);
+
+impl JwksCache {
+    /// Fetches the keys, then refetches them every `interval` until the last
+    /// clone of the cache is dropped.
+    pub async fn new(url: &str, interval: Duration) -> Result<Self, VerifyError> {
+        let cache = JwksCache(Arc::new(Inner {
+            url: url.to_string(),
+            client: reqwest::Client::new(),
+            keys: RwLock::new(Vec::new()),
+            refreshed_at: Mutex::new(None),
+        }));
+        cache.refresh().await?;
+
+        let inner = Arc::downgrade(&cache.0);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                match inner.upgrade() {
+                    // A failed refresh keeps the keys it had.
+                    Some(inner) => JwksCache(inner).refresh().await.ok(),
+                    None => break,
+                };
+            }
+        });
+        Ok(cache)
+    }
+
+    pub async fn refresh(&self) -> Result<(), VerifyError> {
+        let set: JwkSet = self
+            .0
+            .client
+            .get(&self.0.url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| VerifyError::Jwks(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| VerifyError::Jwks(e.to_string()))?;
+        *self.0.keys.write().unwrap() = rsa_keys(set);
+        *self.0.refreshed_at.lock().unwrap() = Some(Instant::now());
+        Ok(())
+    }
+
+    /// The key `kid`; any key when the JWKS has only one and the token names none.
+    pub(crate) async fn key(&self, kid: Option<&str>) -> Result<DecodingKey, VerifyError> {
+        if let Some(key) = self.lookup(kid) {
+            return Ok(key);
+        }
+        let refreshed_recently = self
+            .0
+            .refreshed_at
+            .lock()
+            .unwrap()
+            .is_some_and(|at| at.elapsed() < MIN_REFRESH_INTERVAL);
+        if !refreshed_recently {
+            self.refresh().await?;
+            if let Some(key) = self.lookup(kid) {
+                return Ok(key);
+            }
+        }
+        Err(VerifyError::UnknownKey(kid.map(str::to_string)))
+    }
+
+    fn lookup(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        let keys = self.0.keys.read().unwrap();
+        match kid {
+            Some(kid) => keys
+                .iter()
+                .find(|(id, _)| id.as_deref() == Some(kid))
+                .map(|(_, key)| key.clone()),
+            None if keys.len() == 1 => Some(keys[0].1.clone()),
+            None => None,
+        }
+    }
+}
+
+#[test]
+fn test_rsa_keys() {
+    let set: JwkSet = serde_json::from_str(
+        r#"{"keys": [
+            {"kty": "RSA", "kid": "one", "n": "AQAB", "e": "AQAB", "use": "sig"},
+            {"kty": "EC", "kid": "two", "crv": "P-256"},
+            {"kty": "RSA", "kid": "three"}
+        ]}"#,
+    )
+    .unwrap();
+    let keys = rsa_keys(set);
+
+    assert_eq!(1, keys.len());
+    assert_eq!(Some("one"), keys[0].0.as_deref());
+}
@@ -0,0 +1,332 @@
+//! Verifies the tokens authserver issues, for the services they are sent to.
+//!
+//! A [`TokenVerifier`] checks a token's signature and expiry, with the
+//! realm's RSA key, pinned or, with the `jwks` feature, fetched from the
+//! keys the server publishes at `/jwks`; or with the shared `jwt_secret` of
+//! realms signing with HS512. The `warp` and `tower` features add the
+//! filters and middleware handing the verified [`Claims`] to handlers.
+
+#[cfg(feature = "jwks")]
+use jsonwebtoken::decode_header;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{decode, Algorithm, DecodingKey, TokenData, Validation};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[cfg(feature = "jwks")]
+mod jwks;
+#[cfg(feature = "tower")]
+pub mod tower;
+#[cfg(feature = "warp")]
+pub mod warp;
+
+#[cfg(feature = "jwks")]
+pub use jwks::JwksCache;
+
+/// The `typ` header of access tokens (RFC 9068), telling them from the ID
+/// tokens signed with the same key.
+pub const ACCESS_TOKEN_TYPE: &str = "at+jwt";
+
+/// The claims of the tokens authserver issues to users and clients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: Uuid,
+    pub exp: i64,
+    /// When the token was issued, which tells how recently the user signed in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    /// The session a user's own token belongs to: revoking it rejects the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Key of the access token in the token store.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<Uuid>,
+    /// The service an exchanged token is restricted to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    /// The client acting on behalf of `sub` (RFC 8693 section 4.1).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+}
+
+impl Claims {
+    /// Whether the token was granted `scope`; a user's own token, issued to
+    /// no client, is granted every scope.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match (&self.client_id, &self.scope) {
+            (None, _) => true,
+            (Some(_), Some(scopes)) => scopes.split(' ').any(|s| s == scope),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// A link of a delegation chain: the current actor, then the ones before it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Box<Actor>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("no token in the Authorization header")]
+    MissingToken,
+    #[error("invalid token: {0}")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+    #[error("no key {0:?} in the JWKS")]
+    UnknownKey(Option<String>),
+    #[error("cannot fetch the JWKS: {0}")]
+    Jwks(String),
+    #[error("the token was not granted the {0} scope")]
+    InsufficientScope(String),
+}
+
+impl VerifyError {
+    /// The HTTP status to answer the request with.
+    pub fn status(&self) -> u16 {
+        match self {
+            VerifyError::InsufficientScope(_) => 403,
+            VerifyError::Jwks(_) => 503,
+            _ => 401,
+        }
+    }
+
+    /// The `WWW-Authenticate` challenge of RFC 6750 section 3, if any.
+    pub fn challenge(&self) -> Option<String> {
+        match self {
+            VerifyError::MissingToken => Some("Bearer".to_string()),
+            VerifyError::InvalidToken(_) | VerifyError::UnknownKey(_) => {
+                Some("Bearer error=\"invalid_token\"".to_string())
+            }
+            VerifyError::InsufficientScope(scope) => Some(format!(
+                "Bearer error=\"insufficient_scope\", scope=\"{}\"",
+                scope
+            )),
+            VerifyError::Jwks(_) => None,
+        }
+    }
+}
+
+enum Keys {
+    Secret(DecodingKey),
+    Rsa(DecodingKey),
+    #[cfg(feature = "jwks")]
+    Jwks(JwksCache),
+}
+
+/// Checks the signature, expiry and, if set, issuer and audience of tokens.
+pub struct TokenVerifier {
+    keys: Keys,
+    validation: Validation,
+}
+
+impl TokenVerifier {
+    /// Verifies the HS512 tokens signed with the realm's `jwt_secret`.
+    pub fn from_secret(secret: &[u8]) -> Self {
+        TokenVerifier {
+            keys: Keys::Secret(DecodingKey::from_secret(secret)),
+            validation: Validation::new(Algorithm::HS512),
+        }
+    }
+
+    /// Verifies the RS256 tokens signed with a known RSA key.
+    pub fn from_rsa_key(key: DecodingKey) -> Self {
+        TokenVerifier {
+            keys: Keys::Rsa(key),
+            validation: Validation::new(Algorithm::RS256),
+        }
+    }
+
+    /// Verifies the RS256 tokens signed with the keys of a JWKS.
+    #[cfg(feature = "jwks")]
+    pub fn from_jwks(jwks: JwksCache) -> Self {
+        TokenVerifier {
+            keys: Keys::Jwks(jwks),
+            validation: Validation::new(Algorithm::RS256),
+        }
+    }
+
+    pub fn with_validation(mut self, validation: Validation) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Only accepts the tokens of the issuer, the realm's OIDC issuer.
+    pub fn with_issuer(mut self, issuer: &str) -> Self {
+        self.validation.set_issuer(&[issuer]);
+        self
+    }
+
+    /// Only accepts the tokens restricted to the audience, such as this
+    /// service's name in token exchange or a client's id in ID tokens.
+    pub fn with_audience(mut self, audience: &str) -> Self {
+        self.validation.set_audience(&[audience]);
+        self
+    }
+
+    /// Verifies an access token. ID tokens are signed with the same RSA
+    /// key, so RS256 tokens must also be typed as access tokens.
+    pub async fn verify(&self, token: &str) -> Result<TokenData<Claims>, VerifyError> {
+        let data = self.verify_as::<Claims>(token).await?;
+        let typed = data.header.typ.as_deref() == Some(ACCESS_TOKEN_TYPE);
+        if !typed && !matches!(self.keys, Keys::Secret(_)) {
+            return Err(VerifyError::InvalidToken(ErrorKind::InvalidToken.into()));
+        }
+        Ok(data)
+    }
+
+    /// Verifies a token carrying other claims, such as an ID token.
+    pub async fn verify_as<C: DeserializeOwned>(
+        &self,
+        token: &str,
+    ) -> Result<TokenData<C>, VerifyError> {
+        match &self.keys {
+            Keys::Secret(key) | Keys::Rsa(key) => Ok(decode(token, key, &self.validation)?),
+            #[cfg(feature = "jwks")]
+            Keys::Jwks(jwks) => {
+                let kid = decode_header(token)?.kid;
+                let key = jwks.key(kid.as_deref()).await?;
+                Ok(decode(token, &key, &self.validation)?)
+            }
+        }
+    }
+}
+
+/// The token of an `Authorization` header: the OAuth `Bearer <jwt>` scheme,
+/// or authserver's `Basic <base64 jwt>`.
+pub fn token_from_authorization(header: &str) -> Result<String, VerifyError> {
+    if let Some(token) = header.strip_prefix("Bearer ") {
+        return Ok(token.to_string());
+    }
+    let encoded = header
+        .strip_prefix("Basic ")
+        .ok_or(VerifyError::MissingToken)?;
+    base64::decode_config(encoded, base64::STANDARD)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or(VerifyError::MissingToken)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    use super::*;
+
+    pub const SECRET: &[u8] = b"jwtsecret";
+
+    pub fn token(client_id: Option<Uuid>, scope: Option<&str>, exp: i64) -> String {
+        let claims = Claims {
+            sub: Uuid::new_v4(),
+            exp,
+            iat: None,
+            sid: None,
+            client_id,
+            scope: scope.map(str::to_string),
+            jti: None,
+            aud: None,
+            act: None,
+        };
+        encode(
+            &Header::new(Algorithm::HS512),
+            &claims,
+            &EncodingKey::from_secret(SECRET),
+        )
+        .unwrap()
+    }
+
+    pub fn in_an_hour() -> i64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64
+            + 3600
+    }
+
+    #[tokio::test]
+    async fn verifies_tokens_signed_with_the_secret() {
+        let verifier = TokenVerifier::from_secret(SECRET);
+        let valid = token(None, None, in_an_hour());
+
+        assert!(verifier.verify(&valid).await.is_ok());
+        assert!(TokenVerifier::from_secret(b"other")
+            .verify(&valid)
+            .await
+            .is_err());
+        let expired = token(None, None, 1);
+        assert!(matches!(
+            verifier.verify(&expired).await,
+            Err(VerifyError::InvalidToken(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rsa_keys_only_verify_access_tokens() {
+        use rsa::pkcs1::EncodeRsaPrivateKey;
+        use rsa::traits::PublicKeyParts;
+
+        let key = rsa::RsaPrivateKey::new(&mut rand_core::OsRng, 2048).unwrap();
+        let encoding_key = EncodingKey::from_rsa_der(key.to_pkcs1_der().unwrap().as_bytes());
+        let component =
+            |c: &rsa::BigUint| base64::encode_config(c.to_bytes_be(), base64::URL_SAFE_NO_PAD);
+        let decoding_key =
+            DecodingKey::from_rsa_components(&component(key.n()), &component(key.e())).unwrap();
+        let verifier = TokenVerifier::from_rsa_key(decoding_key);
+        let claims = serde_json::json!({ "sub": Uuid::new_v4(), "exp": in_an_hour() });
+        let sign = |typ: &str| {
+            let header = Header {
+                typ: Some(typ.to_string()),
+                ..Header::new(Algorithm::RS256)
+            };
+            encode(&header, &claims, &encoding_key).unwrap()
+        };
+
+        assert!(verifier.verify(&sign(ACCESS_TOKEN_TYPE)).await.is_ok());
+        // Such as an ID token.
+        assert!(matches!(
+            verifier.verify(&sign("JWT")).await,
+            Err(VerifyError::InvalidToken(_))
+        ));
+        assert!(verifier
+            .verify_as::<serde_json::Value>(&sign("JWT"))
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn reads_both_authorization_schemes() {
+        let basic = format!("Basic {}", base64::encode("a.b.c"));
+
+        assert_eq!("a.b.c", token_from_authorization("Bearer a.b.c").unwrap());
+        assert_eq!("a.b.c", token_from_authorization(&basic).unwrap());
+        assert!(token_from_authorization("Digest a.b.c").is_err());
+    }
+
+    #[test]
+    fn users_have_every_scope_and_clients_theirs() {
+        let claims = |client_id: Option<Uuid>, scope: Option<&str>| Claims {
+            sub: Uuid::new_v4(),
+            exp: 0,
+            iat: None,
+            sid: None,
+            client_id,
+            scope: scope.map(str::to_string),
+            jti: None,
+            aud: None,
+            act: None,
+        };
+
+        assert!(claims(None, None).has_scope("orders:read"));
+        let client = Some(Uuid::new_v4());
+        assert!(claims(client, Some("openid orders:read")).has_scope("orders:read"));
+        assert!(!claims(client, Some("openid")).has_scope("orders:read"));
+        assert!(!claims(client, None).has_scope("orders:read"));
+    }
+}
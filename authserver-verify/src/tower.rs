@@ -0,0 +1,153 @@
+//! tower middleware letting through the requests with a valid token, for
+//! axum, hyper and other tower based servers.
+//!
+//! ```ignore
+//! let app = axum::Router::new()
+//!     .route("/orders", get(|Extension(claims): Extension<Claims>| async move {
+//!         format!("orders of {}", claims.sub)
+//!     }))
+//!     .layer(RequireTokenLayer::new(Arc::new(verifier)));
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use http::{Request, Response, StatusCode};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{token_from_authorization, Claims, TokenVerifier, VerifyError};
+
+/// Wraps services in [`RequireToken`].
+#[derive(Clone)]
+pub struct RequireTokenLayer {
+    verifier: Arc<TokenVerifier>,
+}
+
+impl RequireTokenLayer {
+    pub fn new(verifier: Arc<TokenVerifier>) -> Self {
+        RequireTokenLayer { verifier }
+    }
+}
+
+impl<S> Layer<S> for RequireTokenLayer {
+    type Service = RequireToken<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireToken {
+            inner,
+            verifier: self.verifier.clone(),
+        }
+    }
+}
+
+/// Passes the requests with a valid token in their `Authorization` header
+/// on to the inner service, with the token's [`Claims`] in their extensions
+/// (`Extension<Claims>` in axum); answers the others with a 401.
+#[derive(Clone)]
+pub struct RequireToken<S> {
+    inner: S,
+    verifier: Arc<TokenVerifier>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequireToken<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        // Keep the service that was polled ready for this request.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let verifier = self.verifier.clone();
+        Box::pin(async move {
+            let header = request
+                .headers()
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            let verified = match token_from_authorization(header) {
+                Ok(token) => verifier.verify(&token).await.map(|data| data.claims),
+                Err(e) => Err(e),
+            };
+            match verified {
+                Ok(claims) => {
+                    request.extensions_mut().insert::<Claims>(claims);
+                    inner.call(request).await
+                }
+                Err(e) => Ok(rejection(&e)),
+            }
+        })
+    }
+}
+
+fn rejection<B: Default>(error: &VerifyError) -> Response<B> {
+    let mut response = Response::new(B::default());
+    *response.status_mut() =
+        StatusCode::from_u16(error.status()).unwrap_or(StatusCode::UNAUTHORIZED);
+    if let Some(challenge) = error.challenge().and_then(|c| c.parse().ok()) {
+        response.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+    }
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::test::{in_an_hour, token, SECRET};
+
+    #[tokio::test]
+    async fn only_requests_with_a_valid_token_get_through() {
+        let verifier = Arc::new(TokenVerifier::from_secret(SECRET));
+        let service = RequireTokenLayer::new(verifier).layer(service_fn(
+            |request: Request<String>| async move {
+                let claims = request.extensions().get::<Claims>().unwrap();
+                Ok::<_, Infallible>(Response::new(claims.sub.to_string()))
+            },
+        ));
+        let request = |authorization: Option<String>| {
+            let mut request = Request::builder();
+            if let Some(authorization) = authorization {
+                request = request.header(AUTHORIZATION, authorization);
+            }
+            request.body(String::new()).unwrap()
+        };
+
+        let valid = token(None, None, in_an_hour());
+        let response = service
+            .clone()
+            .oneshot(request(Some(format!("Bearer {}", valid))))
+            .await
+            .unwrap();
+        assert_eq!(200, response.status());
+        assert!(uuid::Uuid::parse_str(response.body()).is_ok());
+
+        let response = service.clone().oneshot(request(None)).await.unwrap();
+        assert_eq!(401, response.status());
+        assert_eq!("Bearer", response.headers()[WWW_AUTHENTICATE]);
+        let forged = format!("Bearer {}.forged", valid);
+        let response = service.oneshot(request(Some(forged))).await.unwrap();
+        assert_eq!(401, response.status());
+        assert_eq!(
+            "Bearer error=\"invalid_token\"",
+            response.headers()[WWW_AUTHENTICATE]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! warp filters yielding the verified [`Claims`] of a request.
+//!
+//! ```no_run
+//! # async fn run(verifier: authserver_verify::TokenVerifier) {
+//! use std::sync::Arc;
+//! use warp::Filter;
+//!
+//! let verifier = Arc::new(verifier);
+//! let orders = warp::path!("orders")
+//!     .and(authserver_verify::warp::with_scope(verifier, "orders:read"))
+//!     .map(|claims: authserver_verify::Claims| format!("orders of {}", claims.sub));
+//! warp::serve(orders.recover(authserver_verify::warp::handle_rejection))
+//!     .run(([127, 0, 0, 1], 8080))
+//!     .await;
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use warp::http::header::WWW_AUTHENTICATE;
+use warp::http::StatusCode;
+use warp::{reject, Filter, Rejection, Reply};
+
+use crate::{token_from_authorization, Claims, TokenVerifier, VerifyError};
+
+impl reject::Reject for VerifyError {}
+
+/// The claims of the token in the `Authorization` header, `Bearer <jwt>` or
+/// `Basic <base64 jwt>`; rejects with a [`VerifyError`] without a valid one.
+pub fn with_token_auth_header(
+    verifier: Arc<TokenVerifier>,
+) -> impl Filter<Extract = (Claims,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(move |header: Option<String>| {
+        let verifier = verifier.clone();
+        async move {
+            let token = token_from_authorization(header.as_deref().unwrap_or_default())
+                .map_err(reject::custom)?;
+            match verifier.verify(&token).await {
+                Ok(data) => Ok(data.claims),
+                Err(e) => Err(reject::custom(e)),
+            }
+        }
+    })
+}
+
+/// As [`with_token_auth_header`], for tokens granted `scope`.
+pub fn with_scope(
+    verifier: Arc<TokenVerifier>,
+    scope: &'static str,
+) -> impl Filter<Extract = (Claims,), Error = Rejection> + Clone {
+    with_token_auth_header(verifier).and_then(move |claims: Claims| async move {
+        if claims.has_scope(scope) {
+            Ok(claims)
+        } else {
+            Err(reject::custom(VerifyError::InsufficientScope(
+                scope.to_string(),
+            )))
+        }
+    })
+}
+
+/// Answers a [`VerifyError`] rejection with its status and challenge;
+/// passes other rejections on.
+pub async fn handle_rejection(err: Rejection) -> Result<warp::reply::Response, Rejection> {
+    let error = match err.find::<VerifyError>() {
+        Some(error) => error,
+        None => return Err(err),
+    };
+    let status = StatusCode::from_u16(error.status()).unwrap_or(StatusCode::UNAUTHORIZED);
+    let mut response = status.into_response();
+    if let Some(challenge) = error.challenge().and_then(|c| c.parse().ok()) {
+        response.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{in_an_hour, token, SECRET};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn filters_yield_the_claims_of_valid_tokens() {
+        let verifier = Arc::new(TokenVerifier::from_secret(SECRET));
+        let filter = with_scope(verifier, "orders:read")
+            .map(|claims: Claims| claims.sub.to_string())
+            .recover(handle_rejection);
+        let client = Some(Uuid::new_v4());
+
+        let reply = |authorization: Option<String>| {
+            let mut request = warp::test::request();
+            if let Some(authorization) = authorization {
+                request = request.header("authorization", authorization);
+            }
+            request.reply(&filter)
+        };
+        let granted = token(client, Some("orders:read"), in_an_hour());
+        assert_eq!(
+            200,
+            reply(Some(format!("Bearer {}", granted))).await.status()
+        );
+
+        let response = reply(None).await;
+        assert_eq!(401, response.status());
+        assert_eq!("Bearer", response.headers()["www-authenticate"]);
+        let other_scope = token(client, Some("profile"), in_an_hour());
+        let response = reply(Some(format!("Bearer {}", other_scope))).await;
+        assert_eq!(403, response.status());
+        let expired = token(None, None, 1);
+        assert_eq!(
+            401,
+            reply(Some(format!("Bearer {}", expired))).await.status()
+        );
+    }
+}
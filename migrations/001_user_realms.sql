@@ -0,0 +1,4 @@
+-- Users are namespaced by realm: the same username may exist in several realms.
+alter table users add column realm varchar not null default 'AuthServer';
+alter table users drop constraint if exists users_username_key;
+create unique index users_realm_username_key on users (realm, username);
@@ -0,0 +1,22 @@
+-- OAuth 2.0 clients and the authorization codes issued to them.
+create table oauth_clients (
+    id uuid primary key default gen_random_uuid(),
+    realm varchar not null,
+    name varchar not null,
+    redirect_uris text[] not null,
+    scopes text[] not null default '{}',
+    created_at timestamptz not null default now()
+);
+
+create table authorization_codes (
+    code_hash varchar primary key,
+    client_id uuid not null references oauth_clients (id) on delete cascade,
+    user_id uuid not null references users (id) on delete cascade,
+    redirect_uri text not null,
+    scope text not null,
+    code_challenge varchar not null,
+    code_challenge_method varchar not null,
+    expires_at timestamptz not null,
+    consumed_at timestamptz,
+    created_at timestamptz not null default now()
+);
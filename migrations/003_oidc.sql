@@ -0,0 +1,3 @@
+-- OIDC: ID tokens repeat the nonce of the authorization request and the time the user logged in.
+alter table authorization_codes add column nonce varchar;
+alter table authorization_codes add column auth_time timestamptz not null default now();
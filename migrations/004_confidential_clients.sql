@@ -0,0 +1,9 @@
+-- Confidential OAuth clients authenticate with a hashed secret and may be
+-- limited to some grant types. PKCE stays mandatory for public clients only.
+alter table oauth_clients add column secret_hash varchar;
+alter table oauth_clients add column grant_types text[] not null default '{authorization_code}';
+alter table authorization_codes alter column code_challenge drop not null;
+alter table authorization_codes alter column code_challenge_method drop not null;
+
+-- Users holding the "admin" role may manage the realm's clients.
+alter table users add column roles text[] not null default '{}';
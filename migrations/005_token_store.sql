@@ -0,0 +1,19 @@
+-- Server-side record of issued OAuth tokens, so they can be introspected and
+-- revoked. Access tokens are looked up by their `jti`, refresh tokens (which
+-- are opaque) by digest. Client credentials tokens have no user.
+create table oauth_tokens (
+    id uuid primary key,
+    realm varchar not null,
+    token_type varchar not null,
+    token_hash varchar unique,
+    client_id uuid not null references oauth_clients (id) on delete cascade,
+    user_id uuid references users (id) on delete cascade,
+    scope text not null,
+    -- access tokens point at the refresh token they were issued with
+    parent_id uuid references oauth_tokens (id) on delete cascade,
+    expires_at timestamptz not null,
+    revoked_at timestamptz,
+    created_at timestamptz not null default now()
+);
+
+create index oauth_tokens_parent_id_idx on oauth_tokens (parent_id);
@@ -0,0 +1,18 @@
+-- RFC 8628 device authorization requests. The device polls with the device
+-- code (stored by digest) while the user approves the short user code.
+create table device_codes (
+    device_code_hash varchar primary key,
+    realm varchar not null,
+    user_code varchar not null,
+    client_id uuid not null references oauth_clients (id) on delete cascade,
+    scope text not null,
+    user_id uuid references users (id) on delete cascade,
+    -- null while pending, then whether the user approved
+    approved boolean,
+    interval_seconds integer not null,
+    last_polled_at timestamptz,
+    expires_at timestamptz not null,
+    consumed_at timestamptz,
+    created_at timestamptz not null default now(),
+    unique (realm, user_code)
+);
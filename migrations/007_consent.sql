@@ -0,0 +1,13 @@
+-- Scopes each user consented to, per client. Consent accumulates: approving
+-- more scopes extends the grant. First-party clients never ask for consent.
+create table user_grants (
+    realm varchar not null,
+    user_id uuid not null references users (id) on delete cascade,
+    client_id uuid not null references oauth_clients (id) on delete cascade,
+    scopes text[] not null,
+    created_at timestamptz not null default now(),
+    updated_at timestamptz not null default now(),
+    primary key (user_id, client_id)
+);
+
+alter table oauth_clients add column first_party boolean not null default false;
@@ -0,0 +1,6 @@
+-- Dynamic client registration (RFC 7591). Registered clients wait for an
+-- admin in the 'pending' state and manage their configuration (RFC 7592)
+-- with a registration access token, stored by digest.
+alter table oauth_clients add column status varchar not null default 'active';
+alter table oauth_clients add column token_endpoint_auth_method varchar;
+alter table oauth_clients add column registration_token_hash varchar;
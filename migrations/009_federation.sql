@@ -0,0 +1,20 @@
+-- Sign-ins in progress at an upstream identity provider, keyed by the digest of their state.
+create table federated_logins (
+    state_hash varchar primary key,
+    realm varchar not null,
+    provider varchar not null,
+    code_verifier varchar not null,
+    nonce varchar not null,
+    expires_at timestamptz not null
+);
+
+-- Accounts at upstream identity providers linked to local users.
+create table identities (
+    realm varchar not null,
+    provider varchar not null,
+    subject varchar not null,
+    user_id uuid not null references users(id) on delete cascade,
+    linked_at timestamptz not null default now(),
+    primary key (realm, provider, subject)
+);
+create index identities_user_id on identities (user_id);
@@ -0,0 +1,2 @@
+-- Where a user's password is checked: 'local' against password_hash, 'ldap' against the realm's directory.
+alter table users add column auth_source varchar not null default 'local';
@@ -0,0 +1,7 @@
+-- AuthnRequests sent to SAML identity providers, until their answer comes back.
+create table saml_requests (
+    request_id varchar primary key,
+    realm varchar not null,
+    provider varchar not null,
+    expires_at timestamptz not null
+);
@@ -0,0 +1,16 @@
+-- Groups pushed by SCIM provisioning clients, and their members.
+create table groups (
+    id uuid primary key default gen_random_uuid(),
+    realm varchar not null,
+    display_name varchar not null,
+    created_at timestamptz not null default now(),
+    updated_at timestamptz not null default now()
+);
+create unique index groups_realm_display_name_key on groups (realm, display_name);
+
+create table group_members (
+    group_id uuid not null references groups(id) on delete cascade,
+    user_id uuid not null references users(id) on delete cascade,
+    primary key (group_id, user_id)
+);
+create index group_members_user_id on group_members (user_id);
@@ -0,0 +1,3 @@
+-- A sign-in at an upstream provider started by a signed-in user, to link
+-- the upstream account to theirs rather than to sign in with it.
+alter table federated_logins add column link_user_id uuid references users(id) on delete cascade;
@@ -0,0 +1,14 @@
+-- Signed-in sessions, one per token issued by `/login`, `/signup` or a federated sign-in.
+-- Tokens carry their session id (`sid`): revoking the session rejects them at once.
+create table sessions (
+    id uuid primary key default gen_random_uuid(),
+    realm varchar not null,
+    user_id uuid not null references users(id) on delete cascade,
+    user_agent varchar,
+    ip varchar,
+    created_at timestamptz not null default now(),
+    last_seen_at timestamptz not null default now(),
+    expires_at timestamptz not null,
+    revoked_at timestamptz
+);
+create index sessions_user_id on sessions (user_id);
@@ -0,0 +1,4 @@
+-- Browser sessions (realms with `cookie_session`): the digest of the secret
+-- held in the session cookie, and the CSRF token pages echo back.
+alter table sessions add column cookie_hash varchar unique;
+alter table sessions add column csrf_token varchar;
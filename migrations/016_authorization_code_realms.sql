@@ -0,0 +1,4 @@
+-- Authorization codes are redeemed only in the realm they were issued in.
+alter table authorization_codes add column realm varchar;
+update authorization_codes a set realm = c.realm from oauth_clients c where c.id = a.client_id;
+alter table authorization_codes alter column realm set not null;
@@ -0,0 +1,3 @@
+-- Whether the authorization request named its redirect_uri, which the token
+-- request must then repeat (RFC 6749 section 4.1.3).
+alter table authorization_codes add column redirect_uri_supplied boolean not null default true;
@@ -0,0 +1,105 @@
+use serde::Deserialize;
+
+fn default_cookie_name() -> String {
+    "session".to_string()
+}
+
+fn default_secure() -> bool {
+    true
+}
+
+/// Header carrying the CSRF token on state-changing cookie authenticated requests.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SameSite {
+    Strict,
+    #[default]
+    Lax,
+    None,
+}
+
+/// Browser sessions: `/login` also sets an `HttpOnly` cookie naming a
+/// server-side session, which authenticates requests in place of a token,
+/// and a cookie with the session's CSRF token, which pages echo in the
+/// `X-CSRF-Token` header of the requests changing anything (double submit).
+#[derive(Debug, Deserialize, Clone)]
+pub struct CookieSession {
+    #[serde(default = "default_cookie_name")]
+    pub cookie_name: String,
+    #[serde(default)]
+    pub same_site: SameSite,
+    /// Only ever turned off to serve plain HTTP in development.
+    #[serde(default = "default_secure")]
+    pub secure: bool,
+}
+
+impl CookieSession {
+    pub fn csrf_cookie_name(&self) -> String {
+        format!("{}_csrf", self.cookie_name)
+    }
+
+    /// `Set-Cookie` values of a session lasting `max_age` seconds.
+    pub fn set_cookies(&self, secret: &str, csrf_token: &str, max_age: i64) -> [String; 2] {
+        [
+            self.cookie(&self.cookie_name, secret, max_age, true),
+            self.cookie(&self.csrf_cookie_name(), csrf_token, max_age, false),
+        ]
+    }
+
+    /// `Set-Cookie` values removing the session's cookies.
+    pub fn clear_cookies(&self) -> [String; 2] {
+        [
+            self.cookie(&self.cookie_name, "", 0, true),
+            self.cookie(&self.csrf_cookie_name(), "", 0, false),
+        ]
+    }
+
+    fn cookie(&self, name: &str, value: &str, max_age: i64, http_only: bool) -> String {
+        let mut cookie = format!(
+            "{}={}; Path=/; Max-Age={}; SameSite={:?}",
+            name, value, max_age, self.same_site
+        );
+        if http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+}
+
+/// The value of the cookie `name` in a `Cookie` request header.
+pub fn cookie_value<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    header
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+#[test]
+fn test_cookies() {
+    let session = CookieSession {
+        cookie_name: "sid".to_string(),
+        same_site: SameSite::Strict,
+        secure: true,
+    };
+    let [secret, csrf] = session.set_cookies("s3cret", "t0ken", 600);
+
+    assert_eq!(
+        secret,
+        "sid=s3cret; Path=/; Max-Age=600; SameSite=Strict; HttpOnly; Secure"
+    );
+    assert_eq!(
+        csrf,
+        "sid_csrf=t0ken; Path=/; Max-Age=600; SameSite=Strict; Secure"
+    );
+    assert!(session.clear_cookies()[0].starts_with("sid=; Path=/; Max-Age=0;"));
+
+    let header = "theme=dark; sid=s3cret;sid_csrf=t0ken";
+    assert_eq!(cookie_value(header, "sid"), Some("s3cret"));
+    assert_eq!(cookie_value(header, "sid_csrf"), Some("t0ken"));
+    assert_eq!(cookie_value(header, "other"), None);
+}
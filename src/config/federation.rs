@@ -0,0 +1,163 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+/// Shared by every provider, so that one that stops answering holds a
+/// sign-in up for seconds rather than for good.
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("HTTP client for identity providers")
+});
+
+fn default_scope() -> String {
+    "openid email profile".to_string()
+}
+
+/// An upstream OpenID Connect provider users of a realm can sign in with,
+/// such as a customer's corporate identity provider.
+#[derive(Debug, Deserialize, Clone)]
+pub struct IdentityProvider {
+    /// Appears in our URLs: `/login/{name}`.
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    #[serde(default = "default_scope")]
+    pub scope: String,
+    /// Whether an upstream account not linked yet is linked to the local
+    /// user with the same verified email. Only for providers trusted to
+    /// verify the addresses of every account they hold.
+    #[serde(default)]
+    pub link_by_email: bool,
+}
+
+/// The part of the provider's discovery document we need.
+#[derive(Debug, Deserialize)]
+pub struct UpstreamMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpstreamTokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpstreamJwks {
+    keys: Vec<UpstreamJwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpstreamJwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+}
+
+/// Who the provider says signed in.
+#[derive(Debug, Deserialize)]
+pub struct UpstreamIdentity {
+    pub sub: String,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+}
+
+impl IdentityProvider {
+    pub async fn discover(&self) -> Result<UpstreamMetadata, String> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.issuer.trim_end_matches('/')
+        );
+        let metadata: UpstreamMetadata = fetch_json(HTTP_CLIENT.get(&url)).await?;
+        // OIDC Discovery section 4.3: the document must be about the issuer we trust.
+        if metadata.issuer.trim_end_matches('/') != self.issuer.trim_end_matches('/') {
+            return Err(format!("{} describes issuer {}", url, metadata.issuer));
+        }
+        Ok(metadata)
+    }
+
+    /// Redeems an authorization code at the token endpoint, returning the ID token.
+    pub async fn exchange_code(
+        &self,
+        metadata: &UpstreamMetadata,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+    ) -> Result<String, String> {
+        let request = HTTP_CLIENT
+            .post(&metadata.token_endpoint)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri),
+                ("code_verifier", code_verifier),
+            ]);
+        let response: UpstreamTokenResponse = fetch_json(request).await?;
+        Ok(response.id_token)
+    }
+
+    /// Checks the ID token's signature against the provider's JWKS, and that
+    /// it was issued by the provider, to us, for this sign-in.
+    pub async fn verify_id_token(
+        &self,
+        metadata: &UpstreamMetadata,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<UpstreamIdentity, String> {
+        let header = decode_header(id_token).map_err(|e| e.to_string())?;
+        if header.alg != Algorithm::RS256 {
+            return Err(format!("unsupported ID token algorithm {:?}", header.alg));
+        }
+        let jwks: UpstreamJwks = fetch_json(HTTP_CLIENT.get(&metadata.jwks_uri)).await?;
+        let jwk = jwks
+            .keys
+            .iter()
+            .filter(|k| k.kty == "RSA")
+            .find(|k| header.kid.is_none() || k.kid == header.kid)
+            .ok_or("no matching key in the provider's JWKS")?;
+        let key = match (&jwk.n, &jwk.e) {
+            (Some(n), Some(e)) => {
+                DecodingKey::from_rsa_components(n, e).map_err(|e| e.to_string())?
+            }
+            _ => return Err("the provider's key has no RSA components".to_string()),
+        };
+
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.client_id]);
+        let identity = decode::<UpstreamIdentity>(id_token, &key, &validation)
+            .map_err(|e| e.to_string())?
+            .claims;
+        if identity.nonce.as_deref() != Some(nonce) {
+            return Err("the ID token nonce does not match".to_string());
+        }
+        Ok(identity)
+    }
+}
+
+async fn fetch_json<T: for<'de> Deserialize<'de>>(
+    request: reqwest::RequestBuilder,
+) -> Result<T, String> {
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("{} answered {}", response.url(), response.status()));
+    }
+    response.json().await.map_err(|e| e.to_string())
+}
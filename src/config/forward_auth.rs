@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+
+use crate::models::auth::VerifiedUser;
+
+fn default_cache_seconds() -> u64 {
+    10
+}
+
+/// Most verification results kept before expired ones are dropped.
+const CACHE_CAPACITY: usize = 10_000;
+
+/// Who may reach the paths behind the reverse proxy, matched against the
+/// forwarded URI.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ForwardAuthRule {
+    /// The forwarded paths the rule applies to: this one and those under it,
+    /// whole segments only.
+    pub path: String,
+    /// Lets requests through without signing in.
+    #[serde(default)]
+    pub public: bool,
+    /// Roles the user must have one of.
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// Settings of `GET /auth/verify`. Paths no rule matches need a signed-in
+/// user, with any roles.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ForwardAuth {
+    /// How long a token or session cookie verified once is trusted without
+    /// checking it again; its session may be revoked in the meantime.
+    #[serde(default = "default_cache_seconds")]
+    pub cache_seconds: u64,
+    #[serde(default)]
+    pub rules: Vec<ForwardAuthRule>,
+}
+
+impl Default for ForwardAuth {
+    fn default() -> Self {
+        ForwardAuth {
+            cache_seconds: default_cache_seconds(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl ForwardAuthRule {
+    fn applies_to(&self, path: &str) -> bool {
+        let prefix = self.path.trim_end_matches('/');
+        match path.strip_prefix(prefix) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+impl ForwardAuth {
+    /// The first declared rule for the path, once normalized.
+    pub fn rule(&self, path: &str) -> Option<&ForwardAuthRule> {
+        self.rules.iter().find(|rule| rule.applies_to(path))
+    }
+}
+
+/// The forwarded path as the application behind the proxy resolves it:
+/// percent-decoded, without empty or `.` segments, `..` segments applied.
+/// Paths that could resolve otherwise there, with encoded separators,
+/// encoded escapes or backslashes, are `None`.
+pub fn normalize_path(path: &str) -> Option<String> {
+    let lowercase = path.to_ascii_lowercase();
+    if lowercase.contains("%2f") || lowercase.contains("%5c") {
+        return None;
+    }
+    let decoded = percent_decode_str(path).decode_utf8().ok()?;
+    if decoded.contains('%') || decoded.contains('\\') {
+        return None;
+    }
+    let mut segments = Vec::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(format!("/{}", segments.join("/")))
+}
+
+/// The users credentials were recently verified for by `GET /auth/verify`,
+/// shared by every request.
+#[derive(Debug, Clone, Default)]
+pub struct VerifyCache(Arc<Mutex<HashMap<String, (Instant, VerifiedUser)>>>);
+
+impl VerifyCache {
+    pub fn get(&self, key: &str) -> Option<VerifiedUser> {
+        let entries = self.0.lock().unwrap();
+        match entries.get(key) {
+            Some((expires_at, user)) if *expires_at > Instant::now() => Some(user.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&self, key: String, user: VerifiedUser, ttl: Duration) {
+        let now = Instant::now();
+        let mut entries = self.0.lock().unwrap();
+        if entries.len() >= CACHE_CAPACITY {
+            entries.retain(|_, (expires_at, _)| *expires_at > now);
+        }
+        if entries.len() < CACHE_CAPACITY {
+            entries.insert(key, (now + ttl, user));
+        }
+    }
+}
+
+#[test]
+fn test_rules() {
+    let rule = |path: &str, public: bool, roles: &[&str]| ForwardAuthRule {
+        path: path.to_string(),
+        public,
+        roles: roles.iter().map(|r| r.to_string()).collect(),
+    };
+    let settings = ForwardAuth {
+        cache_seconds: 10,
+        rules: vec![
+            rule("/admin/public/", true, &[]),
+            rule("/admin/", false, &["admin"]),
+        ],
+    };
+
+    assert!(settings.rule("/admin/public/logo.png").unwrap().public);
+    assert_eq!(settings.rule("/admin/users").unwrap().roles, ["admin"]);
+    assert_eq!(settings.rule("/admin").unwrap().roles, ["admin"]);
+    assert!(settings.rule("/administrator").is_none());
+    assert!(settings.rule("/orders").is_none());
+
+    let rule = |path| settings.rule(&normalize_path(path).unwrap()).unwrap();
+    assert_eq!(rule("/admin/public/../users").roles, ["admin"]);
+    assert_eq!(rule("/admin/public/%2e%2e/users").roles, ["admin"]);
+    assert_eq!(rule("//admin/users").roles, ["admin"]);
+}
+
+#[test]
+fn test_normalize_path() {
+    let normalized = |path| normalize_path(path).unwrap();
+    assert_eq!("/orders/42", normalized("/orders/42"));
+    assert_eq!("/admin", normalized("/admin/public/../../admin"));
+    assert_eq!("/admin", normalized("/admin/public/%2e%2e/%2E%2E/admin"));
+    assert_eq!("/admin/users", normalized("//admin/./users/"));
+    assert_eq!("/admin", normalized("/../../admin"));
+    assert_eq!("/my files", normalized("/my%20files"));
+    assert_eq!("/", normalized(""));
+
+    assert!(normalize_path("/admin/public/..%2F..%2Fadmin").is_none());
+    assert!(normalize_path("/admin/public/%252e%252e/admin").is_none());
+    assert!(normalize_path("/admin/public\\..\\..\\admin").is_none());
+    assert!(normalize_path("/%ff").is_none());
+}
+
+#[test]
+fn test_verify_cache() {
+    let cache = VerifyCache::default();
+    let user = VerifiedUser {
+        id: uuid::Uuid::new_v4(),
+        email: "jane@example.com".to_string(),
+        roles: vec!["admin".to_string()],
+    };
+    cache.insert("fresh".to_string(), user.clone(), Duration::from_secs(60));
+    cache.insert("stale".to_string(), user.clone(), Duration::ZERO);
+
+    assert_eq!(cache.get("fresh").unwrap().id, user.id);
+    assert!(cache.get("stale").is_none());
+    assert!(cache.get("unknown").is_none());
+}
@@ -1,10 +1,11 @@
 use crate::errors::Error::HashError;
+use crate::metrics::metrics;
 
 // use rand_core::OsRng;
 use secrecy::{ExposeSecret, Secret};
 
 use argon2::{
-    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    password_hash::{self, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
 use warp::reject;
@@ -17,23 +18,29 @@ pub struct HashService {
 
 impl HashService {
     pub async fn hash_password(&self, password: String) -> Result<Secret<String>, Rejection> {
+        let _timer = metrics().argon2_timer("hash");
         match Argon2::default().hash_password(password.as_bytes(), &self.salt) {
-            Ok(p) => return Ok(Secret::new(p.to_string())),
-            Err(e) => return Err(reject::custom(HashError(e))),
+            Ok(p) => Ok(Secret::new(p.to_string())),
+            Err(e) => Err(reject::custom(HashError(e))),
         }
     }
-    #[allow(dead_code)]
     pub async fn verify_password_hash(
         &self,
         password: String,
         password_hash: Secret<String>,
     ) -> Result<bool, Rejection> {
-        let password_hash_phc = PasswordHash::new(password_hash.expose_secret()).unwrap();
+        // A malformed or legacy hash matches no password.
+        let password_hash_phc = match PasswordHash::new(password_hash.expose_secret()) {
+            Ok(phc) => phc,
+            Err(_) => return Ok(false),
+        };
+        let _timer = metrics().argon2_timer("verify");
 
         match Argon2::default().verify_password(password.as_bytes(), &password_hash_phc) {
-            Ok(_) => return Ok(true),
-            Err(e) => return Err(reject::custom(HashError(e))),
-        };
+            Ok(_) => Ok(true),
+            Err(password_hash::Error::Password) => Ok(false),
+            Err(e) => Err(reject::custom(HashError(e))),
+        }
     }
 }
 
@@ -69,7 +76,7 @@ async fn test_hash_password() {
     let password = "uuid".to_string();
 
     let password_hash = hash_service.hash_password(password).await.unwrap();
-    assert_eq!(password_hash.expose_secret().is_empty(), false);
+    assert!(!password_hash.expose_secret().is_empty());
 }
 
 #[tokio::test]
@@ -104,3 +111,19 @@ async fn test_verify_password_hash() {
     //     password_hash2.expose_secret()
     // );
 }
+
+#[tokio::test]
+async fn test_verify_malformed_hash() {
+    use argon2::password_hash::{rand_core::OsRng, SaltString};
+
+    let hash_service = HashService {
+        salt: SaltString::generate(&mut OsRng),
+    };
+    for stored in ["", "plaintext", "$2b$12$legacybcrypthash"] {
+        let verified = hash_service
+            .verify_password_hash("plaintext".to_string(), Secret::new(stored.to_string()))
+            .await
+            .unwrap();
+        assert!(!verified, "{}", stored);
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+
+use config::ConfigError;
+use jsonwebtoken::{DecodingKey, EncodingKey};
+use rand_core::OsRng;
+use rsa::pkcs1::{DecodeRsaPrivateKey, EncodeRsaPrivateKey};
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPrivateKey;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Public half of a signing key as published in the JWKS (RFC 7517).
+#[derive(Debug, Clone, Serialize)]
+pub struct Jwk {
+    pub kty: &'static str,
+    #[serde(rename = "use")]
+    pub use_: &'static str,
+    pub alg: &'static str,
+    pub kid: String,
+    pub n: String,
+    pub e: String,
+}
+
+/// RS256 key pair used to sign access and ID tokens.
+pub struct SigningKey {
+    pub encoding_key: EncodingKey,
+    pub decoding_key: DecodingKey,
+    pub jwk: Jwk,
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("kid", &self.jwk.kid)
+            .finish()
+    }
+}
+
+impl SigningKey {
+    /// Reads a PKCS#1 or PKCS#8 PEM encoded RSA private key.
+    pub fn from_pem_file(path: &str) -> Result<Self, ConfigError> {
+        let pem = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Message(format!("cannot read {}: {}", path, e)))?;
+        let key = RsaPrivateKey::from_pkcs1_pem(&pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs8_pem(&pem))
+            .map_err(|e| ConfigError::Message(format!("invalid RSA key in {}: {}", path, e)))?;
+        Self::from_rsa(key)
+    }
+
+    /// Ephemeral key for development: tokens signed with it do not survive a restart.
+    pub fn generate() -> Result<Self, ConfigError> {
+        let key = RsaPrivateKey::new(&mut OsRng, 2048)
+            .map_err(|e| ConfigError::Message(format!("cannot generate RSA key: {}", e)))?;
+        Self::from_rsa(key)
+    }
+
+    fn from_rsa(key: RsaPrivateKey) -> Result<Self, ConfigError> {
+        let der = key
+            .to_pkcs1_der()
+            .map_err(|e| ConfigError::Message(format!("cannot encode RSA key: {}", e)))?;
+        let n = base64::encode_config(key.n().to_bytes_be(), base64::URL_SAFE_NO_PAD);
+        let e = base64::encode_config(key.e().to_bytes_be(), base64::URL_SAFE_NO_PAD);
+
+        // RFC 7638 thumbprint, so the kid changes whenever the key does.
+        let thumbprint = format!("{{\"e\":\"{}\",\"kty\":\"RSA\",\"n\":\"{}\"}}", e, n);
+        let kid = base64::encode_config(
+            Sha256::digest(thumbprint.as_bytes()),
+            base64::URL_SAFE_NO_PAD,
+        );
+
+        let decoding_key = DecodingKey::from_rsa_components(&n, &e)
+            .map_err(|e| ConfigError::Message(format!("invalid RSA key: {}", e)))?;
+        Ok(SigningKey {
+            encoding_key: EncodingKey::from_rsa_der(der.as_bytes()),
+            decoding_key,
+            jwk: Jwk {
+                kty: "RSA",
+                use_: "sig",
+                alg: "RS256",
+                kid,
+                n,
+                e,
+            },
+        })
+    }
+}
+
+#[test]
+fn test_generated_key_verifies_its_signatures() {
+    use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, Header, Validation};
+
+    let key = SigningKey::generate().unwrap();
+    let token = encode(
+        &Header::new(Algorithm::RS256),
+        &serde_json::json!({ "sub": "subject", "exp": 4102444800i64 }),
+        &key.encoding_key,
+    )
+    .unwrap();
+
+    let decoding_key = DecodingKey::from_rsa_components(&key.jwk.n, &key.jwk.e).unwrap();
+    let data =
+        decode::<serde_json::Value>(&token, &decoding_key, &Validation::new(Algorithm::RS256))
+            .unwrap();
+    assert_eq!(data.claims["sub"], "subject");
+    assert_eq!(key.jwk.kid.len(), 43);
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use ldap3::{dn_escape, ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use serde::Deserialize;
+
+use crate::models::user::ExternalProfile;
+
+/// LDAP result code of a bind with a wrong DN or password.
+const INVALID_CREDENTIALS: u32 = 49;
+const CONNECT_TIMEOUT_SECONDS: u64 = 5;
+
+fn default_search_filter() -> String {
+    "(sAMAccountName={username})".to_string()
+}
+
+fn default_email_attribute() -> String {
+    "mail".to_string()
+}
+
+fn default_full_name_attribute() -> String {
+    "displayName".to_string()
+}
+
+/// Directory attributes copied to the `User` on every directory sign-in.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LdapAttributes {
+    #[serde(default = "default_email_attribute")]
+    pub email: String,
+    #[serde(default = "default_full_name_attribute")]
+    pub full_name: String,
+}
+
+impl Default for LdapAttributes {
+    fn default() -> Self {
+        LdapAttributes {
+            email: default_email_attribute(),
+            full_name: default_full_name_attribute(),
+        }
+    }
+}
+
+/// An LDAP or Active Directory server users of a realm can sign in against.
+///
+/// With `bind_dn` (say `uid={username},ou=people,dc=corp,dc=example`) the
+/// user binds directly. Otherwise the service account `search_dn` looks the
+/// user up under `search_base` with `search_filter`, then the user binds
+/// with the DN found.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LdapConfig {
+    pub url: String,
+    #[serde(default)]
+    pub bind_dn: Option<String>,
+    #[serde(default)]
+    pub search_dn: Option<String>,
+    #[serde(default)]
+    pub search_password: Option<String>,
+    #[serde(default)]
+    pub search_base: Option<String>,
+    #[serde(default = "default_search_filter")]
+    pub search_filter: String,
+    #[serde(default)]
+    pub attributes: LdapAttributes,
+}
+
+impl LdapConfig {
+    /// Binds as the user; `None` when the directory rejects the credentials.
+    pub async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<ExternalProfile>, String> {
+        // An empty password is an unauthenticated bind, which succeeds (RFC 4513 section 5.1.2).
+        if username.is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+        let settings =
+            LdapConnSettings::new().set_conn_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECONDS));
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.url)
+            .await
+            .map_err(|e| e.to_string())?;
+        ldap3::drive!(conn);
+
+        let user = match &self.bind_dn {
+            Some(template) => {
+                self.simple_bind(&mut ldap, template, username, password)
+                    .await
+            }
+            None => self.search_then_bind(&mut ldap, username, password).await,
+        };
+        let _ = ldap.unbind().await;
+        user
+    }
+
+    async fn simple_bind(
+        &self,
+        ldap: &mut Ldap,
+        template: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<ExternalProfile>, String> {
+        let dn = template.replace("{username}", &dn_escape(username));
+        if !bind(ldap, &dn, password).await? {
+            return Ok(None);
+        }
+        let (entries, _) = ldap
+            .search(&dn, Scope::Base, "(objectClass=*)", self.attribute_names())
+            .await
+            .and_then(|result| result.success())
+            .map_err(|e| e.to_string())?;
+        Ok(Some(match entries.into_iter().next() {
+            Some(entry) => self.directory_user(&SearchEntry::construct(entry).attrs),
+            None => ExternalProfile::default(),
+        }))
+    }
+
+    async fn search_then_bind(
+        &self,
+        ldap: &mut Ldap,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<ExternalProfile>, String> {
+        let base = self
+            .search_base
+            .as_deref()
+            .ok_or("search_base is required without bind_dn")?;
+        if let Some(search_dn) = &self.search_dn {
+            let search_password = self.search_password.as_deref().unwrap_or_default();
+            if !bind(ldap, search_dn, search_password).await? {
+                return Err(format!("the directory refused the bind of {}", search_dn));
+            }
+        }
+        let filter = self
+            .search_filter
+            .replace("{username}", &ldap_escape(username));
+        let (entries, _) = ldap
+            .search(base, Scope::Subtree, &filter, self.attribute_names())
+            .await
+            .and_then(|result| result.success())
+            .map_err(|e| e.to_string())?;
+        // Nobody, or several people, answer to that name.
+        let entry = match <[_; 1]>::try_from(entries) {
+            Ok([entry]) => SearchEntry::construct(entry),
+            Err(_) => return Ok(None),
+        };
+        if !bind(ldap, &entry.dn, password).await? {
+            return Ok(None);
+        }
+        Ok(Some(self.directory_user(&entry.attrs)))
+    }
+
+    fn attribute_names(&self) -> Vec<&str> {
+        vec![&self.attributes.email, &self.attributes.full_name]
+    }
+
+    fn directory_user(&self, attrs: &HashMap<String, Vec<String>>) -> ExternalProfile {
+        // Attribute names are case insensitive.
+        let first = |name: &str| {
+            attrs
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .and_then(|(_, values)| values.first().cloned())
+        };
+        ExternalProfile {
+            email: first(&self.attributes.email),
+            full_name: first(&self.attributes.full_name),
+        }
+    }
+}
+
+/// Whether the directory accepted the bind; other failures are errors.
+async fn bind(ldap: &mut Ldap, dn: &str, password: &str) -> Result<bool, String> {
+    let result = ldap
+        .simple_bind(dn, password)
+        .await
+        .map_err(|e| e.to_string())?;
+    match result.rc {
+        0 => Ok(true),
+        INVALID_CREDENTIALS => Ok(false),
+        rc => Err(format!("bind of {} failed with result code {}", dn, rc)),
+    }
+}
+
+#[test]
+fn test_directory_user_mapping() {
+    let config: LdapConfig = serde_json::from_value(serde_json::json!({
+        "url": "ldap://localhost",
+        "search_base": "dc=corp,dc=example",
+        "attributes": { "full_name": "cn" }
+    }))
+    .unwrap();
+    assert_eq!("(sAMAccountName={username})", config.search_filter);
+
+    let attrs = HashMap::from([
+        ("Mail".to_string(), vec!["jdoe@corp.example".to_string()]),
+        ("cn".to_string(), vec!["Jane Doe".to_string()]),
+    ]);
+    assert_eq!(
+        ExternalProfile {
+            email: Some("jdoe@corp.example".to_string()),
+            full_name: Some("Jane Doe".to_string()),
+        },
+        config.directory_user(&attrs)
+    );
+}
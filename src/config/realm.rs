@@ -0,0 +1,320 @@
+use std::sync::Arc;
+
+use chrono::Duration;
+use config::ConfigError;
+use jsonwebtoken::{Algorithm, Header, Validation};
+use serde::Deserialize;
+use tracing::warn;
+use uuid::Uuid;
+use validator::{ValidationError as FieldError, ValidationErrors};
+use warp::reject;
+use warp::Rejection;
+
+use super::cookie::CookieSession;
+use super::federation::IdentityProvider;
+use super::forward_auth::ForwardAuth;
+use super::keys::SigningKey;
+use super::ldap::LdapConfig;
+use super::saml::SamlProvider;
+use super::token::TokenService;
+use crate::errors::Error::{Unauthorized, ValidationError};
+
+pub const DEFAULT_REALM: &str = "AuthServer";
+/// The path segment the current version of the API is served under.
+pub const API_VERSION: &str = "v1";
+
+fn default_access_token_ttl_seconds() -> i64 {
+    Duration::days(1).num_seconds()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_digit: bool,
+    pub require_uppercase: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 3,
+            require_digit: false,
+            require_uppercase: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Rejects a password the policy does not allow as an invalid `field`.
+    pub fn check(&self, field: &'static str, password: &str) -> Result<(), Rejection> {
+        let long_enough = password.chars().count() >= self.min_length;
+        let has_digit = !self.require_digit || password.chars().any(|c| c.is_ascii_digit());
+        let has_uppercase = !self.require_uppercase || password.chars().any(|c| c.is_uppercase());
+
+        if long_enough && has_digit && has_uppercase {
+            return Ok(());
+        }
+        let mut error = FieldError::new("password_policy");
+        error.message = Some(self.describe().into());
+        let mut errors = ValidationErrors::new();
+        errors.add(field, error);
+        Err(reject::custom(ValidationError(errors)))
+    }
+    fn describe(&self) -> String {
+        let mut rules = vec![format!("at least {} characters", self.min_length)];
+        if self.require_digit {
+            rules.push("a digit".to_string());
+        }
+        if self.require_uppercase {
+            rules.push("an uppercase letter".to_string());
+        }
+        format!("must have {}", rules.join(", "))
+    }
+}
+
+/// A service clients may exchange tokens for (RFC 8693), and the most
+/// those tokens may carry.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AudiencePolicy {
+    pub audience: String,
+    pub scopes: Vec<String>,
+    /// Clients allowed to request tokens for the audience; any client with
+    /// the token exchange grant when empty.
+    #[serde(default)]
+    pub clients: Vec<Uuid>,
+}
+
+impl AudiencePolicy {
+    pub fn allows_client(&self, client_id: Uuid) -> bool {
+        self.clients.is_empty() || self.clients.contains(&client_id)
+    }
+}
+
+/// A realm is an isolated authentication namespace: it owns its signing keys,
+/// token lifetimes, password policy and the users registered under its name.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Realm {
+    pub name: String,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    pub jwt_secret: String,
+    pub jwt_signing_key: String,
+    #[serde(default = "default_access_token_ttl_seconds")]
+    pub access_token_ttl_seconds: i64,
+    #[serde(default)]
+    pub password_policy: PasswordPolicy,
+    /// OIDC issuer; defaults to the public URL, under `/realms/{name}` for all but the first realm.
+    #[serde(default)]
+    pub issuer: String,
+    /// PEM encoded RSA key signing ID tokens; a key is generated at startup when unset.
+    #[serde(default)]
+    pub id_token_key_file: Option<String>,
+    /// Audiences tokens can be exchanged for; token exchange is off without any.
+    #[serde(default)]
+    pub audiences: Vec<AudiencePolicy>,
+    /// Upstream OIDC providers users can sign in with at `/login/{name}`.
+    #[serde(default)]
+    pub identity_providers: Vec<IdentityProvider>,
+    /// Directory users sign in against when they have no local password.
+    #[serde(default)]
+    pub ldap: Option<LdapConfig>,
+    /// SAML identity providers users can sign in with at `/saml/{name}/login`.
+    #[serde(default)]
+    pub saml_providers: Vec<SamlProvider>,
+    /// Browser sessions kept in a cookie rather than by the page's scripts.
+    #[serde(default)]
+    pub cookie_session: Option<CookieSession>,
+    /// Who `GET /auth/verify` lets reach the paths behind the reverse proxy.
+    #[serde(default)]
+    pub forward_auth: ForwardAuth,
+    #[serde(skip)]
+    pub signing_key: Option<Arc<SigningKey>>,
+}
+
+impl Realm {
+    pub fn token_service(&self) -> TokenService {
+        let header = Header {
+            kid: Some(self.jwt_signing_key.to_owned()),
+            alg: Algorithm::HS512,
+            ..Default::default()
+        };
+        let validation = Validation::new(Algorithm::HS512);
+        TokenService {
+            jwt_secret: Arc::new(self.jwt_secret.clone()),
+            header,
+            validation,
+            ttl: Duration::seconds(self.access_token_ttl_seconds),
+            issuer: self.issuer.clone(),
+            signing_key: self.signing_key.clone(),
+        }
+    }
+
+    /// Where the current version of the API is served for this realm.
+    pub fn api_url(&self) -> String {
+        api_url(&self.issuer)
+    }
+
+    /// Fills in the settings derived at startup rather than configured.
+    pub fn load(mut self, public_url: &str, is_default: bool) -> Result<Self, ConfigError> {
+        if self.issuer.is_empty() {
+            self.issuer = if is_default {
+                public_url.trim_end_matches('/').to_string()
+            } else {
+                format!("{}/realms/{}", public_url.trim_end_matches('/'), self.name)
+            };
+        }
+        let signing_key = match &self.id_token_key_file {
+            Some(path) => SigningKey::from_pem_file(path)?,
+            None => {
+                warn!(
+                    realm = %self.name,
+                    "no id_token_key_file configured, generating an ephemeral key: tokens will not survive a restart"
+                );
+                SigningKey::generate()?
+            }
+        };
+        self.signing_key = Some(Arc::new(signing_key));
+        Ok(self)
+    }
+
+    pub fn audience_policy(&self, audience: &str) -> Option<&AudiencePolicy> {
+        self.audiences.iter().find(|p| p.audience == audience)
+    }
+
+    pub fn identity_provider(&self, name: &str) -> Option<&IdentityProvider> {
+        self.identity_providers.iter().find(|p| p.name == name)
+    }
+
+    pub fn saml_provider(&self, name: &str) -> Option<&SamlProvider> {
+        self.saml_providers.iter().find(|p| p.name == name)
+    }
+
+    /// Rejection answered with a 401 and this realm's `WWW-Authenticate` challenge.
+    pub fn unauthorized(&self) -> Rejection {
+        reject::custom(Unauthorized(self.name.clone()))
+    }
+}
+
+/// The URL of the current version of the API of the realm at `issuer`.
+pub fn api_url(issuer: &str) -> String {
+    format!("{}/{}", issuer, API_VERSION)
+}
+
+#[derive(Deserialize)]
+struct RealmsFile {
+    realms: Vec<Realm>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Realms(Arc<Vec<Realm>>);
+
+impl Realms {
+    pub fn new(realms: Vec<Realm>) -> Self {
+        Realms(Arc::new(realms))
+    }
+
+    /// Reads the realms declared in `path` (any format supported by `config`).
+    pub fn read_file(path: &str) -> Result<Vec<Realm>, ConfigError> {
+        let mut c = config::Config::new();
+        c.merge(config::File::with_name(path))?;
+        let file: RealmsFile = c.try_into()?;
+
+        if file.realms.is_empty() {
+            return Err(ConfigError::Message(format!(
+                "no realms declared in {}",
+                path
+            )));
+        }
+        Ok(file.realms)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Realm> {
+        self.0.iter().find(|r| r.name == name)
+    }
+
+    /// Matches the `Host` header, ignoring any port, against the realms' hosts.
+    pub fn by_host(&self, host: &str) -> Option<&Realm> {
+        let host = host.rsplit_once(':').map_or(host, |(h, _port)| h);
+        self.0
+            .iter()
+            .find(|r| r.hosts.iter().any(|h| h.eq_ignore_ascii_case(host)))
+    }
+
+    pub fn default_realm(&self) -> &Realm {
+        &self.0[0]
+    }
+}
+
+#[cfg(test)]
+fn test_realms() -> Realms {
+    let realm = |name: &str, hosts: &[&str]| Realm {
+        name: name.to_string(),
+        hosts: hosts.iter().map(|h| h.to_string()).collect(),
+        jwt_secret: format!("{}-secret", name),
+        jwt_signing_key: format!("{}-key", name),
+        access_token_ttl_seconds: default_access_token_ttl_seconds(),
+        password_policy: PasswordPolicy::default(),
+        issuer: format!("http://localhost/realms/{}", name),
+        id_token_key_file: None,
+        audiences: Vec::new(),
+        identity_providers: Vec::new(),
+        ldap: None,
+        saml_providers: Vec::new(),
+        cookie_session: None,
+        forward_auth: ForwardAuth::default(),
+        signing_key: None,
+    };
+    Realms::new(vec![
+        realm(DEFAULT_REALM, &[]),
+        realm("staff", &["staff.example.com"]),
+    ])
+}
+
+#[test]
+fn test_realm_lookup() {
+    let realms = test_realms();
+
+    assert_eq!(realms.default_realm().name, DEFAULT_REALM);
+    assert_eq!(realms.by_name("staff").unwrap().name, "staff");
+    assert!(realms.by_name("unknown").is_none());
+    assert_eq!(
+        realms.by_host("STAFF.example.com:3000").unwrap().name,
+        "staff"
+    );
+    assert!(realms.by_host("other.example.com").is_none());
+}
+
+#[test]
+fn test_password_policy() {
+    let policy = PasswordPolicy {
+        min_length: 8,
+        require_digit: true,
+        require_uppercase: true,
+    };
+
+    assert!(policy.check("password", "Password1").is_ok());
+    assert!(policy.check("password", "Pass1").is_err());
+    assert!(policy.check("password", "password1").is_err());
+    assert!(policy.check("password", "Passwords").is_err());
+    assert_eq!(
+        policy.describe(),
+        "must have at least 8 characters, a digit, an uppercase letter"
+    );
+}
+
+#[tokio::test]
+async fn test_realm_tokens_are_isolated() {
+    use uuid::Uuid;
+
+    let realms = test_realms();
+    let default = realms.default_realm().token_service();
+    let staff = realms.by_name("staff").unwrap().token_service();
+
+    let token = default
+        .generate_jwt(Uuid::new_v4(), Uuid::new_v4())
+        .await
+        .unwrap();
+    assert!(default.verify_jwt(token.clone()).await.is_ok());
+    assert!(staff.verify_jwt(token).await.is_err());
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use flate2::{write::DeflateEncoder, Compression};
+use roxmltree::{Document, Node};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::RsaPublicKey;
+use serde::Deserialize;
+use url::Url;
+use x509_cert::der::{Decode, DecodePem, Encode};
+use x509_cert::Certificate;
+
+use super::xmldsig::verify_enveloped_signature;
+use crate::models::user::ExternalProfile;
+
+const PROTOCOL_NS: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+const ASSERTION_NS: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+const POST_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+const UNSPECIFIED_NAME_ID: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:unspecified";
+const SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+const BEARER: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+/// Tolerated difference between our clock and the identity provider's.
+const CLOCK_SKEW_SECONDS: i64 = 60;
+
+fn default_email_attribute() -> String {
+    "email".to_string()
+}
+
+fn default_full_name_attribute() -> String {
+    "displayName".to_string()
+}
+
+/// Assertion attributes copied to the `User`, by SAML attribute `Name`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SamlAttributes {
+    /// The username of provisioned users; the `NameID` when unset.
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default = "default_email_attribute")]
+    pub email: String,
+    #[serde(default = "default_full_name_attribute")]
+    pub full_name: String,
+}
+
+impl Default for SamlAttributes {
+    fn default() -> Self {
+        SamlAttributes {
+            username: None,
+            email: default_email_attribute(),
+            full_name: default_full_name_attribute(),
+        }
+    }
+}
+
+/// A SAML 2.0 identity provider users of a realm can sign in with, for
+/// enterprise customers that don't offer OpenID Connect.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SamlProvider {
+    /// Appears in our URLs: `/saml/{name}/...`.
+    pub name: String,
+    /// The identity provider's entity ID, the `Issuer` of its assertions.
+    pub entity_id: String,
+    /// Its single sign-on service, HTTP-Redirect binding.
+    pub sso_url: String,
+    /// The X.509 certificate it signs with, PEM or base64 DER.
+    pub certificate: String,
+    #[serde(default)]
+    pub attributes: SamlAttributes,
+}
+
+/// Who the identity provider says signed in, and why.
+#[derive(Debug)]
+pub struct SamlAssertion {
+    pub name_id: String,
+    /// The `ID` of our `AuthnRequest` this answers.
+    pub in_response_to: Option<String>,
+    pub username: String,
+    pub profile: ExternalProfile,
+}
+
+/// Where we stand in a SAML exchange: our entity ID and assertion consumer service.
+pub struct ServiceProvider {
+    pub entity_id: String,
+    pub acs_url: String,
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl ServiceProvider {
+    /// Our SP metadata, to register with the identity provider.
+    pub fn metadata(&self) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<md:EntityDescriptor xmlns:md="urn:oasis:names:tc:SAML:2.0:metadata" entityID="{}">
+  <md:SPSSODescriptor AuthnRequestsSigned="false" WantAssertionsSigned="true" protocolSupportEnumeration="{}">
+    <md:NameIDFormat>{}</md:NameIDFormat>
+    <md:AssertionConsumerService Binding="{}" Location="{}" index="0" isDefault="true"/>
+  </md:SPSSODescriptor>
+</md:EntityDescriptor>
+"#,
+            xml_escape(&self.entity_id),
+            PROTOCOL_NS,
+            UNSPECIFIED_NAME_ID,
+            POST_BINDING,
+            xml_escape(&self.acs_url)
+        )
+    }
+}
+
+fn element<'a, 'input>(
+    node: Node<'a, 'input>,
+    namespace: &str,
+    name: &str,
+) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name((namespace, name)))
+}
+
+fn required<'a, 'input>(
+    node: Node<'a, 'input>,
+    namespace: &str,
+    name: &str,
+) -> Result<Node<'a, 'input>, String> {
+    element(node, namespace, name).ok_or_else(|| format!("the response has no {}", name))
+}
+
+fn instant(node: Node, attribute: &str) -> Result<Option<DateTime<Utc>>, String> {
+    match node.attribute(attribute) {
+        Some(value) => DateTime::parse_from_rfc3339(value)
+            .map(|t| Some(t.with_timezone(&Utc)))
+            .map_err(|e| format!("{} {}: {}", attribute, value, e)),
+        None => Ok(None),
+    }
+}
+
+/// Checks `NotBefore <= now < NotOnOrAfter`, give or take the clock skew.
+fn check_validity(node: Node, now: DateTime<Utc>) -> Result<(), String> {
+    let skew = Duration::seconds(CLOCK_SKEW_SECONDS);
+    if let Some(not_before) = instant(node, "NotBefore")? {
+        if now + skew < not_before {
+            return Err("the assertion is not valid yet".to_string());
+        }
+    }
+    if let Some(not_on_or_after) = instant(node, "NotOnOrAfter")? {
+        if now - skew >= not_on_or_after {
+            return Err("the assertion has expired".to_string());
+        }
+    }
+    Ok(())
+}
+
+impl SamlProvider {
+    fn public_key(&self) -> Result<RsaPublicKey, String> {
+        let certificate = self.certificate.trim();
+        let certificate = if certificate.starts_with("-----BEGIN") {
+            Certificate::from_pem(certificate)
+        } else {
+            let der = base64::decode(
+                certificate
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .collect::<String>(),
+            )
+            .map_err(|e| e.to_string())?;
+            Certificate::from_der(&der)
+        }
+        .map_err(|e| format!("invalid certificate: {}", e))?;
+        let spki = certificate
+            .tbs_certificate
+            .subject_public_key_info
+            .to_der()
+            .map_err(|e| e.to_string())?;
+        RsaPublicKey::from_public_key_der(&spki).map_err(|e| format!("not an RSA key: {}", e))
+    }
+
+    /// The identity provider URL that asks it to sign the user in, with an
+    /// `AuthnRequest` in the HTTP-Redirect binding.
+    pub fn authn_request_url(
+        &self,
+        sp: &ServiceProvider,
+        request_id: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Url, String> {
+        let request = format!(
+            r#"<samlp:AuthnRequest xmlns:samlp="{}" xmlns:saml="{}" ID="{}" Version="2.0" IssueInstant="{}" Destination="{}" AssertionConsumerServiceURL="{}" ProtocolBinding="{}"><saml:Issuer>{}</saml:Issuer><samlp:NameIDPolicy Format="{}" AllowCreate="true"/></samlp:AuthnRequest>"#,
+            PROTOCOL_NS,
+            ASSERTION_NS,
+            xml_escape(request_id),
+            now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            xml_escape(&self.sso_url),
+            xml_escape(&sp.acs_url),
+            POST_BINDING,
+            xml_escape(&sp.entity_id),
+            UNSPECIFIED_NAME_ID
+        );
+        let mut deflater = DeflateEncoder::new(Vec::new(), Compression::default());
+        deflater
+            .write_all(request.as_bytes())
+            .map_err(|e| e.to_string())?;
+        let deflated = deflater.finish().map_err(|e| e.to_string())?;
+
+        let mut url = Url::parse(&self.sso_url).map_err(|e| e.to_string())?;
+        url.query_pairs_mut()
+            .append_pair("SAMLRequest", &base64::encode(deflated));
+        Ok(url)
+    }
+
+    /// Verifies a base64 `SAMLResponse` posted to our assertion consumer
+    /// service: signed by the identity provider, about us, and current.
+    pub fn verify_response(
+        &self,
+        saml_response: &str,
+        sp: &ServiceProvider,
+        now: DateTime<Utc>,
+    ) -> Result<SamlAssertion, String> {
+        let encoded: String = saml_response
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let xml = base64::decode(encoded).map_err(|e| e.to_string())?;
+        let xml = String::from_utf8(xml).map_err(|e| e.to_string())?;
+        // DTDs are refused by default, and with them entity expansion attacks.
+        let doc = Document::parse(&xml).map_err(|e| e.to_string())?;
+
+        let response = doc.root_element();
+        if !response.has_tag_name((PROTOCOL_NS, "Response")) {
+            return Err("not a SAML response".to_string());
+        }
+        if let Some(destination) = response.attribute("Destination") {
+            if destination != sp.acs_url {
+                return Err(format!("the response is for {}", destination));
+            }
+        }
+        if let Some(issuer) = element(response, ASSERTION_NS, "Issuer") {
+            if issuer.text().map(str::trim) != Some(self.entity_id.as_str()) {
+                return Err("the response was issued by another provider".to_string());
+            }
+        }
+        let status = required(
+            required(response, PROTOCOL_NS, "Status")?,
+            PROTOCOL_NS,
+            "StatusCode",
+        )?;
+        if status.attribute("Value") != Some(SUCCESS) {
+            return Err(format!(
+                "the identity provider answered {}",
+                status.attribute("Value").unwrap_or_default()
+            ));
+        }
+
+        // Exactly one assertion, where it belongs: anything else smells of signature wrapping.
+        let assertions: Vec<_> = doc
+            .descendants()
+            .filter(|n| n.has_tag_name((ASSERTION_NS, "Assertion")))
+            .collect();
+        let assertion = match assertions.as_slice() {
+            [assertion] if assertion.parent() == Some(response) => *assertion,
+            [] if element(response, ASSERTION_NS, "EncryptedAssertion").is_some() => {
+                return Err("encrypted assertions are not supported".to_string())
+            }
+            _ => return Err("the response must have exactly one assertion".to_string()),
+        };
+        let key = self.public_key()?;
+        let response_signed = verify_enveloped_signature(response, &key)?;
+        let assertion_signed = verify_enveloped_signature(assertion, &key)?;
+        if !response_signed && !assertion_signed {
+            return Err("the assertion is not signed".to_string());
+        }
+
+        let issuer = required(assertion, ASSERTION_NS, "Issuer")?;
+        if issuer.text().map(str::trim) != Some(self.entity_id.as_str()) {
+            return Err(format!(
+                "the assertion was issued by {}",
+                issuer.text().unwrap_or_default()
+            ));
+        }
+        let conditions = required(assertion, ASSERTION_NS, "Conditions")?;
+        check_validity(conditions, now)?;
+        let for_us = conditions
+            .children()
+            .filter(|n| n.has_tag_name((ASSERTION_NS, "AudienceRestriction")))
+            .flat_map(|restriction| restriction.children())
+            .filter(|n| n.has_tag_name((ASSERTION_NS, "Audience")))
+            .any(|audience| audience.text().map(str::trim) == Some(sp.entity_id.as_str()));
+        if !for_us {
+            return Err("the assertion is not meant for us".to_string());
+        }
+
+        let subject = required(assertion, ASSERTION_NS, "Subject")?;
+        let name_id = required(subject, ASSERTION_NS, "NameID")?
+            .text()
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .ok_or("the assertion names nobody")?
+            .to_string();
+        let confirmation = subject
+            .children()
+            .filter(|n| n.has_tag_name((ASSERTION_NS, "SubjectConfirmation")))
+            .find(|n| n.attribute("Method") == Some(BEARER))
+            .ok_or("the subject has no bearer confirmation")?;
+        let data = required(confirmation, ASSERTION_NS, "SubjectConfirmationData")?;
+        if data.attribute("Recipient") != Some(sp.acs_url.as_str()) {
+            return Err("the assertion is for another recipient".to_string());
+        }
+        if instant(data, "NotOnOrAfter")?.is_none() {
+            return Err("the subject confirmation never expires".to_string());
+        }
+        check_validity(data, now)?;
+
+        let mut attributes = HashMap::new();
+        if let Some(statement) = element(assertion, ASSERTION_NS, "AttributeStatement") {
+            for attribute in statement
+                .children()
+                .filter(|n| n.has_tag_name((ASSERTION_NS, "Attribute")))
+            {
+                let value = element(attribute, ASSERTION_NS, "AttributeValue")
+                    .and_then(|v| v.text())
+                    .map(|v| v.trim().to_string());
+                if let (Some(name), Some(value)) = (attribute.attribute("Name"), value) {
+                    attributes.insert(name.to_string(), value);
+                }
+            }
+        }
+        let username = match &self.attributes.username {
+            Some(name) => attributes
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("the assertion has no {} attribute", name))?,
+            None => name_id.clone(),
+        };
+        Ok(SamlAssertion {
+            in_response_to: data.attribute("InResponseTo").map(str::to_string),
+            username,
+            profile: ExternalProfile {
+                email: attributes.remove(&self.attributes.email),
+                full_name: attributes.remove(&self.attributes.full_name),
+            },
+            name_id,
+        })
+    }
+}
@@ -1,7 +1,19 @@
-use crate::errors::Error::TokenError;
-use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
+pub use authserver_verify::{Actor, Claims, ACCESS_TOKEN_TYPE};
+use authserver_verify::{TokenVerifier, VerifyError};
+
+use super::keys::SigningKey;
+use crate::errors::Error::{Forbidden, InvalidToken, NotCompletedError, TokenError};
+use crate::metrics::metrics;
+use crate::models::oauth::StoredToken;
+use crate::models::oidc::IdTokenClaims;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{
+    decode, encode, Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
+};
+use rand_core::{OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
 use uuid::Uuid;
 use warp::reject;
@@ -12,35 +24,298 @@ pub struct TokenService {
     pub jwt_secret: Arc<String>,
     pub header: Header,
     pub validation: Validation,
+    pub ttl: Duration,
+    pub issuer: String,
+    pub signing_key: Option<Arc<SigningKey>>,
 }
 
+/// Short-lived proof carried through a form back to us: that a user signed
+/// in (consent), or that an upstream provider vouched for someone (linking).
 #[derive(Serialize, Deserialize)]
-pub struct Claims {
-    pub sub: Uuid,
-    pub exp: i64,
-    // aud
-    // role
-    // perms
+struct Ticket {
+    sub: String,
+    aud: String,
+    exp: i64,
 }
 
+const TICKET_TTL_SECONDS: i64 = 600;
+
 impl TokenService {
-    pub async fn generate_jwt(&self, uuid: Uuid) -> Result<String, Rejection> {
-        let encoding_key = EncodingKey::from_secret(self.jwt_secret.as_bytes());
-        let now = Utc::now() + Duration::days(1); // Expires in 1 day
-        let claims = Claims {
+    /// The user's own token, for the session `session_id`.
+    pub async fn generate_jwt(&self, uuid: Uuid, session_id: Uuid) -> Result<String, Rejection> {
+        self.generate_session_jwt(uuid, session_id, Utc::now())
+            .await
+    }
+    /// The user's own token, for the session `session_id` they signed in to
+    /// at `signed_in_at`. It is issued then, as far as `iat` tells, so that a
+    /// session's later tokens do not pass for a fresh sign-in.
+    pub async fn generate_session_jwt(
+        &self,
+        uuid: Uuid,
+        session_id: Uuid,
+        signed_in_at: DateTime<Utc>,
+    ) -> Result<String, Rejection> {
+        metrics().token_issued("session");
+        self.encode_claims(&Claims {
+            sub: uuid,
+            exp: (Utc::now() + self.ttl).timestamp(),
+            iat: Some(signed_in_at.timestamp()),
+            sid: Some(session_id),
+            client_id: None,
+            scope: None,
+            jti: None,
+            aud: None,
+            act: None,
+        })
+    }
+    /// Access token issued to an OAuth client on behalf of `uuid`.
+    pub async fn generate_access_token(
+        &self,
+        jti: Uuid,
+        uuid: Uuid,
+        client_id: Uuid,
+        scope: &str,
+    ) -> Result<String, Rejection> {
+        metrics().token_issued("access");
+        self.encode_claims(&Claims {
             sub: uuid,
-            exp: now.timestamp(),
+            exp: (Utc::now() + self.ttl).timestamp(),
+            iat: Some(Utc::now().timestamp()),
+            sid: None,
+            client_id: Some(client_id),
+            scope: Some(scope.to_string()),
+            jti: Some(jti),
+            aud: None,
+            act: None,
+        })
+    }
+    /// Access token from a token exchange, recorded as `stored`, restricted
+    /// to `audience` and naming the delegation chain that obtained it. It
+    /// stays with the subject token's session, so signing out ends it too.
+    pub async fn generate_exchanged_token(
+        &self,
+        stored: &StoredToken,
+        subject: &Claims,
+        audience: &str,
+    ) -> Result<String, Rejection> {
+        metrics().token_issued("exchanged");
+        self.encode_claims(&Claims {
+            sub: subject.sub,
+            exp: stored.expires_at.timestamp(),
+            iat: Some(Utc::now().timestamp()),
+            sid: subject.sid,
+            client_id: Some(stored.client_id),
+            scope: Some(stored.scope.clone()),
+            jti: Some(stored.id),
+            aud: Some(audience.to_string()),
+            act: Some(Actor {
+                sub: stored.client_id,
+                act: subject.act.clone().map(Box::new),
+            }),
+        })
+    }
+    pub async fn verify_jwt(&self, token: String) -> Result<TokenData<Claims>, Rejection> {
+        let verifier = match &self.signing_key {
+            Some(key) => TokenVerifier::from_rsa_key(key.decoding_key.clone()),
+            None => TokenVerifier::from_secret(self.jwt_secret.as_bytes())
+                .with_validation(self.validation.clone()),
         };
-        match encode(&self.header, &claims, &encoding_key) {
-            Ok(token) => return Ok(token),
-            Err(e) => return Err(reject::custom(TokenError(e))),
+        match verifier.verify(&token).await {
+            Ok(c) => {
+                metrics().token_verified("valid");
+                Ok(c)
+            }
+            Err(VerifyError::InvalidToken(e)) => {
+                let result = match e.kind() {
+                    ErrorKind::ExpiredSignature => "expired",
+                    _ => "invalid",
+                };
+                metrics().token_verified(result);
+                Err(reject::custom(InvalidToken(e)))
+            }
+            Err(_) => {
+                metrics().token_verified("error");
+                Err(reject::custom(NotCompletedError(
+                    std::io::ErrorKind::InvalidData,
+                )))
+            }
         }
     }
-    pub async fn verify_jwt(&self, token: String) -> Result<TokenData<Claims>, Rejection> {
-        let decoding_key = DecodingKey::from_secret(self.jwt_secret.as_bytes());
-        match decode::<Claims>(&token, &decoding_key, &self.validation) {
-            Ok(c) => return Ok(c),
-            Err(e) => return Err(reject::custom(TokenError(e))),
+    /// Claims of a token the user signed in for themselves. Tokens issued to
+    /// OAuth clients, or for another audience, act for the user only within
+    /// their scope and do not manage the account.
+    pub async fn verify_user_token(&self, token: String) -> Result<Claims, Rejection> {
+        let claims = self.verify_jwt(token).await?.claims;
+        match (&claims.client_id, &claims.aud) {
+            (None, None) => Ok(claims),
+            _ => Err(reject::custom(Forbidden)),
         }
     }
+    /// OIDC ID token, signed with the realm's RS256 key so clients can verify it from the JWKS.
+    pub async fn generate_id_token(&self, claims: &IdTokenClaims) -> Result<String, Rejection> {
+        let signing_key = match &self.signing_key {
+            Some(key) => key,
+            None => {
+                return Err(reject::custom(NotCompletedError(
+                    std::io::ErrorKind::NotFound,
+                )))
+            }
+        };
+        let header = Header {
+            kid: Some(signing_key.jwk.kid.clone()),
+            ..Header::new(Algorithm::RS256)
+        };
+        match encode(&header, claims, &signing_key.encoding_key) {
+            Ok(token) => {
+                metrics().token_issued("id");
+                Ok(token)
+            }
+            Err(e) => Err(reject::custom(TokenError(e))),
+        }
+    }
+    pub fn generate_consent_ticket(
+        &self,
+        uuid: Uuid,
+        client_id: Uuid,
+    ) -> Result<String, Rejection> {
+        self.generate_ticket("consent", &uuid.to_string(), &client_id.to_string())
+    }
+    /// The user a consent ticket was issued to, if it is valid for `client_id`.
+    pub fn verify_consent_ticket(&self, ticket: &str, client_id: Uuid) -> Option<Uuid> {
+        self.verify_ticket("consent", ticket, &client_id.to_string())
+            .and_then(|sub| Uuid::parse_str(&sub).ok())
+    }
+    /// Carries an upstream subject to the account linking form of `provider`.
+    pub fn generate_link_ticket(&self, subject: &str, provider: &str) -> Result<String, Rejection> {
+        self.generate_ticket("link", subject, provider)
+    }
+    /// The upstream subject of a link ticket, if it is valid for `provider`.
+    pub fn verify_link_ticket(&self, ticket: &str, provider: &str) -> Option<String> {
+        self.verify_ticket("link", ticket, provider)
+    }
+    /// Each purpose has a key of its own, so a ticket is never accepted as
+    /// an access token or as a ticket of another kind.
+    fn generate_ticket(&self, purpose: &str, sub: &str, aud: &str) -> Result<String, Rejection> {
+        let ticket = Ticket {
+            sub: sub.to_string(),
+            aud: aud.to_string(),
+            exp: (Utc::now() + Duration::seconds(TICKET_TTL_SECONDS)).timestamp(),
+        };
+        match encode(
+            &self.header,
+            &ticket,
+            &EncodingKey::from_secret(&self.ticket_key(purpose)),
+        ) {
+            Ok(token) => Ok(token),
+            Err(e) => Err(reject::custom(TokenError(e))),
+        }
+    }
+    fn verify_ticket(&self, purpose: &str, ticket: &str, aud: &str) -> Option<String> {
+        let mut validation = self.validation.clone();
+        validation.set_audience(&[aud]);
+        decode::<Ticket>(
+            ticket,
+            &DecodingKey::from_secret(&self.ticket_key(purpose)),
+            &validation,
+        )
+        .ok()
+        .map(|data| data.claims.sub)
+    }
+    fn ticket_key(&self, purpose: &str) -> Vec<u8> {
+        format!("{}.{}", self.jwt_secret, purpose).into_bytes()
+    }
+    pub fn expires_in(&self) -> i64 {
+        self.ttl.num_seconds()
+    }
+    /// Signs with the realm's RSA key when it has one, so that services
+    /// verify tokens with the JWKS rather than the shared secret.
+    fn encode_claims(&self, claims: &Claims) -> Result<String, Rejection> {
+        let encoded = match &self.signing_key {
+            Some(key) => {
+                let header = Header {
+                    typ: Some(ACCESS_TOKEN_TYPE.to_string()),
+                    kid: Some(key.jwk.kid.clone()),
+                    ..Header::new(Algorithm::RS256)
+                };
+                encode(&header, claims, &key.encoding_key)
+            }
+            None => encode(
+                &self.header,
+                claims,
+                &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
+            ),
+        };
+        match encoded {
+            Ok(token) => Ok(token),
+            Err(e) => Err(reject::custom(TokenError(e))),
+        }
+    }
+}
+
+/// Random, url-safe value used for authorization codes and other opaque tokens.
+pub fn generate_opaque_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Opaque tokens are only ever stored as their SHA-256 digest.
+pub fn digest(value: &str) -> String {
+    base64::encode_config(Sha256::digest(value.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+#[test]
+fn test_opaque_tokens() {
+    let token = generate_opaque_token();
+
+    assert_eq!(token.len(), 43);
+    assert_ne!(token, generate_opaque_token());
+    assert_eq!(digest(&token), digest(&token));
+    // RFC 7636 appendix B
+    assert_eq!(
+        digest("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+        "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+    );
+}
+
+#[tokio::test]
+async fn test_consent_tickets_are_not_access_tokens() {
+    let token_service = TokenService {
+        jwt_secret: Arc::new("secret".to_string()),
+        header: Header::new(Algorithm::HS512),
+        validation: Validation::new(Algorithm::HS512),
+        ttl: Duration::seconds(60),
+        issuer: "http://localhost".to_string(),
+        signing_key: None,
+    };
+    let (user_id, client_id) = (Uuid::new_v4(), Uuid::new_v4());
+    let ticket = token_service
+        .generate_consent_ticket(user_id, client_id)
+        .unwrap();
+
+    assert_eq!(
+        Some(user_id),
+        token_service.verify_consent_ticket(&ticket, client_id)
+    );
+    assert_eq!(
+        None,
+        token_service.verify_consent_ticket(&ticket, Uuid::new_v4())
+    );
+    assert!(token_service.verify_jwt(ticket.clone()).await.is_err());
+
+    let link_ticket = token_service
+        .generate_link_ticket("upstream-subject", "corp")
+        .unwrap();
+    assert_eq!(
+        Some("upstream-subject".to_string()),
+        token_service.verify_link_ticket(&link_ticket, "corp")
+    );
+    assert_eq!(
+        None,
+        token_service.verify_link_ticket(&link_ticket, "other")
+    );
+    assert_eq!(
+        None,
+        token_service.verify_link_ticket(&ticket, &client_id.to_string())
+    );
 }
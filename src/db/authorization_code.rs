@@ -0,0 +1,76 @@
+use mobc::{Connection, Pool};
+use mobc_postgres::{tokio_postgres::NoTls, PgConnectionManager};
+use warp::{reject, Rejection};
+
+use crate::errors::Error::{DBConnError, DBQueryError};
+use crate::models::oauth::AuthorizationCode;
+
+/// Authorization codes of a realm, stored by digest and consumed only once.
+pub struct AuthorizationCodeRepository {
+    db: Connection<PgConnectionManager<NoTls>>,
+    realm: String,
+}
+
+impl AuthorizationCodeRepository {
+    pub async fn new(
+        pool: Pool<PgConnectionManager<NoTls>>,
+        realm: &str,
+    ) -> Result<Self, Rejection> {
+        match pool.get().await {
+            Ok(db) => Ok(Self {
+                db,
+                realm: realm.to_string(),
+            }),
+            Err(e) => Err(reject::custom(DBConnError(e))),
+        }
+    }
+    pub async fn create(&self, code_hash: &str, code: &AuthorizationCode) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "insert into authorization_codes (code_hash, realm, client_id, user_id, redirect_uri, redirect_uri_supplied, scope, code_challenge, code_challenge_method, nonce, auth_time, expires_at) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+                &[
+                    &code_hash,
+                    &self.realm,
+                    &code.client_id,
+                    &code.user_id,
+                    &code.redirect_uri,
+                    &code.redirect_uri_supplied,
+                    &code.scope,
+                    &code.code_challenge,
+                    &code.code_challenge_method,
+                    &code.nonce,
+                    &code.auth_time,
+                    &code.expires_at,
+                ],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
+    /// Marks the code as used and returns it, or `None` if it is unknown or was already used.
+    pub async fn consume(&self, code_hash: &str) -> Result<Option<AuthorizationCode>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "update authorization_codes set consumed_at = now() where code_hash = $1 and realm = $2 and consumed_at is null returning *",
+                &[&code_hash, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(AuthorizationCode {
+            client_id: rows[0].get("client_id"),
+            user_id: rows[0].get("user_id"),
+            redirect_uri: rows[0].get("redirect_uri"),
+            redirect_uri_supplied: rows[0].get("redirect_uri_supplied"),
+            scope: rows[0].get("scope"),
+            code_challenge: rows[0].get("code_challenge"),
+            code_challenge_method: rows[0].get("code_challenge_method"),
+            nonce: rows[0].get("nonce"),
+            auth_time: rows[0].get("auth_time"),
+            expires_at: rows[0].get("expires_at"),
+        }))
+    }
+}
@@ -0,0 +1,194 @@
+use mobc::{Connection, Pool};
+use mobc_postgres::tokio_postgres::{NoTls, Row};
+use mobc_postgres::PgConnectionManager;
+use uuid::Uuid;
+use warp::{reject, Rejection};
+
+use crate::errors::Error::{DBConnError, DBQueryError};
+use crate::models::oauth::{NewClient, OAuthClient, CLIENT_ACTIVE, CLIENT_PENDING};
+
+/// OAuth clients are registered in, and only visible to, a single realm.
+pub struct ClientRepository {
+    db: Connection<PgConnectionManager<NoTls>>,
+    realm: String,
+}
+
+fn oauth_client(row: &Row) -> OAuthClient {
+    OAuthClient {
+        id: row.get("id"),
+        name: row.get("name"),
+        redirect_uris: row.get("redirect_uris"),
+        scopes: row.get("scopes"),
+        grant_types: row.get("grant_types"),
+        secret_hash: row.get("secret_hash"),
+        first_party: row.get("first_party"),
+        status: row.get("status"),
+        token_endpoint_auth_method: row.get("token_endpoint_auth_method"),
+        registration_token_hash: row.get("registration_token_hash"),
+        created_at: row.get("created_at"),
+    }
+}
+
+impl ClientRepository {
+    pub async fn new(
+        pool: Pool<PgConnectionManager<NoTls>>,
+        realm: &str,
+    ) -> Result<Self, Rejection> {
+        match pool.get().await {
+            Ok(db) => Ok(Self {
+                db,
+                realm: realm.to_string(),
+            }),
+            Err(e) => Err(reject::custom(DBConnError(e))),
+        }
+    }
+    /// An active client; pending registrations cannot be used yet.
+    pub async fn get(&self, id: Uuid) -> Result<Option<OAuthClient>, Rejection> {
+        Ok(self
+            .get_registered(id)
+            .await?
+            .filter(|client| client.is_active()))
+    }
+    /// A client whatever its status, for its own configuration and for admins.
+    pub async fn get_registered(&self, id: Uuid) -> Result<Option<OAuthClient>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT * FROM oauth_clients WHERE id = $1 AND realm = $2",
+                &[&id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(oauth_client))
+    }
+    pub async fn list(&self) -> Result<Vec<OAuthClient>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT * FROM oauth_clients WHERE realm = $1 ORDER BY created_at",
+                &[&self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.iter().map(oauth_client).collect())
+    }
+    pub async fn create(
+        &self,
+        client: &NewClient,
+        secret_hash: Option<&str>,
+    ) -> Result<Uuid, Rejection> {
+        let row = self
+            .db
+            .query_one(
+                "insert into oauth_clients (realm, name, redirect_uris, scopes, grant_types, secret_hash, first_party) values ($1, $2, $3, $4, $5, $6, $7) returning id",
+                &[
+                    &self.realm,
+                    &client.name,
+                    &client.redirect_uris,
+                    &client.scopes,
+                    &client.grant_types,
+                    &secret_hash,
+                    &client.first_party,
+                ],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(row.get(0))
+    }
+    /// Creates a dynamically registered client, pending approval.
+    pub async fn register(
+        &self,
+        client: &NewClient,
+        secret_hash: Option<&str>,
+        token_endpoint_auth_method: &str,
+        registration_token_hash: &str,
+    ) -> Result<OAuthClient, Rejection> {
+        let row = self
+            .db
+            .query_one(
+                "insert into oauth_clients (realm, name, redirect_uris, scopes, grant_types, secret_hash, status, token_endpoint_auth_method, registration_token_hash) values ($1, $2, $3, $4, $5, $6, $7, $8, $9) returning *",
+                &[
+                    &self.realm,
+                    &client.name,
+                    &client.redirect_uris,
+                    &client.scopes,
+                    &client.grant_types,
+                    &secret_hash,
+                    &CLIENT_PENDING,
+                    &token_endpoint_auth_method,
+                    &registration_token_hash,
+                ],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(oauth_client(&row))
+    }
+    /// Replaces the metadata of a registered client; `pending` sends it back
+    /// to the admins for approval.
+    pub async fn update_registration(
+        &self,
+        id: Uuid,
+        client: &NewClient,
+        token_endpoint_auth_method: &str,
+        pending: bool,
+    ) -> Result<Option<OAuthClient>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "update oauth_clients set name = $1, redirect_uris = $2, scopes = $3, grant_types = $4, token_endpoint_auth_method = $5, \
+                 status = case when $6 then 'pending' else status end \
+                 where id = $7 and realm = $8 returning *",
+                &[
+                    &client.name,
+                    &client.redirect_uris,
+                    &client.scopes,
+                    &client.grant_types,
+                    &token_endpoint_auth_method,
+                    &pending,
+                    &id,
+                    &self.realm,
+                ],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(oauth_client))
+    }
+    /// Activates a pending client; `None` if there is no such client.
+    pub async fn approve(&self, id: Uuid) -> Result<Option<Uuid>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "update oauth_clients set status = $1 where id = $2 and realm = $3 returning id",
+                &[&CLIENT_ACTIVE, &id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(|row| row.get(0)))
+    }
+    pub async fn delete(&self, id: Uuid) -> Result<Option<Uuid>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "delete from oauth_clients where id = $1 and realm = $2 returning id",
+                &[&id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(|row| row.get(0)))
+    }
+    /// Replaces the secret of a confidential client; `None` if there is no such client.
+    pub async fn set_secret(&self, id: Uuid, secret_hash: &str) -> Result<Option<Uuid>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "update oauth_clients set secret_hash = $1 where id = $2 and realm = $3 and secret_hash is not null returning id",
+                &[&secret_hash, &id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(rows[0].get(0)))
+    }
+}
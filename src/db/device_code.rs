@@ -0,0 +1,144 @@
+use chrono::{DateTime, Utc};
+use mobc::{Connection, Pool};
+use mobc_postgres::tokio_postgres::{NoTls, Row};
+use mobc_postgres::PgConnectionManager;
+use uuid::Uuid;
+use warp::{reject, Rejection};
+
+use crate::errors::Error::{DBConnError, DBQueryError};
+use crate::models::oauth::DeviceCode;
+
+/// Pending device authorizations of a realm, keyed by device code digest.
+pub struct DeviceCodeRepository {
+    db: Connection<PgConnectionManager<NoTls>>,
+    realm: String,
+}
+
+fn device_code(row: &Row) -> DeviceCode {
+    DeviceCode {
+        client_id: row.get("client_id"),
+        user_code: row.get("user_code"),
+        scope: row.get("scope"),
+        user_id: row.get("user_id"),
+        approved: row.get("approved"),
+        interval_seconds: row.get("interval_seconds"),
+        last_polled_at: row.get("last_polled_at"),
+        expires_at: row.get("expires_at"),
+    }
+}
+
+impl DeviceCodeRepository {
+    pub async fn new(
+        pool: Pool<PgConnectionManager<NoTls>>,
+        realm: &str,
+    ) -> Result<Self, Rejection> {
+        match pool.get().await {
+            Ok(db) => Ok(Self {
+                db,
+                realm: realm.to_string(),
+            }),
+            Err(e) => Err(reject::custom(DBConnError(e))),
+        }
+    }
+    /// Records a new authorization; `false` if its user code is taken.
+    pub async fn create(
+        &self,
+        device_code_hash: &str,
+        code: &DeviceCode,
+    ) -> Result<bool, Rejection> {
+        let inserted = self
+            .db
+            .execute(
+                "insert into device_codes (device_code_hash, realm, user_code, client_id, scope, interval_seconds, expires_at) values ($1, $2, $3, $4, $5, $6, $7) on conflict (realm, user_code) do nothing",
+                &[
+                    &device_code_hash,
+                    &self.realm,
+                    &code.user_code,
+                    &code.client_id,
+                    &code.scope,
+                    &code.interval_seconds,
+                    &code.expires_at,
+                ],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(inserted > 0)
+    }
+    /// Forgets authorizations that expired before `before`, freeing their user codes.
+    pub async fn delete_expired(&self, before: DateTime<Utc>) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "delete from device_codes where realm = $1 and expires_at < $2",
+                &[&self.realm, &before],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
+    pub async fn get(&self, device_code_hash: &str) -> Result<Option<DeviceCode>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT * FROM device_codes WHERE device_code_hash = $1 AND realm = $2 AND consumed_at IS NULL",
+                &[&device_code_hash, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(device_code))
+    }
+    /// The request a user code stands for, while it is still awaiting a decision.
+    pub async fn get_pending(&self, user_code: &str) -> Result<Option<DeviceCode>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT * FROM device_codes WHERE user_code = $1 AND realm = $2 AND approved IS NULL AND expires_at > now()",
+                &[&user_code, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(device_code))
+    }
+    /// Records the user's decision; `false` if the code was not pending anymore.
+    pub async fn decide(
+        &self,
+        user_code: &str,
+        user_id: Uuid,
+        approved: bool,
+    ) -> Result<bool, Rejection> {
+        let updated = self
+            .db
+            .execute(
+                "update device_codes set user_id = $1, approved = $2 where user_code = $3 and realm = $4 and approved is null and expires_at > now()",
+                &[&user_id, &approved, &user_code, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(updated > 0)
+    }
+    pub async fn poll(
+        &self,
+        device_code_hash: &str,
+        interval_seconds: i32,
+    ) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "update device_codes set last_polled_at = now(), interval_seconds = $1 where device_code_hash = $2 and realm = $3",
+                &[&interval_seconds, &device_code_hash, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
+    /// Marks an approved code as exchanged; `false` if it already was.
+    pub async fn consume(&self, device_code_hash: &str) -> Result<bool, Rejection> {
+        let updated = self
+            .db
+            .execute(
+                "update device_codes set consumed_at = now() where device_code_hash = $1 and realm = $2 and approved and consumed_at is null",
+                &[&device_code_hash, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(updated > 0)
+    }
+}
@@ -0,0 +1,68 @@
+use mobc::{Connection, Pool};
+use mobc_postgres::tokio_postgres::NoTls;
+use mobc_postgres::PgConnectionManager;
+use warp::{reject, Rejection};
+
+use crate::errors::Error::{DBConnError, DBQueryError};
+use crate::models::federation::FederatedLogin;
+
+/// Sign-ins in progress at upstream providers, keyed by state digest.
+pub struct FederatedLoginRepository {
+    db: Connection<PgConnectionManager<NoTls>>,
+    realm: String,
+}
+
+impl FederatedLoginRepository {
+    pub async fn new(
+        pool: Pool<PgConnectionManager<NoTls>>,
+        realm: &str,
+    ) -> Result<Self, Rejection> {
+        match pool.get().await {
+            Ok(db) => Ok(Self {
+                db,
+                realm: realm.to_string(),
+            }),
+            Err(e) => Err(reject::custom(DBConnError(e))),
+        }
+    }
+    pub async fn create(&self, state_hash: &str, login: &FederatedLogin) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "insert into federated_logins (state_hash, realm, provider, code_verifier, nonce, expires_at, link_user_id) values ($1, $2, $3, $4, $5, $6, $7)",
+                &[
+                    &state_hash,
+                    &self.realm,
+                    &login.provider,
+                    &login.code_verifier,
+                    &login.nonce,
+                    &login.expires_at,
+                    &login.link_user_id,
+                ],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
+    /// Removes the sign-in, so a state is only ever used once; `None` if it
+    /// is unknown or has expired.
+    pub async fn consume(&self, state_hash: &str) -> Result<Option<FederatedLogin>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "delete from federated_logins where state_hash = $1 and realm = $2 returning *",
+                &[&state_hash, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows
+            .first()
+            .map(|row| FederatedLogin {
+                provider: row.get("provider"),
+                code_verifier: row.get("code_verifier"),
+                nonce: row.get("nonce"),
+                expires_at: row.get("expires_at"),
+                link_user_id: row.get("link_user_id"),
+            })
+            .filter(|login| login.expires_at > chrono::Utc::now()))
+    }
+}
@@ -0,0 +1,102 @@
+use mobc::{Connection, Pool};
+use mobc_postgres::tokio_postgres::{NoTls, Row};
+use mobc_postgres::PgConnectionManager;
+use uuid::Uuid;
+use warp::{reject, Rejection};
+
+use crate::errors::Error::{DBConnError, DBQueryError};
+use crate::models::oauth::UserGrant;
+
+/// What users of a realm consented to, per client.
+pub struct GrantRepository {
+    db: Connection<PgConnectionManager<NoTls>>,
+    realm: String,
+}
+
+fn user_grant(row: &Row) -> UserGrant {
+    UserGrant {
+        client_id: row.get("client_id"),
+        client_name: row.get("name"),
+        scopes: row.get("scopes"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+impl GrantRepository {
+    pub async fn new(
+        pool: Pool<PgConnectionManager<NoTls>>,
+        realm: &str,
+    ) -> Result<Self, Rejection> {
+        match pool.get().await {
+            Ok(db) => Ok(Self {
+                db,
+                realm: realm.to_string(),
+            }),
+            Err(e) => Err(reject::custom(DBConnError(e))),
+        }
+    }
+    pub async fn get(
+        &self,
+        user_id: Uuid,
+        client_id: Uuid,
+    ) -> Result<Option<UserGrant>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT g.*, c.name FROM user_grants g JOIN oauth_clients c ON c.id = g.client_id WHERE g.user_id = $1 AND g.client_id = $2 AND g.realm = $3",
+                &[&user_id, &client_id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(user_grant))
+    }
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<UserGrant>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT g.*, c.name FROM user_grants g JOIN oauth_clients c ON c.id = g.client_id WHERE g.user_id = $1 AND g.realm = $2 ORDER BY g.created_at",
+                &[&user_id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.iter().map(user_grant).collect())
+    }
+    /// Adds `scopes` to what the user granted the client.
+    pub async fn add(
+        &self,
+        user_id: Uuid,
+        client_id: Uuid,
+        scopes: &[String],
+    ) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "insert into user_grants (realm, user_id, client_id, scopes) values ($1, $2, $3, $4) \
+                 on conflict (user_id, client_id) do update \
+                 set scopes = array(select distinct unnest(user_grants.scopes || excluded.scopes) order by 1), updated_at = now()",
+                &[&self.realm, &user_id, &client_id, &scopes],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
+    /// Deletes the grant and, in the same statement, revokes every token and
+    /// unused authorization or device code issued under it. `false` if there
+    /// was none.
+    pub async fn revoke(&self, user_id: Uuid, client_id: Uuid) -> Result<bool, Rejection> {
+        let row = self
+            .db
+            .query_one(
+                "with deleted as (delete from user_grants where user_id = $1 and client_id = $2 and realm = $3 returning user_id, client_id), \
+                 tokens as (update oauth_tokens t set revoked_at = now() from deleted d where t.user_id = d.user_id and t.client_id = d.client_id and t.revoked_at is null), \
+                 codes as (update authorization_codes a set consumed_at = now() from deleted d where a.user_id = d.user_id and a.client_id = d.client_id and a.consumed_at is null), \
+                 devices as (update device_codes c set consumed_at = now() from deleted d where c.user_id = d.user_id and c.client_id = d.client_id and c.consumed_at is null) \
+                 select count(*) from deleted",
+                &[&user_id, &client_id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        let count: i64 = row.get(0);
+        Ok(count > 0)
+    }
+}
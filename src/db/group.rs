@@ -0,0 +1,178 @@
+use mobc::{Connection, Pool};
+use mobc_postgres::tokio_postgres::{error::SqlState, NoTls, Row};
+use mobc_postgres::PgConnectionManager;
+use std::io::{Error, ErrorKind};
+use uuid::Uuid;
+use warp::{reject, Rejection};
+
+use crate::errors::Error::{DBConnError, DBQueryError, ExistsError};
+use crate::models::scim::{Group, GroupMember};
+
+/// Groups of users of a realm, as SCIM provisioning clients push them.
+pub struct GroupRepository {
+    db: Connection<PgConnectionManager<NoTls>>,
+    realm: String,
+}
+
+fn exists_error(e: mobc_postgres::tokio_postgres::Error) -> Rejection {
+    if e.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+        reject::custom(ExistsError(Error::from(ErrorKind::AlreadyExists)))
+    } else {
+        reject::custom(DBQueryError(e))
+    }
+}
+
+impl GroupRepository {
+    pub async fn new(
+        pool: Pool<PgConnectionManager<NoTls>>,
+        realm: &str,
+    ) -> Result<Self, Rejection> {
+        match pool.get().await {
+            Ok(db) => Ok(Self {
+                db,
+                realm: realm.to_string(),
+            }),
+            Err(e) => Err(reject::custom(DBConnError(e))),
+        }
+    }
+    async fn members(&self, group_id: Uuid) -> Result<Vec<GroupMember>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT u.id, u.username FROM group_members m JOIN users u ON u.id = m.user_id WHERE m.group_id = $1 ORDER BY u.username",
+                &[&group_id],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows
+            .iter()
+            .map(|row| GroupMember {
+                user_id: row.get("id"),
+                username: row.get("username"),
+            })
+            .collect())
+    }
+    async fn group(&self, row: &Row) -> Result<Group, Rejection> {
+        let id = row.get("id");
+        Ok(Group {
+            id,
+            display_name: row.get("display_name"),
+            members: self.members(id).await?,
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        })
+    }
+    pub async fn get(&self, id: Uuid) -> Result<Option<Group>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT * FROM groups WHERE id = $1 AND realm = $2",
+                &[&id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        match rows.first() {
+            Some(row) => Ok(Some(self.group(row).await?)),
+            None => Ok(None),
+        }
+    }
+    /// A page of the realm's groups by creation date, with how many there
+    /// are in all; only the one named `display_name` if given.
+    pub async fn list(
+        &self,
+        display_name: Option<&str>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(i64, Vec<Group>), Rejection> {
+        let total: i64 = self
+            .db
+            .query_one(
+                "SELECT count(*) FROM groups WHERE realm = $1 AND ($2::varchar IS NULL OR display_name = $2)",
+                &[&self.realm, &display_name],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?
+            .get(0);
+        let rows = self
+            .db
+            .query(
+                "SELECT * FROM groups WHERE realm = $1 AND ($2::varchar IS NULL OR display_name = $2) ORDER BY created_at, id OFFSET $3 LIMIT $4",
+                &[&self.realm, &display_name, &offset, &limit],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        let mut groups = Vec::with_capacity(rows.len());
+        for row in &rows {
+            groups.push(self.group(row).await?);
+        }
+        Ok((total, groups))
+    }
+    /// `None` when the realm already has a group of that name.
+    pub async fn create(&self, display_name: &str) -> Result<Option<Uuid>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "insert into groups (realm, display_name) values ($1, $2) on conflict do nothing returning id",
+                &[&self.realm, &display_name],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(|row| row.get(0)))
+    }
+    /// `false` when there is no such group. Fails with `ExistsError` when
+    /// another group has the name.
+    pub async fn rename(&self, id: Uuid, display_name: &str) -> Result<bool, Rejection> {
+        let updated = self
+            .db
+            .execute(
+                "update groups set display_name = $1, updated_at = now() where id = $2 and realm = $3",
+                &[&display_name, &id, &self.realm],
+            )
+            .await
+            .map_err(exists_error)?;
+        Ok(updated == 1)
+    }
+    /// Adds the users of the realm among `user_ids` to the group; the others
+    /// are ignored.
+    pub async fn add_members(&self, id: Uuid, user_ids: &[Uuid]) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "insert into group_members (group_id, user_id) select $1, u.id from users u where u.id = any($2) and u.realm = $3 on conflict do nothing",
+                &[&id, &user_ids, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        self.touch(id).await
+    }
+    pub async fn remove_members(&self, id: Uuid, user_ids: &[Uuid]) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "delete from group_members where group_id = $1 and user_id = any($2)",
+                &[&id, &user_ids],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        self.touch(id).await
+    }
+    async fn touch(&self, id: Uuid) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "update groups set updated_at = now() where id = $1 and realm = $2",
+                &[&id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
+    pub async fn delete(&self, id: Uuid) -> Result<bool, Rejection> {
+        let deleted = self
+            .db
+            .execute(
+                "delete from groups where id = $1 and realm = $2",
+                &[&id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(deleted == 1)
+    }
+}
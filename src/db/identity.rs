@@ -0,0 +1,87 @@
+use mobc::{Connection, Pool};
+use mobc_postgres::tokio_postgres::{NoTls, Row};
+use mobc_postgres::PgConnectionManager;
+use uuid::Uuid;
+use warp::{reject, Rejection};
+
+use crate::errors::Error::{DBConnError, DBQueryError};
+use crate::models::federation::Identity;
+
+/// Upstream accounts linked to the users of a realm.
+pub struct IdentityRepository {
+    db: Connection<PgConnectionManager<NoTls>>,
+    realm: String,
+}
+
+fn identity(row: &Row) -> Identity {
+    Identity {
+        provider: row.get("provider"),
+        subject: row.get("subject"),
+        user_id: row.get("user_id"),
+        linked_at: row.get("linked_at"),
+    }
+}
+
+impl IdentityRepository {
+    pub async fn new(
+        pool: Pool<PgConnectionManager<NoTls>>,
+        realm: &str,
+    ) -> Result<Self, Rejection> {
+        match pool.get().await {
+            Ok(db) => Ok(Self {
+                db,
+                realm: realm.to_string(),
+            }),
+            Err(e) => Err(reject::custom(DBConnError(e))),
+        }
+    }
+    pub async fn get(&self, provider: &str, subject: &str) -> Result<Option<Identity>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT * FROM identities WHERE realm = $1 AND provider = $2 AND subject = $3",
+                &[&self.realm, &provider, &subject],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(identity))
+    }
+    /// Links the upstream account to `user_id`, unless it already is linked.
+    pub async fn link(
+        &self,
+        provider: &str,
+        subject: &str,
+        user_id: Uuid,
+    ) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "insert into identities (realm, provider, subject, user_id) values ($1, $2, $3, $4) on conflict do nothing",
+                &[&self.realm, &provider, &subject, &user_id],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
+    /// The upstream accounts linked to the user, oldest first.
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<Identity>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT * FROM identities WHERE realm = $1 AND user_id = $2 ORDER BY linked_at",
+                &[&self.realm, &user_id],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.iter().map(identity).collect())
+    }
+    /// Unlinks the user's accounts at `provider`, returning how many there were.
+    pub async fn unlink(&self, user_id: Uuid, provider: &str) -> Result<u64, Rejection> {
+        self.db
+            .execute(
+                "delete from identities where realm = $1 and user_id = $2 and provider = $3",
+                &[&self.realm, &user_id, &provider],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))
+    }
+}
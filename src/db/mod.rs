@@ -1 +1,11 @@
+pub mod authorization_code;
+pub mod client;
+pub mod device_code;
+pub mod federated_login;
+pub mod grant;
+pub mod group;
+pub mod identity;
+pub mod saml_request;
+pub mod session;
+pub mod token;
 pub mod user;
@@ -0,0 +1,59 @@
+use chrono::{DateTime, Utc};
+use mobc::{Connection, Pool};
+use mobc_postgres::tokio_postgres::NoTls;
+use mobc_postgres::PgConnectionManager;
+use warp::{reject, Rejection};
+
+use crate::errors::Error::{DBConnError, DBQueryError};
+
+/// AuthnRequests awaiting the identity provider's answer, by request ID.
+pub struct SamlRequestRepository {
+    db: Connection<PgConnectionManager<NoTls>>,
+    realm: String,
+}
+
+impl SamlRequestRepository {
+    pub async fn new(
+        pool: Pool<PgConnectionManager<NoTls>>,
+        realm: &str,
+    ) -> Result<Self, Rejection> {
+        match pool.get().await {
+            Ok(db) => Ok(Self {
+                db,
+                realm: realm.to_string(),
+            }),
+            Err(e) => Err(reject::custom(DBConnError(e))),
+        }
+    }
+    pub async fn create(
+        &self,
+        request_id: &str,
+        provider: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "insert into saml_requests (request_id, realm, provider, expires_at) values ($1, $2, $3, $4)",
+                &[&request_id, &self.realm, &provider, &expires_at],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
+    /// Removes the request, so each is answered only once; false if it is
+    /// unknown, was sent to another provider or has expired.
+    pub async fn consume(&self, request_id: &str, provider: &str) -> Result<bool, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "delete from saml_requests where request_id = $1 and realm = $2 returning provider, expires_at",
+                &[&request_id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().is_some_and(|row| {
+            row.get::<_, String>("provider") == provider
+                && row.get::<_, DateTime<Utc>>("expires_at") > Utc::now()
+        }))
+    }
+}
@@ -0,0 +1,132 @@
+use chrono::{DateTime, Utc};
+use mobc::{Connection, Pool};
+use mobc_postgres::tokio_postgres::{NoTls, Row};
+use mobc_postgres::PgConnectionManager;
+use uuid::Uuid;
+use warp::{reject, Rejection};
+
+use crate::errors::Error::{DBConnError, DBQueryError};
+use crate::models::session::{BrowserSession, ClientInfo, Session};
+
+/// The signed-in sessions of the users of a realm.
+pub struct SessionRepository {
+    db: Connection<PgConnectionManager<NoTls>>,
+    realm: String,
+}
+
+fn session(row: &Row) -> Session {
+    Session {
+        id: row.get("id"),
+        user_agent: row.get("user_agent"),
+        ip: row.get("ip"),
+        created_at: row.get("created_at"),
+        last_seen_at: row.get("last_seen_at"),
+        expires_at: row.get("expires_at"),
+        current: false,
+    }
+}
+
+impl SessionRepository {
+    pub async fn new(
+        pool: Pool<PgConnectionManager<NoTls>>,
+        realm: &str,
+    ) -> Result<Self, Rejection> {
+        match pool.get().await {
+            Ok(db) => Ok(Self {
+                db,
+                realm: realm.to_string(),
+            }),
+            Err(e) => Err(reject::custom(DBConnError(e))),
+        }
+    }
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        client: &ClientInfo,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Uuid, Rejection> {
+        let row = self
+            .db
+            .query_one(
+                "insert into sessions (realm, user_id, user_agent, ip, expires_at) values ($1, $2, $3, $4, $5) returning id",
+                &[&self.realm, &user_id, &client.user_agent, &client.ip, &expires_at],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(row.get(0))
+    }
+    /// Makes the session a browser session, authenticated by the cookie
+    /// whose secret has the digest `cookie_hash`.
+    pub async fn set_cookie(
+        &self,
+        id: Uuid,
+        cookie_hash: &str,
+        csrf_token: &str,
+    ) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "update sessions set cookie_hash = $1, csrf_token = $2 where id = $3 and realm = $4",
+                &[&cookie_hash, &csrf_token, &id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
+    /// The browser session still in use with the cookie digest `cookie_hash`.
+    pub async fn find_by_cookie(
+        &self,
+        cookie_hash: &str,
+    ) -> Result<Option<BrowserSession>, Rejection> {
+        let row = self
+            .db
+            .query_opt(
+                "SELECT id, user_id, csrf_token, created_at FROM sessions WHERE cookie_hash = $1 AND realm = $2 AND revoked_at IS NULL AND expires_at > now()",
+                &[&cookie_hash, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(row.map(|row| BrowserSession {
+            id: row.get("id"),
+            user_id: row.get("user_id"),
+            csrf_token: row.get("csrf_token"),
+            created_at: row.get("created_at"),
+        }))
+    }
+    /// Records that the session was just used; `false` when it was revoked,
+    /// has expired, is not the user's or the user was deactivated since.
+    pub async fn touch(&self, id: Uuid, user_id: Uuid) -> Result<bool, Rejection> {
+        let updated = self
+            .db
+            .execute(
+                "update sessions s set last_seen_at = now() from users u where s.id = $1 and s.user_id = $2 and s.realm = $3 and s.revoked_at is null and s.expires_at > now() and u.id = s.user_id and u.active",
+                &[&id, &user_id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(updated == 1)
+    }
+    /// The user's sessions still in use, most recently seen first.
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<Session>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT * FROM sessions WHERE user_id = $1 AND realm = $2 AND revoked_at IS NULL AND expires_at > now() ORDER BY last_seen_at DESC",
+                &[&user_id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.iter().map(session).collect())
+    }
+    /// `false` when the user has no such session in use.
+    pub async fn revoke(&self, user_id: Uuid, id: Uuid) -> Result<bool, Rejection> {
+        let updated = self
+            .db
+            .execute(
+                "update sessions set revoked_at = now() where id = $1 and user_id = $2 and realm = $3 and revoked_at is null and expires_at > now()",
+                &[&id, &user_id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(updated == 1)
+    }
+}
@@ -0,0 +1,115 @@
+use mobc::{Connection, Pool};
+use mobc_postgres::tokio_postgres::{NoTls, Row};
+use mobc_postgres::PgConnectionManager;
+use uuid::Uuid;
+use warp::{reject, Rejection};
+
+use crate::errors::Error::{DBConnError, DBQueryError};
+use crate::models::oauth::StoredToken;
+
+/// Issued access and refresh tokens of a realm.
+pub struct TokenRepository {
+    db: Connection<PgConnectionManager<NoTls>>,
+    realm: String,
+}
+
+fn stored_token(row: &Row) -> StoredToken {
+    StoredToken {
+        id: row.get("id"),
+        token_type: row.get("token_type"),
+        client_id: row.get("client_id"),
+        user_id: row.get("user_id"),
+        scope: row.get("scope"),
+        parent_id: row.get("parent_id"),
+        expires_at: row.get("expires_at"),
+        revoked_at: row.get("revoked_at"),
+    }
+}
+
+impl TokenRepository {
+    pub async fn new(
+        pool: Pool<PgConnectionManager<NoTls>>,
+        realm: &str,
+    ) -> Result<Self, Rejection> {
+        match pool.get().await {
+            Ok(db) => Ok(Self {
+                db,
+                realm: realm.to_string(),
+            }),
+            Err(e) => Err(reject::custom(DBConnError(e))),
+        }
+    }
+    pub async fn create(
+        &self,
+        token: &StoredToken,
+        token_hash: Option<&str>,
+    ) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "insert into oauth_tokens (id, realm, token_type, token_hash, client_id, user_id, scope, parent_id, expires_at) values ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+                &[
+                    &token.id,
+                    &self.realm,
+                    &token.token_type,
+                    &token_hash,
+                    &token.client_id,
+                    &token.user_id,
+                    &token.scope,
+                    &token.parent_id,
+                    &token.expires_at,
+                ],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
+    pub async fn get(&self, id: Uuid) -> Result<Option<StoredToken>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT * FROM oauth_tokens WHERE id = $1 AND realm = $2",
+                &[&id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(stored_token))
+    }
+    pub async fn get_by_hash(&self, token_hash: &str) -> Result<Option<StoredToken>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT * FROM oauth_tokens WHERE token_hash = $1 AND realm = $2",
+                &[&token_hash, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(stored_token))
+    }
+    /// Revokes a single token unless it already was; `false` tells a
+    /// concurrent refresh that it lost the race.
+    pub async fn consume(&self, id: Uuid) -> Result<bool, Rejection> {
+        let revoked = self
+            .db
+            .execute(
+                "update oauth_tokens set revoked_at = now() where id = $1 and realm = $2 and revoked_at is null",
+                &[&id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(revoked > 0)
+    }
+    /// Revokes a token along with the access tokens issued with it.
+    pub async fn revoke(&self, id: Uuid) -> Result<u64, Rejection> {
+        self.db
+            .execute(
+                "with recursive family as ( \
+                     select id from oauth_tokens where id = $1 and realm = $2 \
+                     union select t.id from oauth_tokens t join family f on t.parent_id = f.id \
+                 ) \
+                 update oauth_tokens set revoked_at = now() where id in (select id from family) and revoked_at is null",
+                &[&id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))
+    }
+}
@@ -1,34 +1,63 @@
 use mobc::{Connection, Pool};
-use mobc_postgres::{tokio_postgres::NoTls, PgConnectionManager};
+use mobc_postgres::tokio_postgres::{error::SqlState, NoTls, Row};
+use mobc_postgres::PgConnectionManager;
 use secrecy::{ExposeSecret, Secret};
 use uuid::Uuid;
 use warp::{reject, Rejection};
 
-use crate::errors::Error::{DBConnError, DBQueryError};
-use crate::models::user::{NewUser, User};
+use std::io::{Error, ErrorKind};
 
+use crate::errors::Error::{DBConnError, DBQueryError, ExistsError};
+use crate::models::user::{
+    ExternalProfile, NewUser, ProvisionedUser, StoredCredentials, User, LOCAL_AUTH_SOURCE,
+    SCIM_AUTH_SOURCE, UNUSABLE_PASSWORD_HASH,
+};
+
+fn user(row: &Row) -> User {
+    User {
+        id: row.get("id"),
+        username: row.get("username"),
+        email: row.get("email"),
+        password_hash: "secret".to_string(),
+        full_name: row.get("full_name"),
+        bio: row.get("bio"),
+        image: row.get("image"),
+        email_verified: row.get("email_verified"),
+        active: row.get("active"),
+        roles: row.get("roles"),
+    }
+}
+
+/// Users are namespaced by realm: every query is scoped to `realm`.
 pub struct UserRepository {
     db: Connection<PgConnectionManager<NoTls>>,
+    realm: String,
 }
 
 impl UserRepository {
-    pub async fn new(pool: Pool<PgConnectionManager<NoTls>>) -> Result<Self, Rejection> {
+    pub async fn new(
+        pool: Pool<PgConnectionManager<NoTls>>,
+        realm: &str,
+    ) -> Result<Self, Rejection> {
         match pool.get().await {
-            Ok(db) => return Ok(Self { db }),
-            Err(e) => return Err(reject::custom(DBConnError(e))),
+            Ok(db) => Ok(Self {
+                db,
+                realm: realm.to_string(),
+            }),
+            Err(e) => Err(reject::custom(DBConnError(e))),
         }
     }
     pub async fn create(&self, new_user: NewUser) -> Result<Option<Uuid>, Rejection> {
         let rows = self
             .db
             .query(
-                "insert into users (username, email, password_hash) values ($1, $2, $3) returning id",
-                &[&new_user.username, &new_user.email, new_user.password_hash.expose_secret()],
+                "insert into users (realm, username, email, password_hash) values ($1, $2, $3, $4) returning id",
+                &[&self.realm, &new_user.username, &new_user.email, new_user.password_hash.expose_secret()],
             )
             .await
             .map_err(|e| reject::custom(DBQueryError(e)))?;
         if rows.is_empty() {
-            ()
+            return Ok(None);
         };
         let id: Uuid = rows[0].get(0);
         Ok(Some(id))
@@ -36,80 +65,263 @@ impl UserRepository {
     pub async fn delete(&self, id: Uuid) -> Result<Option<Uuid>, Rejection> {
         let rows = self
             .db
-            .query("delete from users where id = $1 returning id", &[&id])
+            .query(
+                "delete from users where id = $1 and realm = $2 returning id",
+                &[&id, &self.realm],
+            )
             .await
             .map_err(|e| reject::custom(DBQueryError(e)))?;
         if rows.is_empty() {
-            ()
+            return Ok(None);
         }
         let id: Uuid = rows[0].get(0);
         Ok(Some(id))
     }
+    /// What is needed to check the password of the user named `username`.
     pub async fn get_password_hash(
         &self,
         username: &String,
-    ) -> Result<Option<(Secret<String>, Uuid)>, Rejection> {
+    ) -> Result<Option<StoredCredentials>, Rejection> {
         match self
             .db
-            .query("SELECT * FROM users WHERE username = $1", &[&username])
+            .query(
+                "SELECT * FROM users WHERE username = $1 AND realm = $2",
+                &[&username, &self.realm],
+            )
             .await
         {
             Ok(rows) => {
-                if rows.is_empty() || rows.len() == 0 {
+                if rows.is_empty() {
                     return Ok(None);
                 }
                 let pass: String = rows[0].get("password_hash");
-                let secret = Secret::new(pass);
-                let id: Uuid = rows[0].get("id");
-                Ok(Some((secret, id)))
-            }
-            Err(e) => {
-                return Err(reject::custom(DBQueryError(e)));
+                Ok(Some(StoredCredentials {
+                    id: rows[0].get("id"),
+                    password_hash: Secret::new(pass),
+                    auth_source: rows[0].get("auth_source"),
+                    active: rows[0].get("active"),
+                }))
             }
+            Err(e) => Err(reject::custom(DBQueryError(e))),
         }
     }
+    /// Provisions a user an external source vouched for on their first
+    /// sign-in. Their password stays with the source: the hash is unusable.
+    /// `None` when the username is taken.
+    pub async fn create_external_user(
+        &self,
+        username: &str,
+        auth_source: &str,
+        profile: &ExternalProfile,
+    ) -> Result<Option<Uuid>, Rejection> {
+        let email = profile.email.as_deref().unwrap_or_default();
+        let rows = self
+            .db
+            .query(
+                "insert into users (realm, username, email, full_name, password_hash, auth_source) values ($1, $2, $3, $4, '!', $5) on conflict do nothing returning id",
+                &[&self.realm, &username, &email, &profile.full_name, &auth_source],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        if rows.is_empty() {
+            return Ok(None);
+        };
+        let id: Uuid = rows[0].get(0);
+        Ok(Some(id))
+    }
+    /// Copies an external source's attributes over the profile of a user it provisioned.
+    pub async fn update_external_user(
+        &self,
+        id: Uuid,
+        auth_source: &str,
+        profile: &ExternalProfile,
+    ) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "update users set email = coalesce($1, email), full_name = coalesce($2, full_name), updated_at = now() where id = $3 and realm = $4 and auth_source = $5",
+                &[&profile.email, &profile.full_name, &id, &self.realm, &auth_source],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
     pub async fn validate_id(&self, id: Uuid) -> Result<Option<Uuid>, Rejection> {
         match self
             .db
-            .query("SELECT id FROM users WHERE id = $1", &[&id])
+            .query(
+                "SELECT id FROM users WHERE id = $1 AND realm = $2",
+                &[&id, &self.realm],
+            )
             .await
             .map_err(|e| reject::custom(DBQueryError(e)))
         {
             Ok(rows) => {
-                if rows.is_empty() || rows.len() == 0 {
+                if rows.is_empty() {
                     return Ok(None);
                 }
                 let id: Uuid = rows[0].get("id");
                 Ok(Some(id))
             }
-            Err(e) => return Err(e),
+            Err(e) => Err(e),
         }
     }
+    /// The only user whose email is verified and equal to `email`; `None`
+    /// when there is none, or several.
+    pub async fn get_by_verified_email(&self, email: &str) -> Result<Option<Uuid>, Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT id FROM users WHERE lower(email) = lower($1) AND email_verified AND realm = $2 LIMIT 2",
+                &[&email, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        match rows.as_slice() {
+            [row] => Ok(Some(row.get("id"))),
+            _ => Ok(None),
+        }
+    }
+    /// A page of the realm's users by creation date, with how many there are
+    /// in all; only the one named `username` (case-insensitively) if given.
+    pub async fn list(
+        &self,
+        username: Option<&str>,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(i64, Vec<User>), Rejection> {
+        let rows = self
+            .db
+            .query(
+                "SELECT *, count(*) over () as total FROM users WHERE realm = $1 AND ($2::varchar IS NULL OR lower(username) = lower($2)) ORDER BY created_at, id OFFSET $3 LIMIT $4",
+                &[&self.realm, &username, &offset, &limit],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        let total = match rows.first() {
+            Some(row) => row.get("total"),
+            // Past the last page, or no users at all.
+            None => self.count(username).await?,
+        };
+        Ok((total, rows.iter().map(user).collect()))
+    }
+    async fn count(&self, username: Option<&str>) -> Result<i64, Rejection> {
+        let row = self
+            .db
+            .query_one(
+                "SELECT count(*) FROM users WHERE realm = $1 AND ($2::varchar IS NULL OR lower(username) = lower($2))",
+                &[&self.realm, &username],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(row.get(0))
+    }
+    /// Creates a user a SCIM client pushed: `local` with a password, `scim`
+    /// without. `None` when the username is taken.
+    pub async fn create_provisioned(
+        &self,
+        provisioned: &ProvisionedUser,
+    ) -> Result<Option<Uuid>, Rejection> {
+        let (password_hash, auth_source) = match &provisioned.password_hash {
+            Some(hash) => (hash.expose_secret().as_str(), LOCAL_AUTH_SOURCE),
+            None => (UNUSABLE_PASSWORD_HASH, SCIM_AUTH_SOURCE),
+        };
+        let rows = self
+            .db
+            .query(
+                "insert into users (realm, username, email, full_name, active, password_hash, auth_source) values ($1, $2, $3, $4, $5, $6, $7) on conflict do nothing returning id",
+                &[
+                    &self.realm,
+                    &provisioned.username,
+                    &provisioned.email,
+                    &provisioned.full_name,
+                    &provisioned.active,
+                    &password_hash,
+                    &auth_source,
+                ],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(rows.first().map(|row| row.get(0)))
+    }
+    /// Replaces what a SCIM client manages of a user; `false` when there is
+    /// no such user. Fails with `ExistsError` when the new username is taken.
+    pub async fn replace_provisioned(
+        &self,
+        id: Uuid,
+        provisioned: &ProvisionedUser,
+    ) -> Result<bool, Rejection> {
+        let password_hash = provisioned
+            .password_hash
+            .as_ref()
+            .map(|hash| hash.expose_secret().as_str());
+        let updated = self
+            .db
+            .execute(
+                "update users set username = $1, email = $2, full_name = $3, active = $4, password_hash = coalesce($5, password_hash), auth_source = case when $5 is null then auth_source else 'local' end, updated_at = now() where id = $6 and realm = $7",
+                &[
+                    &provisioned.username,
+                    &provisioned.email,
+                    &provisioned.full_name,
+                    &provisioned.active,
+                    &password_hash,
+                    &id,
+                    &self.realm,
+                ],
+            )
+            .await
+            .map_err(|e| {
+                if e.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+                    reject::custom(ExistsError(Error::from(ErrorKind::AlreadyExists)))
+                } else {
+                    reject::custom(DBQueryError(e))
+                }
+            })?;
+        Ok(updated == 1)
+    }
+    /// Sets the user's own password, making them a `local` user.
+    pub async fn set_password(
+        &self,
+        id: Uuid,
+        password_hash: &Secret<String>,
+    ) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "update users set password_hash = $1, auth_source = $2, updated_at = now() where id = $3 and realm = $4",
+                &[&password_hash.expose_secret(), &LOCAL_AUTH_SOURCE, &id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
+    /// Takes away the user's password, or directory account: they are left
+    /// with their upstream identities.
+    pub async fn remove_password(&self, id: Uuid) -> Result<(), Rejection> {
+        self.db
+            .execute(
+                "update users set password_hash = $1, auth_source = $2, updated_at = now() where id = $3 and realm = $4",
+                &[&UNUSABLE_PASSWORD_HASH, &LOCAL_AUTH_SOURCE, &id, &self.realm],
+            )
+            .await
+            .map_err(|e| reject::custom(DBQueryError(e)))?;
+        Ok(())
+    }
     pub async fn get_user_by_id(&self, id: Uuid) -> Result<Option<User>, Rejection> {
         match self
             .db
-            .query("SELECT * FROM users WHERE id = $1", &[&id])
+            .query(
+                "SELECT * FROM users WHERE id = $1 AND realm = $2",
+                &[&id, &self.realm],
+            )
             .await
             .map_err(|e| reject::custom(DBQueryError(e)))
         {
             Ok(rows) => {
-                if rows.is_empty() || rows.len() == 0 {
+                if rows.is_empty() {
                     return Ok(None);
                 }
-                let user = User {
-                    id: rows[0].get("id"),
-                    username: rows[0].get("username"),
-                    email: rows[0].get("email"),
-                    password_hash: "secret".to_string(),
-                    full_name: rows[0].get("full_name"),
-                    bio: rows[0].get("bio"),
-                    image: rows[0].get("image"),
-                    email_verified: rows[0].get("email_verified"),
-                    active: rows[0].get("active"),
-                };
-                Ok(Some(user))
+                Ok(Some(user(&rows[0])))
             }
-            Err(e) => return Err(e),
+            Err(e) => Err(e),
         }
     }
 }
@@ -125,7 +337,7 @@ async fn test_delete_user() {
     let db_pool = config.db_pool().expect("db_pool");
 
     let deleted_id = config
-        .user_repo(db_pool)
+        .user_repo(db_pool, config.realms.default_realm())
         .await
         .unwrap()
         .delete(id)
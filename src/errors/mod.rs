@@ -1,15 +1,27 @@
-use std::convert::Infallible;
-
+use authserver_client::models::error::{
+    FieldError, OAuthErrorResponse, Problem, ScimErrorResponse, PROBLEM_CONTENT_TYPE,
+    PROBLEM_TYPE_PREFIX,
+};
 use jsonwebtoken::errors::ErrorKind;
-use mobc_postgres::tokio_postgres;
-use serde::Serialize;
+use mobc_postgres::tokio_postgres::{self, error::SqlState};
 use thiserror::Error;
-use warp::{hyper::StatusCode, Rejection, Reply};
+use tracing::error;
+use validator::ValidationErrors;
+use warp::{
+    http::header::{HeaderValue, CONTENT_TYPE, WWW_AUTHENTICATE},
+    hyper::StatusCode,
+    reply::Response,
+    Rejection, Reply,
+};
+
+use crate::models::scim::{ERROR_SCHEMA, SCIM_CONTENT_TYPE};
 
-// #[derive(Debug)]
-// pub struct PathMismatch;
+/// A route whose path did not match. Not an [`Error`], so the error of the
+/// route that did match is the one answered.
+#[derive(Debug)]
+pub struct PathMismatch;
 
-// impl warp::reject::Reject for PathMismatch {}
+impl warp::reject::Reject for PathMismatch {}
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -23,97 +35,325 @@ pub enum Error {
     // ReadFileError(#[from] std::io::Error),
     #[error("error reading authorization header")]
     AuthError(std::io::Error),
+    #[error("not authorized in realm {0}")]
+    Unauthorized(String),
+    #[error("Forbidden")]
+    Forbidden,
     #[error("error Resource Already Exists")]
     ExistsError(std::io::Error),
     #[error("error Token Generation")]
     TokenError(jsonwebtoken::errors::Error),
+    /// A bearer token that is expired, forged or malformed.
+    #[error("invalid token: {0}")]
+    InvalidToken(jsonwebtoken::errors::Error),
     #[error("error Hash Generation")]
     HashError(argon2::password_hash::Error),
-    #[error("Path not found")]
-    PathMismatch,
     #[error("Operation Could Not Be Completed")]
     NotCompletedError(std::io::ErrorKind),
     #[error("Invalid Input Request")]
     InputError(std::io::ErrorKind),
+    #[error("invalid body: {0}")]
+    BodyError(String),
+    #[error("body over {0} bytes")]
+    PayloadTooLarge(usize),
+    #[error("unsupported media type")]
+    UnsupportedMediaType,
+    #[error("none of the formats the client accepts")]
+    NotAcceptable,
+    /// Answered with the fields that are not valid.
+    #[error("invalid fields: {0}")]
+    ValidationError(ValidationErrors),
     #[error("Entity Not found")]
     NotFoundError(std::io::ErrorKind),
+    #[error("the last login method cannot be removed")]
+    LastLoginMethod,
+    #[error("directory unavailable: {0}")]
+    DirectoryError(String),
+    #[error("OAuth error {0:?}: {1}")]
+    OAuthError(OAuthErrorCode, String),
+    /// Answered to SCIM clients as an error of RFC 7644 section 3.12, with
+    /// its `scimType` if any.
+    #[error("SCIM error {0}: {2}")]
+    ScimError(StatusCode, Option<&'static str>, String),
 }
 
 impl warp::reject::Reject for Error {}
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    message: String,
+/// Error codes of RFC 6749 section 5.2, answered to OAuth clients as
+/// `{ "error": ..., "error_description": ... }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OAuthErrorCode {
+    InvalidRequest,
+    InvalidClient,
+    InvalidGrant,
+    UnauthorizedClient,
+    UnsupportedGrantType,
+    UnsupportedResponseType,
+    InvalidScope,
+    AccessDenied,
+    InvalidToken,
+    InsufficientScope,
+    AuthorizationPending,
+    SlowDown,
+    ExpiredToken,
+    InvalidRedirectUri,
+    InvalidClientMetadata,
+    InvalidTarget,
+}
+
+impl OAuthErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OAuthErrorCode::InvalidRequest => "invalid_request",
+            OAuthErrorCode::InvalidClient => "invalid_client",
+            OAuthErrorCode::InvalidGrant => "invalid_grant",
+            OAuthErrorCode::UnauthorizedClient => "unauthorized_client",
+            OAuthErrorCode::UnsupportedGrantType => "unsupported_grant_type",
+            OAuthErrorCode::UnsupportedResponseType => "unsupported_response_type",
+            OAuthErrorCode::InvalidScope => "invalid_scope",
+            OAuthErrorCode::AccessDenied => "access_denied",
+            OAuthErrorCode::InvalidToken => "invalid_token",
+            OAuthErrorCode::InsufficientScope => "insufficient_scope",
+            OAuthErrorCode::AuthorizationPending => "authorization_pending",
+            OAuthErrorCode::SlowDown => "slow_down",
+            OAuthErrorCode::ExpiredToken => "expired_token",
+            OAuthErrorCode::InvalidRedirectUri => "invalid_redirect_uri",
+            OAuthErrorCode::InvalidClientMetadata => "invalid_client_metadata",
+            OAuthErrorCode::InvalidTarget => "invalid_target",
+        }
+    }
+    pub fn status(&self) -> StatusCode {
+        match self {
+            OAuthErrorCode::InvalidClient | OAuthErrorCode::InvalidToken => {
+                StatusCode::UNAUTHORIZED
+            }
+            OAuthErrorCode::InsufficientScope => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// The detail of 404s answered because no end-point serves the path.
+pub const ROUTE_MISSED: &str = "No end-point matches the request.";
+
+/// The header a request id is read from and answered in.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// What a rejection is answered with, before the request id is added.
+struct Described {
+    status: StatusCode,
+    code: &'static str,
+    title: &'static str,
+    detail: Option<String>,
+    errors: Vec<FieldError>,
+    challenge: Option<String>,
 }
 
-pub async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply, Infallible> {
-    let code;
-    let message;
-    eprintln!("unhandled error: {:?}", err);
+impl Described {
+    fn new(status: StatusCode, code: &'static str, title: &'static str) -> Self {
+        Described {
+            status,
+            code,
+            title,
+            detail: None,
+            errors: vec![],
+            challenge: None,
+        }
+    }
+    fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+    /// No route serves the path, as opposed to a missing resource.
+    fn route_missed() -> Self {
+        Described::new(StatusCode::NOT_FOUND, "not_found", "Not Found").detail(ROUTE_MISSED)
+    }
+    fn internal() -> Self {
+        Described::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Internal Server Error",
+        )
+    }
+}
 
+/// The invalid fields of `errors`, sorted so answers do not change between requests.
+fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut fields: Vec<FieldError> = errors
+        .field_errors()
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors.iter().map(move |e| FieldError {
+                field: field.to_string(),
+                code: e.code.to_string(),
+                message: e.message.as_ref().map(|m| m.to_string()),
+            })
+        })
+        .collect();
+    fields.sort_by(|a, b| a.field.cmp(&b.field).then_with(|| a.code.cmp(&b.code)));
+    fields
+}
+
+fn describe(err: &Rejection) -> Described {
     if err.is_not_found() {
-        code = StatusCode::NOT_FOUND;
-        message = "Not Found";
-    } else if let Some(e) = err.find::<Error>() {
-        match e {
-            Error::PathMismatch => {
-                code = StatusCode::NOT_FOUND;
-                message = "Not Found";
-            }
-            Error::DBQueryError(_) => {
-                code = StatusCode::BAD_REQUEST;
-                message = "Could not Execute request";
-            }
+        return Described::route_missed();
+    }
+    if let Some(e) = err.find::<Error>() {
+        return match e {
             Error::NotFoundError(_) => {
-                code = StatusCode::NOT_FOUND;
-                message = "Entity Not Found";
+                Described::new(StatusCode::NOT_FOUND, "not_found", "Not Found")
             }
-            Error::InputError(_) => {
-                code = StatusCode::BAD_REQUEST;
-                message = "Invalid Input";
+            Error::DBQueryError(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+                Described::new(StatusCode::CONFLICT, "conflict", "Conflict")
+                    .detail("The resource already exists.")
             }
-            Error::AuthError(_) => {
-                code = StatusCode::UNAUTHORIZED;
-                message = "Not authorized";
+            Error::DBPoolError(_) | Error::DBConnError(_) => Described::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "service_unavailable",
+                "Service Unavailable",
+            )
+            .detail("The database cannot be reached."),
+            Error::InputError(_) => {
+                Described::new(StatusCode::BAD_REQUEST, "invalid_input", "Invalid Input")
             }
-            Error::NotCompletedError(_) => {
-                code = StatusCode::BAD_REQUEST;
-                message = "Operation Could Not Be Completed";
+            Error::BodyError(detail) => {
+                Described::new(StatusCode::BAD_REQUEST, "invalid_body", "Invalid Body")
+                    .detail(detail.clone())
             }
-            Error::ExistsError(_) => {
-                code = StatusCode::CONFLICT;
-                message = "Resource Already Exists";
+            Error::PayloadTooLarge(limit) => Described::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "payload_too_large",
+                "Payload Too Large",
+            )
+            .detail(format!("The body is over {} bytes.", limit)),
+            Error::UnsupportedMediaType => Described::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                "Unsupported Media Type",
+            ),
+            Error::NotAcceptable => Described::new(
+                StatusCode::NOT_ACCEPTABLE,
+                "not_acceptable",
+                "Not Acceptable",
+            )
+            .detail("The response can only be sent in formats the Accept header leaves out."),
+            Error::ValidationError(errors) => Described {
+                errors: field_errors(errors),
+                ..Described::new(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "validation_failed",
+                    "Validation Failed",
+                )
+                .detail("Some fields of the request are not valid.")
+            },
+            Error::AuthError(_) => {
+                Described::new(StatusCode::UNAUTHORIZED, "unauthorized", "Unauthorized")
+                    .detail("The Authorization header cannot be read.")
             }
-            Error::TokenError(te) => match te.kind() {
-                ErrorKind::ExpiredSignature => {
-                    code = StatusCode::BAD_REQUEST;
-                    message = "Bad Requestdddd";
-                }
-                _ => {
-                    code = StatusCode::BAD_REQUEST;
-                    message = "Generation Token Error";
-                }
+            Error::Unauthorized(realm) => Described {
+                challenge: Some(format!("Basic realm=\"{}\"", realm)),
+                ..Described::new(StatusCode::UNAUTHORIZED, "unauthorized", "Unauthorized")
             },
-            _ => {
-                eprintln!("unhandled application error: {:?}", err);
-                code = StatusCode::INTERNAL_SERVER_ERROR;
-                message = "Internal Server Error";
+            Error::InvalidToken(e) => {
+                let detail = match e.kind() {
+                    ErrorKind::ExpiredSignature => "The token has expired.",
+                    _ => "The token is not valid.",
+                };
+                Described {
+                    challenge: Some(format!(
+                        "Bearer error=\"invalid_token\", error_description=\"{}\"",
+                        detail
+                    )),
+                    ..Described::new(StatusCode::UNAUTHORIZED, "invalid_token", "Invalid Token")
+                        .detail(detail)
+                }
             }
-        }
-    } else if let Some(_) = err.find::<warp::filters::body::BodyDeserializeError>() {
-        code = StatusCode::BAD_REQUEST;
-        message = "Invalid Body";
-    } else if let Some(_) = err.find::<warp::reject::MethodNotAllowed>() {
-        code = StatusCode::METHOD_NOT_ALLOWED;
-        message = "Method Not Allowed";
-    } else {
-        eprintln!("unhandled error: {:?}", err);
-        code = StatusCode::INTERNAL_SERVER_ERROR;
-        message = "Internal Server Error";
+            Error::Forbidden => Described::new(StatusCode::FORBIDDEN, "forbidden", "Forbidden"),
+            Error::ExistsError(_) => Described::new(StatusCode::CONFLICT, "conflict", "Conflict")
+                .detail("The resource already exists."),
+            Error::LastLoginMethod => Described::new(
+                StatusCode::CONFLICT,
+                "last_login_method",
+                "Last Login Method",
+            )
+            .detail("The last way left to sign in cannot be removed."),
+            Error::DirectoryError(_) => Described::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "directory_unavailable",
+                "Directory Unavailable",
+            ),
+            Error::DBQueryError(_)
+            | Error::TokenError(_)
+            | Error::HashError(_)
+            | Error::NotCompletedError(_)
+            | Error::OAuthError(..)
+            | Error::ScimError(..) => Described::internal(),
+        };
     }
+    if let Some(e) = err.find::<warp::reject::InvalidQuery>() {
+        return Described::new(StatusCode::BAD_REQUEST, "invalid_query", "Invalid Query")
+            .detail(e.to_string());
+    }
+    if let Some(e) = err.find::<warp::reject::MissingHeader>() {
+        return Described::new(StatusCode::BAD_REQUEST, "missing_header", "Missing Header")
+            .detail(e.to_string());
+    }
+    // Only rejected by the routes whose path did not match.
+    if err.find::<PathMismatch>().is_some() {
+        return Described::route_missed();
+    }
+    if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        return Described::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method_not_allowed",
+            "Method Not Allowed",
+        );
+    }
+    Described::internal()
+}
 
-    let json = warp::reply::json(&ErrorResponse {
-        message: message.into(),
-    });
-    Ok(warp::reply::with_status(json, code))
+/// Answers a rejection: errors of the OAuth and SCIM endpoints in the format
+/// of their RFC, everything else as `application/problem+json` (RFC 7807).
+pub fn rejection_response(err: Rejection, request_id: &str) -> Response {
+    if let Some(Error::OAuthError(error, description)) = err.find::<Error>() {
+        let json = warp::reply::json(&OAuthErrorResponse {
+            error: error.as_str().to_string(),
+            error_description: description.clone(),
+        });
+        return warp::reply::with_status(json, error.status()).into_response();
+    }
+    if let Some(Error::ScimError(status, scim_type, detail)) = err.find::<Error>() {
+        let json = warp::reply::json(&ScimErrorResponse {
+            schemas: vec![ERROR_SCHEMA.to_string()],
+            status: status.as_str().to_string(),
+            scim_type: scim_type.map(str::to_string),
+            detail: detail.clone(),
+        });
+        let reply = warp::reply::with_header(json, CONTENT_TYPE, SCIM_CONTENT_TYPE);
+        return warp::reply::with_status(reply, *status).into_response();
+    }
+
+    let described = describe(&err);
+    if described.status.is_server_error() {
+        error!(error = ?err, "request failed");
+    }
+    let problem = Problem {
+        kind: format!("{}{}", PROBLEM_TYPE_PREFIX, described.code),
+        title: described.title.to_string(),
+        status: described.status.as_u16(),
+        detail: described.detail,
+        code: described.code.to_string(),
+        request_id: request_id.to_string(),
+        errors: described.errors,
+    };
+    let json = warp::reply::json(&problem);
+    let reply = warp::reply::with_header(json, CONTENT_TYPE, PROBLEM_CONTENT_TYPE);
+    let mut response = warp::reply::with_status(reply, described.status).into_response();
+    if let Some(value) = described
+        .challenge
+        .and_then(|c| HeaderValue::from_str(&c).ok())
+    {
+        response.headers_mut().insert(WWW_AUTHENTICATE, value);
+    }
+    response
 }
@@ -0,0 +1,170 @@
+use std::io::ErrorKind;
+
+use secrecy::ExposeSecret;
+use url::Url;
+use uuid::Uuid;
+use warp::{http::StatusCode, reject, Rejection, Reply};
+
+use crate::config::realm::Realm;
+use crate::config::token::generate_opaque_token;
+use crate::config::{Config, DBPool};
+use crate::errors::Error::{Forbidden, InputError, NotFoundError};
+use crate::models::oauth::{
+    ClientCredentials, ClientSummary, NewClient, SUPPORTED_GRANT_TYPES, TOKEN_EXCHANGE_GRANT,
+};
+
+pub const ADMIN_ROLE: &str = "admin";
+
+/// Resolves the bearer of `token` and makes sure they administer the realm,
+/// with a token of their own: what an admin delegated to a client does not
+/// carry their rights.
+async fn require_admin(
+    realm: &Realm,
+    token: String,
+    config: &Config,
+    db_pool: &DBPool,
+) -> Result<Uuid, Rejection> {
+    let user_repo = config.user_repo(db_pool.clone(), realm).await?;
+    let id = realm.token_service().verify_user_token(token).await?.sub;
+    match user_repo.get_user_by_id(id).await? {
+        Some(user) if user.has_role(ADMIN_ROLE) => Ok(id),
+        Some(_) => Err(reject::custom(Forbidden)),
+        None => Err(realm.unauthorized()),
+    }
+}
+
+fn validate_client(client: &NewClient) -> Result<(), Rejection> {
+    let invalid = || reject::custom(InputError(ErrorKind::InvalidInput));
+    if client.name.trim().is_empty() || client.grant_types.is_empty() {
+        return Err(invalid());
+    }
+    if client
+        .grant_types
+        .iter()
+        .any(|g| !SUPPORTED_GRANT_TYPES.contains(&g.as_str()))
+    {
+        return Err(invalid());
+    }
+    if client
+        .redirect_uris
+        .iter()
+        .any(|uri| Url::parse(uri).is_err())
+    {
+        return Err(invalid());
+    }
+    // Without a secret there is nothing to authenticate a machine client with.
+    if !client.confidential
+        && client
+            .grant_types
+            .iter()
+            .any(|g| g == "client_credentials" || g == TOKEN_EXCHANGE_GRANT)
+    {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// `POST /admin/clients`: registers a client; confidential clients get their
+/// secret in the response and never again.
+pub async fn create_client(
+    realm: Realm,
+    token: String,
+    config: Config,
+    db_pool: DBPool,
+    client: NewClient,
+) -> Result<impl Reply, Rejection> {
+    require_admin(&realm, token, &config, &db_pool).await?;
+    validate_client(&client)?;
+
+    let (client_secret, secret_hash) = if client.confidential {
+        let secret = generate_opaque_token();
+        let hash = config.hash_service().hash_password(secret.clone()).await?;
+        (Some(secret), Some(hash.expose_secret().clone()))
+    } else {
+        (None, None)
+    };
+
+    let client_repo = config.client_repo(db_pool.clone(), &realm).await?;
+    let client_id = client_repo.create(&client, secret_hash.as_deref()).await?;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&ClientCredentials {
+            client_id,
+            client_secret,
+        }),
+        StatusCode::CREATED,
+    ))
+}
+
+/// `GET /admin/clients`: every client of the realm, pending registrations included.
+pub async fn list_clients(
+    realm: Realm,
+    token: String,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<impl Reply, Rejection> {
+    require_admin(&realm, token, &config, &db_pool).await?;
+    let client_repo = config.client_repo(db_pool, &realm).await?;
+    let clients: Vec<ClientSummary> = client_repo.list().await?.iter().map(Into::into).collect();
+    Ok(warp::reply::json(&clients))
+}
+
+/// `POST /admin/clients/{id}/approve`: lets a registered client be used.
+pub async fn approve_client(
+    realm: Realm,
+    token: String,
+    client_id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<impl Reply, Rejection> {
+    require_admin(&realm, token, &config, &db_pool).await?;
+    let client_repo = config.client_repo(db_pool, &realm).await?;
+    match client_repo.approve(client_id).await? {
+        Some(_) => Ok(StatusCode::NO_CONTENT),
+        None => Err(reject::custom(NotFoundError(ErrorKind::NotFound))),
+    }
+}
+
+/// `POST /admin/clients/{id}/secret`: replaces the secret of a confidential
+/// client, invalidating the previous one immediately.
+pub async fn rotate_client_secret(
+    realm: Realm,
+    token: String,
+    client_id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<impl Reply, Rejection> {
+    require_admin(&realm, token, &config, &db_pool).await?;
+
+    let secret = generate_opaque_token();
+    let hash = config.hash_service().hash_password(secret.clone()).await?;
+    let client_repo = config.client_repo(db_pool.clone(), &realm).await?;
+    match client_repo
+        .set_secret(client_id, hash.expose_secret())
+        .await?
+    {
+        Some(client_id) => Ok(warp::reply::json(&ClientCredentials {
+            client_id,
+            client_secret: Some(secret),
+        })),
+        None => Err(reject::custom(NotFoundError(ErrorKind::NotFound))),
+    }
+}
+
+#[test]
+fn test_validate_client() {
+    let client = |grant_types: &[&str], redirect_uris: &[&str], confidential| NewClient {
+        name: "Reporting".to_string(),
+        redirect_uris: redirect_uris.iter().map(|s| s.to_string()).collect(),
+        scopes: vec!["read".to_string()],
+        grant_types: grant_types.iter().map(|s| s.to_string()).collect(),
+        confidential,
+        first_party: false,
+    };
+
+    assert!(validate_client(&client(&["client_credentials"], &[], true)).is_ok());
+    assert!(validate_client(&client(&["authorization_code"], &["https://app/cb"], false)).is_ok());
+    assert!(validate_client(&client(&["client_credentials"], &[], false)).is_err());
+    assert!(validate_client(&client(&[TOKEN_EXCHANGE_GRANT], &[], false)).is_err());
+    assert!(validate_client(&client(&["password"], &[], true)).is_err());
+    assert!(validate_client(&client(&["authorization_code"], &["not a url"], false)).is_err());
+}
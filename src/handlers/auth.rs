@@ -1,12 +1,16 @@
 use crate::config::hash::HashService;
+use crate::config::ldap::LdapConfig;
 use crate::db::user::UserRepository;
+use crate::metrics::metrics;
+use crate::models::user::{ExternalProfile, LDAP_AUTH_SOURCE, LOCAL_AUTH_SOURCE};
 use crate::{
-    errors::Error::{AuthError, NotCompletedError, NotFoundError},
+    errors::Error::{AuthError, DirectoryError, NotFoundError},
     models::auth::Credentials,
 };
 use base64::decode_config;
 use std::io::Error;
 use std::io::ErrorKind;
+use tracing::{info, warn};
 use uuid::Uuid;
 use warp::reject;
 use warp::Rejection;
@@ -14,8 +18,10 @@ use warp::Rejection;
 pub async fn decode_token(base64encoded_segment: String) -> Result<String, Rejection> {
     match decode_config(base64encoded_segment, base64::STANDARD) {
         Ok(token_bits) => match String::from_utf8(token_bits) {
-            Ok(token) => return Ok(token),
-            Err(_e) => return Err(reject::custom(NotCompletedError(ErrorKind::InvalidInput))),
+            Ok(token) => Ok(token),
+            Err(_e) => Err(reject::custom(AuthError(Error::from(
+                ErrorKind::InvalidInput,
+            )))),
         },
 
         Err(_) => Err(reject::custom(AuthError(Error::from(
@@ -24,47 +30,153 @@ pub async fn decode_token(base64encoded_segment: String) -> Result<String, Rejec
     }
 }
 pub async fn decode_credentials(base64encoded_segment: String) -> Result<Credentials, Rejection> {
-    match decode_config(base64encoded_segment, base64::STANDARD) {
-        Ok(decoded_credentials) => {
-            let credentials_bytes = String::from_utf8(decoded_credentials).unwrap();
-            let credentials: Vec<&str> = credentials_bytes.splitn(2, ":").collect();
-            let username = String::from(credentials[0]);
-            let password = String::from(credentials[1]);
-
-            Ok(Credentials { username, password })
-        }
-        Err(_e) => Err(reject::custom(AuthError(Error::from(
-            ErrorKind::InvalidInput,
-        )))),
+    let invalid = || reject::custom(AuthError(Error::from(ErrorKind::InvalidInput)));
+    let decoded_credentials =
+        decode_config(base64encoded_segment, base64::STANDARD).map_err(|_e| invalid())?;
+    let credentials_bytes = String::from_utf8(decoded_credentials).map_err(|_e| invalid())?;
+    match credentials_bytes.split_once(':') {
+        Some((username, password)) => Ok(Credentials {
+            username: String::from(username),
+            password: String::from(password),
+        }),
+        None => Err(invalid()),
     }
 }
 
+/// How checking a username and password went.
+enum Checked {
+    /// The user, and what checked their password: `password` or `directory`.
+    Valid(Uuid, &'static str),
+    /// Why the credentials were refused.
+    Refused(&'static str),
+}
+
+/// Checks the credentials against the user's password or directory account,
+/// the identities signed in to with a username and password; the others are
+/// upstream accounts. A user the directory knows but we don't is provisioned
+/// on their first sign-in.
 pub async fn validate_credentials(
     credentials: &Credentials,
     user_repo: &UserRepository,
     hash_service: HashService,
+    directory: Option<&LdapConfig>,
 ) -> Result<Option<Uuid>, Rejection> {
-    println!("validate_credentials 1");
+    let checked = check_credentials(credentials, user_repo, hash_service, directory).await;
+    match &checked {
+        Ok(Checked::Valid(id, source)) => {
+            info!(user_id = %id, source, "sign-in succeeded");
+            metrics().login_succeeded(source)
+        }
+        Ok(Checked::Refused(reason)) => {
+            info!(reason, "sign-in refused");
+            metrics().login_failed(reason)
+        }
+        Err(e) => {
+            let reason = match e.find::<crate::errors::Error>() {
+                Some(NotFoundError(_)) => "unknown_user",
+                Some(DirectoryError(_)) => "directory_unavailable",
+                _ => "error",
+            };
+            info!(reason, "sign-in refused");
+            metrics().login_failed(reason)
+        }
+    }
+    checked.map(|checked| match checked {
+        Checked::Valid(id, _) => Some(id),
+        Checked::Refused(_) => None,
+    })
+}
 
-    let (password_hash, id) = match user_repo.get_password_hash(&credentials.username).await {
-        Ok(Some((pass, id))) => (pass, id),
-        Ok(None) => return Err(reject::custom(NotFoundError(ErrorKind::NotFound))),
+async fn check_credentials(
+    credentials: &Credentials,
+    user_repo: &UserRepository,
+    hash_service: HashService,
+    directory: Option<&LdapConfig>,
+) -> Result<Checked, Rejection> {
+    let stored = match user_repo.get_password_hash(&credentials.username).await {
+        Ok(Some(found)) => found,
+        Ok(None) => match directory {
+            Some(directory) => {
+                return match provision_directory_user(credentials, user_repo, directory).await? {
+                    Some(id) => Ok(Checked::Valid(id, "directory")),
+                    None => Ok(Checked::Refused("inactive")),
+                }
+            }
+            None => return Err(reject::custom(NotFoundError(ErrorKind::NotFound))),
+        },
         Err(e) => return Err(e),
     };
-    println!("validate_credentials 2");
+    let id = stored.id;
+    // Deactivated users, by a provisioning client for instance, cannot sign in.
+    if !stored.active {
+        return Ok(Checked::Refused("inactive"));
+    }
+
+    match (stored.auth_source.as_str(), directory) {
+        (LOCAL_AUTH_SOURCE, _) if stored.has_password() => {}
+        (LDAP_AUTH_SOURCE, Some(directory)) => {
+            return match directory_authenticate(credentials, directory).await? {
+                Some(profile) => {
+                    user_repo
+                        .update_external_user(id, LDAP_AUTH_SOURCE, &profile)
+                        .await?;
+                    Ok(Checked::Valid(id, "directory"))
+                }
+                None => Ok(Checked::Refused("invalid_password")),
+            };
+        }
+        // Users without a password, or of other sources, sign in otherwise.
+        _ => return Ok(Checked::Refused("no_password")),
+    }
 
     match hash_service
-        .verify_password_hash(credentials.password.clone(), password_hash)
+        .verify_password_hash(credentials.password.clone(), stored.password_hash)
         .await
     {
         Ok(valid) => {
             if valid {
-                println!("validate_credentials 3");
-                return Ok(Some(id));
+                return Ok(Checked::Valid(id, "password"));
             };
-            return Ok(None);
+            Ok(Checked::Refused("invalid_password"))
         }
-        Err(e) => return Err(e),
+        Err(e) => Err(e),
+    }
+}
+
+async fn directory_authenticate(
+    credentials: &Credentials,
+    directory: &LdapConfig,
+) -> Result<Option<ExternalProfile>, Rejection> {
+    directory
+        .authenticate(&credentials.username, &credentials.password)
+        .await
+        .map_err(|e| {
+            warn!(directory = %directory.url, error = %e, "directory unavailable");
+            reject::custom(DirectoryError(e))
+        })
+}
+
+async fn provision_directory_user(
+    credentials: &Credentials,
+    user_repo: &UserRepository,
+    directory: &LdapConfig,
+) -> Result<Option<Uuid>, Rejection> {
+    let profile = match directory_authenticate(credentials, directory).await? {
+        Some(profile) => profile,
+        None => return Err(reject::custom(NotFoundError(ErrorKind::NotFound))),
+    };
+    if let Some(id) = user_repo
+        .create_external_user(&credentials.username, LDAP_AUTH_SOURCE, &profile)
+        .await?
+    {
+        return Ok(Some(id));
+    }
+    // Provisioned by a concurrent sign-in.
+    match user_repo.get_password_hash(&credentials.username).await? {
+        Some(stored) if stored.auth_source == LDAP_AUTH_SOURCE && stored.active => {
+            Ok(Some(stored.id))
+        }
+        _ => Ok(None),
     }
 }
 
@@ -72,10 +184,13 @@ pub async fn validate_credentials(
 async fn test_decode_credentials() {
     use base64::encode_config;
     let encoded_credentials: &str = &encode_config(b"username:password", base64::STANDARD);
-    let auth_value = format!("{}", encoded_credentials);
+    let auth_value = encoded_credentials.to_string();
     assert_eq!(auth_value, "dXNlcm5hbWU6cGFzc3dvcmQ=");
 
     let decoded = decode_credentials(auth_value).await.unwrap();
     assert_eq!(decoded.username, "username");
     assert_eq!(decoded.password, "password");
+
+    let no_separator = encode_config(b"username", base64::STANDARD);
+    assert!(decode_credentials(no_separator).await.is_err());
 }
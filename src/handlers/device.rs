@@ -0,0 +1,346 @@
+use chrono::{Duration, Utc};
+use rand_core::{OsRng, RngCore};
+use std::io::{Error, ErrorKind};
+use warp::http::header::{CACHE_CONTROL, PRAGMA};
+use warp::http::Method;
+use warp::{http::StatusCode, reject, reply::Response, Rejection, Reply};
+
+use crate::config::cookie::cookie_value;
+use crate::config::realm::Realm;
+use crate::config::token::{digest, generate_opaque_token};
+use crate::config::{Config, DBPool};
+use crate::errors::Error::ExistsError;
+use crate::errors::OAuthErrorCode::{
+    AccessDenied, AuthorizationPending, ExpiredToken, InvalidGrant, InvalidRequest, InvalidScope,
+    SlowDown, UnauthorizedClient,
+};
+use crate::handlers::oauth::{
+    authenticate_client, issue_access_token, issue_refresh_token, oauth_error, token_response,
+};
+use crate::handlers::pages::{device_page, message_page, page};
+use crate::handlers::session::request_token;
+use crate::models::oauth::{
+    DeviceAuthorizationRequest, DeviceAuthorizationResponse, DeviceCode, DeviceForm,
+    DevicePageParams, OAuthClient, TokenRequest, TokenResponse, DEVICE_CODE_GRANT, REFRESH_TOKEN,
+};
+
+const DEVICE_CODE_TTL_SECONDS: i64 = 600;
+const POLL_INTERVAL_SECONDS: i32 = 5;
+/// RFC 8628 section 3.5: every `slow_down` adds five seconds to the interval.
+const SLOW_DOWN_SECONDS: i32 = 5;
+/// User codes drawn before giving up on finding one not in use.
+const USER_CODE_ATTEMPTS: usize = 5;
+/// No vowels, so user codes cannot spell words (RFC 8628 section 6.1).
+const USER_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+
+/// Eight characters shown to the user as `XXXX-XXXX`, stored without the dash.
+fn generate_user_code() -> String {
+    (0..8)
+        .map(|_| {
+            let i = OsRng.next_u32() as usize % USER_CODE_ALPHABET.len();
+            USER_CODE_ALPHABET[i] as char
+        })
+        .collect()
+}
+
+/// Users may type codes in lower case and with or without the dash.
+fn normalize_user_code(user_code: &str) -> String {
+    user_code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn display_user_code(user_code: &str) -> String {
+    let (first, second) = user_code.split_at(user_code.len() / 2);
+    format!("{}-{}", first, second)
+}
+
+/// `POST /device_authorization`: starts the device flow for clients that
+/// cannot receive a redirect, such as CLIs and TVs.
+pub async fn device_authorization(
+    realm: Realm,
+    config: Config,
+    db_pool: DBPool,
+    authorization: Option<String>,
+    request: DeviceAuthorizationRequest,
+) -> Result<Response, Rejection> {
+    let client = authenticate_client(
+        &realm,
+        &config,
+        &db_pool,
+        authorization.as_deref(),
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+    if !client.allows_grant(DEVICE_CODE_GRANT) {
+        return Err(oauth_error(
+            UnauthorizedClient,
+            "The client may not use the device flow.",
+        ));
+    }
+    let scope = match client.grant_scope(request.scope.as_deref()) {
+        Some(scope) => scope,
+        None => {
+            return Err(oauth_error(
+                InvalidScope,
+                "The requested scope is not allowed.",
+            ))
+        }
+    };
+
+    let device_code_repo = config.device_code_repo(db_pool, &realm).await?;
+    // Expired codes are kept for another lifetime, so that devices still
+    // polling learn they expired.
+    device_code_repo
+        .delete_expired(Utc::now() - Duration::seconds(DEVICE_CODE_TTL_SECONDS))
+        .await?;
+    let device_code = generate_opaque_token();
+    let mut code = DeviceCode {
+        client_id: client.id,
+        user_code: String::new(),
+        scope,
+        user_id: None,
+        approved: None,
+        interval_seconds: POLL_INTERVAL_SECONDS,
+        last_polled_at: None,
+        expires_at: Utc::now() + Duration::seconds(DEVICE_CODE_TTL_SECONDS),
+    };
+    let mut attempts = 0;
+    loop {
+        code.user_code = generate_user_code();
+        if device_code_repo
+            .create(&digest(&device_code), &code)
+            .await?
+        {
+            break;
+        }
+        attempts += 1;
+        if attempts == USER_CODE_ATTEMPTS {
+            return Err(reject::custom(ExistsError(Error::from(
+                ErrorKind::AlreadyExists,
+            ))));
+        }
+    }
+
+    let user_code = display_user_code(&code.user_code);
+    let verification_uri = format!("{}/device", realm.api_url());
+    let response = DeviceAuthorizationResponse {
+        verification_uri_complete: format!("{}?user_code={}", verification_uri, user_code),
+        verification_uri,
+        device_code,
+        user_code,
+        expires_in: DEVICE_CODE_TTL_SECONDS,
+        interval: POLL_INTERVAL_SECONDS,
+    };
+    let reply = warp::reply::with_header(warp::reply::json(&response), CACHE_CONTROL, "no-store");
+    Ok(warp::reply::with_header(reply, PRAGMA, "no-cache").into_response())
+}
+
+/// `GET /device`: the verification page, pre-filled when the user followed
+/// `verification_uri_complete`.
+pub async fn device_verification(
+    realm: Realm,
+    config: Config,
+    db_pool: DBPool,
+    cookies: Option<String>,
+    params: DevicePageParams,
+) -> Result<Response, Rejection> {
+    let csrf_token = realm
+        .cookie_session
+        .as_ref()
+        .zip(cookies.as_deref())
+        .and_then(|(settings, cookies)| cookie_value(cookies, &settings.csrf_cookie_name()));
+    let user_code = params.user_code.unwrap_or_default();
+    let device_code_repo = config.device_code_repo(db_pool.clone(), &realm).await?;
+    let pending = match normalize_user_code(&user_code) {
+        code if code.is_empty() => None,
+        code => device_code_repo.get_pending(&code).await?,
+    };
+    let client = match &pending {
+        Some(code) => {
+            config
+                .client_repo(db_pool, &realm)
+                .await?
+                .get(code.client_id)
+                .await?
+        }
+        None => None,
+    };
+    let request = pending
+        .as_ref()
+        .zip(client.as_ref())
+        .map(|(code, client)| (client.name.as_str(), code.scope.as_str()));
+    Ok(page(
+        StatusCode::OK,
+        device_page(&realm.name, &user_code, request, csrf_token, None),
+    ))
+}
+
+/// `POST /device`: the signed-in user approves or denies the device, with
+/// their bearer token or session cookie like any other request.
+pub async fn device_approval(
+    realm: Realm,
+    config: Config,
+    db_pool: DBPool,
+    authorization: Option<String>,
+    cookies: Option<String>,
+    csrf_header: Option<String>,
+    form: DeviceForm,
+) -> Result<Response, Rejection> {
+    let token = request_token(
+        &realm,
+        authorization.as_deref(),
+        cookies.as_deref(),
+        &Method::POST,
+        csrf_header.as_deref().or(form.csrf_token.as_deref()),
+        &config,
+        &db_pool,
+    )
+    .await?;
+    let user_id = realm.token_service().verify_user_token(token).await?.sub;
+    let retry = |error: &str, status: StatusCode| {
+        let html = device_page(
+            &realm.name,
+            &form.user_code,
+            None,
+            form.csrf_token.as_deref(),
+            Some(error),
+        );
+        Ok(page(status, html))
+    };
+
+    let approved = form.action == "approve";
+    let user_code = normalize_user_code(&form.user_code);
+    let device_code_repo = config.device_code_repo(db_pool.clone(), &realm).await?;
+    let pending = device_code_repo.get_pending(&user_code).await?;
+    let decided = match &pending {
+        Some(_) => {
+            device_code_repo
+                .decide(&user_code, user_id, approved)
+                .await?
+        }
+        None => false,
+    };
+    let code = match pending {
+        Some(code) if decided => code,
+        _ => {
+            return retry(
+                "The code is invalid or has expired.",
+                StatusCode::BAD_REQUEST,
+            )
+        }
+    };
+    // Approving the device is the user's consent to what it asked for.
+    if approved {
+        let scopes: Vec<String> = code.scope.split_whitespace().map(str::to_string).collect();
+        let grant_repo = config.grant_repo(db_pool, &realm).await?;
+        grant_repo.add(user_id, code.client_id, &scopes).await?;
+    }
+
+    let html = if approved {
+        message_page(
+            "Device connected",
+            "You can return to your device, it is now signed in.",
+        )
+    } else {
+        message_page("Device denied", "The device was not given access.")
+    };
+    Ok(page(StatusCode::OK, html))
+}
+
+/// `/token` with the device code grant: answers `authorization_pending`
+/// until the user decides, and `slow_down` to devices polling too often.
+pub async fn device_code_grant(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    client: &OAuthClient,
+    request: TokenRequest,
+) -> Result<Response, Rejection> {
+    let device_code_hash = match &request.device_code {
+        Some(device_code) => digest(device_code),
+        None => return Err(oauth_error(InvalidRequest, "device_code is required.")),
+    };
+
+    let device_code_repo = config.device_code_repo(db_pool.clone(), realm).await?;
+    let code = match device_code_repo.get(&device_code_hash).await? {
+        Some(code) if code.client_id == client.id => code,
+        _ => return Err(oauth_error(InvalidGrant, "The device code is invalid.")),
+    };
+    let now = Utc::now();
+    if code.expires_at < now {
+        return Err(oauth_error(ExpiredToken, "The device code has expired."));
+    }
+
+    let too_fast = code
+        .last_polled_at
+        .is_some_and(|at| now - at < Duration::seconds(code.interval_seconds.into()));
+    let interval = if too_fast {
+        code.interval_seconds + SLOW_DOWN_SECONDS
+    } else {
+        code.interval_seconds
+    };
+    device_code_repo.poll(&device_code_hash, interval).await?;
+    if too_fast {
+        return Err(oauth_error(SlowDown, "Polling too often."));
+    }
+
+    let user_id = match (code.approved, code.user_id) {
+        (None, _) => {
+            return Err(oauth_error(
+                AuthorizationPending,
+                "The user has not approved the device yet.",
+            ))
+        }
+        (Some(true), Some(user_id)) => user_id,
+        _ => return Err(oauth_error(AccessDenied, "The user denied the device.")),
+    };
+    if !device_code_repo.consume(&device_code_hash).await? {
+        return Err(oauth_error(
+            InvalidGrant,
+            "The device code was already used.",
+        ));
+    }
+
+    let (refresh_id, refresh_token) = if client.allows_grant(REFRESH_TOKEN) {
+        let (id, token) =
+            issue_refresh_token(realm, config, db_pool, client, user_id, &code.scope).await?;
+        (Some(id), Some(token))
+    } else {
+        (None, None)
+    };
+    let access_token = issue_access_token(
+        realm,
+        config,
+        db_pool,
+        client,
+        Some(user_id),
+        &code.scope,
+        refresh_id,
+    )
+    .await?;
+    Ok(token_response(&TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: realm.token_service().expires_in(),
+        scope: code.scope,
+        refresh_token,
+        id_token: None,
+        issued_token_type: None,
+    }))
+}
+
+#[test]
+fn test_user_codes() {
+    let user_code = generate_user_code();
+    assert_eq!(8, user_code.len());
+    assert!(user_code.bytes().all(|c| USER_CODE_ALPHABET.contains(&c)));
+
+    let displayed = display_user_code("BCDFGHJK");
+    assert_eq!("BCDF-GHJK", displayed);
+    assert_eq!("BCDFGHJK", normalize_user_code(&displayed));
+    assert_eq!("BCDFGHJK", normalize_user_code(" bcdf ghjk"));
+}
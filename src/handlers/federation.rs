@@ -0,0 +1,419 @@
+use std::io::ErrorKind;
+
+use chrono::{Duration, Utc};
+use tracing::warn;
+use url::Url;
+use uuid::Uuid;
+use warp::{http::StatusCode, reject, reply::Response, Rejection};
+
+use crate::config::cookie::cookie_value;
+use crate::config::federation::{IdentityProvider, UpstreamIdentity};
+use crate::config::realm::Realm;
+use crate::config::token::{digest, generate_opaque_token};
+use crate::config::{Config, DBPool};
+use crate::errors::Error::{self, NotFoundError};
+use crate::handlers::auth::validate_credentials;
+use crate::handlers::oauth::redirect_to;
+use crate::handlers::pages::{error_page, link_page, message_page, page};
+use crate::handlers::session::{signed_in, start_browser_session, with_cookies};
+use crate::models::auth::Credentials;
+use crate::models::federation::{
+    FederatedCallback, FederatedLogin, FederatedLoginParams, LinkForm,
+};
+use crate::models::session::ClientInfo;
+use crate::server::negotiation::Format;
+
+const FEDERATED_LOGIN_TTL_SECONDS: i64 = 600;
+
+pub(crate) fn identity_provider<'a>(
+    realm: &'a Realm,
+    name: &str,
+) -> Result<&'a IdentityProvider, Rejection> {
+    match realm.identity_provider(name) {
+        Some(provider) => Ok(provider),
+        None => Err(reject::custom(NotFoundError(ErrorKind::NotFound))),
+    }
+}
+
+fn callback_uri(realm: &Realm, provider: &IdentityProvider) -> String {
+    format!("{}/login/{}/callback", realm.issuer, provider.name)
+}
+
+fn state_cookie_name(provider: &IdentityProvider) -> String {
+    format!("federated_login_{}", provider.name)
+}
+
+/// `Set-Cookie` value binding a sign-in to the browser that started it: the
+/// digest of its `state`, sent back with the callback only. Without it, a
+/// callback from someone else's sign-in would sign the browser into their
+/// account, or link theirs to the user's.
+fn state_cookie(realm: &Realm, provider: &IdentityProvider, value: &str, max_age: i64) -> String {
+    let callback_uri = callback_uri(realm, provider);
+    let path = Url::parse(&callback_uri)
+        .map(|url| url.path().to_string())
+        .unwrap_or_else(|_| "/".to_string());
+    let mut cookie = format!(
+        "{}={}; Path={}; Max-Age={}; SameSite=Lax; HttpOnly",
+        state_cookie_name(provider),
+        value,
+        path,
+        max_age
+    );
+    if callback_uri.starts_with("https://") {
+        cookie.push_str("; Secure");
+    }
+    cookie
+}
+
+/// Signs the user in with our own JWT, and the session cookies in realms
+/// with cookie sessions, as `/login` does.
+pub(crate) async fn sign_in(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    user_id: Uuid,
+    client: &ClientInfo,
+    format: Format,
+) -> Result<Response, Rejection> {
+    let user_repo = config.user_repo(db_pool.clone(), realm).await?;
+    match user_repo.get_user_by_id(user_id).await? {
+        Some(user) if user.active => {}
+        _ => {
+            return Ok(page(
+                StatusCode::FORBIDDEN,
+                error_page("This account is not active."),
+            ))
+        }
+    }
+    let (token, cookies) = start_browser_session(realm, config, db_pool, user_id, client).await?;
+    Ok(with_cookies(signed_in(realm, token, format), &cookies))
+}
+
+/// The local user an upstream account belongs to: the one it was linked to,
+/// or, for providers linking by email, the only one with the same verified
+/// email, which links them.
+async fn linked_user(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    provider: &IdentityProvider,
+    identity: &UpstreamIdentity,
+) -> Result<Option<Uuid>, Rejection> {
+    let identity_repo = config.identity_repo(db_pool.clone(), realm).await?;
+    if let Some(linked) = identity_repo.get(&provider.name, &identity.sub).await? {
+        return Ok(Some(linked.user_id));
+    }
+    let email = match &identity.email {
+        Some(email) if provider.link_by_email && identity.email_verified => email,
+        _ => return Ok(None),
+    };
+    let user_repo = config.user_repo(db_pool.clone(), realm).await?;
+    let user_id = match user_repo.get_by_verified_email(email).await? {
+        Some(user_id) => user_id,
+        None => return Ok(None),
+    };
+    identity_repo
+        .link(&provider.name, &identity.sub, user_id)
+        .await?;
+    Ok(Some(user_id))
+}
+
+/// Starts a sign-in at an upstream OIDC provider with the authorization code
+/// flow and PKCE, returning where to send the user and the cookie to set for
+/// the callback; or the page to show when the provider cannot be reached. A
+/// sign-in for `link_user_id` links the upstream account to that user.
+pub(crate) async fn start_federated_login(
+    realm: &Realm,
+    provider: &IdentityProvider,
+    config: &Config,
+    db_pool: &DBPool,
+    login_hint: Option<&str>,
+    link_user_id: Option<Uuid>,
+) -> Result<Result<(Url, String), Response>, Rejection> {
+    let metadata = match provider.discover().await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            warn!(provider = %provider.name, error = %e, "identity provider discovery failed");
+            return Ok(Err(page(
+                StatusCode::BAD_GATEWAY,
+                error_page("The identity provider is unavailable."),
+            )));
+        }
+    };
+    let mut url = match Url::parse(&metadata.authorization_endpoint) {
+        Ok(url) => url,
+        Err(_) => {
+            return Ok(Err(page(
+                StatusCode::BAD_GATEWAY,
+                error_page("The identity provider is misconfigured."),
+            )))
+        }
+    };
+
+    let state = generate_opaque_token();
+    let login = FederatedLogin {
+        provider: provider.name.clone(),
+        code_verifier: generate_opaque_token(),
+        nonce: generate_opaque_token(),
+        expires_at: Utc::now() + Duration::seconds(FEDERATED_LOGIN_TTL_SECONDS),
+        link_user_id,
+    };
+    let federated_login_repo = config.federated_login_repo(db_pool.clone(), realm).await?;
+    federated_login_repo.create(&digest(&state), &login).await?;
+
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", &callback_uri(realm, provider))
+        .append_pair("scope", &provider.scope)
+        .append_pair("state", &state)
+        .append_pair("nonce", &login.nonce)
+        .append_pair("code_challenge", &digest(&login.code_verifier))
+        .append_pair("code_challenge_method", "S256");
+    if let Some(login_hint) = login_hint {
+        url.query_pairs_mut().append_pair("login_hint", login_hint);
+    }
+    let cookie = state_cookie(
+        realm,
+        provider,
+        &digest(&state),
+        FEDERATED_LOGIN_TTL_SECONDS,
+    );
+    Ok(Ok((url, cookie)))
+}
+
+/// `GET /login/{provider}`: sends the user to sign in at an upstream OIDC provider.
+pub async fn federated_login(
+    realm: Realm,
+    provider: String,
+    config: Config,
+    db_pool: DBPool,
+    params: FederatedLoginParams,
+) -> Result<Response, Rejection> {
+    let provider = identity_provider(&realm, &provider)?;
+    match start_federated_login(
+        &realm,
+        provider,
+        &config,
+        &db_pool,
+        params.login_hint.as_deref(),
+        None,
+    )
+    .await?
+    {
+        Ok((url, cookie)) => Ok(with_cookies(redirect_to(url), &[cookie])),
+        Err(page) => Ok(page),
+    }
+}
+
+/// `GET /login/{provider}/callback`: where the provider sends the user back.
+/// Redeems the code, verifies the ID token and signs the linked user in, or
+/// asks which local account to link when there is none. Only the browser
+/// that started the sign-in may finish it.
+pub async fn federated_callback(
+    realm: Realm,
+    provider: String,
+    config: Config,
+    db_pool: DBPool,
+    client: ClientInfo,
+    format: Format,
+    callback: FederatedCallback,
+) -> Result<Response, Rejection> {
+    let provider = identity_provider(&realm, &provider)?;
+    let params = callback.params;
+    if let Some(error) = &params.error {
+        let reason = params.error_description.as_deref().unwrap_or(error);
+        return Ok(page(
+            StatusCode::UNAUTHORIZED,
+            error_page(&format!(
+                "{} did not sign you in: {}",
+                provider.name, reason
+            )),
+        ));
+    }
+    let (code, state) = match (&params.code, &params.state) {
+        (Some(code), Some(state)) => (code, state),
+        _ => {
+            return Ok(page(
+                StatusCode::BAD_REQUEST,
+                error_page("The sign-in response is incomplete."),
+            ))
+        }
+    };
+    let started_here = callback
+        .cookies
+        .as_deref()
+        .and_then(|cookies| cookie_value(cookies, &state_cookie_name(provider)))
+        .is_some_and(|value| value == digest(state));
+    if !started_here {
+        return Ok(page(
+            StatusCode::BAD_REQUEST,
+            error_page("The sign-in was started in another browser, please try again."),
+        ));
+    }
+    let federated_login_repo = config.federated_login_repo(db_pool.clone(), &realm).await?;
+    let login = match federated_login_repo.consume(&digest(state)).await? {
+        Some(login) if login.provider == provider.name => login,
+        _ => {
+            return Ok(page(
+                StatusCode::BAD_REQUEST,
+                error_page("The sign-in has expired, please try again."),
+            ))
+        }
+    };
+
+    let unavailable = |e: String| {
+        warn!(provider = %provider.name, error = %e, "identity provider unavailable");
+        page(
+            StatusCode::BAD_GATEWAY,
+            error_page("The identity provider is unavailable."),
+        )
+    };
+    let metadata = match provider.discover().await {
+        Ok(metadata) => metadata,
+        Err(e) => return Ok(unavailable(e)),
+    };
+    let id_token = match provider
+        .exchange_code(
+            &metadata,
+            code,
+            &callback_uri(&realm, provider),
+            &login.code_verifier,
+        )
+        .await
+    {
+        Ok(id_token) => id_token,
+        Err(e) => return Ok(unavailable(e)),
+    };
+    let identity = match provider
+        .verify_id_token(&metadata, &id_token, &login.nonce)
+        .await
+    {
+        Ok(identity) => identity,
+        Err(e) => {
+            warn!(provider = %provider.name, error = %e, "invalid ID token from identity provider");
+            return Ok(page(
+                StatusCode::UNAUTHORIZED,
+                error_page("The identity provider's answer could not be verified."),
+            ));
+        }
+    };
+
+    let response = match login.link_user_id {
+        Some(user_id) => {
+            link_to(&realm, &config, &db_pool, provider, &identity.sub, user_id).await?
+        }
+        None => match linked_user(&realm, &config, &db_pool, provider, &identity).await? {
+            Some(user_id) => sign_in(&realm, &config, &db_pool, user_id, &client, format).await?,
+            None => {
+                let ticket = realm
+                    .token_service()
+                    .generate_link_ticket(&identity.sub, &provider.name)?;
+                page(
+                    StatusCode::OK,
+                    link_page(&realm.name, &provider.name, &ticket, None),
+                )
+            }
+        },
+    };
+    Ok(with_cookies(
+        response,
+        &[state_cookie(&realm, provider, "", 0)],
+    ))
+}
+
+/// Links the upstream account to the user who asked to, from `/me/identities`.
+async fn link_to(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    provider: &IdentityProvider,
+    subject: &str,
+    user_id: Uuid,
+) -> Result<Response, Rejection> {
+    let identity_repo = config.identity_repo(db_pool.clone(), realm).await?;
+    identity_repo.link(&provider.name, subject, user_id).await?;
+    match identity_repo.get(&provider.name, subject).await? {
+        Some(identity) if identity.user_id == user_id => Ok(page(
+            StatusCode::OK,
+            message_page(
+                "Account linked",
+                &format!("You can now sign in with {}.", provider.name),
+            ),
+        )),
+        _ => Ok(page(
+            StatusCode::CONFLICT,
+            error_page("This account is already linked to another user."),
+        )),
+    }
+}
+
+/// `POST /login/{provider}/link`: links the upstream account to the local
+/// account the user signs in to, then signs them in.
+pub async fn link_identity(
+    realm: Realm,
+    provider: String,
+    config: Config,
+    db_pool: DBPool,
+    client: ClientInfo,
+    format: Format,
+    form: LinkForm,
+) -> Result<Response, Rejection> {
+    let provider = identity_provider(&realm, &provider)?;
+    let token_service = realm.token_service();
+    let subject = match token_service.verify_link_ticket(&form.link_ticket, &provider.name) {
+        Some(subject) => subject,
+        None => {
+            return Ok(page(
+                StatusCode::BAD_REQUEST,
+                error_page("The sign-in has expired, please try again."),
+            ))
+        }
+    };
+
+    let credentials = Credentials {
+        username: form.username.clone(),
+        password: form.password.clone(),
+    };
+    let user_repo = config.user_repo(db_pool.clone(), &realm).await?;
+    let user_id = match validate_credentials(
+        &credentials,
+        &user_repo,
+        config.hash_service(),
+        realm.ldap.as_ref(),
+    )
+    .await
+    {
+        Ok(Some(id)) => Some(id),
+        Ok(None) => None,
+        Err(e) if matches!(e.find::<Error>(), Some(NotFoundError(_))) => None,
+        Err(e) => return Err(e),
+    };
+    let user_id = match user_id {
+        Some(id) => id,
+        None => {
+            let retry = link_page(
+                &realm.name,
+                &provider.name,
+                &form.link_ticket,
+                Some("Invalid username or password."),
+            );
+            return Ok(page(StatusCode::UNAUTHORIZED, retry));
+        }
+    };
+
+    let identity_repo = config.identity_repo(db_pool.clone(), &realm).await?;
+    identity_repo
+        .link(&provider.name, &subject, user_id)
+        .await?;
+    match identity_repo.get(&provider.name, &subject).await? {
+        Some(identity) if identity.user_id == user_id => {}
+        _ => {
+            return Ok(page(
+                StatusCode::CONFLICT,
+                error_page("This account is already linked to another user."),
+            ))
+        }
+    }
+    sign_in(&realm, &config, &db_pool, user_id, &client, format).await
+}
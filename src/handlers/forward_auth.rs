@@ -0,0 +1,136 @@
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use chrono::Utc;
+use warp::http::header::HeaderValue;
+use warp::http::{Method, StatusCode};
+use warp::{reject, reply::Response, Rejection, Reply};
+
+use crate::config::cookie::cookie_value;
+use crate::config::forward_auth::normalize_path;
+use crate::config::realm::Realm;
+use crate::config::token::digest;
+use crate::config::{Config, DBPool};
+use crate::errors::Error::{Forbidden, InputError};
+use crate::handlers::session::request_token;
+use crate::models::auth::{ForwardedRequest, VerifiedUser};
+
+/// The key the credentials of the request are cached under, unless the
+/// request must prove it is not forged: the CSRF token of a cookie
+/// authenticated request is checked every time.
+fn cache_key(realm: &Realm, request: &ForwardedRequest, method: &Method) -> Option<String> {
+    let credential = match (&request.authorization, &realm.cookie_session) {
+        (Some(authorization), _) => authorization.as_str(),
+        (None, Some(settings)) if method.is_safe() => {
+            cookie_value(request.cookies.as_deref()?, &settings.cookie_name)?
+        }
+        _ => return None,
+    };
+    Some(format!("{}:{}", realm.name, digest(credential)))
+}
+
+/// The signed-in user the forwarded request comes from.
+async fn verified_user(
+    realm: &Realm,
+    request: &ForwardedRequest,
+    config: &Config,
+    db_pool: &DBPool,
+) -> Result<VerifiedUser, Rejection> {
+    let method = request
+        .method
+        .as_deref()
+        .and_then(|m| Method::from_bytes(m.as_bytes()).ok())
+        .unwrap_or(Method::GET);
+    let key = cache_key(realm, request, &method);
+    if let Some(user) = key.as_deref().and_then(|key| config.verify_cache.get(key)) {
+        return Ok(user);
+    }
+
+    let token = request_token(
+        realm,
+        request.authorization.as_deref(),
+        request.cookies.as_deref(),
+        &method,
+        request.csrf_token.as_deref(),
+        config,
+        db_pool,
+    )
+    .await?;
+    let claims = match realm.token_service().verify_jwt(token).await {
+        Ok(data) => data.claims,
+        Err(_) => return Err(realm.unauthorized()),
+    };
+    // The user's own sign-in, not a token issued to a client or for another
+    // audience.
+    if claims.client_id.is_some() || claims.aud.is_some() {
+        return Err(reject::custom(Forbidden));
+    }
+    let user_repo = config.user_repo(db_pool.clone(), realm).await?;
+    let user = match user_repo.get_user_by_id(claims.sub).await? {
+        Some(user) if user.active => user,
+        _ => return Err(realm.unauthorized()),
+    };
+    let verified = VerifiedUser {
+        id: user.id,
+        email: user.email,
+        roles: user.roles,
+    };
+    if let Some(key) = key {
+        let token_lifetime = u64::try_from(claims.exp - Utc::now().timestamp()).unwrap_or(0);
+        let ttl = realm.forward_auth.cache_seconds.min(token_lifetime);
+        config
+            .verify_cache
+            .insert(key, verified.clone(), Duration::from_secs(ttl));
+    }
+    Ok(verified)
+}
+
+/// `GET /auth/verify`: asked by a reverse proxy (nginx `auth_request`,
+/// Traefik `ForwardAuth`) whether to let a request through, answers 200
+/// with the user in `X-Auth-User-Id`, `X-Auth-Email` and `X-Auth-Roles`, 401
+/// when the request is not signed in, or 403 when the path's rule wants a
+/// role the user does not have. Paths are matched as the application will
+/// resolve them; those that might resolve otherwise are refused (400).
+pub async fn verify(
+    realm: Realm,
+    request: ForwardedRequest,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<Response, Rejection> {
+    let path = request
+        .uri
+        .as_deref()
+        .map(|uri| uri.split('?').next().unwrap_or_default())
+        .unwrap_or("/");
+    let path = match normalize_path(path) {
+        Some(path) => path,
+        None => return Err(reject::custom(InputError(ErrorKind::InvalidInput))),
+    };
+    let rule = realm.forward_auth.rule(&path);
+    let user = match verified_user(&realm, &request, &config, &db_pool).await {
+        Ok(user) => user,
+        Err(_) if rule.is_some_and(|rule| rule.public) => {
+            return Ok(StatusCode::OK.into_response());
+        }
+        Err(e) => return Err(e),
+    };
+    if let Some(rule) = rule {
+        if !rule.roles.is_empty() && !rule.roles.iter().any(|role| user.roles.contains(role)) {
+            return Err(reject::custom(Forbidden));
+        }
+    }
+
+    let mut response = StatusCode::OK.into_response();
+    let headers = response.headers_mut();
+    let values = [
+        ("x-auth-user-id", user.id.to_string()),
+        ("x-auth-email", user.email),
+        ("x-auth-roles", user.roles.join(",")),
+    ];
+    for (name, value) in values {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(name, value);
+        }
+    }
+    Ok(response)
+}
@@ -0,0 +1,43 @@
+use std::io::ErrorKind;
+
+use uuid::Uuid;
+use warp::{http::StatusCode, reject, Rejection, Reply};
+
+use crate::config::realm::Realm;
+use crate::config::{Config, DBPool};
+use crate::errors::Error::NotFoundError;
+
+/// Grants are managed by users themselves, never by the clients holding them.
+async fn grant_owner(realm: &Realm, token: String) -> Result<Uuid, Rejection> {
+    Ok(realm.token_service().verify_user_token(token).await?.sub)
+}
+
+/// `GET /me/grants`: the clients the user consented to, and for which scopes.
+pub async fn list_grants(
+    realm: Realm,
+    token: String,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<impl Reply, Rejection> {
+    let user_id = grant_owner(&realm, token).await?;
+    let grant_repo = config.grant_repo(db_pool, &realm).await?;
+    Ok(warp::reply::json(&grant_repo.list(user_id).await?))
+}
+
+/// `DELETE /me/grants/{client_id}`: withdraws consent and revokes every
+/// token the client holds for the user.
+pub async fn revoke_grant(
+    realm: Realm,
+    token: String,
+    client_id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<impl Reply, Rejection> {
+    let user_id = grant_owner(&realm, token).await?;
+    let grant_repo = config.grant_repo(db_pool, &realm).await?;
+    if grant_repo.revoke(user_id, client_id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(reject::custom(NotFoundError(ErrorKind::NotFound)))
+    }
+}
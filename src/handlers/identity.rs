@@ -0,0 +1,224 @@
+use std::io::ErrorKind;
+
+use chrono::Utc;
+use uuid::Uuid;
+use warp::{http::StatusCode, reject, reply::Response, Rejection, Reply};
+
+use crate::config::realm::Realm;
+use crate::config::token::Claims;
+use crate::config::{Config, DBPool};
+use crate::db::user::UserRepository;
+use crate::errors::Error::{InputError, LastLoginMethod, NotFoundError};
+use crate::handlers::auth::validate_credentials;
+use crate::handlers::federation::{identity_provider, start_federated_login};
+use crate::handlers::session::with_cookies;
+use crate::models::auth::Credentials;
+use crate::models::federation::{
+    AddIdentityForm, LinkStarted, LoginIdentity, DIRECTORY_PROVIDER, PASSWORD_PROVIDER,
+};
+use crate::models::user::{User, LDAP_AUTH_SOURCE};
+
+/// How recently a user without a password must have signed in to link an account.
+const REAUTHENTICATION_MAX_AGE_SECONDS: i64 = 300;
+
+/// Identities are managed by users themselves, never by OAuth clients.
+async fn identity_owner(realm: &Realm, token: String) -> Result<Claims, Rejection> {
+    realm.token_service().verify_user_token(token).await
+}
+
+async fn get_user(user_repo: &UserRepository, id: Uuid, realm: &Realm) -> Result<User, Rejection> {
+    match user_repo.get_user_by_id(id).await? {
+        Some(user) if user.active => Ok(user),
+        _ => Err(realm.unauthorized()),
+    }
+}
+
+/// The ways the user signs in: their password or directory account, then
+/// the upstream accounts linked to theirs.
+async fn login_identities(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    user: &User,
+) -> Result<Vec<LoginIdentity>, Rejection> {
+    let mut identities = Vec::new();
+    let username = user.username.clone().unwrap_or_default();
+    let user_repo = config.user_repo(db_pool.clone(), realm).await?;
+    if let Some(stored) = user_repo.get_password_hash(&username).await? {
+        let provider = if stored.has_password() {
+            Some(PASSWORD_PROVIDER)
+        } else if stored.auth_source == LDAP_AUTH_SOURCE {
+            Some(DIRECTORY_PROVIDER)
+        } else {
+            None
+        };
+        if let Some(provider) = provider {
+            identities.push(LoginIdentity {
+                provider: provider.to_string(),
+                subject: username,
+                linked_at: None,
+            });
+        }
+    }
+    let identity_repo = config.identity_repo(db_pool.clone(), realm).await?;
+    for identity in identity_repo.list(user.id).await? {
+        identities.push(LoginIdentity {
+            provider: identity.provider,
+            subject: identity.subject,
+            linked_at: Some(identity.linked_at),
+        });
+    }
+    Ok(identities)
+}
+
+/// Makes the user prove who they are again: with their password, or
+/// directory account, when they have one; otherwise by having signed in
+/// moments ago.
+async fn reauthenticate(
+    realm: &Realm,
+    config: &Config,
+    user_repo: &UserRepository,
+    user: &User,
+    claims: &Claims,
+    password: Option<&str>,
+) -> Result<(), Rejection> {
+    let username = user.username.clone().unwrap_or_default();
+    let signs_in_with_password = match user_repo.get_password_hash(&username).await? {
+        Some(stored) => {
+            stored.has_password()
+                || (stored.auth_source == LDAP_AUTH_SOURCE && realm.ldap.is_some())
+        }
+        None => false,
+    };
+    if signs_in_with_password {
+        let credentials = Credentials {
+            username,
+            password: password.unwrap_or_default().to_string(),
+        };
+        return match validate_credentials(
+            &credentials,
+            user_repo,
+            config.hash_service(),
+            realm.ldap.as_ref(),
+        )
+        .await?
+        {
+            Some(id) if id == user.id => Ok(()),
+            _ => Err(realm.unauthorized()),
+        };
+    }
+    let signed_in_at = claims.iat.unwrap_or_default();
+    if Utc::now().timestamp() - signed_in_at <= REAUTHENTICATION_MAX_AGE_SECONDS {
+        Ok(())
+    } else {
+        Err(realm.unauthorized())
+    }
+}
+
+/// `GET /me/identities`
+pub async fn list_identities(
+    realm: Realm,
+    token: String,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<impl Reply, Rejection> {
+    let claims = identity_owner(&realm, token).await?;
+    let user_repo = config.user_repo(db_pool.clone(), &realm).await?;
+    let user = get_user(&user_repo, claims.sub, &realm).await?;
+    let identities = login_identities(&realm, &config, &db_pool, &user).await?;
+    Ok(warp::reply::json(&identities))
+}
+
+/// `POST /me/identities/{provider}`: after re-authentication, sets the
+/// user's password (`password`), or returns where to sign in to an
+/// upstream provider for its account to be linked to theirs.
+pub async fn add_identity(
+    realm: Realm,
+    token: String,
+    provider: String,
+    config: Config,
+    db_pool: DBPool,
+    form: AddIdentityForm,
+) -> Result<Response, Rejection> {
+    let claims = identity_owner(&realm, token).await?;
+    let user_repo = config.user_repo(db_pool.clone(), &realm).await?;
+    let user = get_user(&user_repo, claims.sub, &realm).await?;
+    if provider != PASSWORD_PROVIDER && realm.identity_provider(&provider).is_none() {
+        return Err(reject::custom(NotFoundError(ErrorKind::NotFound)));
+    }
+    reauthenticate(
+        &realm,
+        &config,
+        &user_repo,
+        &user,
+        &claims,
+        form.password.as_deref(),
+    )
+    .await?;
+
+    if provider == PASSWORD_PROVIDER {
+        let new_password = match &form.new_password {
+            Some(new_password) => new_password,
+            None => return Err(reject::custom(InputError(ErrorKind::InvalidInput))),
+        };
+        realm.password_policy.check("new_password", new_password)?;
+        let hash = config
+            .hash_service()
+            .hash_password(new_password.clone())
+            .await?;
+        user_repo.set_password(user.id, &hash).await?;
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+    let provider = identity_provider(&realm, &provider)?;
+    match start_federated_login(
+        &realm,
+        provider,
+        &config,
+        &db_pool,
+        form.login_hint.as_deref(),
+        Some(user.id),
+    )
+    .await?
+    {
+        Ok((url, cookie)) => Ok(with_cookies(
+            warp::reply::json(&LinkStarted {
+                authorization_url: url.to_string(),
+            })
+            .into_response(),
+            &[cookie],
+        )),
+        Err(page) => Ok(page),
+    }
+}
+
+/// `DELETE /me/identities/{provider}`: unlinks the user's accounts at the
+/// provider, or takes away their password, unless it is the last way they
+/// have left to sign in.
+pub async fn remove_identity(
+    realm: Realm,
+    token: String,
+    provider: String,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<impl Reply, Rejection> {
+    let claims = identity_owner(&realm, token).await?;
+    let user_repo = config.user_repo(db_pool.clone(), &realm).await?;
+    let user = get_user(&user_repo, claims.sub, &realm).await?;
+    let identities = login_identities(&realm, &config, &db_pool, &user).await?;
+    let (removed, left): (Vec<_>, Vec<_>) = identities
+        .iter()
+        .partition(|identity| identity.provider == provider);
+    if removed.is_empty() {
+        return Err(reject::custom(NotFoundError(ErrorKind::NotFound)));
+    }
+    if left.is_empty() {
+        return Err(reject::custom(LastLoginMethod));
+    }
+    if provider == PASSWORD_PROVIDER || provider == DIRECTORY_PROVIDER {
+        user_repo.remove_password(user.id).await?;
+    } else {
+        let identity_repo = config.identity_repo(db_pool, &realm).await?;
+        identity_repo.unlink(user.id, &provider).await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,151 @@
+use uuid::Uuid;
+use warp::http::header::CACHE_CONTROL;
+use warp::{http::StatusCode, reply::Response, Rejection, Reply};
+
+use crate::config::realm::Realm;
+use crate::config::token::digest;
+use crate::config::{Config, DBPool};
+use crate::errors::OAuthErrorCode::{InvalidClient, UnauthorizedClient};
+use crate::handlers::oauth::{authenticate_client, oauth_error};
+use crate::handlers::session::is_live;
+use crate::models::oauth::{IntrospectionResponse, TokenReference};
+
+/// What a presented token stands for, before the state of its user is checked.
+struct TokenInfo {
+    id: Option<Uuid>,
+    sub: Uuid,
+    user_id: Option<Uuid>,
+    exp: i64,
+    scope: Option<String>,
+    client_id: Option<Uuid>,
+    aud: Option<String>,
+}
+
+/// Resolves a JWT, which must not have been revoked nor its session signed
+/// out of, or an opaque refresh token from the token store.
+async fn lookup(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    token: &str,
+) -> Result<Option<TokenInfo>, Rejection> {
+    if let Ok(data) = realm.token_service().verify_jwt(token.to_string()).await {
+        let claims = data.claims;
+        if !is_live(realm, &claims, config, db_pool).await? {
+            return Ok(None);
+        }
+        // Client credentials tokens are issued to the client itself.
+        let user_id = match claims.client_id {
+            Some(client_id) if client_id == claims.sub => None,
+            _ => Some(claims.sub),
+        };
+        return Ok(Some(TokenInfo {
+            id: claims.jti,
+            sub: claims.sub,
+            user_id,
+            exp: claims.exp,
+            scope: claims.scope,
+            client_id: claims.client_id,
+            aud: claims.aud,
+        }));
+    }
+
+    let token_repo = config.token_repo(db_pool.clone(), realm).await?;
+    match token_repo.get_by_hash(&digest(token)).await? {
+        Some(stored) if stored.is_active() => Ok(Some(TokenInfo {
+            id: Some(stored.id),
+            sub: stored.user_id.unwrap_or(stored.client_id),
+            user_id: stored.user_id,
+            exp: stored.expires_at.timestamp(),
+            scope: Some(stored.scope),
+            client_id: Some(stored.client_id),
+            aud: None,
+        })),
+        _ => Ok(None),
+    }
+}
+
+/// `POST /introspect` (RFC 7662): lets resource servers that cannot verify
+/// our tokens ask whether one is active. Only confidential clients may ask.
+pub async fn introspect(
+    realm: Realm,
+    config: Config,
+    db_pool: DBPool,
+    authorization: Option<String>,
+    request: TokenReference,
+) -> Result<Response, Rejection> {
+    let client = authenticate_client(
+        &realm,
+        &config,
+        &db_pool,
+        authorization.as_deref(),
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+    if !client.is_confidential() {
+        return Err(oauth_error(
+            InvalidClient,
+            "Only confidential clients may introspect tokens.",
+        ));
+    }
+
+    let mut response = IntrospectionResponse::default();
+    if let Some(info) = lookup(&realm, &config, &db_pool, &request.token).await? {
+        // Tokens of suspended or deleted users are no longer active.
+        let user_active = match info.user_id {
+            Some(user_id) => {
+                let user_repo = config.user_repo(db_pool.clone(), &realm).await?;
+                matches!(user_repo.get_user_by_id(user_id).await?, Some(user) if user.active)
+            }
+            None => true,
+        };
+        if user_active {
+            response = IntrospectionResponse {
+                active: true,
+                sub: Some(info.sub),
+                exp: Some(info.exp),
+                scope: info.scope,
+                client_id: info.client_id,
+                aud: info.aud,
+            };
+        }
+    }
+    let reply = warp::reply::with_header(warp::reply::json(&response), CACHE_CONTROL, "no-store");
+    Ok(reply.into_response())
+}
+
+/// `POST /revoke` (RFC 7009): revokes an access or refresh token issued to
+/// the calling client; revoking a refresh token also revokes the access
+/// tokens issued with it. Unknown tokens are not an error.
+pub async fn revoke(
+    realm: Realm,
+    config: Config,
+    db_pool: DBPool,
+    authorization: Option<String>,
+    request: TokenReference,
+) -> Result<Response, Rejection> {
+    let client = authenticate_client(
+        &realm,
+        &config,
+        &db_pool,
+        authorization.as_deref(),
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    if let Some(info) = lookup(&realm, &config, &db_pool, &request.token).await? {
+        if info.client_id != Some(client.id) {
+            return Err(oauth_error(
+                UnauthorizedClient,
+                "The token was not issued to this client.",
+            ));
+        }
+        if let Some(id) = info.id {
+            let token_repo = config.token_repo(db_pool.clone(), &realm).await?;
+            token_repo.revoke(id).await?;
+        }
+    }
+    Ok(StatusCode::OK.into_response())
+}
@@ -1,9 +1,28 @@
+pub(crate) mod admin;
 pub(crate) mod auth;
+pub(crate) mod device;
+pub(crate) mod federation;
+pub(crate) mod forward_auth;
+pub(crate) mod grant;
+pub(crate) mod identity;
+pub(crate) mod introspection;
+pub(crate) mod oauth;
+pub(crate) mod oidc;
+pub(crate) mod openapi;
+pub(crate) mod pages;
+pub(crate) mod registration;
+pub(crate) mod saml;
+pub(crate) mod scim;
+pub(crate) mod session;
+pub(crate) mod token_exchange;
 pub(crate) mod user;
 
+use crate::config::token::digest;
 use crate::config::{Config, DBPool};
-use crate::errors::Error::{DBConnError, DBQueryError};
+use crate::errors::Error::{AuthError, DBConnError, DBQueryError};
+use crate::metrics::metrics;
 
+use std::io::ErrorKind;
 use warp::{http::StatusCode, reject, Rejection, Reply};
 
 pub async fn health_handler(db_pool: DBPool) -> std::result::Result<impl Reply, Rejection> {
@@ -16,3 +35,28 @@ pub async fn health_handler(db_pool: DBPool) -> std::result::Result<impl Reply,
         .map_err(|e| reject::custom(DBQueryError(e)))?;
     Ok(StatusCode::OK)
 }
+
+/// `GET /metrics`, in the Prometheus text format.
+pub async fn metrics_handler(
+    authorization: Option<String>,
+    config: Config,
+    db_pool: DBPool,
+) -> std::result::Result<impl Reply, Rejection> {
+    if let Some(expected) = &config.metrics_token {
+        // Compared by digest, so the time taken says nothing of the token.
+        let token = authorization
+            .as_deref()
+            .and_then(|a| a.strip_prefix("Bearer "));
+        if token.map(digest) != Some(digest(expected)) {
+            return Err(reject::custom(AuthError(
+                ErrorKind::PermissionDenied.into(),
+            )));
+        }
+    }
+    metrics().observe_pool(&db_pool).await;
+    Ok(warp::reply::with_header(
+        metrics().render(),
+        "content-type",
+        prometheus::TEXT_FORMAT,
+    ))
+}
@@ -0,0 +1,730 @@
+use chrono::{Duration, Utc};
+use percent_encoding::percent_decode_str;
+use secrecy::Secret;
+use url::Url;
+use uuid::Uuid;
+use warp::http::header::{CACHE_CONTROL, LOCATION, PRAGMA};
+use warp::{http::StatusCode, reject, reply::Response, Rejection, Reply};
+
+use crate::config::realm::Realm;
+use crate::config::token::{digest, generate_opaque_token};
+use crate::config::{Config, DBPool};
+use crate::errors::Error::{self, NotFoundError, OAuthError};
+use crate::errors::OAuthErrorCode::{
+    self, AccessDenied, InvalidClient, InvalidGrant, InvalidRequest, InvalidScope,
+    UnauthorizedClient, UnsupportedGrantType, UnsupportedResponseType,
+};
+use crate::handlers::auth::{decode_credentials, validate_credentials};
+use crate::handlers::device::device_code_grant;
+use crate::handlers::pages::{consent_page, error_page, login_page, page};
+use crate::handlers::token_exchange::token_exchange_grant;
+use crate::metrics::metrics;
+use crate::models::auth::Credentials;
+use crate::models::oauth::{
+    AuthorizationCode, AuthorizeForm, AuthorizeParams, ConsentForm, OAuthClient, StoredToken,
+    TokenRequest, TokenResponse, ACCESS_TOKEN, DEVICE_CODE_GRANT, REFRESH_TOKEN,
+    SUPPORTED_GRANT_TYPES, TOKEN_EXCHANGE_GRANT,
+};
+use crate::models::oidc::{user_info, IdTokenClaims};
+
+const AUTHORIZATION_CODE_TTL_SECONDS: i64 = 600;
+const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+pub fn oauth_error(code: OAuthErrorCode, description: &str) -> Rejection {
+    reject::custom(OAuthError(code, description.to_string()))
+}
+
+/// Why an authorization request was refused. Until the client and its
+/// redirect URI are known to be genuine the user is shown an error page;
+/// afterwards errors are reported back to the client (RFC 6749 section 4.1.2.1).
+enum AuthorizeError {
+    Page(&'static str),
+    Redirect(Url, OAuthErrorCode, &'static str),
+    Rejected(Rejection),
+}
+
+impl From<Rejection> for AuthorizeError {
+    fn from(rejection: Rejection) -> Self {
+        AuthorizeError::Rejected(rejection)
+    }
+}
+
+struct ValidRequest {
+    client: OAuthClient,
+    redirect_uri: String,
+    redirect_url: Url,
+    scope: String,
+    code_challenge: Option<String>,
+}
+
+async fn validate_request(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    params: &AuthorizeParams,
+) -> Result<ValidRequest, AuthorizeError> {
+    let client_id = match params.client_id.as_deref().map(Uuid::parse_str) {
+        Some(Ok(id)) => id,
+        _ => return Err(AuthorizeError::Page("Unknown client.")),
+    };
+    let client_repo = config.client_repo(db_pool.clone(), realm).await?;
+    let client = match client_repo.get(client_id).await? {
+        Some(client) => client,
+        None => return Err(AuthorizeError::Page("Unknown client.")),
+    };
+
+    let redirect_uri = match (&params.redirect_uri, client.redirect_uris.as_slice()) {
+        (Some(uri), registered) if registered.contains(uri) => uri.clone(),
+        (None, [only]) => only.clone(),
+        _ => return Err(AuthorizeError::Page("The redirect URI is not registered.")),
+    };
+    let redirect_url = match Url::parse(&redirect_uri) {
+        Ok(url) => url,
+        Err(_) => return Err(AuthorizeError::Page("The redirect URI is not registered.")),
+    };
+    let redirect = |code, description| {
+        Err(AuthorizeError::Redirect(
+            redirect_url.clone(),
+            code,
+            description,
+        ))
+    };
+
+    if params.response_type.as_deref() != Some("code") {
+        return redirect(
+            UnsupportedResponseType,
+            "Only the authorization code flow is supported.",
+        );
+    }
+    if !client.allows_grant("authorization_code") {
+        return redirect(
+            UnauthorizedClient,
+            "The client may not use the authorization code flow.",
+        );
+    }
+    let code_challenge = params.code_challenge.clone();
+    if code_challenge.is_none() && !client.is_confidential() {
+        return redirect(InvalidRequest, "code_challenge is required.");
+    }
+    if code_challenge.is_some() && params.code_challenge_method.as_deref() != Some("S256") {
+        return redirect(InvalidRequest, "code_challenge_method must be S256.");
+    }
+    let scope = match client.grant_scope(params.scope.as_deref()) {
+        Some(scope) => scope,
+        None => return redirect(InvalidScope, "The requested scope is not allowed."),
+    };
+
+    Ok(ValidRequest {
+        client,
+        redirect_uri,
+        redirect_url,
+        scope,
+        code_challenge,
+    })
+}
+
+pub(crate) fn redirect_to(url: Url) -> Response {
+    warp::reply::with_status(
+        warp::reply::with_header(warp::reply(), LOCATION, url.as_str()),
+        StatusCode::FOUND,
+    )
+    .into_response()
+}
+
+fn refuse(error: AuthorizeError, state: &Option<String>) -> Result<Response, Rejection> {
+    match error {
+        AuthorizeError::Page(message) => Ok(page(StatusCode::BAD_REQUEST, error_page(message))),
+        AuthorizeError::Redirect(mut url, code, description) => {
+            {
+                let mut query = url.query_pairs_mut();
+                query.append_pair("error", code.as_str());
+                query.append_pair("error_description", description);
+                if let Some(state) = state {
+                    query.append_pair("state", state);
+                }
+            }
+            Ok(redirect_to(url))
+        }
+        AuthorizeError::Rejected(rejection) => Err(rejection),
+    }
+}
+
+/// `GET /authorize`: renders the login page for a valid authorization request.
+pub async fn authorize(
+    realm: Realm,
+    config: Config,
+    db_pool: DBPool,
+    params: AuthorizeParams,
+) -> Result<Response, Rejection> {
+    match validate_request(&realm, &config, &db_pool, &params).await {
+        Ok(request) => Ok(page(
+            StatusCode::OK,
+            login_page(&realm.name, &request.client.name, &params, None),
+        )),
+        Err(e) => refuse(e, &params.state),
+    }
+}
+
+/// `POST /authorize`: checks the submitted credentials, then asks for consent
+/// or redirects back to the client with a single-use authorization code.
+pub async fn authorize_login(
+    realm: Realm,
+    config: Config,
+    db_pool: DBPool,
+    form: AuthorizeForm,
+) -> Result<Response, Rejection> {
+    let params = form.params;
+    let request = match validate_request(&realm, &config, &db_pool, &params).await {
+        Ok(request) => request,
+        Err(e) => return refuse(e, &params.state),
+    };
+
+    let credentials = Credentials {
+        username: form.username,
+        password: form.password,
+    };
+    let user_repo = config.user_repo(db_pool.clone(), &realm).await?;
+    let user_id = match validate_credentials(
+        &credentials,
+        &user_repo,
+        config.hash_service(),
+        realm.ldap.as_ref(),
+    )
+    .await
+    {
+        Ok(id) => id,
+        Err(e) if matches!(e.find::<Error>(), Some(NotFoundError(_))) => None,
+        Err(e) => return Err(e),
+    };
+    let user_id = match user_id {
+        Some(id) => id,
+        None => {
+            let html = login_page(
+                &realm.name,
+                &request.client.name,
+                &params,
+                Some("Invalid username or password."),
+            );
+            return Ok(page(StatusCode::UNAUTHORIZED, html));
+        }
+    };
+
+    consent_or_code(&realm, &config, &db_pool, request, &params, user_id).await
+}
+
+/// `POST /authorize/consent`: records the scopes the user approved and
+/// redirects with a code, or with `access_denied`.
+pub async fn authorize_consent(
+    realm: Realm,
+    config: Config,
+    db_pool: DBPool,
+    form: ConsentForm,
+) -> Result<Response, Rejection> {
+    let params = form.params;
+    let request = match validate_request(&realm, &config, &db_pool, &params).await {
+        Ok(request) => request,
+        Err(e) => return refuse(e, &params.state),
+    };
+    let user_id = match realm
+        .token_service()
+        .verify_consent_ticket(&form.consent_ticket, request.client.id)
+    {
+        Some(user_id) => user_id,
+        None => {
+            return refuse(
+                AuthorizeError::Page("Your sign-in has expired, please try again."),
+                &params.state,
+            )
+        }
+    };
+    if form.action != "approve" {
+        return refuse(
+            AuthorizeError::Redirect(
+                request.redirect_url,
+                AccessDenied,
+                "The user denied the request.",
+            ),
+            &params.state,
+        );
+    }
+
+    let scopes: Vec<String> = request
+        .scope
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    let grant_repo = config.grant_repo(db_pool.clone(), &realm).await?;
+    grant_repo.add(user_id, request.client.id, &scopes).await?;
+    issue_code(&realm, &config, &db_pool, request, &params, user_id).await
+}
+
+/// Skips the consent screen for first-party clients and for requests the
+/// user already consented to.
+async fn consent_or_code(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    request: ValidRequest,
+    params: &AuthorizeParams,
+    user_id: Uuid,
+) -> Result<Response, Rejection> {
+    if !request.client.first_party {
+        let grant_repo = config.grant_repo(db_pool.clone(), realm).await?;
+        let covered = grant_repo
+            .get(user_id, request.client.id)
+            .await?
+            .is_some_and(|grant| grant.covers(&request.scope));
+        if !covered {
+            let ticket = realm
+                .token_service()
+                .generate_consent_ticket(user_id, request.client.id)?;
+            let html = consent_page(
+                &realm.name,
+                &request.client.name,
+                &request.scope,
+                params,
+                &ticket,
+            );
+            return Ok(page(StatusCode::OK, html));
+        }
+    }
+    issue_code(realm, config, db_pool, request, params, user_id).await
+}
+
+/// Redirects back to the client with a single-use authorization code.
+async fn issue_code(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    request: ValidRequest,
+    params: &AuthorizeParams,
+    user_id: Uuid,
+) -> Result<Response, Rejection> {
+    let code = generate_opaque_token();
+    let code_repo = config
+        .authorization_code_repo(db_pool.clone(), realm)
+        .await?;
+    code_repo
+        .create(
+            &digest(&code),
+            &AuthorizationCode {
+                client_id: request.client.id,
+                user_id,
+                redirect_uri: request.redirect_uri,
+                redirect_uri_supplied: params.redirect_uri.is_some(),
+                scope: request.scope,
+                code_challenge_method: request.code_challenge.as_ref().map(|_| "S256".to_string()),
+                code_challenge: request.code_challenge,
+                nonce: params.nonce.clone(),
+                auth_time: Utc::now(),
+                expires_at: Utc::now() + Duration::seconds(AUTHORIZATION_CODE_TTL_SECONDS),
+            },
+        )
+        .await?;
+
+    let mut url = request.redirect_url;
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("code", &code);
+        if let Some(state) = &params.state {
+            query.append_pair("state", state);
+        }
+    }
+    Ok(redirect_to(url))
+}
+
+/// Authenticates the client of a token request (RFC 6749 section 2.3) with
+/// `client_secret_basic`, `client_secret_post`, or a bare `client_id` for
+/// public clients.
+pub async fn authenticate_client(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    authorization: Option<&str>,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<OAuthClient, Rejection> {
+    let failed = || oauth_error(InvalidClient, "Client authentication failed.");
+    let (client_id, client_secret) = match authorization.and_then(|a| a.strip_prefix("Basic ")) {
+        Some(encoded) => {
+            let credentials = decode_credentials(encoded.to_string())
+                .await
+                .map_err(|_| failed())?;
+            let decode = |value: &str| percent_decode_str(value).decode_utf8_lossy().into_owned();
+            (
+                decode(&credentials.username),
+                Some(decode(&credentials.password)),
+            )
+        }
+        None => (
+            client_id.unwrap_or_default().to_string(),
+            client_secret.map(str::to_string),
+        ),
+    };
+    let client_id = Uuid::parse_str(&client_id).map_err(|_| failed())?;
+    let client_repo = config.client_repo(db_pool.clone(), realm).await?;
+    let client = match client_repo.get(client_id).await? {
+        Some(client) => client,
+        None => return Err(failed()),
+    };
+
+    match (&client.secret_hash, client_secret) {
+        (None, None) => Ok(client),
+        (Some(hash), Some(secret)) => {
+            let valid = config
+                .hash_service()
+                .verify_password_hash(secret, Secret::new(hash.clone()))
+                .await?;
+            if valid {
+                Ok(client)
+            } else {
+                Err(failed())
+            }
+        }
+        _ => Err(failed()),
+    }
+}
+
+/// `POST /token`: exchanges a grant for an access token.
+pub async fn token(
+    realm: Realm,
+    config: Config,
+    db_pool: DBPool,
+    authorization: Option<String>,
+    request: TokenRequest,
+) -> Result<Response, Rejection> {
+    let grant_type = request.grant_type.clone();
+    if !SUPPORTED_GRANT_TYPES.contains(&grant_type.as_str()) {
+        return Err(oauth_error(
+            UnsupportedGrantType,
+            "The grant type is not supported.",
+        ));
+    }
+    let client = authenticate_client(
+        &realm,
+        &config,
+        &db_pool,
+        authorization.as_deref(),
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+    if !client.allows_grant(&grant_type) {
+        return Err(oauth_error(
+            UnauthorizedClient,
+            "The client may not use this grant type.",
+        ));
+    }
+
+    match grant_type.as_str() {
+        "client_credentials" => {
+            client_credentials_grant(&realm, &config, &db_pool, &client, request).await
+        }
+        "refresh_token" => refresh_token_grant(&realm, &config, &db_pool, &client, request).await,
+        DEVICE_CODE_GRANT => device_code_grant(&realm, &config, &db_pool, &client, request).await,
+        TOKEN_EXCHANGE_GRANT => {
+            token_exchange_grant(&realm, &config, &db_pool, &client, request).await
+        }
+        _ => authorization_code_grant(&realm, &config, &db_pool, &client, request).await,
+    }
+}
+
+async fn authorization_code_grant(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    client: &OAuthClient,
+    request: TokenRequest,
+) -> Result<Response, Rejection> {
+    let code = match &request.code {
+        Some(code) => code,
+        None => return Err(oauth_error(InvalidRequest, "code is required.")),
+    };
+
+    let code_repo = config
+        .authorization_code_repo(db_pool.clone(), realm)
+        .await?;
+    let grant = match code_repo.consume(&digest(code)).await? {
+        Some(grant) => grant,
+        None => {
+            return Err(oauth_error(
+                InvalidGrant,
+                "The authorization code is invalid or was already used.",
+            ))
+        }
+    };
+    // RFC 6749 section 4.1.3: required, and identical, when the
+    // authorization request included it.
+    let redirect_matches = match &request.redirect_uri {
+        Some(uri) => *uri == grant.redirect_uri,
+        None => !grant.redirect_uri_supplied,
+    };
+    if grant.client_id != client.id || !redirect_matches || grant.expires_at < Utc::now() {
+        return Err(oauth_error(
+            InvalidGrant,
+            "The authorization code was not issued for this request.",
+        ));
+    }
+    match (&grant.code_challenge, &request.code_verifier) {
+        (Some(challenge), Some(verifier)) if verify_pkce(verifier, challenge) => {}
+        (None, None) if client.is_confidential() => {}
+        _ => return Err(oauth_error(InvalidGrant, "PKCE verification failed.")),
+    }
+
+    let (refresh_id, refresh_token) = if client.allows_grant(REFRESH_TOKEN) {
+        let (id, token) =
+            issue_refresh_token(realm, config, db_pool, client, grant.user_id, &grant.scope)
+                .await?;
+        (Some(id), Some(token))
+    } else {
+        (None, None)
+    };
+    let access_token = issue_access_token(
+        realm,
+        config,
+        db_pool,
+        client,
+        Some(grant.user_id),
+        &grant.scope,
+        refresh_id,
+    )
+    .await?;
+    let id_token = if grant.scope.split_whitespace().any(|s| s == "openid") {
+        Some(id_token(realm, config, db_pool, client, &grant).await?)
+    } else {
+        None
+    };
+    Ok(token_response(&TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: realm.token_service().expires_in(),
+        scope: grant.scope,
+        refresh_token,
+        id_token,
+        issued_token_type: None,
+    }))
+}
+
+/// Trades a refresh token for new tokens, rotating the refresh token
+/// (RFC 6749 section 6). The scope may only be narrowed.
+async fn refresh_token_grant(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    client: &OAuthClient,
+    request: TokenRequest,
+) -> Result<Response, Rejection> {
+    let refresh_token = match &request.refresh_token {
+        Some(token) => token,
+        None => return Err(oauth_error(InvalidRequest, "refresh_token is required.")),
+    };
+    let invalid = || {
+        oauth_error(
+            InvalidGrant,
+            "The refresh token is invalid, expired or revoked.",
+        )
+    };
+
+    let token_repo = config.token_repo(db_pool.clone(), realm).await?;
+    let stored = match token_repo.get_by_hash(&digest(refresh_token)).await? {
+        Some(stored)
+            if stored.token_type == REFRESH_TOKEN
+                && stored.client_id == client.id
+                && stored.is_active() =>
+        {
+            stored
+        }
+        _ => return Err(invalid()),
+    };
+    let user_id = match stored.user_id {
+        Some(user_id) => user_id,
+        None => return Err(invalid()),
+    };
+    let user_repo = config.user_repo(db_pool.clone(), realm).await?;
+    match user_repo.get_user_by_id(user_id).await? {
+        Some(user) if user.active => {}
+        _ => return Err(invalid()),
+    }
+
+    let granted: Vec<&str> = stored.scope.split_whitespace().collect();
+    let scope = match request.scope.as_deref() {
+        None => stored.scope.clone(),
+        Some(requested) if requested.split_whitespace().all(|s| granted.contains(&s)) => {
+            requested.split_whitespace().collect::<Vec<_>>().join(" ")
+        }
+        Some(_) => {
+            return Err(oauth_error(
+                InvalidScope,
+                "The requested scope exceeds the original grant.",
+            ))
+        }
+    };
+    if !token_repo.consume(stored.id).await? {
+        return Err(invalid());
+    }
+
+    let (refresh_id, refresh_token) =
+        issue_refresh_token(realm, config, db_pool, client, user_id, &scope).await?;
+    let access_token = issue_access_token(
+        realm,
+        config,
+        db_pool,
+        client,
+        Some(user_id),
+        &scope,
+        Some(refresh_id),
+    )
+    .await?;
+    Ok(token_response(&TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: realm.token_service().expires_in(),
+        scope,
+        refresh_token: Some(refresh_token),
+        id_token: None,
+        issued_token_type: None,
+    }))
+}
+
+/// Machine-to-machine tokens: the client is its own subject (RFC 6749 section 4.4).
+async fn client_credentials_grant(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    client: &OAuthClient,
+    request: TokenRequest,
+) -> Result<Response, Rejection> {
+    if !client.is_confidential() {
+        return Err(oauth_error(
+            UnauthorizedClient,
+            "Only confidential clients may use client_credentials.",
+        ));
+    }
+    let scope = match client.grant_scope(request.scope.as_deref()) {
+        Some(scope) => scope,
+        None => {
+            return Err(oauth_error(
+                InvalidScope,
+                "The requested scope is not allowed.",
+            ))
+        }
+    };
+
+    let access_token =
+        issue_access_token(realm, config, db_pool, client, None, &scope, None).await?;
+    Ok(token_response(&TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: realm.token_service().expires_in(),
+        scope,
+        refresh_token: None,
+        id_token: None,
+        issued_token_type: None,
+    }))
+}
+
+/// Signs an access token and records it in the token store, so it can be
+/// introspected and revoked. Without a user the client is the subject.
+pub async fn issue_access_token(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    client: &OAuthClient,
+    user_id: Option<Uuid>,
+    scope: &str,
+    parent_id: Option<Uuid>,
+) -> Result<String, Rejection> {
+    let token_service = realm.token_service();
+    let stored = StoredToken {
+        id: Uuid::new_v4(),
+        token_type: ACCESS_TOKEN.to_string(),
+        client_id: client.id,
+        user_id,
+        scope: scope.to_string(),
+        parent_id,
+        expires_at: Utc::now() + token_service.ttl,
+        revoked_at: None,
+    };
+    let access_token = token_service
+        .generate_access_token(stored.id, user_id.unwrap_or(client.id), client.id, scope)
+        .await?;
+    let token_repo = config.token_repo(db_pool.clone(), realm).await?;
+    token_repo.create(&stored, None).await?;
+    Ok(access_token)
+}
+
+/// Refresh tokens are opaque and only their digest is stored.
+pub async fn issue_refresh_token(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    client: &OAuthClient,
+    user_id: Uuid,
+    scope: &str,
+) -> Result<(Uuid, String), Rejection> {
+    let refresh_token = generate_opaque_token();
+    let stored = StoredToken {
+        id: Uuid::new_v4(),
+        token_type: REFRESH_TOKEN.to_string(),
+        client_id: client.id,
+        user_id: Some(user_id),
+        scope: scope.to_string(),
+        parent_id: None,
+        expires_at: Utc::now() + Duration::days(REFRESH_TOKEN_TTL_DAYS),
+        revoked_at: None,
+    };
+    let token_repo = config.token_repo(db_pool.clone(), realm).await?;
+    token_repo
+        .create(&stored, Some(&digest(&refresh_token)))
+        .await?;
+    metrics().token_issued("refresh");
+    Ok((stored.id, refresh_token))
+}
+
+async fn id_token(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    client: &OAuthClient,
+    grant: &AuthorizationCode,
+) -> Result<String, Rejection> {
+    let user_repo = config.user_repo(db_pool.clone(), realm).await?;
+    let user = match user_repo.get_user_by_id(grant.user_id).await? {
+        Some(user) => user,
+        None => return Err(oauth_error(InvalidGrant, "The user no longer exists.")),
+    };
+    let token_service = realm.token_service();
+    let now = Utc::now();
+    token_service
+        .generate_id_token(&IdTokenClaims {
+            iss: token_service.issuer.clone(),
+            aud: client.id.to_string(),
+            exp: (now + token_service.ttl).timestamp(),
+            iat: now.timestamp(),
+            auth_time: grant.auth_time.timestamp(),
+            nonce: grant.nonce.clone(),
+            user: user_info(&user, &grant.scope),
+        })
+        .await
+}
+
+pub fn token_response(response: &TokenResponse) -> Response {
+    let reply = warp::reply::with_header(warp::reply::json(response), CACHE_CONTROL, "no-store");
+    warp::reply::with_header(reply, PRAGMA, "no-cache").into_response()
+}
+
+/// RFC 7636 section 4.6, S256 being the only method we accept.
+fn verify_pkce(verifier: &str, challenge: &str) -> bool {
+    (43..=128).contains(&verifier.len()) && digest(verifier) == challenge
+}
+
+#[test]
+fn test_verify_pkce() {
+    let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    let challenge = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+    assert!(verify_pkce(verifier, challenge));
+    assert!(!verify_pkce("too-short", challenge));
+    assert!(!verify_pkce(&verifier.replace('d', "e"), challenge));
+    assert!(!verify_pkce(verifier, verifier));
+}
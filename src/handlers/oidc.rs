@@ -0,0 +1,55 @@
+use warp::{Rejection, Reply};
+
+use crate::config::realm::Realm;
+use crate::config::{Config, DBPool};
+use crate::errors::OAuthErrorCode::{InsufficientScope, InvalidToken};
+use crate::handlers::oauth::oauth_error;
+use crate::models::oidc::{provider_metadata, user_info, Jwks};
+
+/// `GET /.well-known/openid-configuration`
+pub async fn discovery(realm: Realm) -> Result<impl Reply, Rejection> {
+    Ok(warp::reply::json(&provider_metadata(&realm.issuer)))
+}
+
+/// `GET /.well-known/jwks.json`: the keys ID tokens of this realm are signed with.
+pub async fn jwks(realm: Realm) -> Result<impl Reply, Rejection> {
+    let keys = realm
+        .signing_key
+        .iter()
+        .map(|key| key.jwk.clone())
+        .collect();
+    Ok(warp::reply::json(&Jwks { keys }))
+}
+
+/// `GET /userinfo`: the claims of the token's user, limited to the granted scopes.
+pub async fn userinfo(
+    realm: Realm,
+    token: String,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<impl Reply, Rejection> {
+    let claims = realm.token_service().verify_jwt(token).await?.claims;
+    if claims.aud.is_some() {
+        return Err(oauth_error(
+            InvalidToken,
+            "The access token was issued for another audience.",
+        ));
+    }
+    let scope = claims.scope.unwrap_or_default();
+    if !scope.split_whitespace().any(|s| s == "openid") {
+        return Err(oauth_error(
+            InsufficientScope,
+            "The access token was not granted the openid scope.",
+        ));
+    }
+
+    // Revoked tokens were turned away with the request.
+    let user_repo = config.user_repo(db_pool, &realm).await?;
+    match user_repo.get_user_by_id(claims.sub).await? {
+        Some(user) if user.active => Ok(warp::reply::json(&user_info(&user, &scope))),
+        _ => Err(oauth_error(
+            InvalidToken,
+            "The user is suspended or no longer exists.",
+        )),
+    }
+}
@@ -0,0 +1,20 @@
+use warp::http::StatusCode;
+use warp::{Rejection, Reply};
+
+use crate::config::realm::Realm;
+use crate::config::Config;
+use crate::handlers::pages::{page, swagger_page};
+use crate::server::openapi::document;
+
+/// `GET /openapi.json`: the API of the realm, with its current version as the server.
+pub async fn openapi(realm: Realm) -> Result<impl Reply, Rejection> {
+    Ok(warp::reply::json(&document(&realm.issuer)))
+}
+
+/// `GET /docs`: Swagger UI, when `SWAGGER_UI` is set.
+pub async fn swagger_ui(realm: Realm, config: Config) -> Result<impl Reply, Rejection> {
+    if !config.swagger_ui {
+        return Err(warp::reject::not_found());
+    }
+    Ok(page(StatusCode::OK, swagger_page(&realm.name)))
+}
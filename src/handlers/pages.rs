@@ -0,0 +1,175 @@
+use warp::http::header::{HeaderValue, CONTENT_SECURITY_POLICY, X_FRAME_OPTIONS};
+use warp::http::StatusCode;
+use warp::{reply::Response, Reply};
+
+use crate::models::oauth::AuthorizeParams;
+
+const LOGIN_PAGE: &str = include_str!("../templates/login.html");
+const ERROR_PAGE: &str = include_str!("../templates/error.html");
+const DEVICE_PAGE: &str = include_str!("../templates/device.html");
+const MESSAGE_PAGE: &str = include_str!("../templates/message.html");
+const CONSENT_PAGE: &str = include_str!("../templates/consent.html");
+const LINK_PAGE: &str = include_str!("../templates/link.html");
+const SWAGGER_PAGE: &str = include_str!("../templates/swagger.html");
+
+/// An HTML page, which no other site may frame: the forms on it could
+/// otherwise be clickjacked.
+pub fn page(status: StatusCode, html: String) -> Response {
+    let mut response = warp::reply::with_status(warp::reply::html(html), status).into_response();
+    let headers = response.headers_mut();
+    headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    headers.insert(
+        CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static("frame-ancestors 'none'"),
+    );
+    response
+}
+
+pub fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+fn hidden_fields(params: &AuthorizeParams) -> String {
+    [
+        ("response_type", &params.response_type),
+        ("client_id", &params.client_id),
+        ("redirect_uri", &params.redirect_uri),
+        ("scope", &params.scope),
+        ("state", &params.state),
+        ("code_challenge", &params.code_challenge),
+        ("code_challenge_method", &params.code_challenge_method),
+        ("nonce", &params.nonce),
+    ]
+    .iter()
+    .filter_map(|(name, value)| {
+        value.as_ref().map(|v| {
+            format!(
+                "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+                name,
+                escape(v)
+            )
+        })
+    })
+    .collect::<Vec<_>>()
+    .join("\n      ")
+}
+
+pub fn login_page(
+    realm: &str,
+    client: &str,
+    params: &AuthorizeParams,
+    error: Option<&str>,
+) -> String {
+    let error = error
+        .map(|e| format!("<p role=\"alert\">{}</p>", escape(e)))
+        .unwrap_or_default();
+    LOGIN_PAGE
+        .replace("{{realm}}", &escape(realm))
+        .replace("{{client}}", &escape(client))
+        .replace("{{error}}", &error)
+        .replace("{{hidden_fields}}", &hidden_fields(params))
+}
+
+pub fn error_page(message: &str) -> String {
+    ERROR_PAGE.replace("{{message}}", &escape(message))
+}
+
+/// Asks a signed-in user to approve the scopes a client requested. The
+/// ticket stands in for the sign-in until the form is submitted.
+pub fn consent_page(
+    realm: &str,
+    client: &str,
+    scope: &str,
+    params: &AuthorizeParams,
+    consent_ticket: &str,
+) -> String {
+    let scopes = scope
+        .split_whitespace()
+        .map(|s| format!("<li>{}</li>", escape(s)))
+        .collect::<Vec<_>>()
+        .join("\n      ");
+    let hidden_fields = format!(
+        "{}\n      <input type=\"hidden\" name=\"consent_ticket\" value=\"{}\">",
+        hidden_fields(params),
+        escape(consent_ticket)
+    );
+    CONSENT_PAGE
+        .replace("{{realm}}", &escape(realm))
+        .replace("{{client}}", &escape(client))
+        .replace("{{scopes}}", &scopes)
+        .replace("{{hidden_fields}}", &hidden_fields)
+}
+
+/// Where users enter the code shown by a device; `client` and `scope` are
+/// known once the code is.
+pub fn device_page(
+    realm: &str,
+    user_code: &str,
+    request: Option<(&str, &str)>,
+    csrf_token: Option<&str>,
+    error: Option<&str>,
+) -> String {
+    let request = request
+        .map(|(client, scope)| {
+            format!(
+                "<p>{} is requesting access to your account ({}).</p>",
+                escape(client),
+                escape(scope)
+            )
+        })
+        .unwrap_or_default();
+    let error = error
+        .map(|e| format!("<p role=\"alert\">{}</p>", escape(e)))
+        .unwrap_or_default();
+    DEVICE_PAGE
+        .replace("{{realm}}", &escape(realm))
+        .replace("{{user_code}}", &escape(user_code))
+        .replace("{{csrf_token}}", &escape(csrf_token.unwrap_or_default()))
+        .replace("{{request}}", &request)
+        .replace("{{error}}", &error)
+}
+
+/// Asks someone an upstream provider signed in to prove which local account
+/// is theirs; the ticket stands in for the upstream sign-in.
+pub fn link_page(realm: &str, provider: &str, link_ticket: &str, error: Option<&str>) -> String {
+    let error = error
+        .map(|e| format!("<p role=\"alert\">{}</p>", escape(e)))
+        .unwrap_or_default();
+    LINK_PAGE
+        .replace("{{realm}}", &escape(realm))
+        .replace("{{provider}}", &escape(provider))
+        .replace("{{link_ticket}}", &escape(link_ticket))
+        .replace("{{error}}", &error)
+}
+
+pub fn message_page(title: &str, message: &str) -> String {
+    MESSAGE_PAGE
+        .replace("{{title}}", &escape(title))
+        .replace("{{message}}", &escape(message))
+}
+
+/// Swagger UI, reading the realm's `openapi.json` next to it.
+pub fn swagger_page(realm: &str) -> String {
+    SWAGGER_PAGE.replace("{{realm}}", &escape(realm))
+}
+
+#[test]
+fn test_login_page_escapes_request_values() {
+    let params = AuthorizeParams {
+        client_id: Some("client".to_string()),
+        state: Some("\"><script>alert(1)</script>".to_string()),
+        ..Default::default()
+    };
+    let page = login_page("AuthServer", "<App>", &params, Some("Invalid password"));
+
+    assert!(page.contains("name=\"client_id\" value=\"client\""));
+    assert!(page.contains("value=\"&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt;\""));
+    assert!(page.contains("&lt;App&gt;"));
+    assert!(page.contains("Invalid password"));
+    assert!(!page.contains("redirect_uri"));
+}
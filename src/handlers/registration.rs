@@ -0,0 +1,310 @@
+use secrecy::ExposeSecret;
+use url::{Host, Url};
+use uuid::Uuid;
+use warp::{http::StatusCode, reply::Response, Rejection, Reply};
+
+use crate::config::realm::Realm;
+use crate::config::token::{digest, generate_opaque_token};
+use crate::config::{Config, DBPool};
+use crate::errors::OAuthErrorCode::{InvalidClientMetadata, InvalidRedirectUri, InvalidToken};
+use crate::handlers::oauth::oauth_error;
+use crate::models::oauth::{NewClient, OAuthClient, SUPPORTED_GRANT_TYPES, TOKEN_EXCHANGE_GRANT};
+use crate::models::oidc::SUPPORTED_SCOPES;
+use crate::models::registration::{
+    ClientMetadata, RegistrationResponse, TOKEN_ENDPOINT_AUTH_METHODS,
+};
+
+/// Redirect URIs must use https, except for native apps listening on loopback.
+fn valid_redirect_uri(uri: &str) -> bool {
+    let url = match Url::parse(uri) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+    let loopback = match url.host() {
+        Some(Host::Domain(domain)) => domain == "localhost",
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    };
+    url.fragment().is_none() && (url.scheme() == "https" || (url.scheme() == "http" && loopback))
+}
+
+fn validate_metadata(metadata: &ClientMetadata) -> Result<(), Rejection> {
+    let invalid = |description| Err(oauth_error(InvalidClientMetadata, description));
+    if metadata
+        .client_name
+        .as_deref()
+        .unwrap_or_default()
+        .trim()
+        .is_empty()
+    {
+        return invalid("client_name is required.");
+    }
+    if !TOKEN_ENDPOINT_AUTH_METHODS.contains(&metadata.token_endpoint_auth_method.as_str()) {
+        return invalid("The token_endpoint_auth_method is not supported.");
+    }
+    if metadata.grant_types.is_empty()
+        || metadata
+            .grant_types
+            .iter()
+            .any(|g| !SUPPORTED_GRANT_TYPES.contains(&g.as_str()))
+    {
+        return invalid("The grant_types are not supported.");
+    }
+    let has_grant = |grant_type: &str| metadata.grant_types.iter().any(|g| g == grant_type);
+    if (has_grant("client_credentials") || has_grant(TOKEN_EXCHANGE_GRANT))
+        && metadata.token_endpoint_auth_method == "none"
+    {
+        return invalid("client_credentials and token exchange require a client secret.");
+    }
+    let code_flow = has_grant("authorization_code");
+    if let Some(response_types) = &metadata.response_types {
+        if response_types.iter().any(|r| r == "code") != code_flow
+            || response_types.iter().any(|r| r != "code")
+        {
+            return invalid("The response_types do not match the grant_types.");
+        }
+    }
+    if let Some(scope) = &metadata.scope {
+        if scope
+            .split_whitespace()
+            .any(|s| !SUPPORTED_SCOPES.contains(&s))
+        {
+            return invalid("Registered clients may only request openid, profile and email.");
+        }
+    }
+
+    if code_flow && metadata.redirect_uris.is_empty() {
+        return Err(oauth_error(
+            InvalidRedirectUri,
+            "redirect_uris are required for the authorization code flow.",
+        ));
+    }
+    if !metadata
+        .redirect_uris
+        .iter()
+        .all(|uri| valid_redirect_uri(uri))
+    {
+        return Err(oauth_error(
+            InvalidRedirectUri,
+            "Redirect URIs must use https, or http on a loopback address, and have no fragment.",
+        ));
+    }
+    Ok(())
+}
+
+fn new_client(metadata: &ClientMetadata) -> NewClient {
+    let scopes = match &metadata.scope {
+        Some(scope) => scope.split_whitespace().map(str::to_string).collect(),
+        None => SUPPORTED_SCOPES.iter().map(|s| s.to_string()).collect(),
+    };
+    NewClient {
+        name: metadata.client_name.clone().unwrap_or_default(),
+        redirect_uris: metadata.redirect_uris.clone(),
+        scopes,
+        grant_types: metadata.grant_types.clone(),
+        confidential: metadata.token_endpoint_auth_method != "none",
+        first_party: false,
+    }
+}
+
+fn registration_response(
+    realm: &Realm,
+    client: &OAuthClient,
+    client_secret: Option<String>,
+    registration_access_token: Option<String>,
+) -> RegistrationResponse {
+    let code_flow = client.grant_types.iter().any(|g| g == "authorization_code");
+    RegistrationResponse {
+        client_id: client.id,
+        client_secret,
+        client_id_issued_at: client.created_at.timestamp(),
+        client_secret_expires_at: 0,
+        registration_access_token,
+        registration_client_uri: format!("{}/register/{}", realm.api_url(), client.id),
+        status: client.status.clone(),
+        metadata: ClientMetadata {
+            client_name: Some(client.name.clone()),
+            redirect_uris: client.redirect_uris.clone(),
+            grant_types: client.grant_types.clone(),
+            response_types: Some(if code_flow {
+                vec!["code".to_string()]
+            } else {
+                vec![]
+            }),
+            token_endpoint_auth_method: client.token_endpoint_auth_method().to_string(),
+            scope: Some(client.scopes.join(" ")),
+        },
+    }
+}
+
+/// The client a registration access token was issued for. Unknown clients
+/// get the same answer, so client ids cannot be probed (RFC 7592 section 3).
+async fn registered_client(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    client_id: Uuid,
+    token: &str,
+) -> Result<OAuthClient, Rejection> {
+    let client_repo = config.client_repo(db_pool.clone(), realm).await?;
+    match client_repo.get_registered(client_id).await? {
+        Some(client) if client.registration_token_hash.as_deref() == Some(&digest(token)) => {
+            Ok(client)
+        }
+        _ => Err(oauth_error(
+            InvalidToken,
+            "The registration access token is invalid.",
+        )),
+    }
+}
+
+/// `POST /register` (RFC 7591): registers a client that stays pending until
+/// an admin approves it.
+pub async fn register(
+    realm: Realm,
+    config: Config,
+    db_pool: DBPool,
+    metadata: ClientMetadata,
+) -> Result<Response, Rejection> {
+    validate_metadata(&metadata)?;
+    let client = new_client(&metadata);
+
+    let (client_secret, secret_hash) = if client.confidential {
+        let secret = generate_opaque_token();
+        let hash = config.hash_service().hash_password(secret.clone()).await?;
+        (Some(secret), Some(hash.expose_secret().clone()))
+    } else {
+        (None, None)
+    };
+    let registration_access_token = generate_opaque_token();
+
+    let client_repo = config.client_repo(db_pool, &realm).await?;
+    let registered = client_repo
+        .register(
+            &client,
+            secret_hash.as_deref(),
+            &metadata.token_endpoint_auth_method,
+            &digest(&registration_access_token),
+        )
+        .await?;
+    let response = registration_response(
+        &realm,
+        &registered,
+        client_secret,
+        Some(registration_access_token),
+    );
+    Ok(warp::reply::with_status(warp::reply::json(&response), StatusCode::CREATED).into_response())
+}
+
+/// `GET /register/{client_id}` (RFC 7592): the client's current configuration.
+pub async fn get_registration(
+    realm: Realm,
+    token: String,
+    client_id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<Response, Rejection> {
+    let client = registered_client(&realm, &config, &db_pool, client_id, &token).await?;
+    let response = registration_response(&realm, &client, None, None);
+    Ok(warp::reply::json(&response).into_response())
+}
+
+/// `PUT /register/{client_id}` (RFC 7592): replaces the client's metadata.
+/// Changing what the client may do sends it back for approval.
+pub async fn update_registration(
+    realm: Realm,
+    token: String,
+    client_id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+    metadata: ClientMetadata,
+) -> Result<Response, Rejection> {
+    let client = registered_client(&realm, &config, &db_pool, client_id, &token).await?;
+    validate_metadata(&metadata)?;
+    let update = new_client(&metadata);
+    if update.confidential != client.is_confidential() {
+        return Err(oauth_error(
+            InvalidClientMetadata,
+            "A client cannot switch between public and confidential.",
+        ));
+    }
+
+    let pending = update.redirect_uris != client.redirect_uris
+        || update.grant_types != client.grant_types
+        || update.scopes != client.scopes;
+    let client_repo = config.client_repo(db_pool, &realm).await?;
+    match client_repo
+        .update_registration(
+            client_id,
+            &update,
+            &metadata.token_endpoint_auth_method,
+            pending,
+        )
+        .await?
+    {
+        Some(updated) => {
+            let response = registration_response(&realm, &updated, None, None);
+            Ok(warp::reply::json(&response).into_response())
+        }
+        None => Err(oauth_error(
+            InvalidToken,
+            "The registration access token is invalid.",
+        )),
+    }
+}
+
+/// `DELETE /register/{client_id}` (RFC 7592): deletes the client along with
+/// its grants and tokens.
+pub async fn delete_registration(
+    realm: Realm,
+    token: String,
+    client_id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<Response, Rejection> {
+    registered_client(&realm, &config, &db_pool, client_id, &token).await?;
+    let client_repo = config.client_repo(db_pool, &realm).await?;
+    client_repo.delete(client_id).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[test]
+fn test_validate_metadata() {
+    let metadata =
+        |value: serde_json::Value| -> ClientMetadata { serde_json::from_value(value).unwrap() };
+
+    let web_app = metadata(serde_json::json!({
+        "client_name": "Partner",
+        "redirect_uris": ["https://partner.example/callback"]
+    }));
+    assert!(validate_metadata(&web_app).is_ok());
+    assert!(new_client(&web_app).confidential);
+    let native_app = metadata(serde_json::json!({
+        "client_name": "Partner CLI",
+        "redirect_uris": ["http://127.0.0.1:8400/callback"],
+        "token_endpoint_auth_method": "none",
+        "response_types": ["code"],
+        "scope": "openid profile"
+    }));
+    assert!(validate_metadata(&native_app).is_ok());
+    assert!(!new_client(&native_app).confidential);
+
+    for invalid in [
+        serde_json::json!({ "redirect_uris": ["https://partner.example/cb"] }),
+        serde_json::json!({ "client_name": "P", "redirect_uris": ["http://partner.example/cb"] }),
+        serde_json::json!({ "client_name": "P", "redirect_uris": ["https://partner.example/cb#x"] }),
+        serde_json::json!({ "client_name": "P" }),
+        serde_json::json!({ "client_name": "P", "grant_types": ["password"] }),
+        serde_json::json!({ "client_name": "P", "grant_types": ["client_credentials"], "token_endpoint_auth_method": "none" }),
+        serde_json::json!({ "client_name": "P", "grant_types": ["client_credentials"], "response_types": ["code"] }),
+        serde_json::json!({ "client_name": "P", "grant_types": ["client_credentials"], "token_endpoint_auth_method": "private_key_jwt" }),
+        serde_json::json!({ "client_name": "P", "grant_types": ["client_credentials"], "scope": "admin" }),
+    ] {
+        assert!(
+            validate_metadata(&metadata(invalid.clone())).is_err(),
+            "{}",
+            invalid
+        );
+    }
+}
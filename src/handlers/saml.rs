@@ -0,0 +1,163 @@
+use std::io::ErrorKind;
+
+use chrono::{Duration, Utc};
+use tracing::warn;
+use warp::{http::StatusCode, reject, reply::Response, Rejection, Reply};
+
+use crate::config::realm::Realm;
+use crate::config::saml::{SamlProvider, ServiceProvider};
+use crate::config::token::generate_opaque_token;
+use crate::config::{Config, DBPool};
+use crate::errors::Error::NotFoundError;
+use crate::handlers::federation::sign_in;
+use crate::handlers::oauth::redirect_to;
+use crate::handlers::pages::{error_page, page};
+use crate::models::saml::SamlResponseForm;
+use crate::models::session::ClientInfo;
+use crate::models::user::SAML_AUTH_SOURCE;
+use crate::server::negotiation::Format;
+
+const SAML_REQUEST_TTL_SECONDS: i64 = 600;
+
+fn saml_provider<'a>(realm: &'a Realm, name: &str) -> Result<&'a SamlProvider, Rejection> {
+    match realm.saml_provider(name) {
+        Some(provider) => Ok(provider),
+        None => Err(reject::custom(NotFoundError(ErrorKind::NotFound))),
+    }
+}
+
+/// Our side of the exchange with `provider`: an entity ID and assertion
+/// consumer service per provider, under the realm's issuer.
+fn service_provider(realm: &Realm, provider: &SamlProvider) -> ServiceProvider {
+    let base = format!("{}/saml/{}", realm.issuer, provider.name);
+    ServiceProvider {
+        entity_id: format!("{}/metadata", base),
+        acs_url: format!("{}/acs", base),
+    }
+}
+
+/// `GET /saml/{provider}/metadata`: the SP metadata to give the identity provider.
+pub async fn saml_metadata(realm: Realm, provider: String) -> Result<Response, Rejection> {
+    let provider = saml_provider(&realm, &provider)?;
+    let metadata = service_provider(&realm, provider).metadata();
+    Ok(
+        warp::reply::with_header(metadata, "content-type", "application/samlmetadata+xml")
+            .into_response(),
+    )
+}
+
+/// `GET /saml/{provider}/login`: sends the user to sign in at the identity
+/// provider with an `AuthnRequest`.
+pub async fn saml_login(
+    realm: Realm,
+    provider: String,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<Response, Rejection> {
+    let provider = saml_provider(&realm, &provider)?;
+    // IDs are xs:ID, which may not start with a digit.
+    let request_id = format!("_{}", generate_opaque_token());
+    let now = Utc::now();
+    let url =
+        match provider.authn_request_url(&service_provider(&realm, provider), &request_id, now) {
+            Ok(url) => url,
+            Err(e) => {
+                warn!(provider = %provider.name, error = %e, "SAML request failed");
+                return Ok(page(
+                    StatusCode::BAD_GATEWAY,
+                    error_page("The identity provider is misconfigured."),
+                ));
+            }
+        };
+    let saml_request_repo = config.saml_request_repo(db_pool, &realm).await?;
+    saml_request_repo
+        .create(
+            &request_id,
+            &provider.name,
+            now + Duration::seconds(SAML_REQUEST_TTL_SECONDS),
+        )
+        .await?;
+    Ok(redirect_to(url))
+}
+
+/// `POST /saml/{provider}/acs`: where the identity provider posts its answer
+/// to our request. Verifies the assertion and signs in the user it names,
+/// provisioning them on their first sign-in.
+pub async fn saml_acs(
+    realm: Realm,
+    provider: String,
+    config: Config,
+    db_pool: DBPool,
+    client: ClientInfo,
+    format: Format,
+    form: SamlResponseForm,
+) -> Result<Response, Rejection> {
+    let provider = saml_provider(&realm, &provider)?;
+    let assertion = match provider.verify_response(
+        &form.saml_response,
+        &service_provider(&realm, provider),
+        Utc::now(),
+    ) {
+        Ok(assertion) => assertion,
+        Err(e) => {
+            warn!(provider = %provider.name, error = %e, "invalid SAML response");
+            return Ok(page(
+                StatusCode::UNAUTHORIZED,
+                error_page("The identity provider's answer could not be verified."),
+            ));
+        }
+    };
+    // Only answers to our own requests, each once: unsolicited or replayed
+    // responses are refused.
+    let saml_request_repo = config.saml_request_repo(db_pool.clone(), &realm).await?;
+    let requested = match &assertion.in_response_to {
+        Some(request_id) => {
+            saml_request_repo
+                .consume(request_id, &provider.name)
+                .await?
+        }
+        None => false,
+    };
+    if !requested {
+        return Ok(page(
+            StatusCode::BAD_REQUEST,
+            error_page("The sign-in has expired, please try again."),
+        ));
+    }
+
+    let identity_repo = config.identity_repo(db_pool.clone(), &realm).await?;
+    let user_repo = config.user_repo(db_pool.clone(), &realm).await?;
+    let user_id = match identity_repo
+        .get(&provider.name, &assertion.name_id)
+        .await?
+    {
+        Some(identity) => {
+            user_repo
+                .update_external_user(identity.user_id, SAML_AUTH_SOURCE, &assertion.profile)
+                .await?;
+            identity.user_id
+        }
+        None => {
+            let user_id = match user_repo
+                .create_external_user(&assertion.username, SAML_AUTH_SOURCE, &assertion.profile)
+                .await?
+            {
+                Some(user_id) => user_id,
+                None => {
+                    return Ok(page(
+                        StatusCode::CONFLICT,
+                        error_page(&format!(
+                            "Another account is already named {}.",
+                            assertion.username
+                        )),
+                    ))
+                }
+            };
+            identity_repo
+                .link(&provider.name, &assertion.name_id, user_id)
+                .await?;
+            user_id
+        }
+    };
+    sign_in(&realm, &config, &db_pool, user_id, &client, format).await
+}
@@ -0,0 +1,544 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use uuid::Uuid;
+use warp::http::header::{CONTENT_TYPE, LOCATION};
+use warp::{http::StatusCode, reject, reply::Response, Rejection, Reply};
+
+use crate::config::realm::Realm;
+use crate::config::{Config, DBPool};
+use crate::db::group::GroupRepository;
+use crate::db::user::UserRepository;
+use crate::errors::Error::{ExistsError, ScimError};
+use crate::models::scim::{
+    apply_patch, equality_filter, Group, ListParams, ListResponse, Meta, PatchRequest, ScimEmail,
+    ScimGroup, ScimMember, ScimName, ScimUser, GROUP_SCHEMA, SCIM_CONTENT_TYPE, USER_SCHEMA,
+};
+use crate::models::user::{ProvisionedUser, User};
+
+/// The scope a client credentials token needs to provision users.
+pub const SCIM_SCOPE: &str = "scim";
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 500;
+
+pub fn scim_error(status: StatusCode, scim_type: Option<&'static str>, detail: &str) -> Rejection {
+    reject::custom(ScimError(status, scim_type, detail.to_string()))
+}
+
+fn not_found() -> Rejection {
+    scim_error(StatusCode::NOT_FOUND, None, "Resource not found.")
+}
+
+fn is_conflict(rejection: &Rejection) -> bool {
+    matches!(rejection.find(), Some(ExistsError(_)))
+}
+
+fn scim_json<T: Serialize>(resource: &T, status: StatusCode) -> Response {
+    let reply =
+        warp::reply::with_header(warp::reply::json(resource), CONTENT_TYPE, SCIM_CONTENT_TYPE);
+    warp::reply::with_status(reply, status).into_response()
+}
+
+fn created<T: Serialize>(resource: &T, location: &str) -> Response {
+    let reply = scim_json(resource, StatusCode::CREATED);
+    warp::reply::with_header(reply, LOCATION, location).into_response()
+}
+
+/// Only provisioning clients: a client credentials token granted the `scim`
+/// scope, still in the token store.
+async fn authenticate(
+    realm: &Realm,
+    token: String,
+    config: &Config,
+    db_pool: &DBPool,
+) -> Result<(), Rejection> {
+    let invalid = || {
+        scim_error(
+            StatusCode::UNAUTHORIZED,
+            None,
+            "The access token is invalid, expired or revoked.",
+        )
+    };
+    let claims = match realm.token_service().verify_jwt(token).await {
+        Ok(data) => data.claims,
+        Err(_) => return Err(invalid()),
+    };
+    // The client acts for itself, not for a user, nor for another audience.
+    if claims.client_id != Some(claims.sub) || claims.aud.is_some() {
+        return Err(scim_error(
+            StatusCode::FORBIDDEN,
+            None,
+            "Only provisioning clients may use this endpoint.",
+        ));
+    }
+    let scope = claims.scope.unwrap_or_default();
+    if !scope.split_whitespace().any(|s| s == SCIM_SCOPE) {
+        return Err(scim_error(
+            StatusCode::FORBIDDEN,
+            None,
+            "The access token was not granted the scim scope.",
+        ));
+    }
+    let token_repo = config.token_repo(db_pool.clone(), realm).await?;
+    match claims.jti {
+        Some(jti) => match token_repo.get(jti).await? {
+            Some(stored) if stored.is_active() => Ok(()),
+            _ => Err(invalid()),
+        },
+        None => Err(invalid()),
+    }
+}
+
+/// The filter value of a list request, and its page as an offset and a limit.
+fn page(
+    params: &ListParams,
+    attribute: &str,
+) -> Result<(Option<String>, i64, i64, i64), Rejection> {
+    let filter = match &params.filter {
+        Some(filter) => match equality_filter(filter, attribute) {
+            Some(value) => Some(value),
+            None => {
+                return Err(scim_error(
+                    StatusCode::BAD_REQUEST,
+                    Some("invalidFilter"),
+                    &format!("Only `{} eq \"...\"` filters are supported.", attribute),
+                ))
+            }
+        },
+        None => None,
+    };
+    let start_index = params.start_index.unwrap_or(1).max(1);
+    let count = params
+        .count
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(0, MAX_PAGE_SIZE);
+    Ok((filter, start_index, start_index - 1, count))
+}
+
+fn location(realm: &Realm, resource_type: &str, id: Uuid) -> String {
+    format!("{}/scim/v2/{}s/{}", realm.api_url(), resource_type, id)
+}
+
+fn user_resource(realm: &Realm, user: &User) -> ScimUser {
+    ScimUser {
+        schemas: vec![USER_SCHEMA.to_string()],
+        id: Some(user.id.to_string()),
+        user_name: user.username.clone().unwrap_or_default(),
+        name: user.full_name.as_ref().map(|full_name| ScimName {
+            formatted: Some(full_name.clone()),
+            ..ScimName::default()
+        }),
+        display_name: user.full_name.clone(),
+        emails: match user.email.as_str() {
+            "" => vec![],
+            email => vec![ScimEmail {
+                value: email.to_string(),
+                // Users have one email: the work one identity providers patch.
+                kind: Some("work".to_string()),
+                primary: Some(true),
+            }],
+        },
+        active: user.active,
+        password: None,
+        meta: Some(Meta {
+            resource_type: "User".to_string(),
+            location: location(realm, "User", user.id),
+            created: None,
+            last_modified: None,
+        }),
+    }
+}
+
+/// What the resource sets on a user whose full name is `current_full_name`,
+/// the password hashed.
+async fn provisioned_user(
+    realm: &Realm,
+    config: &Config,
+    resource: &ScimUser,
+    current_full_name: Option<&str>,
+) -> Result<ProvisionedUser, Rejection> {
+    let username = resource.user_name.trim();
+    if username.is_empty() {
+        return Err(scim_error(
+            StatusCode::BAD_REQUEST,
+            Some("invalidValue"),
+            "userName is required.",
+        ));
+    }
+    let password_hash = match &resource.password {
+        Some(password) => {
+            if realm.password_policy.check("password", password).is_err() {
+                return Err(scim_error(
+                    StatusCode::BAD_REQUEST,
+                    Some("invalidValue"),
+                    "The password does not meet the realm's password policy.",
+                ));
+            }
+            Some(
+                config
+                    .hash_service()
+                    .hash_password(password.clone())
+                    .await?,
+            )
+        }
+        None => None,
+    };
+    Ok(ProvisionedUser {
+        username: username.to_string(),
+        email: resource.email().unwrap_or_default().to_string(),
+        full_name: resource.full_name(current_full_name),
+        active: resource.active,
+        password_hash,
+    })
+}
+
+async fn get_user(user_repo: &UserRepository, id: Uuid) -> Result<User, Rejection> {
+    match user_repo.get_user_by_id(id).await? {
+        Some(user) => Ok(user),
+        None => Err(not_found()),
+    }
+}
+
+fn username_taken(username: &str) -> Rejection {
+    scim_error(
+        StatusCode::CONFLICT,
+        Some("uniqueness"),
+        &format!("A user is already named {}.", username),
+    )
+}
+
+/// Replaces the user `id` with what `resource` sets, answering the result.
+async fn replace_user(
+    realm: &Realm,
+    config: &Config,
+    user_repo: &UserRepository,
+    id: Uuid,
+    resource: &ScimUser,
+) -> Result<Response, Rejection> {
+    let current = get_user(user_repo, id).await?;
+    let provisioned =
+        provisioned_user(realm, config, resource, current.full_name.as_deref()).await?;
+    match user_repo.replace_provisioned(id, &provisioned).await {
+        Ok(true) => {}
+        Ok(false) => return Err(not_found()),
+        Err(e) if is_conflict(&e) => return Err(username_taken(&provisioned.username)),
+        Err(e) => return Err(e),
+    }
+    let user = get_user(user_repo, id).await?;
+    Ok(scim_json(&user_resource(realm, &user), StatusCode::OK))
+}
+
+/// `GET /scim/v2/Users`, optionally filtered with `userName eq "..."`.
+pub async fn scim_list_users(
+    realm: Realm,
+    token: String,
+    config: Config,
+    db_pool: DBPool,
+    params: ListParams,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let (username, start_index, offset, limit) = page(&params, "userName")?;
+    let user_repo = config.user_repo(db_pool, &realm).await?;
+    let (total, users) = user_repo.list(username.as_deref(), offset, limit).await?;
+    let resources = users
+        .iter()
+        .map(|user| user_resource(&realm, user))
+        .collect();
+    Ok(scim_json(
+        &ListResponse::new(total, start_index, resources),
+        StatusCode::OK,
+    ))
+}
+
+/// `POST /scim/v2/Users`
+pub async fn scim_create_user(
+    realm: Realm,
+    token: String,
+    config: Config,
+    db_pool: DBPool,
+    resource: ScimUser,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let provisioned = provisioned_user(&realm, &config, &resource, None).await?;
+    let user_repo = config.user_repo(db_pool, &realm).await?;
+    let id = match user_repo.create_provisioned(&provisioned).await? {
+        Some(id) => id,
+        None => return Err(username_taken(&provisioned.username)),
+    };
+    let user = get_user(&user_repo, id).await?;
+    Ok(created(
+        &user_resource(&realm, &user),
+        &location(&realm, "User", id),
+    ))
+}
+
+/// `GET /scim/v2/Users/{id}`
+pub async fn scim_get_user(
+    realm: Realm,
+    token: String,
+    id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let user_repo = config.user_repo(db_pool, &realm).await?;
+    let user = get_user(&user_repo, id).await?;
+    Ok(scim_json(&user_resource(&realm, &user), StatusCode::OK))
+}
+
+/// `PUT /scim/v2/Users/{id}`: replaces the user. A password, if given,
+/// makes them a `local` user.
+pub async fn scim_replace_user(
+    realm: Realm,
+    token: String,
+    id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+    resource: ScimUser,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let user_repo = config.user_repo(db_pool, &realm).await?;
+    replace_user(&realm, &config, &user_repo, id, &resource).await
+}
+
+/// `PATCH /scim/v2/Users/{id}`: applies the operations to the user as
+/// `GET` shows them, then replaces them with the result. Setting `active`
+/// to false is how identity providers suspend users.
+pub async fn scim_patch_user(
+    realm: Realm,
+    token: String,
+    id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+    patch: PatchRequest,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let user_repo = config.user_repo(db_pool, &realm).await?;
+    let user = get_user(&user_repo, id).await?;
+    let resource = patched(&user_resource(&realm, &user), &patch, USER_SCHEMA)?;
+    replace_user(&realm, &config, &user_repo, id, &resource).await
+}
+
+/// The resource with the operations of `patch` applied.
+fn patched<T: Serialize + serde::de::DeserializeOwned>(
+    resource: &T,
+    patch: &PatchRequest,
+    schema: &str,
+) -> Result<T, Rejection> {
+    let invalid = |e: String| scim_error(StatusCode::BAD_REQUEST, Some("invalidValue"), &e);
+    let mut json = serde_json::to_value(resource).map_err(|e| invalid(e.to_string()))?;
+    for operation in &patch.operations {
+        apply_patch(&mut json, operation, schema).map_err(invalid)?;
+    }
+    serde_json::from_value(json).map_err(|e| invalid(e.to_string()))
+}
+
+/// `DELETE /scim/v2/Users/{id}`: deprovisions the user.
+pub async fn scim_delete_user(
+    realm: Realm,
+    token: String,
+    id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let user_repo = config.user_repo(db_pool, &realm).await?;
+    match user_repo.delete(id).await? {
+        Some(_) => Ok(StatusCode::NO_CONTENT.into_response()),
+        None => Err(not_found()),
+    }
+}
+
+fn group_resource(realm: &Realm, group: &Group) -> ScimGroup {
+    ScimGroup {
+        schemas: vec![GROUP_SCHEMA.to_string()],
+        id: Some(group.id.to_string()),
+        display_name: group.display_name.clone(),
+        members: group
+            .members
+            .iter()
+            .map(|member| ScimMember {
+                value: member.user_id.to_string(),
+                display: member.username.clone(),
+            })
+            .collect(),
+        meta: Some(Meta {
+            resource_type: "Group".to_string(),
+            location: location(realm, "Group", group.id),
+            created: Some(group.created_at),
+            last_modified: Some(group.updated_at),
+        }),
+    }
+}
+
+async fn get_group(group_repo: &GroupRepository, id: Uuid) -> Result<Group, Rejection> {
+    match group_repo.get(id).await? {
+        Some(group) => Ok(group),
+        None => Err(not_found()),
+    }
+}
+
+fn group_name_taken(display_name: &str) -> Rejection {
+    scim_error(
+        StatusCode::CONFLICT,
+        Some("uniqueness"),
+        &format!("A group is already named {}.", display_name),
+    )
+}
+
+/// The user ids of the members of `resource`.
+fn member_ids(resource: &ScimGroup) -> Result<HashSet<Uuid>, Rejection> {
+    resource
+        .members
+        .iter()
+        .map(|member| {
+            Uuid::parse_str(&member.value).map_err(|_| {
+                scim_error(
+                    StatusCode::BAD_REQUEST,
+                    Some("invalidValue"),
+                    &format!("No user has the id {}.", member.value),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Makes the group `current` what `resource` describes, answering the result.
+async fn replace_group(
+    realm: &Realm,
+    group_repo: &GroupRepository,
+    current: Group,
+    resource: &ScimGroup,
+) -> Result<Response, Rejection> {
+    let members = member_ids(resource)?;
+    if resource.display_name != current.display_name {
+        match group_repo.rename(current.id, &resource.display_name).await {
+            Ok(true) => {}
+            Ok(false) => return Err(not_found()),
+            Err(e) if is_conflict(&e) => return Err(group_name_taken(&resource.display_name)),
+            Err(e) => return Err(e),
+        }
+    }
+    let current_members: HashSet<Uuid> = current.members.iter().map(|m| m.user_id).collect();
+    let added: Vec<Uuid> = members.difference(&current_members).copied().collect();
+    let removed: Vec<Uuid> = current_members.difference(&members).copied().collect();
+    if !added.is_empty() {
+        group_repo.add_members(current.id, &added).await?;
+    }
+    if !removed.is_empty() {
+        group_repo.remove_members(current.id, &removed).await?;
+    }
+    let group = get_group(group_repo, current.id).await?;
+    Ok(scim_json(&group_resource(realm, &group), StatusCode::OK))
+}
+
+/// `GET /scim/v2/Groups`, optionally filtered with `displayName eq "..."`.
+pub async fn scim_list_groups(
+    realm: Realm,
+    token: String,
+    config: Config,
+    db_pool: DBPool,
+    params: ListParams,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let (display_name, start_index, offset, limit) = page(&params, "displayName")?;
+    let group_repo = config.group_repo(db_pool, &realm).await?;
+    let (total, groups) = group_repo
+        .list(display_name.as_deref(), offset, limit)
+        .await?;
+    let resources = groups
+        .iter()
+        .map(|group| group_resource(&realm, group))
+        .collect();
+    Ok(scim_json(
+        &ListResponse::new(total, start_index, resources),
+        StatusCode::OK,
+    ))
+}
+
+/// `POST /scim/v2/Groups`. Members that are not users of the realm are left out.
+pub async fn scim_create_group(
+    realm: Realm,
+    token: String,
+    config: Config,
+    db_pool: DBPool,
+    resource: ScimGroup,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let members: Vec<Uuid> = member_ids(&resource)?.into_iter().collect();
+    let group_repo = config.group_repo(db_pool, &realm).await?;
+    let id = match group_repo.create(&resource.display_name).await? {
+        Some(id) => id,
+        None => return Err(group_name_taken(&resource.display_name)),
+    };
+    if !members.is_empty() {
+        group_repo.add_members(id, &members).await?;
+    }
+    let group = get_group(&group_repo, id).await?;
+    Ok(created(
+        &group_resource(&realm, &group),
+        &location(&realm, "Group", id),
+    ))
+}
+
+/// `GET /scim/v2/Groups/{id}`
+pub async fn scim_get_group(
+    realm: Realm,
+    token: String,
+    id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let group_repo = config.group_repo(db_pool, &realm).await?;
+    let group = get_group(&group_repo, id).await?;
+    Ok(scim_json(&group_resource(&realm, &group), StatusCode::OK))
+}
+
+/// `PUT /scim/v2/Groups/{id}`: renames the group and replaces its members.
+pub async fn scim_replace_group(
+    realm: Realm,
+    token: String,
+    id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+    resource: ScimGroup,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let group_repo = config.group_repo(db_pool, &realm).await?;
+    let current = get_group(&group_repo, id).await?;
+    replace_group(&realm, &group_repo, current, &resource).await
+}
+
+/// `PATCH /scim/v2/Groups/{id}`: usually adds or removes members.
+pub async fn scim_patch_group(
+    realm: Realm,
+    token: String,
+    id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+    patch: PatchRequest,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let group_repo = config.group_repo(db_pool, &realm).await?;
+    let current = get_group(&group_repo, id).await?;
+    let resource = patched(&group_resource(&realm, &current), &patch, GROUP_SCHEMA)?;
+    replace_group(&realm, &group_repo, current, &resource).await
+}
+
+/// `DELETE /scim/v2/Groups/{id}`
+pub async fn scim_delete_group(
+    realm: Realm,
+    token: String,
+    id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<Response, Rejection> {
+    authenticate(&realm, token, &config, &db_pool).await?;
+    let group_repo = config.group_repo(db_pool, &realm).await?;
+    if group_repo.delete(id).await? {
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Err(not_found())
+    }
+}
@@ -0,0 +1,285 @@
+use std::io::ErrorKind;
+
+use chrono::Utc;
+use uuid::Uuid;
+use warp::http::header::{HeaderValue, CACHE_CONTROL, SET_COOKIE};
+use warp::http::{Method, StatusCode};
+use warp::{reject, reply::Response, Rejection, Reply};
+
+use crate::config::cookie::cookie_value;
+use crate::config::realm::Realm;
+use crate::config::token::{digest, generate_opaque_token, Claims};
+use crate::config::{Config, DBPool};
+use crate::db::session::SessionRepository;
+use crate::errors::Error::{Forbidden, NotFoundError};
+use crate::handlers::auth::decode_token;
+use crate::models::session::{ClientInfo, SignInResponse};
+use crate::server::negotiation::Format;
+
+async fn create_session(
+    realm: &Realm,
+    session_repo: &SessionRepository,
+    user_id: Uuid,
+    client: &ClientInfo,
+) -> Result<(Uuid, String), Rejection> {
+    let token_service = realm.token_service();
+    let session_id = session_repo
+        .create(user_id, client, Utc::now() + token_service.ttl)
+        .await?;
+    let token = token_service.generate_jwt(user_id, session_id).await?;
+    Ok((session_id, token))
+}
+
+/// Signs the user in: records a session for the client and returns the
+/// user's token, bound to it.
+pub(crate) async fn start_session(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    user_id: Uuid,
+    client: &ClientInfo,
+) -> Result<String, Rejection> {
+    let session_repo = config.session_repo(db_pool.clone(), realm).await?;
+    let (_, token) = create_session(realm, &session_repo, user_id, client).await?;
+    Ok(token)
+}
+
+/// The answer to a sign-in: the token as JSON, or bare to clients that ask
+/// for `text/plain`.
+pub(crate) fn signed_in(realm: &Realm, token: String, format: Format) -> Response {
+    match format {
+        Format::Text => token.into_response(),
+        Format::Json => {
+            let body = SignInResponse {
+                access_token: token,
+                token_type: "Bearer".to_string(),
+                expires_in: realm.token_service().expires_in(),
+            };
+            warp::reply::with_header(warp::reply::json(&body), CACHE_CONTROL, "no-store")
+                .into_response()
+        }
+    }
+}
+
+/// Signs the user in to a browser: as `start_session`, and in realms with
+/// cookie sessions, also returns the `Set-Cookie` values the browser is
+/// authenticated with from then on.
+pub(crate) async fn start_browser_session(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    user_id: Uuid,
+    client: &ClientInfo,
+) -> Result<(String, Vec<String>), Rejection> {
+    let session_repo = config.session_repo(db_pool.clone(), realm).await?;
+    let (session_id, token) = create_session(realm, &session_repo, user_id, client).await?;
+    let settings = match &realm.cookie_session {
+        Some(settings) => settings,
+        None => return Ok((token, Vec::new())),
+    };
+    let secret = generate_opaque_token();
+    let csrf_token = generate_opaque_token();
+    session_repo
+        .set_cookie(session_id, &digest(&secret), &csrf_token)
+        .await?;
+    let cookies = settings.set_cookies(&secret, &csrf_token, realm.access_token_ttl_seconds);
+    Ok((token, cookies.to_vec()))
+}
+
+/// Adds `Set-Cookie` headers to the response.
+pub(crate) fn with_cookies(mut response: Response, cookies: &[String]) -> Response {
+    for cookie in cookies {
+        if let Ok(value) = HeaderValue::from_str(cookie) {
+            response.headers_mut().append(SET_COOKIE, value);
+        }
+    }
+    response
+}
+
+/// The token of the browser session named by the request's cookie, if the
+/// realm has cookie sessions and the request a session cookie. Requests
+/// that may change anything must also send the session's CSRF token, in
+/// both the `X-CSRF-Token` header and the CSRF cookie.
+async fn cookie_session_token(
+    realm: &Realm,
+    cookies: Option<&str>,
+    method: &Method,
+    csrf_header: Option<&str>,
+    config: &Config,
+    db_pool: &DBPool,
+) -> Result<Option<String>, Rejection> {
+    let (settings, cookies) = match (&realm.cookie_session, cookies) {
+        (Some(settings), Some(cookies)) => (settings, cookies),
+        _ => return Ok(None),
+    };
+    let secret = match cookie_value(cookies, &settings.cookie_name) {
+        Some(secret) if !secret.is_empty() => secret,
+        _ => return Ok(None),
+    };
+    let session_repo = config.session_repo(db_pool.clone(), realm).await?;
+    let session = match session_repo.find_by_cookie(&digest(secret)).await? {
+        Some(session) => session,
+        None => return Err(realm.unauthorized()),
+    };
+    if !method.is_safe() {
+        let csrf_cookie = cookie_value(cookies, &settings.csrf_cookie_name());
+        let submitted = csrf_header.is_some() && csrf_header == csrf_cookie;
+        if !submitted || csrf_header != Some(session.csrf_token.as_str()) {
+            return Err(reject::custom(Forbidden));
+        }
+    }
+    if !session_repo.touch(session.id, session.user_id).await? {
+        return Err(realm.unauthorized());
+    }
+    let token = realm
+        .token_service()
+        .generate_session_jwt(session.user_id, session.id, session.created_at)
+        .await?;
+    Ok(Some(token))
+}
+
+/// Whether a verified token is still live: neither its session nor, for
+/// access tokens, its record in the token store was revoked, and its user
+/// was not deactivated since. Sessions in use are recorded as seen.
+pub(crate) async fn is_live(
+    realm: &Realm,
+    claims: &Claims,
+    config: &Config,
+    db_pool: &DBPool,
+) -> Result<bool, Rejection> {
+    if let Some(session_id) = claims.sid {
+        let session_repo = config.session_repo(db_pool.clone(), realm).await?;
+        if !session_repo.touch(session_id, claims.sub).await? {
+            return Ok(false);
+        }
+    }
+    if let Some(jti) = claims.jti {
+        let token_repo = config.token_repo(db_pool.clone(), realm).await?;
+        let user_id = match token_repo.get(jti).await? {
+            Some(stored) if stored.revoked_at.is_none() => stored.user_id,
+            _ => return Ok(false),
+        };
+        if let Some(user_id) = user_id {
+            let user_repo = config.user_repo(db_pool.clone(), realm).await?;
+            if !matches!(user_repo.get_user_by_id(user_id).await?, Some(user) if user.active) {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Rejects revoked tokens. Tokens that fail to verify are left to the
+/// handler.
+async fn check_revocation(
+    realm: &Realm,
+    token: &str,
+    config: &Config,
+    db_pool: &DBPool,
+) -> Result<(), Rejection> {
+    let claims = match realm.token_service().verify_jwt(token.to_string()).await {
+        Ok(data) => data.claims,
+        Err(_) => return Ok(()),
+    };
+    if is_live(realm, &claims, config, db_pool).await? {
+        Ok(())
+    } else {
+        Err(realm.unauthorized())
+    }
+}
+
+/// The token a request is authenticated with: from its `Authorization`
+/// header, the OAuth `Bearer <jwt>` scheme or our `Basic <base64 jwt>`, or
+/// else from the realm's session cookie. Tokens of sessions the user signed
+/// out of, and access tokens revoked since, are rejected.
+pub(crate) async fn request_token(
+    realm: &Realm,
+    authorization: Option<&str>,
+    cookies: Option<&str>,
+    method: &Method,
+    csrf_header: Option<&str>,
+    config: &Config,
+    db_pool: &DBPool,
+) -> Result<String, Rejection> {
+    let authorization = match authorization {
+        Some(authorization) => authorization,
+        None => {
+            return match cookie_session_token(realm, cookies, method, csrf_header, config, db_pool)
+                .await?
+            {
+                Some(token) => Ok(token),
+                None => Err(realm.unauthorized()),
+            }
+        }
+    };
+    let token = match authorization.strip_prefix("Bearer ") {
+        Some(token) => token.to_string(),
+        None => match authorization.strip_prefix("Basic ") {
+            Some(e) => decode_token(String::from(e))
+                .await
+                .map_err(|_| realm.unauthorized())?,
+            None => return Err(realm.unauthorized()),
+        },
+    };
+    check_revocation(realm, &token, config, db_pool).await?;
+    Ok(token)
+}
+
+/// Sessions are managed by users themselves, never by OAuth clients.
+async fn session_owner(realm: &Realm, token: String) -> Result<Claims, Rejection> {
+    realm.token_service().verify_user_token(token).await
+}
+
+/// `GET /me/sessions`: where the user is signed in.
+pub async fn list_sessions(
+    realm: Realm,
+    token: String,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<impl Reply, Rejection> {
+    let claims = session_owner(&realm, token).await?;
+    let session_repo = config.session_repo(db_pool, &realm).await?;
+    let mut sessions = session_repo.list(claims.sub).await?;
+    for session in &mut sessions {
+        session.current = Some(session.id) == claims.sid;
+    }
+    Ok(warp::reply::json(&sessions))
+}
+
+/// `DELETE /me/sessions/{id}`: signs the user out of the session; its token
+/// is rejected from then on.
+pub async fn revoke_session(
+    realm: Realm,
+    token: String,
+    session_id: Uuid,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<impl Reply, Rejection> {
+    let claims = session_owner(&realm, token).await?;
+    let session_repo = config.session_repo(db_pool, &realm).await?;
+    if session_repo.revoke(claims.sub, session_id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(reject::custom(NotFoundError(ErrorKind::NotFound)))
+    }
+}
+
+/// `POST /logout`: ends the session the request is authenticated with, and
+/// removes its cookies.
+pub async fn logout(
+    realm: Realm,
+    token: String,
+    config: Config,
+    db_pool: DBPool,
+) -> Result<impl Reply, Rejection> {
+    let claims = session_owner(&realm, token).await?;
+    if let Some(session_id) = claims.sid {
+        let session_repo = config.session_repo(db_pool, &realm).await?;
+        session_repo.revoke(claims.sub, session_id).await?;
+    }
+    let response = StatusCode::NO_CONTENT.into_response();
+    Ok(match &realm.cookie_session {
+        Some(settings) => with_cookies(response, &settings.clear_cookies()),
+        None => response,
+    })
+}
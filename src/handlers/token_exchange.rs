@@ -0,0 +1,205 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use warp::{reply::Response, Rejection};
+
+use crate::config::realm::Realm;
+use crate::config::{Config, DBPool};
+use crate::errors::OAuthErrorCode::{
+    InvalidGrant, InvalidRequest, InvalidScope, InvalidTarget, UnauthorizedClient,
+};
+use crate::handlers::oauth::{oauth_error, token_response};
+use crate::handlers::session::is_live;
+use crate::models::oauth::{
+    OAuthClient, StoredToken, TokenRequest, TokenResponse, ACCESS_TOKEN, ACCESS_TOKEN_TYPE,
+};
+
+/// Scope of an exchanged token: what was asked for, which may not exceed the
+/// subject token, the client or the audience. Tokens without a scope are
+/// first-party tokens and are only limited by the client and the audience.
+fn exchange_scope(
+    client_scopes: &[String],
+    audience_scopes: &[String],
+    subject_scope: Option<&str>,
+    requested: Option<&str>,
+) -> Option<String> {
+    let available: Vec<&str> = client_scopes
+        .iter()
+        .map(String::as_str)
+        .filter(|s| audience_scopes.iter().any(|a| a == s))
+        .filter(|s| subject_scope.is_none_or(|scope| scope.split_whitespace().any(|g| g == *s)))
+        .collect();
+    let scopes: Vec<&str> = match requested {
+        None => available,
+        Some(requested) if requested.split_whitespace().all(|s| available.contains(&s)) => {
+            requested.split_whitespace().collect()
+        }
+        Some(_) => return None,
+    };
+    if scopes.is_empty() {
+        return None;
+    }
+    Some(scopes.join(" "))
+}
+
+/// `/token` with the token exchange grant (RFC 8693): trades a user's access
+/// token for a narrower one restricted to a configured audience. The client
+/// becomes the actor, in front of any actors of the subject token.
+pub async fn token_exchange_grant(
+    realm: &Realm,
+    config: &Config,
+    db_pool: &DBPool,
+    client: &OAuthClient,
+    request: TokenRequest,
+) -> Result<Response, Rejection> {
+    if !client.is_confidential() {
+        return Err(oauth_error(
+            UnauthorizedClient,
+            "Only confidential clients may exchange tokens.",
+        ));
+    }
+    let subject_token = match (
+        &request.subject_token,
+        request.subject_token_type.as_deref(),
+    ) {
+        (Some(token), Some(ACCESS_TOKEN_TYPE)) => token.clone(),
+        (Some(_), Some(_)) => {
+            return Err(oauth_error(
+                InvalidRequest,
+                "Only access tokens can be exchanged.",
+            ))
+        }
+        _ => {
+            return Err(oauth_error(
+                InvalidRequest,
+                "subject_token and subject_token_type are required.",
+            ))
+        }
+    };
+    if request.actor_token.is_some() {
+        return Err(oauth_error(
+            InvalidRequest,
+            "actor_token is not supported, the client is the actor.",
+        ));
+    }
+    if matches!(&request.requested_token_type, Some(t) if t != ACCESS_TOKEN_TYPE) {
+        return Err(oauth_error(
+            InvalidRequest,
+            "Only access tokens can be issued.",
+        ));
+    }
+    let audience = match &request.audience {
+        Some(audience) => audience,
+        None => return Err(oauth_error(InvalidRequest, "audience is required.")),
+    };
+    let policy = match realm.audience_policy(audience) {
+        Some(policy) if policy.allows_client(client.id) => policy,
+        _ => {
+            return Err(oauth_error(
+                InvalidTarget,
+                "The client may not request tokens for this audience.",
+            ))
+        }
+    };
+
+    let token_service = realm.token_service();
+    let invalid = || oauth_error(InvalidGrant, "The subject token is invalid.");
+    let claims = match token_service.verify_jwt(subject_token).await {
+        Ok(data) => data.claims,
+        Err(_) => return Err(invalid()),
+    };
+    // Client credentials tokens have no user to act for.
+    if claims.client_id == Some(claims.sub) {
+        return Err(invalid());
+    }
+    // Neither a session the user signed out of nor a revoked access token.
+    if !is_live(realm, &claims, config, db_pool).await? {
+        return Err(invalid());
+    }
+    let user_repo = config.user_repo(db_pool.clone(), realm).await?;
+    match user_repo.get_user_by_id(claims.sub).await? {
+        Some(user) if user.active => {}
+        _ => return Err(invalid()),
+    }
+
+    let scope = match exchange_scope(
+        &client.scopes,
+        &policy.scopes,
+        claims.scope.as_deref(),
+        request.scope.as_deref(),
+    ) {
+        Some(scope) => scope,
+        None => return Err(oauth_error(
+            InvalidScope,
+            "The requested scope exceeds what the subject token, the client or the audience allow.",
+        )),
+    };
+
+    // Never outlives the subject token.
+    let expires_at = match DateTime::from_timestamp(claims.exp, 0) {
+        Some(subject_expires_at) => subject_expires_at.min(Utc::now() + token_service.ttl),
+        None => return Err(invalid()),
+    };
+    // Recorded under the subject token, so revoking it revokes this one too.
+    let stored = StoredToken {
+        id: Uuid::new_v4(),
+        token_type: ACCESS_TOKEN.to_string(),
+        client_id: client.id,
+        user_id: Some(claims.sub),
+        scope: scope.clone(),
+        parent_id: claims.jti,
+        expires_at,
+        revoked_at: None,
+    };
+    let access_token = token_service
+        .generate_exchanged_token(&stored, &claims, audience)
+        .await?;
+    let token_repo = config.token_repo(db_pool.clone(), realm).await?;
+    token_repo.create(&stored, None).await?;
+    Ok(token_response(&TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: (expires_at - Utc::now()).num_seconds(),
+        scope,
+        refresh_token: None,
+        id_token: None,
+        issued_token_type: Some(ACCESS_TOKEN_TYPE.to_string()),
+    }))
+}
+
+#[test]
+fn test_exchange_scope() {
+    let scopes = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    let client = scopes(&["orders:read", "orders:write", "profile"]);
+    let audience = scopes(&["orders:read", "orders:write"]);
+
+    assert_eq!(
+        Some("orders:read orders:write".to_string()),
+        exchange_scope(&client, &audience, None, None)
+    );
+    assert_eq!(
+        Some("orders:read".to_string()),
+        exchange_scope(&client, &audience, Some("orders:read profile"), None)
+    );
+    assert_eq!(
+        Some("orders:read".to_string()),
+        exchange_scope(&client, &audience, None, Some("orders:read"))
+    );
+    // Never wider than the subject token, the client or the audience.
+    assert_eq!(
+        None,
+        exchange_scope(
+            &client,
+            &audience,
+            Some("orders:read"),
+            Some("orders:write")
+        )
+    );
+    assert_eq!(
+        None,
+        exchange_scope(&client, &audience, None, Some("profile"))
+    );
+    assert_eq!(
+        None,
+        exchange_scope(&client, &audience, Some("profile"), None)
+    );
+}
@@ -1,55 +1,59 @@
-use std::io::Error;
 use std::io::ErrorKind;
 
-use warp::{http::StatusCode, reject, Rejection, Reply};
+use validator::Validate;
+use warp::{reject, Rejection, Reply};
 
+use crate::config::realm::Realm;
 use crate::config::Config;
 
 use crate::config::DBPool;
-use crate::errors::Error::{AuthError, NotCompletedError};
+use crate::errors::Error::{NotCompletedError, ValidationError};
 use crate::handlers::auth::validate_credentials;
+use crate::handlers::session::{signed_in, start_browser_session, start_session, with_cookies};
+use crate::metrics::metrics;
 use crate::models::{
     auth::Credentials,
-    user::{NewUser, ValidateEmail},
+    session::ClientInfo,
+    user::{DeletedUser, NewUser, Profile, SignupBody},
 };
+use crate::server::negotiation::Format;
 
 pub async fn me(
+    realm: Realm,
     token: String,
-    _realm_header: String,
     config: Config,
     db_pool: DBPool,
 ) -> std::result::Result<impl Reply, Rejection> {
-    let user_repo = match config.user_repo(db_pool.clone()).await {
+    let user_repo = match config.user_repo(db_pool.clone(), &realm).await {
         Ok(repo) => repo,
         Err(e) => return Err(e),
     };
-    let id = match config.token_service().verify_jwt(token).await {
-        Ok(data) => data.claims.sub,
+    let id = match realm.token_service().verify_user_token(token).await {
+        Ok(claims) => claims.sub,
         Err(e) => return Err(e),
     };
     match user_repo.get_user_by_id(id).await? {
-        Some(user) => {
-            match serde_json::to_string(&user) {
-                Ok(user_json) => return Ok(warp::reply::with_status(user_json, StatusCode::OK)),
-                Err(_) => return Err(reject::custom(NotCompletedError(ErrorKind::WriteZero))),
-            };
-        }
-        None => {
-            return Err(reject::custom(AuthError(Error::from(
-                ErrorKind::PermissionDenied,
-            ))))
-        }
-    };
+        Some(user) => Ok(warp::reply::json(&Profile::from(user))),
+        None => Err(realm.unauthorized()),
+    }
 }
 
 pub async fn create_user(
+    realm: Realm,
     credentials: Credentials,
-    _realm_header: String,
+    body: SignupBody,
+    format: Format,
     config: Config,
     db_pool: DBPool,
-    body: ValidateEmail,
+    client: ClientInfo,
 ) -> Result<impl Reply, Rejection> {
-    let user_repo = match config.user_repo(db_pool.clone()).await {
+    body.validate()
+        .map_err(|errors| reject::custom(ValidationError(errors)))?;
+    realm
+        .password_policy
+        .check("password", &credentials.password)?;
+
+    let user_repo = match config.user_repo(db_pool.clone(), &realm).await {
         Ok(repo) => repo,
         Err(e) => return Err(e),
     };
@@ -71,72 +75,77 @@ pub async fn create_user(
                 password_hash: p,
                 email: body.email,
             };
+            new_user
+                .validate()
+                .map_err(|errors| reject::custom(ValidationError(errors)))?;
 
             let id = match user_repo.create(new_user).await? {
                 Some(id) => id,
                 None => return Err(reject::custom(NotCompletedError(ErrorKind::WriteZero))),
             };
+            metrics().user_signed_up();
 
-            match config.token_service().generate_jwt(id).await {
-                Ok(token) => return Ok(warp::reply::with_status(token, StatusCode::OK)),
-                Err(e) => return Err(e),
-            };
+            match start_session(&realm, &config, &db_pool, id, &client).await {
+                Ok(token) => Ok(signed_in(&realm, token, format)),
+                Err(e) => Err(e),
+            }
         }
-        Err(e) => return Err(e),
-    };
+        Err(e) => Err(e),
+    }
 }
 
 pub async fn login(
+    realm: Realm,
     credentials: Credentials,
-    _realm_header: String,
+    format: Format,
     config: Config,
     db_pool: DBPool,
+    client: ClientInfo,
 ) -> Result<impl Reply, Rejection> {
-    let user_repo = match config.user_repo(db_pool.clone()).await {
+    let user_repo = match config.user_repo(db_pool.clone(), &realm).await {
         Ok(repo) => repo,
         Err(e) => return Err(e),
     };
-    let id = match validate_credentials(&credentials, &user_repo, config.hash_service()).await {
+    let id = match validate_credentials(
+        &credentials,
+        &user_repo,
+        config.hash_service(),
+        realm.ldap.as_ref(),
+    )
+    .await
+    {
         Ok(Some(id)) => id,
-        Ok(None) => {
-            return Err(reject::custom(AuthError(Error::from(
-                ErrorKind::PermissionDenied,
-            ))))
-        }
+        Ok(None) => return Err(realm.unauthorized()),
         Err(e) => return Err(e),
     };
 
-    match config.token_service().generate_jwt(id).await {
-        Ok(token) => return Ok(warp::reply::with_status(token, StatusCode::OK)),
-        Err(e) => return Err(e),
-    };
+    match start_browser_session(&realm, &config, &db_pool, id, &client).await {
+        Ok((token, cookies)) => Ok(with_cookies(signed_in(&realm, token, format), &cookies)),
+        Err(e) => Err(e),
+    }
 }
 pub async fn delete_user(
+    realm: Realm,
     token: String,
-    _realm_header: String,
     config: Config,
     db_pool: DBPool,
 ) -> Result<impl Reply, Rejection> {
-    let user_repo = match config.user_repo(db_pool.clone()).await {
+    let user_repo = match config.user_repo(db_pool.clone(), &realm).await {
         Ok(repo) => repo,
         Err(e) => return Err(e),
     };
-    let id = match config.token_service().verify_jwt(token).await {
-        Ok(data) => data.claims.sub,
+    let id = match realm.token_service().verify_user_token(token).await {
+        Ok(claims) => claims.sub,
         Err(e) => return Err(e),
     };
 
     let uuid = match user_repo.validate_id(id).await? {
         Some(id) => id,
-        None => {
-            return Err(reject::custom(AuthError(Error::from(
-                ErrorKind::PermissionDenied,
-            ))))
-        }
+        None => return Err(realm.unauthorized()),
     };
 
     match user_repo.delete(uuid).await? {
-        Some(id) => Ok(warp::reply::with_status(id.to_string(), StatusCode::OK)),
-        None => return Err(reject::custom(NotCompletedError(ErrorKind::WriteZero))),
+        Some(id) => Ok(warp::reply::json(&DeletedUser { id })),
+        None => Err(reject::custom(NotCompletedError(ErrorKind::WriteZero))),
     }
 }
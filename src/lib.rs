@@ -2,8 +2,10 @@ mod config;
 mod db;
 pub mod errors;
 mod handlers;
+mod metrics;
 mod models;
 mod server;
+mod telemetry;
 
 use crate::config::Config;
 use crate::errors::Error;
@@ -14,7 +16,9 @@ pub async fn run() -> Result<(), Error> {
 
     let db_pool = config.db_pool().expect("Database Pool can be created");
 
-    let server = warp::serve(make_routes(config.clone(), db_pool)).run((config.host, config.port));
+    warp::serve(make_routes(config.clone(), db_pool))
+        .run((config.host, config.port))
+        .await;
 
-    Ok(server.await)
+    Ok(())
 }
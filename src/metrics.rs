@@ -0,0 +1,229 @@
+//! Prometheus metrics of the server, served at `/metrics` in the text
+//! exposition format. They are process-wide: every realm and every copy of
+//! the routes records to the same registry.
+
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use prometheus::{
+    Counter, Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::config::DBPool;
+
+/// Argon2 takes tens of milliseconds by design.
+const ARGON2_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    logins: IntCounterVec,
+    signups: IntCounter,
+    tokens_issued: IntCounterVec,
+    token_verifications: IntCounterVec,
+    argon2_duration: HistogramVec,
+    pool_connections: IntGaugeVec,
+    pool_max_open: IntGauge,
+    pool_waits: IntCounter,
+    pool_wait_seconds: Counter,
+    /// Serializes copying the pool's running totals into the counters.
+    pool_update: Mutex<()>,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// The metrics of the process.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(registry: &Registry, metric: T) -> T {
+    registry
+        .register(Box::new(metric.clone()))
+        .expect("metric names are unique");
+    metric
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("authserver".to_string()), None)
+            .expect("the prefix is a valid metric name");
+        let counters = |name: &str, help: &str, labels: &[&str]| {
+            register(
+                &registry,
+                IntCounterVec::new(Opts::new(name, help), labels).unwrap(),
+            )
+        };
+        let histograms = |name: &str, help: &str, labels: &[&str], buckets: &[f64]| {
+            let opts = HistogramOpts::new(name, help).buckets(buckets.to_vec());
+            register(&registry, HistogramVec::new(opts, labels).unwrap())
+        };
+        Metrics {
+            http_requests: counters(
+                "http_requests_total",
+                "Requests answered, by route, method and status.",
+                &["route", "method", "status"],
+            ),
+            http_request_duration: histograms(
+                "http_request_duration_seconds",
+                "Time taken to answer requests, by route, method and status.",
+                &["route", "method", "status"],
+                prometheus::DEFAULT_BUCKETS,
+            ),
+            logins: counters(
+                "logins_total",
+                "Username and password sign-ins: successes by where the password was checked, failures by reason.",
+                &["result", "reason"],
+            ),
+            signups: register(
+                &registry,
+                IntCounter::new("signups_total", "Users created through /signup.").unwrap(),
+            ),
+            tokens_issued: counters(
+                "tokens_issued_total",
+                "Tokens issued, by kind.",
+                &["kind"],
+            ),
+            token_verifications: counters(
+                "token_verifications_total",
+                "Access tokens verified, by result.",
+                &["result"],
+            ),
+            argon2_duration: histograms(
+                "argon2_duration_seconds",
+                "Time taken to hash or verify a password with Argon2.",
+                &["operation"],
+                ARGON2_BUCKETS,
+            ),
+            pool_connections: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new(
+                        "db_pool_connections",
+                        "Database connections of the pool, open ones by whether they are in use.",
+                    ),
+                    &["state"],
+                )
+                .unwrap(),
+            ),
+            pool_max_open: register(
+                &registry,
+                IntGauge::new(
+                    "db_pool_max_open",
+                    "The most database connections the pool opens.",
+                )
+                .unwrap(),
+            ),
+            pool_waits: register(
+                &registry,
+                IntCounter::new(
+                    "db_pool_waits_total",
+                    "Times a request waited for a database connection.",
+                )
+                .unwrap(),
+            ),
+            pool_wait_seconds: register(
+                &registry,
+                Counter::new(
+                    "db_pool_wait_seconds_total",
+                    "Time spent waiting for database connections.",
+                )
+                .unwrap(),
+            ),
+            pool_update: Mutex::new(()),
+            registry,
+        }
+    }
+
+    pub fn observe_request(&self, route: &str, method: &str, status: u16, elapsed: Duration) {
+        let status = status.to_string();
+        let labels = [route, method, status.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_request_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn login_succeeded(&self, source: &str) {
+        self.logins.with_label_values(&["success", source]).inc();
+    }
+
+    pub fn login_failed(&self, reason: &str) {
+        self.logins.with_label_values(&["failure", reason]).inc();
+    }
+
+    pub fn user_signed_up(&self) {
+        self.signups.inc();
+    }
+
+    /// `kind` is `session`, `access`, `exchanged`, `id` or `refresh`.
+    pub fn token_issued(&self, kind: &str) {
+        self.tokens_issued.with_label_values(&[kind]).inc();
+    }
+
+    /// `result` is `valid`, `expired`, `invalid` or `error`.
+    pub fn token_verified(&self, result: &str) {
+        self.token_verifications.with_label_values(&[result]).inc();
+    }
+
+    /// Observes the duration of an Argon2 `operation` when dropped.
+    pub fn argon2_timer(&self, operation: &str) -> HistogramTimer {
+        self.argon2_duration
+            .with_label_values(&[operation])
+            .start_timer()
+    }
+
+    /// Copies the state of the pool, which keeps its own running totals.
+    pub async fn observe_pool(&self, db_pool: &DBPool) {
+        let state = db_pool.state().await;
+        let _update = self.pool_update.lock().unwrap();
+        let gauge = |state: &str, value: u64| {
+            self.pool_connections
+                .with_label_values(&[state])
+                .set(value as i64)
+        };
+        gauge("open", state.connections);
+        gauge("in_use", state.in_use);
+        gauge("idle", state.idle);
+        self.pool_max_open.set(state.max_open as i64);
+        self.pool_waits
+            .inc_by(state.wait_count.saturating_sub(self.pool_waits.get()));
+        let waited = state.wait_duration.as_secs_f64() - self.pool_wait_seconds.get();
+        if waited > 0.0 {
+            self.pool_wait_seconds.inc_by(waited);
+        }
+    }
+
+    /// Every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut text = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut text)
+            .expect("metrics can be encoded");
+        String::from_utf8(text).expect("the text format is UTF-8")
+    }
+}
+
+#[test]
+fn test_render() {
+    let metrics = Metrics::new();
+    metrics.observe_request("me", "GET", 200, Duration::from_millis(3));
+    metrics.login_failed("invalid_password");
+    metrics.token_issued("access");
+    drop(metrics.argon2_timer("hash"));
+
+    let text = metrics.render();
+    assert!(text
+        .contains("authserver_http_requests_total{method=\"GET\",route=\"me\",status=\"200\"} 1"));
+    assert!(text.contains(
+        "authserver_http_request_duration_seconds_bucket{method=\"GET\",route=\"me\",status=\"200\",le=\"0.005\"} 1"
+    ));
+    assert!(
+        text.contains("authserver_logins_total{reason=\"invalid_password\",result=\"failure\"} 1")
+    );
+    assert!(text.contains("authserver_tokens_issued_total{kind=\"access\"} 1"));
+    assert!(text.contains("authserver_argon2_duration_seconds_count{operation=\"hash\"} 1"));
+    assert!(text.contains("# TYPE authserver_signups_total counter"));
+}
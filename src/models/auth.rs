@@ -1,3 +1,5 @@
+use serde::Deserialize;
+use uuid::Uuid;
 use validator::Validate;
 
 #[derive(Validate, Debug)]
@@ -6,3 +8,53 @@ pub struct Credentials {
     #[validate(length(min = 3))]
     pub password: String,
 }
+
+/// A body that may carry the username and password instead of the Basic header.
+pub trait BodyCredentials {
+    fn take_credentials(&mut self) -> Option<Credentials>;
+}
+
+/// The credentials of a body that has both.
+pub fn take_credentials(
+    username: &mut Option<String>,
+    password: &mut Option<String>,
+) -> Option<Credentials> {
+    match (username.take(), password.take()) {
+        (Some(username), Some(password)) => Some(Credentials { username, password }),
+        _ => None,
+    }
+}
+
+/// The body of `/login`, empty when the credentials are in the Basic header.
+#[derive(Debug, Default, Deserialize)]
+pub struct LoginBody {
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl BodyCredentials for LoginBody {
+    fn take_credentials(&mut self) -> Option<Credentials> {
+        take_credentials(&mut self.username, &mut self.password)
+    }
+}
+
+/// A user `GET /auth/verify` let through, as told to the proxied app.
+#[derive(Debug, Clone)]
+pub struct VerifiedUser {
+    pub id: Uuid,
+    pub email: String,
+    pub roles: Vec<String>,
+}
+
+/// The request a reverse proxy asks `GET /auth/verify` about: the URI and
+/// method it forwards, and the credentials the client sent.
+#[derive(Debug, Default)]
+pub struct ForwardedRequest {
+    pub uri: Option<String>,
+    pub method: Option<String>,
+    pub authorization: Option<String>,
+    pub cookies: Option<String>,
+    pub csrf_token: Option<String>,
+}
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub use authserver_client::models::federation::{AddIdentityForm, LinkStarted, LoginIdentity};
+
+/// A sign-in at an upstream provider, kept until the user comes back.
+#[derive(Debug)]
+pub struct FederatedLogin {
+    pub provider: String,
+    pub code_verifier: String,
+    pub nonce: String,
+    pub expires_at: DateTime<Utc>,
+    /// The signed-in user linking the upstream account, rather than signing in with it.
+    pub link_user_id: Option<Uuid>,
+}
+
+/// The identities standing for the user's own password, checked by
+/// `validate_credentials`, and for their directory account.
+pub const PASSWORD_PROVIDER: &str = "password";
+pub const DIRECTORY_PROVIDER: &str = "ldap";
+
+/// An account at an upstream provider linked to a local user.
+#[derive(Debug, Serialize)]
+pub struct Identity {
+    pub provider: String,
+    pub subject: String,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    pub linked_at: DateTime<Utc>,
+}
+
+/// Query of `GET /login/{provider}`; the hint is passed on to the provider.
+#[derive(Debug, Deserialize)]
+pub struct FederatedLoginParams {
+    pub login_hint: Option<String>,
+}
+
+/// Query the provider redirects back to `/login/{provider}/callback` with.
+#[derive(Debug, Deserialize)]
+pub struct FederatedCallbackParams {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// What the browser brings back to `/login/{provider}/callback`: the
+/// provider's answer, and the cookie set when the sign-in started.
+#[derive(Debug)]
+pub struct FederatedCallback {
+    pub params: FederatedCallbackParams,
+    pub cookies: Option<String>,
+}
+
+/// Form of `POST /login/{provider}/link`: proves the local account an
+/// upstream account should be linked to.
+#[derive(Debug, Deserialize)]
+pub struct LinkForm {
+    pub link_ticket: String,
+    pub username: String,
+    pub password: String,
+}
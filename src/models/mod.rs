@@ -1,3 +1,10 @@
 pub mod auth;
+pub mod federation;
+pub mod oauth;
+pub mod oidc;
+pub mod registration;
+pub mod saml;
+pub mod scim;
+pub mod session;
 mod token;
 pub mod user;
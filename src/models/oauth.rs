@@ -0,0 +1,191 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub use authserver_client::models::oauth::{
+    ClientCredentials, ClientSummary, DeviceAuthorizationRequest, DeviceAuthorizationResponse,
+    IntrospectionResponse, NewClient, TokenReference, TokenRequest, TokenResponse, UserGrant,
+    ACCESS_TOKEN_TYPE, DEVICE_CODE_GRANT, TOKEN_EXCHANGE_GRANT,
+};
+
+pub const SUPPORTED_GRANT_TYPES: [&str; 5] = [
+    "authorization_code",
+    "client_credentials",
+    "refresh_token",
+    DEVICE_CODE_GRANT,
+    TOKEN_EXCHANGE_GRANT,
+];
+
+pub const CLIENT_ACTIVE: &str = "active";
+pub const CLIENT_PENDING: &str = "pending";
+
+pub const ACCESS_TOKEN: &str = "access_token";
+pub const REFRESH_TOKEN: &str = "refresh_token";
+
+#[derive(Debug, Clone)]
+pub struct OAuthClient {
+    pub id: Uuid,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub grant_types: Vec<String>,
+    /// Argon2 hash of the client secret; public clients have none.
+    pub secret_hash: Option<String>,
+    /// Our own applications, which users are not asked to consent to.
+    pub first_party: bool,
+    /// `pending` for registered clients an admin has not approved yet.
+    pub status: String,
+    /// As registered; `None` for clients created by an admin.
+    pub token_endpoint_auth_method: Option<String>,
+    pub registration_token_hash: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl OAuthClient {
+    pub fn is_confidential(&self) -> bool {
+        self.secret_hash.is_some()
+    }
+    pub fn is_active(&self) -> bool {
+        self.status == CLIENT_ACTIVE
+    }
+    pub fn token_endpoint_auth_method(&self) -> &str {
+        match (&self.token_endpoint_auth_method, self.is_confidential()) {
+            (Some(method), _) => method,
+            (None, true) => "client_secret_basic",
+            (None, false) => "none",
+        }
+    }
+    /// Clients acting for a user (authorization code or device flow) may always refresh.
+    pub fn allows_grant(&self, grant_type: &str) -> bool {
+        self.grant_types.iter().any(|g| {
+            g == grant_type
+                || (grant_type == REFRESH_TOKEN
+                    && (g == "authorization_code" || g == DEVICE_CODE_GRANT))
+        })
+    }
+    /// Granted scope for a space separated request: the client's scopes when
+    /// nothing is requested, `None` when anything requested is not allowed.
+    pub fn grant_scope(&self, requested: Option<&str>) -> Option<String> {
+        let requested: Vec<&str> = requested.unwrap_or_default().split_whitespace().collect();
+        if requested.is_empty() {
+            Some(self.scopes.join(" "))
+        } else if requested.iter().all(|s| self.scopes.iter().any(|a| a == s)) {
+            Some(requested.join(" "))
+        } else {
+            None
+        }
+    }
+}
+
+impl From<&OAuthClient> for ClientSummary {
+    fn from(client: &OAuthClient) -> Self {
+        ClientSummary {
+            client_id: client.id,
+            client_name: client.name.clone(),
+            status: client.status.clone(),
+            redirect_uris: client.redirect_uris.clone(),
+            scopes: client.scopes.clone(),
+            grant_types: client.grant_types.clone(),
+            token_endpoint_auth_method: client.token_endpoint_auth_method().to_string(),
+            first_party: client.first_party,
+            created_at: client.created_at,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AuthorizationCode {
+    pub client_id: Uuid,
+    pub user_id: Uuid,
+    pub redirect_uri: String,
+    /// Whether the authorization request named `redirect_uri`, which the
+    /// token request must then repeat.
+    pub redirect_uri_supplied: bool,
+    pub scope: String,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>,
+    pub auth_time: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Parameters of an authorization request (RFC 6749 section 4.1.1, RFC 7636 section 4.3).
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AuthorizeParams {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>,
+}
+
+/// Login form posted back to `/authorize`, carrying the original request along.
+#[derive(Debug, Deserialize)]
+pub struct AuthorizeForm {
+    pub username: String,
+    pub password: String,
+    #[serde(flatten)]
+    pub params: AuthorizeParams,
+}
+
+/// Consent form posted to `/authorize/consent`, with `action` set to
+/// `approve` or `deny`.
+#[derive(Debug, Deserialize)]
+pub struct ConsentForm {
+    pub consent_ticket: String,
+    pub action: String,
+    #[serde(flatten)]
+    pub params: AuthorizeParams,
+}
+
+/// A device authorization request (RFC 8628) as stored while the device polls.
+#[derive(Debug)]
+pub struct DeviceCode {
+    pub client_id: Uuid,
+    pub user_code: String,
+    pub scope: String,
+    pub user_id: Option<Uuid>,
+    /// `None` until the user decides.
+    pub approved: Option<bool>,
+    pub interval_seconds: i32,
+    pub last_polled_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DevicePageParams {
+    pub user_code: Option<String>,
+}
+
+/// The verification page, submitted with `action` set to `approve` or `deny`
+/// by a signed-in user. Browsers cannot set the CSRF header on a form post,
+/// so the page carries the token in `csrf_token` instead.
+#[derive(Debug, Deserialize)]
+pub struct DeviceForm {
+    pub user_code: String,
+    pub action: String,
+    pub csrf_token: Option<String>,
+}
+
+/// An issued access or refresh token as recorded in the token store.
+#[derive(Debug)]
+pub struct StoredToken {
+    pub id: Uuid,
+    pub token_type: String,
+    pub client_id: Uuid,
+    /// `None` for client credentials tokens.
+    pub user_id: Option<Uuid>,
+    pub scope: String,
+    pub parent_id: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl StoredToken {
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > Utc::now()
+    }
+}
@@ -0,0 +1,119 @@
+use serde::Serialize;
+
+use crate::config::keys::Jwk;
+use crate::config::realm::api_url;
+use crate::models::oauth::SUPPORTED_GRANT_TYPES;
+use crate::models::user::User;
+
+pub use authserver_client::models::oidc::{ProviderMetadata, UserInfo};
+
+pub const SUPPORTED_SCOPES: [&str; 3] = ["openid", "profile", "email"];
+
+/// The claims of `user` the scopes of a space separated `scope` release.
+pub fn user_info(user: &User, scope: &str) -> UserInfo {
+    let scopes: Vec<&str> = scope.split_whitespace().collect();
+    let profile = scopes.contains(&"profile");
+    let email = scopes.contains(&"email");
+
+    UserInfo {
+        sub: user.id,
+        preferred_username: user.username.clone().filter(|_| profile),
+        name: user.full_name.clone().filter(|_| profile),
+        picture: user.image.clone().filter(|_| profile),
+        email: Some(user.email.clone()).filter(|_| email),
+        email_verified: Some(user.email_verified).filter(|_| email),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub aud: String,
+    pub exp: i64,
+    pub iat: i64,
+    pub auth_time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(flatten)]
+    pub user: UserInfo,
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+/// What `/.well-known/openid-configuration` says of the issuer (OIDC
+/// Discovery section 3): its endpoints on the current version of the API.
+pub fn provider_metadata(issuer: &str) -> ProviderMetadata {
+    let api = api_url(issuer);
+    ProviderMetadata {
+        issuer: issuer.to_string(),
+        authorization_endpoint: format!("{}/authorize", api),
+        token_endpoint: format!("{}/token", api),
+        userinfo_endpoint: format!("{}/userinfo", api),
+        introspection_endpoint: format!("{}/introspect", api),
+        revocation_endpoint: format!("{}/revoke", api),
+        device_authorization_endpoint: format!("{}/device_authorization", api),
+        registration_endpoint: format!("{}/register", api),
+        jwks_uri: format!("{}/.well-known/jwks.json", issuer),
+        scopes_supported: strings(&SUPPORTED_SCOPES),
+        response_types_supported: strings(&["code"]),
+        grant_types_supported: strings(&SUPPORTED_GRANT_TYPES),
+        subject_types_supported: strings(&["public"]),
+        id_token_signing_alg_values_supported: strings(&["RS256"]),
+        token_endpoint_auth_methods_supported: strings(&[
+            "none",
+            "client_secret_basic",
+            "client_secret_post",
+        ]),
+        code_challenge_methods_supported: strings(&["S256"]),
+        claims_supported: strings(&[
+            "sub",
+            "iss",
+            "aud",
+            "exp",
+            "iat",
+            "auth_time",
+            "nonce",
+            "preferred_username",
+            "name",
+            "picture",
+            "email",
+            "email_verified",
+        ]),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+#[test]
+fn test_userinfo_is_filtered_by_scope() {
+    let user = User {
+        id: uuid::Uuid::new_v4(),
+        username: Some("username".to_string()),
+        email: "user@example.com".to_string(),
+        password_hash: "secret".to_string(),
+        full_name: Some("User Name".to_string()),
+        bio: None,
+        image: None,
+        email_verified: true,
+        active: true,
+        roles: Vec::new(),
+    };
+
+    let openid = serde_json::to_value(user_info(&user, "openid")).unwrap();
+    assert_eq!(openid, serde_json::json!({ "sub": user.id }));
+
+    let email = serde_json::to_value(user_info(&user, "openid email")).unwrap();
+    assert_eq!(email["email"], "user@example.com");
+    assert_eq!(email["email_verified"], true);
+    assert!(email.get("preferred_username").is_none());
+
+    let profile = serde_json::to_value(user_info(&user, "openid profile")).unwrap();
+    assert_eq!(profile["preferred_username"], "username");
+    assert_eq!(profile["name"], "User Name");
+    assert!(profile.get("email").is_none());
+}
@@ -0,0 +1,3 @@
+pub use authserver_client::models::registration::{
+    ClientMetadata, RegistrationResponse, TOKEN_ENDPOINT_AUTH_METHODS,
+};
@@ -0,0 +1,8 @@
+use serde::Deserialize;
+
+/// Form the identity provider posts to `/saml/{provider}/acs` (HTTP-POST binding).
+#[derive(Debug, Deserialize)]
+pub struct SamlResponseForm {
+    #[serde(rename = "SAMLResponse")]
+    pub saml_response: String,
+}
@@ -0,0 +1,338 @@
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+pub use authserver_client::models::scim::{
+    ListParams, ListResponse, Meta, PatchOperation, PatchRequest, ScimEmail, ScimGroup, ScimMember,
+    ScimName, ScimUser, ERROR_SCHEMA, GROUP_SCHEMA, SCIM_CONTENT_TYPE, USER_SCHEMA,
+};
+
+/// A group of the realm, with its members.
+#[derive(Debug)]
+pub struct Group {
+    pub id: Uuid,
+    pub display_name: String,
+    pub members: Vec<GroupMember>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct GroupMember {
+    pub user_id: Uuid,
+    pub username: Option<String>,
+}
+
+/// The value of a filter of the only form supported, `{attribute} eq "{value}"`.
+pub fn equality_filter(filter: &str, attribute: &str) -> Option<String> {
+    let (name, rest) = filter.trim().split_once(char::is_whitespace)?;
+    let (operator, value) = rest.trim_start().split_once(char::is_whitespace)?;
+    if !name.eq_ignore_ascii_case(attribute) || !operator.eq_ignore_ascii_case("eq") {
+        return None;
+    }
+    serde_json::from_str::<String>(value.trim()).ok()
+}
+
+/// The key of `object` that is `name`, ignoring case as SCIM attribute names do.
+fn key(object: &Map<String, Value>, name: &str) -> String {
+    object
+        .keys()
+        .find(|k| k.eq_ignore_ascii_case(name))
+        .cloned()
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// A path of RFC 7644 section 3.5.2: `attribute[.sub]` or `attribute[filter][.sub]`.
+struct Path<'a> {
+    attribute: &'a str,
+    filter: Option<(&'a str, String)>,
+    sub_attribute: Option<&'a str>,
+}
+
+fn parse_path<'a>(path: &'a str, schema: &str) -> Result<Path<'a>, String> {
+    let path = path
+        .strip_prefix(schema)
+        .and_then(|p| p.strip_prefix(':'))
+        .unwrap_or(path);
+    if let Some((attribute, rest)) = path.split_once('[') {
+        let (filter, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| format!("unterminated filter in {}", path))?;
+        let (name, _) = filter
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or_default();
+        let value = equality_filter(filter, name)
+            .ok_or_else(|| format!("unsupported filter {}", filter))?;
+        let sub_attribute = match rest {
+            "" => None,
+            rest => Some(rest.strip_prefix('.').ok_or("invalid path")?),
+        };
+        return Ok(Path {
+            attribute,
+            filter: Some((name, value)),
+            sub_attribute,
+        });
+    }
+    let (attribute, sub_attribute) = match path.split_once('.') {
+        Some((attribute, sub_attribute)) => (attribute, Some(sub_attribute)),
+        None => (path, None),
+    };
+    Ok(Path {
+        attribute,
+        filter: None,
+        sub_attribute,
+    })
+}
+
+fn matches(element: &Value, (name, value): &(&str, String)) -> bool {
+    element
+        .as_object()
+        .map(|object| object.get(&key(object, name)) == Some(&Value::String(value.clone())))
+        .unwrap_or(false)
+}
+
+/// The values of a multi-valued `value`, compared by their own `value`.
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a.get("value"), b.get("value")) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Applies one PATCH operation (RFC 7644 section 3.5.2) to the JSON of a
+/// resource of `schema`.
+pub fn apply_patch(
+    resource: &mut Value,
+    operation: &PatchOperation,
+    schema: &str,
+) -> Result<(), String> {
+    let op = operation.op.to_ascii_lowercase();
+    let object = resource.as_object_mut().ok_or("not an object")?;
+    let path = match &operation.path {
+        Some(path) => parse_path(path, schema)?,
+        // The value holds the attributes to add or replace.
+        None => {
+            if op == "remove" {
+                return Err("remove needs a path".to_string());
+            }
+            let values = match &operation.value {
+                Some(Value::Object(values)) => values,
+                _ => return Err("the value must be an object".to_string()),
+            };
+            for (path, value) in values {
+                let operation = PatchOperation {
+                    op: op.clone(),
+                    path: Some(path.clone()),
+                    value: Some(value.clone()),
+                };
+                apply_patch(resource, &operation, schema)?;
+            }
+            return Ok(());
+        }
+    };
+    // Attributes of other schemas, such as extensions, are not kept.
+    if path.attribute.starts_with("urn:") {
+        return Ok(());
+    }
+    let attribute = key(object, path.attribute);
+    let value = operation.value.clone();
+
+    match (op.as_str(), &path.filter, path.sub_attribute) {
+        ("add" | "replace", None, None) => {
+            let value = value.ok_or("the operation has no value")?;
+            match (object.get_mut(&attribute), value) {
+                (Some(Value::Array(elements)), Value::Array(added)) if op == "add" => {
+                    for element in added {
+                        if !elements.iter().any(|e| same_value(e, &element)) {
+                            elements.push(element);
+                        }
+                    }
+                }
+                (Some(Value::Object(current)), Value::Object(values)) if op == "add" => {
+                    for (name, value) in values {
+                        current.insert(key(current, &name), value);
+                    }
+                }
+                (_, value) => {
+                    object.insert(attribute, value);
+                }
+            }
+        }
+        ("add" | "replace", None, Some(sub_attribute)) => {
+            let value = value.ok_or("the operation has no value")?;
+            let parent = object
+                .entry(attribute)
+                .or_insert_with(|| Value::Object(Map::new()));
+            let parent = parent.as_object_mut().ok_or("not a complex attribute")?;
+            parent.insert(key(parent, sub_attribute), value);
+        }
+        ("add" | "replace", Some(filter), sub_attribute) => {
+            let value = value.ok_or("the operation has no value")?;
+            let elements = object
+                .entry(attribute)
+                .or_insert_with(|| Value::Array(Vec::new()));
+            let elements = elements
+                .as_array_mut()
+                .ok_or("not a multi-valued attribute")?;
+            if !elements.iter().any(|e| matches(e, filter)) {
+                let mut element = Map::new();
+                element.insert(filter.0.to_string(), Value::String(filter.1.clone()));
+                elements.push(Value::Object(element));
+            }
+            for element in elements.iter_mut().filter(|e| matches(e, filter)) {
+                match (sub_attribute, element.as_object_mut()) {
+                    (Some(sub_attribute), Some(element)) => {
+                        element.insert(key(element, sub_attribute), value.clone());
+                    }
+                    (None, _) => *element = value.clone(),
+                    (Some(_), None) => return Err("not a complex attribute".to_string()),
+                }
+            }
+        }
+        ("remove", None, None) => match (object.get_mut(&attribute), value) {
+            // Removes the values listed, rather than them all.
+            (Some(Value::Array(elements)), Some(Value::Array(removed))) => {
+                elements.retain(|e| !removed.iter().any(|r| same_value(e, r)));
+            }
+            _ => {
+                object.remove(&attribute);
+            }
+        },
+        ("remove", None, Some(sub_attribute)) => {
+            if let Some(Value::Object(parent)) = object.get_mut(&attribute) {
+                parent.remove(&key(parent, sub_attribute));
+            }
+        }
+        ("remove", Some(filter), sub_attribute) => {
+            if let Some(Value::Array(elements)) = object.get_mut(&attribute) {
+                match sub_attribute {
+                    None => elements.retain(|e| !matches(e, filter)),
+                    Some(sub_attribute) => {
+                        for element in elements.iter_mut().filter(|e| matches(e, filter)) {
+                            if let Some(element) = element.as_object_mut() {
+                                element.remove(&key(element, sub_attribute));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        _ => return Err(format!("unsupported operation {}", operation.op)),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_equality_filter() {
+    assert_eq!(
+        Some("jane@example.com".to_string()),
+        equality_filter("userName eq \"jane@example.com\"", "userName")
+    );
+    assert_eq!(
+        Some("a \"b\"".to_string()),
+        equality_filter(" username  EQ  \"a \\\"b\\\"\" ", "userName")
+    );
+    assert_eq!(None, equality_filter("userName co \"jane\"", "userName"));
+    assert_eq!(None, equality_filter("emails eq \"jane\"", "userName"));
+    assert_eq!(None, equality_filter("userName eq jane", "userName"));
+}
+
+#[test]
+fn test_apply_patch() {
+    use serde_json::json;
+    let patch = |resource: &mut Value, op: &str, path: Option<&str>, value: Value| {
+        let operation = PatchOperation {
+            op: op.to_string(),
+            path: path.map(str::to_string),
+            value: Some(value),
+        };
+        apply_patch(resource, &operation, USER_SCHEMA)
+    };
+    let mut user = json!({
+        "userName": "jane",
+        "name": {"formatted": "Jane Doe"},
+        "emails": [{"value": "jane@example.com", "type": "work", "primary": true}],
+        "active": true
+    });
+
+    patch(
+        &mut user,
+        "Replace",
+        None,
+        json!({"active": "False", "name.givenName": "Jane"}),
+    )
+    .unwrap();
+    assert_eq!(json!("False"), user["active"]);
+    assert_eq!(json!("Jane"), user["name"]["givenName"]);
+    assert_eq!(json!("Jane Doe"), user["name"]["formatted"]);
+
+    patch(
+        &mut user,
+        "replace",
+        Some("emails[type eq \"work\"].value"),
+        json!("j@example.com"),
+    )
+    .unwrap();
+    patch(
+        &mut user,
+        "add",
+        Some("emails[type eq \"home\"].value"),
+        json!("home@example.com"),
+    )
+    .unwrap();
+    assert_eq!(json!("j@example.com"), user["emails"][0]["value"]);
+    assert_eq!(json!(true), user["emails"][0]["primary"]);
+    assert_eq!(
+        json!({"type": "home", "value": "home@example.com"}),
+        user["emails"][1]
+    );
+
+    patch(
+        &mut user,
+        "replace",
+        Some(&format!("{}:userName", USER_SCHEMA)),
+        json!("janet"),
+    )
+    .unwrap();
+    assert_eq!(json!("janet"), user["userName"]);
+    patch(
+        &mut user,
+        "add",
+        Some("urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:department"),
+        json!("Sales"),
+    )
+    .unwrap();
+    assert!(user
+        .get("urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:department")
+        .is_none());
+
+    let mut group = json!({"displayName": "Staff", "members": [{"value": "a"}, {"value": "b"}]});
+    patch(
+        &mut group,
+        "add",
+        Some("members"),
+        json!([{"value": "b"}, {"value": "c"}]),
+    )
+    .unwrap();
+    assert_eq!(
+        json!([{"value": "a"}, {"value": "b"}, {"value": "c"}]),
+        group["members"]
+    );
+    patch(
+        &mut group,
+        "remove",
+        Some("members[value eq \"a\"]"),
+        Value::Null,
+    )
+    .unwrap();
+    patch(
+        &mut group,
+        "remove",
+        Some("members"),
+        json!([{"value": "c"}]),
+    )
+    .unwrap();
+    assert_eq!(json!([{"value": "b"}]), group["members"]);
+    assert!(patch(&mut group, "move", Some("members"), Value::Null).is_err());
+}
@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+pub use authserver_client::models::session::{Session, SignInResponse};
+
+/// Where a sign-in comes from, as recorded with its session.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+/// A session authenticated by a cookie, and the CSRF token its pages send.
+pub struct BrowserSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub csrf_token: String,
+    /// When the user signed in.
+    pub created_at: DateTime<Utc>,
+}
@@ -1,27 +1,99 @@
 // use chrono::NaiveDateTime;
-use secrecy::Secret;
-use serde::{Deserialize, Serialize};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
 use uuid::Uuid;
 use validator::Validate;
 
-#[derive(Serialize, Deserialize, Debug)]
+use super::auth::{take_credentials, BodyCredentials, Credentials};
+
+/// `users.auth_source`: where a user's password is checked.
+pub const LOCAL_AUTH_SOURCE: &str = "local";
+pub const LDAP_AUTH_SOURCE: &str = "ldap";
+/// Signs in through a SAML identity provider only.
+pub const SAML_AUTH_SOURCE: &str = "saml";
+/// Provisioned by a SCIM client without a password: signs in through an identity provider.
+pub const SCIM_AUTH_SOURCE: &str = "scim";
+
+pub use authserver_client::models::user::DeletedUser;
+pub use authserver_client::models::User as Profile;
+
+/// A user of the realm as stored; `Profile` is what the API shows of them.
+#[derive(Debug)]
 pub struct User {
     pub id: Uuid,
     pub username: Option<String>,
     pub email: String,
-    #[serde(skip_serializing)]
     pub password_hash: String,
     pub full_name: Option<String>,
     pub bio: Option<String>,
     pub image: Option<String>,
-    #[serde(skip_serializing)]
     pub email_verified: bool,
-    #[serde(skip_serializing)]
     pub active: bool,
+    pub roles: Vec<String>,
     // pub created_at: NaiveDateTime,
     // pub updated_at: NaiveDateTime,
 }
 
+impl User {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
+impl From<User> for Profile {
+    fn from(user: User) -> Self {
+        Profile {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            full_name: user.full_name,
+            bio: user.bio,
+            image: user.image,
+            roles: user.roles,
+        }
+    }
+}
+
+/// What an external source of users (a directory, an identity provider)
+/// knows about someone, copied to their `User` whenever they sign in.
+#[derive(Debug, Default, PartialEq)]
+pub struct ExternalProfile {
+    pub email: Option<String>,
+    pub full_name: Option<String>,
+}
+
+/// The hash of users without a password of their own: it matches none.
+pub const UNUSABLE_PASSWORD_HASH: &str = "!";
+
+/// What `validate_credentials` needs to know of a user.
+#[derive(Debug)]
+pub struct StoredCredentials {
+    pub id: Uuid,
+    pub password_hash: Secret<String>,
+    /// `local`, `ldap`, `saml` or `scim`.
+    pub auth_source: String,
+    pub active: bool,
+}
+
+impl StoredCredentials {
+    /// Whether the user signs in with a password of their own.
+    pub fn has_password(&self) -> bool {
+        self.auth_source == LOCAL_AUTH_SOURCE
+            && self.password_hash.expose_secret() != UNUSABLE_PASSWORD_HASH
+    }
+}
+
+/// A user as a SCIM provisioning client sets it, on creation or replacement.
+#[derive(Debug)]
+pub struct ProvisionedUser {
+    pub username: String,
+    pub email: String,
+    pub full_name: Option<String>,
+    pub active: bool,
+    /// Makes the user a `local` one; without it, they keep their password, if any.
+    pub password_hash: Option<Secret<String>>,
+}
+
 #[derive(Debug, Validate)]
 pub struct NewUser {
     #[validate(length(min = 3))]
@@ -31,16 +103,20 @@ pub struct NewUser {
     pub password_hash: Secret<String>,
 }
 
+/// The body of `/signup`: a form with the email and the credentials in the
+/// Basic header, or JSON with all three.
 #[derive(Debug, Deserialize, Validate)]
-pub struct UpdateProfile {
-    pub full_name: Option<String>,
-    pub bio: Option<String>,
-    #[validate(url)]
-    pub image: Option<String>,
-}
-
-#[derive(Debug, Deserialize, Validate)]
-pub struct ValidateEmail {
+pub struct SignupBody {
+    #[serde(default)]
+    pub username: Option<String>,
     #[validate(email)]
     pub email: String,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl BodyCredentials for SignupBody {
+    fn take_credentials(&mut self) -> Option<Credentials> {
+        take_credentials(&mut self.username, &mut self.password)
+    }
 }
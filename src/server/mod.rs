@@ -1,3 +1,5 @@
+pub(crate) mod negotiation;
+pub(crate) mod openapi;
 pub(crate) mod routes;
 
 // use crate::config::DBPool;
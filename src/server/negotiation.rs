@@ -0,0 +1,242 @@
+//! Reading request bodies and choosing the format of responses.
+
+use std::convert::Infallible;
+
+use futures_util::{Stream, TryStreamExt};
+use serde::de::DeserializeOwned;
+use warp::http::header::ACCEPT;
+use warp::http::HeaderMap;
+use warp::hyper::body::Buf;
+use warp::{body, reject, Filter, Rejection};
+
+use crate::errors::Error::{BodyError, NotAcceptable, PayloadTooLarge, UnsupportedMediaType};
+
+/// Request bodies over this many bytes are refused with 413.
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// What a response can be sent as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Text,
+}
+
+impl Format {
+    fn media_type(self) -> (&'static str, &'static str) {
+        match self {
+            Format::Json => ("application", "json"),
+            Format::Text => ("text", "plain"),
+        }
+    }
+}
+
+/// The body formats requests can be sent in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyFormat {
+    Json,
+    Form,
+}
+
+/// The format of a body by its Content-Type: JSON for `application/json`
+/// and `application/*+json`, a form for `application/x-www-form-urlencoded`,
+/// and `default` when the client did not say.
+fn body_format(content_type: Option<&str>, default: BodyFormat) -> Option<BodyFormat> {
+    let content_type = match content_type {
+        Some(content_type) => content_type,
+        None => return Some(default),
+    };
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    let essence = essence.to_ascii_lowercase();
+    match essence.split_once('/') {
+        Some(("application", "json")) => Some(BodyFormat::Json),
+        Some(("application", subtype)) if subtype.ends_with("+json") => Some(BodyFormat::Json),
+        Some(("application", "x-www-form-urlencoded")) => Some(BodyFormat::Form),
+        _ => None,
+    }
+}
+
+/// The body, read up to `MAX_BODY_BYTES` whether or not it came with a
+/// Content-Length.
+pub fn limited_body() -> impl Filter<Extract = (Vec<u8>,), Error = Rejection> + Clone {
+    warp::header::optional::<u64>("content-length")
+        .and_then(|length: Option<u64>| async move {
+            match length {
+                Some(length) if length > MAX_BODY_BYTES as u64 => {
+                    Err(reject::custom(PayloadTooLarge(MAX_BODY_BYTES)))
+                }
+                _ => Ok(()),
+            }
+        })
+        .untuple_one()
+        .and(body::stream())
+        .and_then(read_limited)
+}
+
+async fn read_limited<S, B>(stream: S) -> Result<Vec<u8>, Rejection>
+where
+    S: Stream<Item = Result<B, warp::Error>>,
+    B: Buf,
+{
+    futures_util::pin_mut!(stream);
+    let mut bytes = Vec::new();
+    while let Some(mut chunk) = stream
+        .try_next()
+        .await
+        .map_err(|e| reject::custom(BodyError(e.to_string())))?
+    {
+        if bytes.len() + chunk.remaining() > MAX_BODY_BYTES {
+            return Err(reject::custom(PayloadTooLarge(MAX_BODY_BYTES)));
+        }
+        while chunk.has_remaining() {
+            let part = chunk.chunk();
+            bytes.extend_from_slice(part);
+            let read = part.len();
+            chunk.advance(read);
+        }
+    }
+    Ok(bytes)
+}
+
+fn parse<T: DeserializeOwned>(format: BodyFormat, bytes: &[u8]) -> Result<T, Rejection> {
+    let parsed = match format {
+        BodyFormat::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+        BodyFormat::Form => serde_urlencoded::from_bytes(bytes).map_err(|e| e.to_string()),
+    };
+    parsed.map_err(|e| reject::custom(BodyError(e)))
+}
+
+fn body_of<T: DeserializeOwned + Send>(
+    accepted: &'static [BodyFormat],
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("content-type")
+        .and(limited_body())
+        .and_then(
+            move |content_type: Option<String>, bytes: Vec<u8>| async move {
+                match body_format(content_type.as_deref(), accepted[0]) {
+                    Some(format) if accepted.contains(&format) => parse(format, &bytes),
+                    _ => Err(reject::custom(UnsupportedMediaType)),
+                }
+            },
+        )
+}
+
+/// A JSON body.
+pub fn json_body<T: DeserializeOwned + Send>(
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    body_of(&[BodyFormat::Json])
+}
+
+/// An `application/x-www-form-urlencoded` body.
+pub fn form_body<T: DeserializeOwned + Send>(
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    body_of(&[BodyFormat::Form])
+}
+
+/// A form or a JSON body, by its Content-Type; a form without one.
+pub fn json_or_form_body<T: DeserializeOwned + Send>(
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    body_of(&[BodyFormat::Form, BodyFormat::Json])
+}
+
+/// The quality the Accept header gives `format`: that of the most specific
+/// media range matching it, 0 when none does.
+fn quality(accept: &str, format: Format) -> f32 {
+    let (kind, subtype) = format.media_type();
+    let mut best: Option<(u8, f32)> = None;
+    for range in accept.split(',') {
+        let mut params = range.split(';');
+        let media_range = params.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        let specificity = match media_range.split_once('/') {
+            Some(("*", "*")) => 0,
+            Some((k, "*")) if k == kind => 1,
+            Some((k, s)) if k == kind && s == subtype => 2,
+            _ => continue,
+        };
+        if best.is_none_or(|(most, _)| specificity > most) {
+            best = Some((specificity, q));
+        }
+    }
+    best.map_or(0.0, |(_, q)| q)
+}
+
+/// Which of `offered` the client prefers, the first one on ties or without
+/// an Accept header; none if it accepts none of them.
+pub fn negotiate(accept: Option<&str>, offered: &[Format]) -> Option<Format> {
+    let accept = match accept.map(str::trim) {
+        Some(accept) if !accept.is_empty() => accept,
+        _ => return offered.first().copied(),
+    };
+    let mut chosen: Option<(Format, f32)> = None;
+    for &format in offered {
+        let q = quality(accept, format);
+        if q > 0.0 && chosen.is_none_or(|(_, best)| q > best) {
+            chosen = Some((format, q));
+        }
+    }
+    chosen.map(|(format, _)| format)
+}
+
+/// The format to answer in among `offered`, refusing with 406 clients that
+/// accept none of them.
+pub fn with_format(
+    offered: &'static [Format],
+) -> impl Filter<Extract = (Format,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept").and_then(move |accept: Option<String>| async move {
+        negotiate(accept.as_deref(), offered).ok_or_else(|| reject::custom(NotAcceptable))
+    })
+}
+
+/// As `with_format`, but falls back to the first of `offered` rather than
+/// refusing, for the pages browsers are sent through.
+pub fn preferred_format(
+    offered: &'static [Format],
+) -> impl Filter<Extract = (Format,), Error = Infallible> + Clone {
+    warp::header::headers_cloned().map(move |headers: HeaderMap| {
+        let accept = headers.get(ACCEPT).and_then(|value| value.to_str().ok());
+        negotiate(accept, offered).unwrap_or(offered[0])
+    })
+}
+
+/// Refuses with 406 clients that do not accept JSON.
+pub fn accepts_json() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    with_format(&[Format::Json]).map(|_| ()).untuple_one()
+}
+
+#[test]
+fn test_negotiate() {
+    let both = &[Format::Json, Format::Text];
+    assert_eq!(Some(Format::Json), negotiate(None, both));
+    assert_eq!(Some(Format::Json), negotiate(Some("*/*"), both));
+    assert_eq!(Some(Format::Text), negotiate(Some("text/plain"), both));
+    assert_eq!(
+        Some(Format::Text),
+        negotiate(Some("application/json;q=0.5, text/*"), both)
+    );
+    assert_eq!(
+        Some(Format::Json),
+        negotiate(Some("text/html, */*;q=0.8"), both)
+    );
+    assert_eq!(None, negotiate(Some("text/html"), &[Format::Json]));
+    assert_eq!(
+        None,
+        negotiate(Some("application/json;q=0, */*"), &[Format::Json])
+    );
+}
+
+#[test]
+fn test_body_format() {
+    assert_eq!(
+        Some(BodyFormat::Json),
+        body_format(Some("application/json; charset=utf-8"), BodyFormat::Form)
+    );
+    assert_eq!(
+        Some(BodyFormat::Json),
+        body_format(Some("application/scim+json"), BodyFormat::Form)
+    );
+    assert_eq!(Some(BodyFormat::Form), body_format(None, BodyFormat::Form));
+    assert_eq!(None, body_format(Some("text/xml"), BodyFormat::Json));
+}
@@ -0,0 +1,1096 @@
+//! The OpenAPI 3.1 description of the routes of `make_routes`, served at
+//! `/openapi.json`. Each operation is named after its route, which the tests
+//! below check against `routes.rs`.
+
+use serde_json::{json, Map, Value};
+
+use crate::config::realm::api_url;
+
+const JSON: &str = "application/json";
+const FORM: &str = "application/x-www-form-urlencoded";
+const HTML: &str = "text/html";
+const SCIM: &str = "application/scim+json";
+
+/// The ways an operation can be called, as named in `securitySchemes`.
+const TOKEN: &[&str] = &["bearerAuth", "cookieAuth"];
+const BASIC: &[&str] = &["basicAuth"];
+const PUBLIC: &[&str] = &[];
+
+/// The body an operation answers or accepts, by media type.
+type Content = &'static [(&'static str, &'static str)];
+
+/// How an operation answers its errors.
+#[derive(Clone, Copy)]
+enum Errors {
+    Problem,
+    OAuth,
+    Scim,
+}
+
+pub(crate) struct Operation {
+    /// The name of the route in `make_routes`.
+    pub id: &'static str,
+    pub method: &'static str,
+    /// With `{name}` path parameters.
+    pub path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    security: &'static [&'static str],
+    query: &'static [(&'static str, &'static str)],
+    request: Content,
+    responses: &'static [(u16, &'static str, Content)],
+    errors: Errors,
+}
+
+impl Operation {
+    const fn new(id: &'static str, method: &'static str, path: &'static str) -> Self {
+        Operation {
+            id,
+            method,
+            path,
+            tag: "",
+            summary: "",
+            security: PUBLIC,
+            query: &[],
+            request: &[],
+            responses: &[],
+            errors: Errors::Problem,
+        }
+    }
+}
+
+macro_rules! operation {
+    ($id:ident, $method:literal, $path:literal, $tag:literal, $summary:literal $(, $field:ident: $value:expr)* $(,)?) => {
+        Operation {
+            tag: $tag,
+            summary: $summary,
+            $($field: $value,)*
+            ..Operation::new(stringify!($id), $method, $path)
+        }
+    };
+}
+
+pub(crate) const OPERATIONS: &[Operation] = &[
+    operation!(health, "get", "/health", "meta", "Whether the server can reach its database",
+        responses: &[(200, "The server is up.", &[])]),
+    operation!(user_agent, "get", "/hello/{name}", "meta", "Greets the caller and echoes their user agent",
+        responses: &[(200, "A greeting.", &[("text/plain", "")])]),
+    operation!(openapi, "get", "/openapi.json", "meta", "This document",
+        responses: &[(200, "The OpenAPI document.", &[(JSON, "")])]),
+    operation!(signup, "post", "/signup", "account", "Creates a user and signs them in",
+        security: &["", "basicAuth"],
+        request: &[(JSON, "SignupRequest"), (FORM, "SignupRequest")],
+        responses: &[(200, "The user's token, bare to clients that accept only `text/plain`.",
+            &[(JSON, "SignInResponse"), ("text/plain", "")])]),
+    operation!(login, "post", "/login", "account", "Signs a user in",
+        security: &["", "basicAuth"],
+        request: &[(JSON, "LoginRequest"), (FORM, "LoginRequest")],
+        responses: &[(200, "The user's token, and the session cookies in realms with cookie sessions.",
+            &[(JSON, "SignInResponse"), ("text/plain", "")])]),
+    operation!(logout, "post", "/logout", "account", "Ends the session of the token",
+        security: TOKEN,
+        responses: &[(204, "Signed out.", &[])]),
+    operation!(me, "get", "/me", "account", "The profile of the signed-in user",
+        security: TOKEN,
+        responses: &[(200, "The user.", &[(JSON, "User")])]),
+    operation!(delete, "delete", "/me", "account", "Deletes the signed-in user",
+        security: TOKEN,
+        responses: &[(200, "The user was deleted.", &[(JSON, "DeletedUser")])]),
+    operation!(list_sessions, "get", "/me/sessions", "account", "The user's sessions",
+        security: TOKEN,
+        responses: &[(200, "The sessions.", &[(JSON, "Session[]")])]),
+    operation!(revoke_session, "delete", "/me/sessions/{id}", "account", "Signs one of the user's sessions out",
+        security: TOKEN,
+        responses: &[(204, "Revoked.", &[])]),
+    operation!(list_grants, "get", "/me/grants", "account", "The clients the user consented to",
+        security: TOKEN,
+        responses: &[(200, "The grants.", &[(JSON, "UserGrant[]")])]),
+    operation!(revoke_grant, "delete", "/me/grants/{client_id}", "account", "Withdraws the user's consent to a client",
+        security: TOKEN,
+        responses: &[(204, "Revoked, with the client's tokens.", &[])]),
+    operation!(list_identities, "get", "/me/identities", "account", "The ways the user signs in",
+        security: TOKEN,
+        responses: &[(200, "The login identities.", &[(JSON, "LoginIdentity[]")])]),
+    operation!(add_identity, "post", "/me/identities/{provider}", "account", "Adds a way to sign in",
+    security: TOKEN,
+    request: &[(FORM, "AddIdentityForm")],
+    responses: &[
+        (200, "Where to send the user to link an upstream account.", &[(JSON, "LinkStarted")]),
+        (204, "The password was set.", &[]),
+    ]),
+    operation!(remove_identity, "delete", "/me/identities/{provider}", "account", "Removes a way to sign in",
+        security: TOKEN,
+        responses: &[(204, "Removed.", &[])]),
+    operation!(verify, "get", "/auth/verify", "account", "Forward authentication for reverse proxies",
+        security: TOKEN,
+        responses: &[(200, "The request may go through; `X-Auth-User-*` headers describe the user.", &[])]),
+    operation!(authorize, "get", "/authorize", "oauth", "Starts an authorization code flow",
+        query: &[
+            ("response_type", "`code`"), ("client_id", "The client"), ("redirect_uri", "One of the client's"),
+            ("scope", "Space-separated scopes"), ("state", "Returned as is"),
+            ("code_challenge", "PKCE challenge"), ("code_challenge_method", "`S256`"), ("nonce", "Put in the ID token"),
+        ],
+        responses: &[(200, "The login page.", &[(HTML, "")]), (302, "Back to the client.", &[])]),
+    operation!(authorize_login, "post", "/authorize", "oauth", "Submits the login page",
+        request: &[(FORM, "")],
+        responses: &[(200, "The consent page.", &[(HTML, "")]), (302, "Back to the client with a code.", &[])]),
+    operation!(authorize_consent, "post", "/authorize/consent", "oauth", "Submits the consent page",
+        request: &[(FORM, "")],
+        responses: &[(302, "Back to the client with a code or `access_denied`.", &[])]),
+    operation!(token, "post", "/token", "oauth", "Issues tokens (RFC 6749, RFC 8628, RFC 8693)",
+        security: &["", "basicAuth"],
+        request: &[(FORM, "TokenRequest")],
+        responses: &[(200, "The tokens.", &[(JSON, "TokenResponse")])],
+        errors: Errors::OAuth),
+    operation!(device_authorization, "post", "/device_authorization", "oauth", "Starts a device flow (RFC 8628)",
+        security: &["", "basicAuth"],
+        request: &[(FORM, "DeviceAuthorizationRequest")],
+        responses: &[(200, "The codes.", &[(JSON, "DeviceAuthorizationResponse")])],
+        errors: Errors::OAuth),
+    operation!(device_verification, "get", "/device", "oauth", "The page users enter a device's code on",
+        query: &[("user_code", "The code the device shows")],
+        responses: &[(200, "The verification page.", &[(HTML, "")])]),
+    operation!(device_approval, "post", "/device", "oauth", "Submits the verification page",
+        security: TOKEN,
+        request: &[(FORM, "")],
+        responses: &[(200, "Whether the device was approved.", &[(HTML, "")])]),
+    operation!(introspect, "post", "/introspect", "oauth", "Describes a token (RFC 7662)",
+        security: BASIC,
+        request: &[(FORM, "TokenReference")],
+        responses: &[(200, "The token's state.", &[(JSON, "IntrospectionResponse")])],
+        errors: Errors::OAuth),
+    operation!(revoke, "post", "/revoke", "oauth", "Revokes a token (RFC 7009)",
+        security: &["", "basicAuth"],
+        request: &[(FORM, "TokenReference")],
+        responses: &[(200, "Revoked, or unknown.", &[])],
+        errors: Errors::OAuth),
+    operation!(discovery, "get", "/.well-known/openid-configuration", "oidc", "OpenID provider metadata",
+        responses: &[(200, "The metadata.", &[(JSON, "ProviderMetadata")])]),
+    operation!(jwks, "get", "/.well-known/jwks.json", "oidc", "The keys ID tokens are signed with",
+        responses: &[(200, "The keys.", &[(JSON, "Jwks")])]),
+    operation!(userinfo, "get", "/userinfo", "oidc", "The claims of the token's user",
+        security: &["bearerAuth"],
+        responses: &[(200, "The claims the token's scopes allow.", &[(JSON, "UserInfo")])],
+        errors: Errors::OAuth),
+    operation!(userinfo, "post", "/userinfo", "oidc", "The claims of the token's user",
+        security: &["bearerAuth"],
+        responses: &[(200, "The claims the token's scopes allow.", &[(JSON, "UserInfo")])],
+        errors: Errors::OAuth),
+    operation!(federated_login, "get", "/login/{provider}", "federation", "Signs in with an upstream OpenID provider",
+        query: &[("login_hint", "Passed on to the provider")],
+        responses: &[(302, "To the provider.", &[])]),
+    operation!(federated_callback, "get", "/login/{provider}/callback", "federation", "Where the upstream provider sends the user back",
+        query: &[("code", ""), ("state", ""), ("error", ""), ("error_description", "")],
+        responses: &[(200, "The user's token, or the page linking the account.",
+            &[(JSON, "SignInResponse"), (HTML, "")])]),
+    operation!(link_identity, "post", "/login/{provider}/link", "federation", "Links an upstream account to a local one",
+        request: &[(FORM, "")],
+        responses: &[(200, "The user's token.", &[(JSON, "SignInResponse"), (HTML, "")])]),
+    operation!(saml_metadata, "get", "/saml/{provider}/metadata", "federation", "Our SAML service provider metadata",
+        responses: &[(200, "The metadata.", &[("application/samlmetadata+xml", "")])]),
+    operation!(saml_login, "get", "/saml/{provider}/login", "federation", "Signs in with a SAML identity provider",
+        responses: &[(302, "To the identity provider.", &[])]),
+    operation!(saml_acs, "post", "/saml/{provider}/acs", "federation", "The SAML assertion consumer service",
+        request: &[(FORM, "")],
+        responses: &[(200, "The user's token.", &[(JSON, "SignInResponse"), (HTML, "")])]),
+    operation!(register, "post", "/register", "clients", "Registers a client (RFC 7591)",
+        request: &[(JSON, "ClientMetadata")],
+        responses: &[(201, "The client, pending approval.", &[(JSON, "RegistrationResponse")])],
+        errors: Errors::OAuth),
+    operation!(get_registration, "get", "/register/{client_id}", "clients", "A registered client (RFC 7592)",
+        security: &["bearerAuth"],
+        responses: &[(200, "The client.", &[(JSON, "RegistrationResponse")])],
+        errors: Errors::OAuth),
+    operation!(update_registration, "put", "/register/{client_id}", "clients", "Replaces a client's metadata (RFC 7592)",
+        security: &["bearerAuth"],
+        request: &[(JSON, "ClientMetadata")],
+        responses: &[(200, "The client.", &[(JSON, "RegistrationResponse")])],
+        errors: Errors::OAuth),
+    operation!(delete_registration, "delete", "/register/{client_id}", "clients", "Deletes a registered client (RFC 7592)",
+        security: &["bearerAuth"],
+        responses: &[(204, "Deleted.", &[])],
+        errors: Errors::OAuth),
+    operation!(list_clients, "get", "/admin/clients", "clients", "Every client of the realm",
+        security: TOKEN,
+        responses: &[(200, "The clients.", &[(JSON, "ClientSummary[]")])]),
+    operation!(create_client, "post", "/admin/clients", "clients", "Creates an approved client",
+        security: TOKEN,
+        request: &[(JSON, "NewClient")],
+        responses: &[(201, "The client's credentials.", &[(JSON, "ClientCredentials")])]),
+    operation!(approve_client, "post", "/admin/clients/{client_id}/approve", "clients", "Approves a registered client",
+        security: TOKEN,
+        responses: &[(204, "Approved.", &[])]),
+    operation!(rotate_client_secret, "post", "/admin/clients/{client_id}/secret", "clients", "Issues a new client secret",
+        security: TOKEN,
+        responses: &[(200, "The new credentials.", &[(JSON, "ClientCredentials")])]),
+    operation!(scim_list_users, "get", "/scim/v2/Users", "scim", "Lists users (RFC 7644)",
+        security: TOKEN,
+        query: &[("filter", "Such as `userName eq \"jane\"`"), ("startIndex", "1-based"), ("count", "Page size")],
+        responses: &[(200, "A page of users.", &[(SCIM, "ScimListResponse")])],
+        errors: Errors::Scim),
+    operation!(scim_create_user, "post", "/scim/v2/Users", "scim", "Provisions a user",
+        security: TOKEN,
+        request: &[(SCIM, "ScimUser"), (JSON, "ScimUser")],
+        responses: &[(201, "The user.", &[(SCIM, "ScimUser")])],
+        errors: Errors::Scim),
+    operation!(scim_get_user, "get", "/scim/v2/Users/{id}", "scim", "A user",
+        security: TOKEN,
+        responses: &[(200, "The user.", &[(SCIM, "ScimUser")])],
+        errors: Errors::Scim),
+    operation!(scim_replace_user, "put", "/scim/v2/Users/{id}", "scim", "Replaces a user",
+        security: TOKEN,
+        request: &[(SCIM, "ScimUser"), (JSON, "ScimUser")],
+        responses: &[(200, "The user.", &[(SCIM, "ScimUser")])],
+        errors: Errors::Scim),
+    operation!(scim_patch_user, "patch", "/scim/v2/Users/{id}", "scim", "Modifies a user",
+        security: TOKEN,
+        request: &[(SCIM, "ScimPatchRequest"), (JSON, "ScimPatchRequest")],
+        responses: &[(200, "The user.", &[(SCIM, "ScimUser")])],
+        errors: Errors::Scim),
+    operation!(scim_delete_user, "delete", "/scim/v2/Users/{id}", "scim", "Deprovisions a user",
+        security: TOKEN,
+        responses: &[(204, "Deleted.", &[])],
+        errors: Errors::Scim),
+    operation!(scim_list_groups, "get", "/scim/v2/Groups", "scim", "Lists groups",
+        security: TOKEN,
+        query: &[("filter", "Such as `displayName eq \"admins\"`"), ("startIndex", "1-based"), ("count", "Page size")],
+        responses: &[(200, "A page of groups.", &[(SCIM, "ScimListResponse")])],
+        errors: Errors::Scim),
+    operation!(scim_create_group, "post", "/scim/v2/Groups", "scim", "Creates a group",
+        security: TOKEN,
+        request: &[(SCIM, "ScimGroup"), (JSON, "ScimGroup")],
+        responses: &[(201, "The group.", &[(SCIM, "ScimGroup")])],
+        errors: Errors::Scim),
+    operation!(scim_get_group, "get", "/scim/v2/Groups/{id}", "scim", "A group",
+        security: TOKEN,
+        responses: &[(200, "The group.", &[(SCIM, "ScimGroup")])],
+        errors: Errors::Scim),
+    operation!(scim_replace_group, "put", "/scim/v2/Groups/{id}", "scim", "Replaces a group",
+        security: TOKEN,
+        request: &[(SCIM, "ScimGroup"), (JSON, "ScimGroup")],
+        responses: &[(200, "The group.", &[(SCIM, "ScimGroup")])],
+        errors: Errors::Scim),
+    operation!(scim_patch_group, "patch", "/scim/v2/Groups/{id}", "scim", "Modifies a group's members",
+        security: TOKEN,
+        request: &[(SCIM, "ScimPatchRequest"), (JSON, "ScimPatchRequest")],
+        responses: &[(200, "The group.", &[(SCIM, "ScimGroup")])],
+        errors: Errors::Scim),
+    operation!(scim_delete_group, "delete", "/scim/v2/Groups/{id}", "scim", "Deletes a group",
+        security: TOKEN,
+        responses: &[(204, "Deleted.", &[])],
+        errors: Errors::Scim),
+];
+
+/// The operation serving `method` on `path`, a path of the current version
+/// without its prefixes.
+pub(crate) fn operation_for(method: &str, path: &str) -> Option<&'static Operation> {
+    let matches = |operation: &Operation| {
+        let mut template = operation.path.split('/');
+        let mut segments = path.split('/');
+        loop {
+            match (template.next(), segments.next()) {
+                (None, None) => return true,
+                (Some(expected), Some(segment))
+                    if expected == segment || expected.starts_with('{') && !segment.is_empty() => {}
+                _ => return false,
+            }
+        }
+    };
+    OPERATIONS
+        .iter()
+        .find(|operation| operation.method.eq_ignore_ascii_case(method) && matches(operation))
+}
+
+/// A schema by name: a component, a `[]`-suffixed array of one, or any
+/// value for the empty name.
+fn schema(name: &str) -> Value {
+    match name.strip_suffix("[]") {
+        Some(item) => json!({ "type": "array", "items": schema(item) }),
+        None if name.is_empty() => json!({}),
+        None => json!({ "$ref": format!("#/components/schemas/{}", name) }),
+    }
+}
+
+fn content(content: Content) -> Value {
+    content
+        .iter()
+        .map(|(media_type, name)| (media_type.to_string(), json!({ "schema": schema(name) })))
+        .collect::<Map<_, _>>()
+        .into()
+}
+
+fn parameters(operation: &Operation) -> Vec<Value> {
+    let in_path = operation
+        .path
+        .split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+        .map(|name| {
+            let schema = match name {
+                "id" | "client_id" if !operation.path.starts_with("/scim/") => {
+                    json!({ "type": "string", "format": "uuid" })
+                }
+                _ => json!({ "type": "string" }),
+            };
+            json!({ "name": name, "in": "path", "required": true, "schema": schema })
+        });
+    let in_query = operation.query.iter().map(|(name, description)| {
+        json!({
+            "name": name,
+            "in": "query",
+            "description": description,
+            "schema": { "type": "string" },
+        })
+    });
+    in_path.chain(in_query).collect()
+}
+
+fn responses(operation: &Operation) -> Value {
+    let mut responses: Map<String, Value> = operation
+        .responses
+        .iter()
+        .map(|(status, description, body)| {
+            let mut response = json!({ "description": description });
+            if !body.is_empty() {
+                response["content"] = content(body);
+            }
+            (status.to_string(), response)
+        })
+        .collect();
+    let errors = match operation.errors {
+        Errors::Problem => json!({ "application/problem+json": { "schema": schema("Problem") } }),
+        Errors::OAuth => json!({
+            JSON: { "schema": schema("OAuthError") },
+            "application/problem+json": { "schema": schema("Problem") },
+        }),
+        Errors::Scim => json!({
+            SCIM: { "schema": schema("ScimError") },
+            "application/problem+json": { "schema": schema("Problem") },
+        }),
+    };
+    responses.insert(
+        "default".to_string(),
+        json!({ "description": "An error.", "content": errors }),
+    );
+    responses.into()
+}
+
+fn security(names: &[&str]) -> Value {
+    names
+        .iter()
+        .map(|name| match *name {
+            "" => json!({}),
+            name => json!({ name: [] }),
+        })
+        .collect()
+}
+
+fn paths() -> Value {
+    let mut paths = Map::new();
+    for operation in OPERATIONS {
+        // Routes answering several methods are one operation per method.
+        let operation_id = if OPERATIONS.iter().filter(|op| op.id == operation.id).count() > 1 {
+            format!("{}_{}", operation.id, operation.method)
+        } else {
+            operation.id.to_string()
+        };
+        let mut described = json!({
+            "operationId": operation_id,
+            "tags": [operation.tag],
+            "summary": operation.summary,
+            "responses": responses(operation),
+        });
+        let parameters = parameters(operation);
+        if !parameters.is_empty() {
+            described["parameters"] = parameters.into();
+        }
+        if !operation.request.is_empty() {
+            described["requestBody"] =
+                json!({ "required": true, "content": content(operation.request) });
+        }
+        if !operation.security.is_empty() {
+            described["security"] = security(operation.security);
+        }
+        let item = paths
+            .entry(operation.path.to_string())
+            .or_insert_with(|| json!({}));
+        item[operation.method] = described;
+    }
+    paths.into()
+}
+
+fn nullable(kind: &str) -> Value {
+    json!({ "type": [kind, "null"] })
+}
+
+fn strings() -> Value {
+    json!({ "type": "array", "items": { "type": "string" } })
+}
+
+fn schemas() -> Value {
+    let uuid = json!({ "type": "string", "format": "uuid" });
+    let date_time = json!({ "type": "string", "format": "date-time" });
+    let string = json!({ "type": "string" });
+    let integer = json!({ "type": "integer" });
+    let boolean = json!({ "type": "boolean" });
+    let schemas = [
+        (
+            "User",
+            json!({
+                "type": "object",
+                "required": ["id", "email"],
+                "properties": {
+                    "id": uuid, "username": nullable("string"), "email": string,
+                    "full_name": nullable("string"), "bio": nullable("string"), "image": nullable("string"),
+                    "roles": strings(),
+                },
+            }),
+        ),
+        (
+            "SignupRequest",
+            json!({
+                "description": "`username` and `password` may be sent in the Basic header instead.",
+                "type": "object",
+                "required": ["email"],
+                "properties": { "username": string, "email": { "type": "string", "format": "email" }, "password": string },
+            }),
+        ),
+        (
+            "LoginRequest",
+            json!({
+                "description": "May be sent in the Basic header instead.",
+                "type": "object",
+                "properties": { "username": string, "password": string },
+            }),
+        ),
+        (
+            "SignInResponse",
+            json!({
+                "type": "object",
+                "required": ["access_token", "token_type", "expires_in"],
+                "properties": { "access_token": string, "token_type": { "const": "Bearer" }, "expires_in": integer },
+            }),
+        ),
+        (
+            "DeletedUser",
+            json!({
+                "type": "object",
+                "required": ["id"],
+                "properties": { "id": uuid },
+            }),
+        ),
+        (
+            "Session",
+            json!({
+                "type": "object",
+                "required": ["id", "created_at", "last_seen_at", "expires_at", "current"],
+                "properties": {
+                    "id": uuid, "user_agent": nullable("string"), "ip": nullable("string"),
+                    "created_at": date_time, "last_seen_at": date_time, "expires_at": date_time, "current": boolean,
+                },
+            }),
+        ),
+        (
+            "UserGrant",
+            json!({
+                "type": "object",
+                "required": ["client_id", "client_name", "scopes", "created_at", "updated_at"],
+                "properties": {
+                    "client_id": uuid, "client_name": string, "scopes": strings(),
+                    "created_at": date_time, "updated_at": date_time,
+                },
+            }),
+        ),
+        (
+            "LoginIdentity",
+            json!({
+                "type": "object",
+                "required": ["provider", "subject"],
+                "properties": { "provider": string, "subject": string, "linked_at": { "type": ["string", "null"], "format": "date-time" } },
+            }),
+        ),
+        (
+            "AddIdentityForm",
+            json!({
+                "description": "`new_password` for `local`, `login_hint` for upstream providers; `password` proves the account when it has one.",
+                "type": "object",
+                "properties": { "password": string, "new_password": string, "login_hint": string },
+            }),
+        ),
+        (
+            "LinkStarted",
+            json!({
+                "type": "object",
+                "required": ["authorization_url"],
+                "properties": { "authorization_url": { "type": "string", "format": "uri" } },
+            }),
+        ),
+        (
+            "Problem",
+            json!({
+                "description": "RFC 7807 problem details.",
+                "type": "object",
+                "required": ["type", "title", "status", "code", "request_id"],
+                "properties": {
+                    "type": { "type": "string", "format": "uri" }, "title": string, "status": integer,
+                    "detail": string, "code": string, "request_id": string,
+                    "errors": { "type": "array", "items": schema("FieldError") },
+                },
+            }),
+        ),
+        (
+            "FieldError",
+            json!({
+                "type": "object",
+                "required": ["field", "code"],
+                "properties": { "field": string, "code": string, "message": string },
+            }),
+        ),
+        (
+            "OAuthError",
+            json!({
+                "type": "object",
+                "required": ["error"],
+                "properties": { "error": string, "error_description": string },
+            }),
+        ),
+        (
+            "ScimError",
+            json!({
+                "type": "object",
+                "required": ["schemas", "status", "detail"],
+                "properties": { "schemas": strings(), "status": string, "scimType": string, "detail": string },
+            }),
+        ),
+        (
+            "TokenRequest",
+            json!({
+                "type": "object",
+                "required": ["grant_type"],
+                "properties": {
+                    "grant_type": string, "code": string, "redirect_uri": string, "client_id": string,
+                    "client_secret": string, "code_verifier": string, "refresh_token": string, "device_code": string,
+                    "scope": string, "subject_token": string, "subject_token_type": string, "actor_token": string,
+                    "requested_token_type": string, "audience": string,
+                },
+            }),
+        ),
+        (
+            "TokenResponse",
+            json!({
+                "type": "object",
+                "required": ["access_token", "token_type", "expires_in", "scope"],
+                "properties": {
+                    "access_token": string, "token_type": string, "expires_in": integer, "scope": string,
+                    "refresh_token": string, "id_token": string, "issued_token_type": string,
+                },
+            }),
+        ),
+        (
+            "DeviceAuthorizationRequest",
+            json!({
+                "type": "object",
+                "properties": { "client_id": string, "client_secret": string, "scope": string },
+            }),
+        ),
+        (
+            "DeviceAuthorizationResponse",
+            json!({
+                "type": "object",
+                "required": ["device_code", "user_code", "verification_uri", "verification_uri_complete", "expires_in", "interval"],
+                "properties": {
+                    "device_code": string, "user_code": string, "verification_uri": string,
+                    "verification_uri_complete": string, "expires_in": integer, "interval": integer,
+                },
+            }),
+        ),
+        (
+            "TokenReference",
+            json!({
+                "type": "object",
+                "required": ["token"],
+                "properties": { "token": string, "client_id": string, "client_secret": string },
+            }),
+        ),
+        (
+            "IntrospectionResponse",
+            json!({
+                "type": "object",
+                "required": ["active"],
+                "properties": {
+                    "active": boolean, "sub": uuid, "exp": integer, "scope": string, "client_id": uuid, "aud": string,
+                },
+            }),
+        ),
+        (
+            "ProviderMetadata",
+            json!({
+                "type": "object",
+                "required": ["issuer", "authorization_endpoint", "token_endpoint", "jwks_uri"],
+                "properties": {
+                    "issuer": string, "authorization_endpoint": string, "token_endpoint": string,
+                    "userinfo_endpoint": string, "introspection_endpoint": string, "revocation_endpoint": string,
+                    "device_authorization_endpoint": string, "registration_endpoint": string, "jwks_uri": string,
+                    "scopes_supported": strings(), "response_types_supported": strings(),
+                    "grant_types_supported": strings(), "subject_types_supported": strings(),
+                    "id_token_signing_alg_values_supported": strings(),
+                    "token_endpoint_auth_methods_supported": strings(),
+                    "code_challenge_methods_supported": strings(), "claims_supported": strings(),
+                },
+            }),
+        ),
+        (
+            "Jwks",
+            json!({
+                "type": "object",
+                "required": ["keys"],
+                "properties": {
+                    "keys": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": { "kty": string, "use": string, "alg": string, "kid": string, "n": string, "e": string },
+                        },
+                    },
+                },
+            }),
+        ),
+        (
+            "UserInfo",
+            json!({
+                "type": "object",
+                "required": ["sub"],
+                "properties": {
+                    "sub": uuid, "preferred_username": string, "name": string, "picture": string,
+                    "email": string, "email_verified": boolean,
+                },
+            }),
+        ),
+        (
+            "ClientMetadata",
+            json!({
+                "type": "object",
+                "properties": {
+                    "client_name": nullable("string"), "redirect_uris": strings(), "grant_types": strings(),
+                    "response_types": { "type": ["array", "null"], "items": string },
+                    "token_endpoint_auth_method": string, "scope": string,
+                },
+            }),
+        ),
+        (
+            "RegistrationResponse",
+            json!({
+                "allOf": [
+                    schema("ClientMetadata"),
+                    {
+                        "type": "object",
+                        "required": ["client_id", "client_id_issued_at", "client_secret_expires_at", "registration_client_uri", "status"],
+                        "properties": {
+                            "client_id": uuid, "client_secret": string, "client_id_issued_at": integer,
+                            "client_secret_expires_at": integer, "registration_access_token": string,
+                            "registration_client_uri": string, "status": string,
+                        },
+                    },
+                ],
+            }),
+        ),
+        (
+            "NewClient",
+            json!({
+                "type": "object",
+                "required": ["name"],
+                "properties": {
+                    "name": string, "redirect_uris": strings(), "scopes": strings(), "grant_types": strings(),
+                    "confidential": boolean, "first_party": boolean,
+                },
+            }),
+        ),
+        (
+            "ClientSummary",
+            json!({
+                "type": "object",
+                "required": ["client_id", "client_name", "status", "redirect_uris", "scopes", "grant_types", "token_endpoint_auth_method", "first_party", "created_at"],
+                "properties": {
+                    "client_id": uuid, "client_name": string, "status": string, "redirect_uris": strings(),
+                    "scopes": strings(), "grant_types": strings(), "token_endpoint_auth_method": string,
+                    "first_party": boolean, "created_at": date_time,
+                },
+            }),
+        ),
+        (
+            "ClientCredentials",
+            json!({
+                "type": "object",
+                "required": ["client_id"],
+                "properties": { "client_id": uuid, "client_secret": nullable("string") },
+            }),
+        ),
+        (
+            "ScimUser",
+            json!({
+                "type": "object",
+                "required": ["userName"],
+                "properties": {
+                    "schemas": strings(), "id": string, "userName": string,
+                    "name": {
+                        "type": "object",
+                        "properties": { "formatted": string, "givenName": string, "familyName": string },
+                    },
+                    "displayName": string,
+                    "emails": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["value"],
+                            "properties": { "value": string, "type": string, "primary": boolean },
+                        },
+                    },
+                    "active": boolean, "password": { "type": "string", "writeOnly": true }, "meta": schema("ScimMeta"),
+                },
+            }),
+        ),
+        (
+            "ScimGroup",
+            json!({
+                "type": "object",
+                "required": ["displayName"],
+                "properties": {
+                    "schemas": strings(), "id": string, "displayName": string,
+                    "members": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["value"],
+                            "properties": { "value": string, "display": string },
+                        },
+                    },
+                    "meta": schema("ScimMeta"),
+                },
+            }),
+        ),
+        (
+            "ScimMeta",
+            json!({
+                "type": "object",
+                "required": ["resourceType", "location"],
+                "properties": { "resourceType": string, "location": string, "created": date_time, "lastModified": date_time },
+            }),
+        ),
+        (
+            "ScimListResponse",
+            json!({
+                "type": "object",
+                "required": ["schemas", "totalResults", "startIndex", "itemsPerPage", "Resources"],
+                "properties": {
+                    "schemas": strings(), "totalResults": integer, "startIndex": integer, "itemsPerPage": integer,
+                    "Resources": { "type": "array", "items": { "oneOf": [schema("ScimUser"), schema("ScimGroup")] } },
+                },
+            }),
+        ),
+        (
+            "ScimPatchRequest",
+            json!({
+                "type": "object",
+                "required": ["Operations"],
+                "properties": {
+                    "schemas": strings(),
+                    "Operations": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["op"],
+                            "properties": { "op": { "enum": ["add", "remove", "replace"] }, "path": string, "value": {} },
+                        },
+                    },
+                },
+            }),
+        ),
+    ];
+    schemas
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect::<Map<_, _>>()
+        .into()
+}
+
+/// The document for the realm of `issuer`, served on the current version.
+pub fn document(issuer: &str) -> Value {
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "authserver",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Authentication server. For the realm `name`, the API is served under `/realms/{name}/v1`. The unprefixed paths of before versioning are deprecated aliases.",
+        },
+        "servers": [{ "url": api_url(issuer) }],
+        "tags": [
+            { "name": "account", "description": "Signing up and in, and the signed-in user" },
+            { "name": "oauth", "description": "OAuth 2.0 authorization server" },
+            { "name": "oidc", "description": "OpenID Connect provider" },
+            { "name": "federation", "description": "Signing in with upstream OpenID Connect and SAML providers" },
+            { "name": "clients", "description": "OAuth clients" },
+            { "name": "scim", "description": "SCIM 2.0 provisioning" },
+            { "name": "meta", "description": "The server itself" },
+        ],
+        "paths": paths(),
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "bearerAuth": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "bearerFormat": "JWT",
+                    "description": "The token of `/login`, `/signup` or `/token`. `Authorization: Basic <base64 token>` is accepted too.",
+                },
+                "basicAuth": {
+                    "type": "http",
+                    "scheme": "basic",
+                    "description": "A user's username and password, or a client's id and secret on the OAuth endpoints.",
+                },
+                "cookieAuth": {
+                    "type": "apiKey",
+                    "in": "cookie",
+                    "name": "session",
+                    "description": "In realms with cookie sessions; state-changing requests also send the `X-CSRF-Token` header.",
+                },
+                "oauth2": {
+                    "type": "oauth2",
+                    "flows": {
+                        "authorizationCode": {
+                            "authorizationUrl": format!("{}/authorize", api_url(issuer)),
+                            "tokenUrl": format!("{}/token", api_url(issuer)),
+                            "scopes": { "openid": "ID token", "profile": "Name and picture", "email": "Email address" },
+                        },
+                        "clientCredentials": {
+                            "tokenUrl": format!("{}/token", api_url(issuer)),
+                            "scopes": {},
+                        },
+                    },
+                },
+                "openIdConnect": {
+                    "type": "openIdConnect",
+                    "openIdConnectUrl": format!("{}/.well-known/openid-configuration", issuer),
+                },
+            },
+        },
+    })
+}
+
+/// Routes that are not part of the API.
+#[cfg(test)]
+const UNDOCUMENTED: &[&str] = &["swagger_ui"];
+
+/// Each route answers the method and path of the operation named after it,
+/// rather than missing it as routes do whose method or path differ. What
+/// the route then answers does not matter, nor does the database.
+#[tokio::test]
+async fn test_every_route_is_documented() {
+    use crate::config::Config;
+    use crate::errors::PathMismatch;
+    use crate::server::routes::v1_route_table;
+    use std::collections::BTreeSet;
+
+    let config = Config::from_env().unwrap();
+    let db_pool = config.db_pool().unwrap();
+    let routes = v1_route_table(config, db_pool, Some("v1"));
+    let mut served: BTreeSet<&str> = routes.iter().map(|(id, _)| *id).collect();
+    for route in UNDOCUMENTED {
+        assert!(served.remove(route), "{} is not a route any more", route);
+    }
+    let documented: BTreeSet<&str> = OPERATIONS.iter().map(|op| op.id).collect();
+    assert_eq!(served, documented);
+
+    for (id, route) in routes.iter().filter(|(id, _)| !UNDOCUMENTED.contains(id)) {
+        for op in OPERATIONS.iter().filter(|op| op.id == *id) {
+            let path: String = op
+                .path
+                .split('/')
+                .map(|segment| match segment.starts_with('{') {
+                    true => uuid::Uuid::nil().to_string(),
+                    false => segment.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            let answer = warp::test::request()
+                .method(&op.method.to_uppercase())
+                .path(&format!("/v1{}", path))
+                .filter(route)
+                .await;
+            if let Err(rejection) = answer {
+                let missed = rejection.is_not_found()
+                    || rejection.find::<PathMismatch>().is_some()
+                    || rejection.find::<warp::reject::MethodNotAllowed>().is_some();
+                assert!(!missed, "{} does not serve {} {}", id, op.method, op.path);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_operations_are_unique_and_refer_to_schemas() {
+    use std::collections::HashSet;
+
+    let document = document("https://auth.example.com");
+    let schemas = document["components"]["schemas"].as_object().unwrap();
+    let mut seen = HashSet::new();
+    for op in OPERATIONS {
+        assert!(
+            seen.insert((op.method, op.path)),
+            "{} {} twice",
+            op.method,
+            op.path
+        );
+        let named = op
+            .request
+            .iter()
+            .chain(op.responses.iter().flat_map(|(_, _, body)| body.iter()))
+            .map(|(_, name)| name.trim_end_matches("[]"))
+            .filter(|name| !name.is_empty());
+        for name in named {
+            assert!(schemas.contains_key(name), "{} refers to {}", op.id, name);
+        }
+    }
+    let text = document.to_string();
+    for reference in text.split("#/components/schemas/").skip(1) {
+        let name: String = reference.chars().take_while(|c| *c != '"').collect();
+        assert!(schemas.contains_key(&name), "missing schema {}", name);
+    }
+}
+
+#[test]
+fn test_operation_for() {
+    let id = |method, path| operation_for(method, path).map(|operation| operation.id);
+    assert_eq!(Some("me"), id("GET", "/me"));
+    assert_eq!(Some("delete"), id("DELETE", "/me"));
+    let session = format!("/me/sessions/{}", uuid::Uuid::nil());
+    assert_eq!(Some("revoke_session"), id("DELETE", &session));
+    assert_eq!(Some("scim_get_user"), id("GET", "/scim/v2/Users/42"));
+    assert_eq!(None, id("GET", "/me/sessions/"));
+    assert_eq!(None, id("PUT", "/me"));
+    assert_eq!(None, id("GET", "/unknown"));
+}
+
+/// The properties of the schema `name` are the fields `sample` serializes.
+#[cfg(test)]
+fn assert_schema_of<T: serde::Serialize>(name: &str, sample: T) {
+    use std::collections::BTreeSet;
+
+    let schemas = schemas();
+    let properties: BTreeSet<&String> = schemas[name]["properties"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .collect();
+    let sample = serde_json::to_value(sample).unwrap();
+    let fields: BTreeSet<&String> = sample.as_object().unwrap().keys().collect();
+    assert_eq!(properties, fields, "schema {}", name);
+}
+
+#[test]
+fn test_schemas_match_the_models() {
+    use authserver_client::models::*;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    let some = || Some("x".to_string());
+    assert_schema_of(
+        "User",
+        User {
+            id: Uuid::nil(),
+            username: some(),
+            email: "jane@example.com".to_string(),
+            full_name: some(),
+            bio: some(),
+            image: some(),
+            roles: vec![],
+        },
+    );
+    assert_schema_of(
+        "SignupRequest",
+        SignupRequest {
+            username: "jane".to_string(),
+            email: "jane@example.com".to_string(),
+            password: "password".to_string(),
+        },
+    );
+    assert_schema_of(
+        "LoginRequest",
+        LoginRequest {
+            username: "jane".to_string(),
+            password: "password".to_string(),
+        },
+    );
+    assert_schema_of(
+        "SignInResponse",
+        SignInResponse {
+            access_token: "token".to_string(),
+            token_type: "Bearer".to_string(),
+            expires_in: 3600,
+        },
+    );
+    assert_schema_of("DeletedUser", DeletedUser { id: Uuid::nil() });
+    assert_schema_of(
+        "Session",
+        Session {
+            id: Uuid::nil(),
+            user_agent: some(),
+            ip: some(),
+            created_at: Utc::now(),
+            last_seen_at: Utc::now(),
+            expires_at: Utc::now(),
+            current: true,
+        },
+    );
+    assert_schema_of(
+        "Problem",
+        Problem {
+            kind: "urn:authserver:problem:conflict".to_string(),
+            title: "Conflict".to_string(),
+            status: 409,
+            detail: some(),
+            code: "conflict".to_string(),
+            request_id: "1".to_string(),
+            errors: vec![FieldError {
+                field: "email".to_string(),
+                code: "email".to_string(),
+                message: some(),
+            }],
+        },
+    );
+    assert_schema_of(
+        "FieldError",
+        FieldError {
+            field: "email".to_string(),
+            code: "email".to_string(),
+            message: some(),
+        },
+    );
+    assert_schema_of(
+        "TokenResponse",
+        TokenResponse {
+            access_token: "token".to_string(),
+            token_type: "Bearer".to_string(),
+            expires_in: 3600,
+            scope: "openid".to_string(),
+            refresh_token: some(),
+            id_token: some(),
+            issued_token_type: some(),
+        },
+    );
+    assert_schema_of(
+        "ClientCredentials",
+        ClientCredentials {
+            client_id: Uuid::nil(),
+            client_secret: some(),
+        },
+    );
+    assert_schema_of(
+        "LinkStarted",
+        LinkStarted {
+            authorization_url: "https://idp.example.com".to_string(),
+        },
+    );
+    assert_schema_of(
+        "ProviderMetadata",
+        crate::models::oidc::provider_metadata("https://auth.example.com"),
+    );
+}
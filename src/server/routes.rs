@@ -1,18 +1,59 @@
 use std::convert::Infallible;
 
+use crate::config::cookie::CSRF_HEADER;
+use crate::config::realm::{Realm, API_VERSION};
 use crate::config::{Config, DBPool};
 use crate::errors;
-use crate::errors::Error::{AuthError, PathMismatch};
-use crate::handlers::auth::{decode_credentials, decode_token};
-use crate::handlers::health_handler;
+use crate::errors::Error::NotFoundError;
+use crate::errors::PathMismatch;
+use crate::handlers::admin::{approve_client, create_client, list_clients, rotate_client_secret};
+use crate::handlers::auth::decode_credentials;
+use crate::handlers::device::{device_approval, device_authorization, device_verification};
+use crate::handlers::federation::{federated_callback, federated_login, link_identity};
+use crate::handlers::forward_auth::verify;
+use crate::handlers::grant::{list_grants, revoke_grant};
+use crate::handlers::identity::{add_identity, list_identities, remove_identity};
+use crate::handlers::introspection::{introspect, revoke};
+use crate::handlers::oauth::{authorize, authorize_consent, authorize_login, token};
+use crate::handlers::oidc::{discovery, jwks, userinfo};
+use crate::handlers::openapi::{openapi, swagger_ui};
+use crate::handlers::registration::{
+    delete_registration, get_registration, register, update_registration,
+};
+use crate::handlers::saml::{saml_acs, saml_login, saml_metadata};
+use crate::handlers::scim::{
+    scim_create_group, scim_create_user, scim_delete_group, scim_delete_user, scim_error,
+    scim_get_group, scim_get_user, scim_list_groups, scim_list_users, scim_patch_group,
+    scim_patch_user, scim_replace_group, scim_replace_user,
+};
+use crate::handlers::session::{list_sessions, logout, request_token, revoke_session};
 use crate::handlers::user::{create_user, delete_user, login, me};
-use crate::models::auth::Credentials;
+use crate::handlers::{health_handler, metrics_handler};
+use crate::metrics::metrics;
+use crate::models::auth::{BodyCredentials, Credentials, ForwardedRequest, LoginBody};
+use crate::models::federation::FederatedCallback;
+use crate::models::scim::ListParams;
+use crate::models::session::ClientInfo;
+use crate::server::negotiation::{
+    accepts_json, form_body, json_body, json_or_form_body, limited_body, preferred_format,
+    with_format, Format,
+};
+use crate::server::openapi::operation_for;
 
-use std::io::Error;
 use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::time::Instant;
+use uuid::Uuid;
 
-use warp::{body, path, reject, Rejection};
+use serde::de::DeserializeOwned;
+use tracing::{field, info, info_span, Span};
+use warp::http::header::LINK;
+use warp::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use warp::path::FullPath;
+use warp::reply::Response;
+use warp::trace::Info;
 use warp::{filters::BoxedFilter, Filter, Reply};
+use warp::{path, reject, Rejection};
 
 fn with_db(db_pool: DBPool) -> impl Filter<Extract = (DBPool,), Error = Infallible> + Clone {
     warp::any().map(move || db_pool.clone())
@@ -21,103 +62,934 @@ fn with_config(config: Config) -> impl Filter<Extract = (Config,), Error = Infal
     warp::any().map(move || config.clone())
 }
 
-fn with_realm_header() -> impl Filter<Extract = (String,), Error = Rejection> + Copy {
-    warp::header::<String>("WWW-Authenticate").and_then(|a: String| async move {
-        let realm = a.replace("Basic realm=", "");
-        if realm == "AuthServer" {
-            Ok(a)
-        } else {
-            Err(reject::custom(AuthError(Error::from(
-                ErrorKind::InvalidData,
-            ))))
-        }
+/// The path prefix of a version of the API, such as `v1`; none for the
+/// legacy surface.
+fn with_version(version: Option<&'static str>) -> BoxedFilter<()> {
+    match version {
+        Some(version) => warp::path(version).boxed(),
+        None => warp::any().boxed(),
+    }
+}
+
+/// Resolves the realm of a request from a `/realms/{name}` path prefix,
+/// falling back to the `Host` header and then to the default realm, and
+/// takes the prefix of the `version` being served after it.
+fn with_realm(config: Config, version: Option<&'static str>) -> BoxedFilter<(Realm,)> {
+    let realms = config.realms.clone();
+    let by_path = warp::path("realms")
+        .and(warp::path::param::<String>())
+        .and_then(move |name: String| {
+            let realm = realms.by_name(&name).cloned();
+            async move { realm.ok_or_else(|| reject::custom(NotFoundError(ErrorKind::NotFound))) }
+        });
+
+    let realms = config.realms;
+    let by_host = warp::header::optional::<String>("host").map(move |host: Option<String>| {
+        host.and_then(|h| realms.by_host(&h).cloned())
+            .unwrap_or_else(|| realms.default_realm().clone())
+    });
+
+    by_path
+        .or(by_host)
+        .unify()
+        .and(with_version(version))
+        .boxed()
+}
+/// Accepts the OAuth `Bearer <jwt>` scheme as well as our `Basic <base64 jwt>`,
+/// or else the realm's session cookie, and rejects tokens of sessions the
+/// user signed out of.
+fn with_token_auth_header(
+    realm: impl Filter<Extract = (Realm,), Error = Rejection> + Clone,
+    config: Config,
+    db_pool: DBPool,
+) -> impl Filter<Extract = (Realm, String), Error = Rejection> + Clone {
+    realm
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("cookie"))
+        .and(warp::method())
+        .and(warp::header::optional::<String>(CSRF_HEADER))
+        .and(with_config(config))
+        .and(with_db(db_pool))
+        .and_then(
+            |realm: Realm,
+             authorization: Option<String>,
+             cookies: Option<String>,
+             method: Method,
+             csrf: Option<String>,
+             config: Config,
+             db_pool: DBPool| async move {
+                let token = request_token(
+                    &realm,
+                    authorization.as_deref(),
+                    cookies.as_deref(),
+                    &method,
+                    csrf.as_deref(),
+                    &config,
+                    &db_pool,
+                )
+                .await?;
+                Ok::<_, Rejection>((realm, token))
+            },
+        )
+        .untuple_one()
+}
+/// The user agent and address a sign-in comes from.
+fn with_client_info() -> impl Filter<Extract = (ClientInfo,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("user-agent")
+        .and(warp::addr::remote())
+        .map(|user_agent, remote: Option<SocketAddr>| ClientInfo {
+            user_agent,
+            ip: remote.map(|addr| addr.ip().to_string()),
+        })
+}
+/// The `X-Request-Id` the request came with, if it is safe to echo back
+/// and to log, or else a new one. It is recorded on the request's span.
+fn with_request_id() -> impl Filter<Extract = (String,), Error = Infallible> + Clone {
+    warp::header::headers_cloned().map(|headers: HeaderMap| {
+        let request_id = headers
+            .get(errors::REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| {
+                !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+            })
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        Span::current().record("request_id", request_id.as_str());
+        request_id
     })
 }
-fn with_token_auth_header() -> impl Filter<Extract = (String,), Error = Rejection> + Copy {
-    warp::header::<String>("authorization").and_then(|a: String| async move {
-        if let Some(e) = a.strip_prefix("Basic ") {
-            match decode_token(String::from(e)).await {
-                Ok(a) => return Ok(a),
-                Err(_e) => {
-                    return Err(reject::custom(AuthError(Error::from(
-                        ErrorKind::PermissionDenied,
-                    ))))
-                }
-            };
-        } else {
-            Err(reject::custom(AuthError(Error::from(
-                ErrorKind::PermissionDenied,
-            ))))
-        }
+/// The span every event of a request is logged in.
+fn request_span(info: Info) -> Span {
+    info_span!(
+        "request",
+        request_id = field::Empty,
+        method = %info.method(),
+        path = info.path(),
+    )
+}
+/// What a reverse proxy forwards about the request it asks `/auth/verify`
+/// about, in Traefik's `X-Forwarded-*` headers or nginx's `X-Original-*`.
+fn with_forwarded_request() -> impl Filter<Extract = (ForwardedRequest,), Error = Rejection> + Clone
+{
+    let either = |traefik: &'static str, nginx: &'static str| {
+        warp::header::optional::<String>(traefik)
+            .and(warp::header::optional::<String>(nginx))
+            .map(|a: Option<String>, b: Option<String>| a.or(b))
+    };
+    either("x-forwarded-uri", "x-original-uri")
+        .and(either("x-forwarded-method", "x-original-method"))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("cookie"))
+        .and(warp::header::optional::<String>(CSRF_HEADER))
+        .map(
+            |uri, method, authorization, cookies, csrf_token| ForwardedRequest {
+                uri,
+                method,
+                authorization,
+                cookies,
+                csrf_token,
+            },
+        )
+}
+/// Credentials from a JSON or form body that has them, or else from the
+/// Basic header, along with the rest of the body.
+fn with_credentials<T: BodyCredentials + DeserializeOwned + Send + 'static>(
+    realm: impl Filter<Extract = (Realm,), Error = Rejection> + Clone,
+) -> impl Filter<Extract = (Realm, Credentials, T), Error = Rejection> + Clone {
+    realm
+        .and(warp::header::optional::<String>("authorization"))
+        .and(json_or_form_body())
+        .and_then(|realm: Realm, a: Option<String>, mut body: T| async move {
+            if let Some(credentials) = body.take_credentials() {
+                return Ok((realm, credentials, body));
+            }
+            match a.as_deref().and_then(|a| a.strip_prefix("Basic ")) {
+                Some(e) => match decode_credentials(String::from(e)).await {
+                    Ok(credentials) => Ok((realm, credentials, body)),
+                    Err(e) => Err(e),
+                },
+                None => Err(realm.unauthorized()),
+            }
+        })
+        .untuple_one()
+}
+
+/// A JSON body, as SCIM clients send it: `application/scim+json` or
+/// `application/json`, malformed bodies answered as SCIM errors.
+fn scim_body<T: DeserializeOwned + Send>() -> impl Filter<Extract = (T,), Error = Rejection> + Clone
+{
+    limited_body().and_then(|bytes: Vec<u8>| async move {
+        serde_json::from_slice(&bytes).map_err(|e| {
+            scim_error(
+                StatusCode::BAD_REQUEST,
+                Some("invalidSyntax"),
+                &format!("Invalid body: {}", e),
+            )
+        })
     })
 }
-fn with_basic_auth_header() -> impl Filter<Extract = (Credentials,), Error = Rejection> + Copy {
-    warp::header::<String>("authorization").and_then(|a: String| async move {
-        if let Some(e) = a.strip_prefix("Basic ") {
-            match decode_credentials(String::from(e)).await {
-                Ok(a) => return Ok(a),
-                Err(e) => return Err(e),
-            };
-        } else {
-            Err(reject::custom(AuthError(Error::from(
-                ErrorKind::InvalidInput,
-            ))))
+
+/// Paths other parties are configured with, which the legacy surface keeps
+/// serving without deprecating: well-known URIs (RFC 8615) and the callbacks
+/// registered with upstream OpenID and SAML providers.
+const FIXED_PATHS: &[&str] = &["/.well-known/", "/login/", "/saml/"];
+/// When the unprefixed paths were deprecated (RFC 9745): 2026-10-19.
+const LEGACY_DEPRECATION: &str = "@1792368000";
+/// When the unprefixed paths stop being served (RFC 8594).
+const LEGACY_SUNSET: &str = "Mon, 19 Apr 2027 00:00:00 GMT";
+
+/// Splits `path` after its `/realms/{name}` prefix, if it has one.
+fn split_realm(path: &str) -> (&str, &str) {
+    match path.strip_prefix("/realms/") {
+        Some(rest) => {
+            let end = rest.find('/').map_or(path.len(), |i| "/realms/".len() + i);
+            path.split_at(end)
         }
-    })
+        None => ("", path),
+    }
+}
+
+/// Where the legacy `path` is served by the current version.
+fn successor_path(path: &str) -> String {
+    let (realm, rest) = split_realm(path);
+    format!("{}/{}{}", realm, API_VERSION, rest)
+}
+
+/// Marks an answer of the legacy surface as deprecated, pointing at the
+/// path of the current version.
+fn deprecated(path: FullPath, mut response: Response) -> Response {
+    let (_, rest) = split_realm(path.as_str());
+    if FIXED_PATHS.iter().any(|fixed| rest.starts_with(fixed)) {
+        return response;
+    }
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static(LEGACY_DEPRECATION));
+    headers.insert("sunset", HeaderValue::from_static(LEGACY_SUNSET));
+    let link = format!(
+        "<{}>; rel=\"successor-version\"",
+        successor_path(path.as_str())
+    );
+    if let Ok(link) = HeaderValue::from_str(&link) {
+        headers.insert(LINK, link);
+    }
+    response
+}
+
+/// The route a request went to, named after its OpenAPI operation, so that
+/// metrics are labelled by route rather than by path.
+fn route_name(method: &Method, path: &str) -> &'static str {
+    let (_, path) = split_realm(path);
+    let path = match path
+        .strip_prefix('/')
+        .and_then(|p| p.strip_prefix(API_VERSION))
+    {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ => path,
+    };
+    match path {
+        "/metrics" => "metrics",
+        "/docs" => "swagger_ui",
+        _ => operation_for(method.as_str(), path).map_or("unmatched", |operation| operation.id),
+    }
+}
+
+/// The method as a metric label; others than the standard ones would let
+/// clients add labels at will.
+fn method_name(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::HEAD => "HEAD",
+        Method::OPTIONS => "OPTIONS",
+        _ => "other",
+    }
 }
 
 pub fn make_routes(config: Config, db_pool: DBPool) -> BoxedFilter<(impl Reply,)> {
-    let health = warp::path("health")
+    // Operational rather than part of the API, so neither versioned nor
+    // scoped to a realm.
+    let metrics_route = warp::get()
+        .and(path!("metrics"))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and_then(metrics_handler)
+        .map(Reply::into_response);
+
+    // Each version is a surface of its own under its prefix. A `/v2` with
+    // different handlers is a `v2_routes` built the way `v1_routes` is,
+    // reusing the routes that do not change, and chained before `v1`.
+    let v1 = v1_routes(config.clone(), db_pool.clone(), Some(API_VERSION));
+    // The paths of before versioning, answered by v1 until their sunset.
+    let legacy = warp::path::full()
+        .and(v1_routes(config, db_pool, None))
+        .map(deprecated);
+
+    let routes = metrics_route
+        .or(v1)
+        .unify()
+        .or(legacy)
+        .unify()
+        .map(Ok::<_, Rejection>)
+        .or_else(|err: Rejection| async move { Ok::<_, Rejection>((Err(err),)) });
+
+    with_request_id()
+        .and(warp::any().map(Instant::now))
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(routes)
+        .map(
+            |request_id: String,
+             started: Instant,
+             method: Method,
+             path: FullPath,
+             result: Result<Response, Rejection>| {
+                let mut response =
+                    result.unwrap_or_else(|err| errors::rejection_response(err, &request_id));
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    response
+                        .headers_mut()
+                        .insert(errors::REQUEST_ID_HEADER, value);
+                }
+                let route = route_name(&method, path.as_str());
+                let status = response.status().as_u16();
+                let elapsed = started.elapsed();
+                info!(
+                    route,
+                    status,
+                    elapsed_ms = elapsed.as_secs_f64() * 1000.0,
+                    "request answered"
+                );
+                metrics().observe_request(route, method_name(&method), status, elapsed);
+                response
+            },
+        )
+        .with(warp::trace(request_span))
+        .boxed()
+}
+
+/// A route of the API, named after the operation `openapi::OPERATIONS`
+/// documents it as.
+pub(crate) type Route = (&'static str, BoxedFilter<(Response,)>);
+
+/// The routes held by the variables, each named after its variable.
+macro_rules! routes {
+    ($($route:ident),+ $(,)?) => {
+        vec![$((stringify!($route), $route.map(Reply::into_response).boxed())),+]
+    };
+}
+
+/// The routes of version 1 under the `version` prefix, or of the legacy
+/// surface without one.
+fn v1_routes(
+    config: Config,
+    db_pool: DBPool,
+    version: Option<&'static str>,
+) -> BoxedFilter<(Response,)> {
+    v1_route_table(config, db_pool, version)
+        .into_iter()
+        .map(|(_, route)| route)
+        .reduce(|routes, route| routes.or(route).unify().boxed())
+        .expect("version 1 has routes")
+}
+
+/// Every route of version 1, in the order they are tried.
+pub(crate) fn v1_route_table(
+    config: Config,
+    db_pool: DBPool,
+    version: Option<&'static str>,
+) -> Vec<Route> {
+    let health = with_version(version)
+        .and(warp::path("health"))
         .and(with_db(db_pool.clone()))
         .and_then(health_handler);
 
-    let user_agent = warp::path("hello")
+    let user_agent = with_version(version)
+        .and(warp::path("hello"))
         .and(warp::path::param())
         .and(warp::header("user-agent"))
         .map(|param: String, agent: String| format!("Hello {}, whose agent is {}", param, agent));
 
+    let openapi = warp::get().and(
+        with_realm(config.clone(), version)
+            .and(path!("openapi.json"))
+            .and(accepts_json())
+            .and_then(openapi),
+    );
+    let swagger_ui = warp::get().and(
+        with_realm(config.clone(), version)
+            .and(path!("docs"))
+            .and(with_config(config.clone()))
+            .and_then(swagger_ui),
+    );
+
     let signup = warp::post().and(
-        path!("signup")
-            .and(with_basic_auth_header())
-            .and(with_realm_header())
+        with_credentials(with_realm(config.clone(), version).and(path!("signup")))
+            .and(with_format(&[Format::Json, Format::Text]))
             .and(with_config(config.clone()))
             .and(with_db(db_pool.clone()))
-            .and(body::form())
+            .and(with_client_info())
             .and_then(create_user),
     );
     let delete = warp::delete().and(
-        path!("me")
-            .and(with_token_auth_header())
-            .and(with_realm_header())
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("me")),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(accepts_json())
+        .and_then(delete_user),
+    );
+    let me = warp::get().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version)
+                .and(path!("me").or_else(|_| async { Err(reject::custom(PathMismatch)) })),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(accepts_json())
+        .and_then(me),
+    );
+    let login = warp::post().and(
+        with_credentials(
+            with_realm(config.clone(), version)
+                .and(path!("login").or_else(|_| async { Err(reject::custom(PathMismatch)) })),
+        )
+        .map(|realm, credentials, _: LoginBody| (realm, credentials))
+        .untuple_one()
+        .and(with_format(&[Format::Json, Format::Text]))
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(with_client_info())
+        .and_then(login),
+    );
+    let verify = warp::get().and(
+        with_realm(config.clone(), version)
+            .and(path!("auth" / "verify"))
+            .and(with_forwarded_request())
             .and(with_config(config.clone()))
             .and(with_db(db_pool.clone()))
-            .and_then(delete_user),
+            .and_then(verify),
     );
-    let me = warp::get().and(
-        path!("me")
-            .or_else(|_| async { Err(reject::custom(PathMismatch)) })
-            .and(with_token_auth_header())
-            .and(with_realm_header())
+    let logout = warp::post().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("logout")),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and_then(logout),
+    );
+    let authorize = warp::get().and(
+        with_realm(config.clone(), version)
+            .and(path!("authorize"))
             .and(with_config(config.clone()))
             .and(with_db(db_pool.clone()))
-            .and_then(me),
+            .and(warp::query())
+            .and_then(authorize),
     );
-    let login = warp::post().and(
-        path!("login")
-            .or_else(|_| async { Err(reject::custom(PathMismatch)) })
-            .and(with_basic_auth_header())
-            .and(with_realm_header())
+    let authorize_login = warp::post().and(
+        with_realm(config.clone(), version)
+            .and(path!("authorize"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(form_body())
+            .and_then(authorize_login),
+    );
+    let authorize_consent = warp::post().and(
+        with_realm(config.clone(), version)
+            .and(path!("authorize" / "consent"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(form_body())
+            .and_then(authorize_consent),
+    );
+    let token = warp::post().and(
+        with_realm(config.clone(), version)
+            .and(path!("token"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(warp::header::optional::<String>("authorization"))
+            .and(form_body())
+            .and(accepts_json())
+            .and_then(token),
+    );
+    let device_authorization = warp::post().and(
+        with_realm(config.clone(), version)
+            .and(path!("device_authorization"))
             .and(with_config(config.clone()))
             .and(with_db(db_pool.clone()))
-            .and_then(login),
+            .and(warp::header::optional::<String>("authorization"))
+            .and(form_body())
+            .and(accepts_json())
+            .and_then(device_authorization),
+    );
+    let device_verification = warp::get().and(
+        with_realm(config.clone(), version)
+            .and(path!("device"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(warp::header::optional::<String>("cookie"))
+            .and(warp::query())
+            .and_then(device_verification),
+    );
+    let device_approval = warp::post().and(
+        with_realm(config.clone(), version)
+            .and(path!("device"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::header::optional::<String>("cookie"))
+            .and(warp::header::optional::<String>(CSRF_HEADER))
+            .and(form_body())
+            .and_then(device_approval),
+    );
+    let introspect = warp::post().and(
+        with_realm(config.clone(), version)
+            .and(path!("introspect"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(warp::header::optional::<String>("authorization"))
+            .and(form_body())
+            .and(accepts_json())
+            .and_then(introspect),
+    );
+    let revoke = warp::post().and(
+        with_realm(config.clone(), version)
+            .and(path!("revoke"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(warp::header::optional::<String>("authorization"))
+            .and(form_body())
+            .and_then(revoke),
+    );
+    let discovery = warp::get().and(
+        with_realm(config.clone(), version)
+            .and(path!(".well-known" / "openid-configuration"))
+            .and(accepts_json())
+            .and_then(discovery),
+    );
+    let jwks = warp::get().and(
+        with_realm(config.clone(), version)
+            .and(path!(".well-known" / "jwks.json"))
+            .and(accepts_json())
+            .and_then(jwks),
+    );
+    let userinfo = warp::get().or(warp::post()).unify().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("userinfo")),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(accepts_json())
+        .and_then(userinfo),
+    );
+    let list_grants = warp::get().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("me" / "grants")),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(accepts_json())
+        .and_then(list_grants),
+    );
+    let revoke_grant = warp::delete().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("me" / "grants" / ..)),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(path!(Uuid))
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and_then(revoke_grant),
+    );
+    let federated_login = warp::get().and(
+        with_realm(config.clone(), version)
+            .and(path!("login" / String))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(warp::query())
+            .and_then(federated_login),
+    );
+    let federated_callback = warp::get().and(
+        with_realm(config.clone(), version)
+            .and(path!("login" / String / "callback"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(with_client_info())
+            .and(preferred_format(&[Format::Json, Format::Text]))
+            .and(
+                warp::query()
+                    .and(warp::header::optional::<String>("cookie"))
+                    .map(|params, cookies| FederatedCallback { params, cookies }),
+            )
+            .and_then(federated_callback),
+    );
+    let link_identity = warp::post().and(
+        with_realm(config.clone(), version)
+            .and(path!("login" / String / "link"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(with_client_info())
+            .and(preferred_format(&[Format::Json, Format::Text]))
+            .and(form_body())
+            .and_then(link_identity),
+    );
+    let saml_metadata = warp::get().and(
+        with_realm(config.clone(), version)
+            .and(path!("saml" / String / "metadata"))
+            .and_then(saml_metadata),
+    );
+    let saml_login = warp::get().and(
+        with_realm(config.clone(), version)
+            .and(path!("saml" / String / "login"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and_then(saml_login),
+    );
+    let saml_acs = warp::post().and(
+        with_realm(config.clone(), version)
+            .and(path!("saml" / String / "acs"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(with_client_info())
+            .and(preferred_format(&[Format::Json, Format::Text]))
+            .and(form_body())
+            .and_then(saml_acs),
+    );
+    let register = warp::post().and(
+        with_realm(config.clone(), version)
+            .and(path!("register"))
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(json_body())
+            .and(accepts_json())
+            .and_then(register),
+    );
+    let registration = || {
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("register" / ..)),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(path!(Uuid))
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+    };
+    let get_registration = warp::get().and(
+        registration()
+            .and(accepts_json())
+            .and_then(get_registration),
+    );
+    let update_registration = warp::put().and(
+        registration()
+            .and(json_body())
+            .and(accepts_json())
+            .and_then(update_registration),
+    );
+    let delete_registration = warp::delete().and(registration().and_then(delete_registration));
+    let list_clients = warp::get().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("admin" / "clients")),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(accepts_json())
+        .and_then(list_clients),
+    );
+    let approve_client = warp::post().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("admin" / "clients" / ..)),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(path!(Uuid / "approve"))
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(accepts_json())
+        .and_then(approve_client),
+    );
+    let create_client = warp::post().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("admin" / "clients")),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(json_body())
+        .and(accepts_json())
+        .and_then(create_client),
+    );
+    let rotate_client_secret = warp::post().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("admin" / "clients" / ..)),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(path!(Uuid / "secret"))
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(accepts_json())
+        .and_then(rotate_client_secret),
     );
 
-    health
-        .or(signup)
-        .or(login)
-        .or(delete)
-        .or(user_agent)
-        .or(me)
-        .recover(errors::handle_rejection)
-        .boxed()
+    let scim_users = || with_realm(config.clone(), version).and(path!("scim" / "v2" / "Users"));
+    let scim_user = || {
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("scim" / "v2" / "Users" / ..)),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(path!(Uuid))
+    };
+    let scim_list_users = warp::get().and(
+        with_token_auth_header(scim_users(), config.clone(), db_pool.clone())
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(warp::query::<ListParams>())
+            .and_then(scim_list_users),
+    );
+    let scim_create_user = warp::post().and(
+        with_token_auth_header(scim_users(), config.clone(), db_pool.clone())
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(scim_body())
+            .and_then(scim_create_user),
+    );
+    let scim_get_user = warp::get().and(
+        scim_user()
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and_then(scim_get_user),
+    );
+    let scim_replace_user = warp::put().and(
+        scim_user()
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(scim_body())
+            .and_then(scim_replace_user),
+    );
+    let scim_patch_user = warp::patch().and(
+        scim_user()
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(scim_body())
+            .and_then(scim_patch_user),
+    );
+    let scim_delete_user = warp::delete().and(
+        scim_user()
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and_then(scim_delete_user),
+    );
+    let scim_groups = || with_realm(config.clone(), version).and(path!("scim" / "v2" / "Groups"));
+    let scim_group = || {
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("scim" / "v2" / "Groups" / ..)),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(path!(Uuid))
+    };
+    let scim_list_groups = warp::get().and(
+        with_token_auth_header(scim_groups(), config.clone(), db_pool.clone())
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(warp::query::<ListParams>())
+            .and_then(scim_list_groups),
+    );
+    let scim_create_group = warp::post().and(
+        with_token_auth_header(scim_groups(), config.clone(), db_pool.clone())
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(scim_body())
+            .and_then(scim_create_group),
+    );
+    let scim_get_group = warp::get().and(
+        scim_group()
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and_then(scim_get_group),
+    );
+    let scim_replace_group = warp::put().and(
+        scim_group()
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(scim_body())
+            .and_then(scim_replace_group),
+    );
+    let scim_patch_group = warp::patch().and(
+        scim_group()
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and(scim_body())
+            .and_then(scim_patch_group),
+    );
+    let scim_delete_group = warp::delete().and(
+        scim_group()
+            .and(with_config(config.clone()))
+            .and(with_db(db_pool.clone()))
+            .and_then(scim_delete_group),
+    );
+
+    let list_sessions = warp::get().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("me" / "sessions")),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(accepts_json())
+        .and_then(list_sessions),
+    );
+    let revoke_session = warp::delete().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("me" / "sessions" / ..)),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(path!(Uuid))
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and_then(revoke_session),
+    );
+    let list_identities = warp::get().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("me" / "identities")),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(accepts_json())
+        .and_then(list_identities),
+    );
+    let add_identity = warp::post().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("me" / "identities" / ..)),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(path!(String))
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and(form_body())
+        .and(accepts_json())
+        .and_then(add_identity),
+    );
+    let remove_identity = warp::delete().and(
+        with_token_auth_header(
+            with_realm(config.clone(), version).and(path!("me" / "identities" / ..)),
+            config.clone(),
+            db_pool.clone(),
+        )
+        .and(path!(String))
+        .and(with_config(config.clone()))
+        .and(with_db(db_pool.clone()))
+        .and_then(remove_identity),
+    );
+
+    routes![
+        health,
+        signup,
+        login,
+        logout,
+        delete,
+        user_agent,
+        me,
+        list_grants,
+        revoke_grant,
+        list_sessions,
+        revoke_session,
+        list_identities,
+        add_identity,
+        remove_identity,
+        verify,
+        openapi,
+        swagger_ui,
+        authorize,
+        authorize_login,
+        authorize_consent,
+        token,
+        device_authorization,
+        device_verification,
+        device_approval,
+        introspect,
+        revoke,
+        discovery,
+        jwks,
+        userinfo,
+        federated_login,
+        federated_callback,
+        link_identity,
+        saml_metadata,
+        saml_login,
+        saml_acs,
+        register,
+        get_registration,
+        update_registration,
+        delete_registration,
+        list_clients,
+        approve_client,
+        create_client,
+        rotate_client_secret,
+        scim_list_users,
+        scim_create_user,
+        scim_get_user,
+        scim_replace_user,
+        scim_patch_user,
+        scim_delete_user,
+        scim_list_groups,
+        scim_create_group,
+        scim_get_group,
+        scim_replace_group,
+        scim_patch_group,
+        scim_delete_group,
+    ]
+}
+
+#[test]
+fn test_route_name() {
+    assert_eq!("me", route_name(&Method::GET, "/v1/me"));
+    assert_eq!("me", route_name(&Method::GET, "/realms/acme/me"));
+    assert_eq!(
+        "revoke_grant",
+        route_name(
+            &Method::DELETE,
+            &format!("/realms/acme/v1/me/grants/{}", Uuid::nil())
+        )
+    );
+    assert_eq!("metrics", route_name(&Method::GET, "/metrics"));
+    assert_eq!("unmatched", route_name(&Method::GET, "/v1x/me"));
+}
+
+#[test]
+fn test_successor_path() {
+    assert_eq!("/v1/me/sessions", successor_path("/me/sessions"));
+    assert_eq!(
+        "/realms/acme/v1/token",
+        successor_path("/realms/acme/token")
+    );
+    assert_eq!("/realms/acme/v1", successor_path("/realms/acme"));
+    assert_eq!(
+        ("/realms/acme", "/saml/idp/acs"),
+        split_realm("/realms/acme/saml/idp/acs")
+    );
 }
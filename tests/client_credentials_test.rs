@@ -0,0 +1,207 @@
+use reqwest::{header, Client};
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+mod common;
+
+#[derive(Deserialize)]
+struct ClientCredentials {
+    client_id: String,
+    client_secret: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: String,
+    scope: String,
+}
+
+#[derive(Deserialize)]
+struct OAuthError {
+    error: String,
+}
+
+async fn admin_token() -> String {
+    let credentials = common::Credentials {
+        username: format!("admin-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, token) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    common::make_admin(&credentials.username).await;
+    token
+}
+
+async fn create_client(token: &str) -> ClientCredentials {
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/admin/clients")
+        .bearer_auth(token)
+        .json(&json!({
+            "name": "Reporting",
+            "scopes": ["reports:read", "reports:write"],
+            "grant_types": ["client_credentials"],
+            "confidential": true
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request to /admin/clients");
+    assert_eq!(201, response.status().as_u16());
+    response.json().await.expect("client credentials")
+}
+
+#[tokio::test]
+async fn client_credentials_with_basic_and_post_authentication() {
+    common::spawn_app().await;
+    let client = create_client(&admin_token().await).await;
+
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .basic_auth(&client.client_id, Some(&client.client_secret))
+        .form(&[
+            ("grant_type", "client_credentials"),
+            ("scope", "reports:read"),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    assert_eq!(200, response.status().as_u16());
+    let token: TokenResponse = response.json().await.unwrap();
+    assert_eq!("Bearer", token.token_type);
+    assert_eq!("reports:read", token.scope);
+    assert!(!token.access_token.is_empty());
+
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .form(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", &client.client_id),
+            ("client_secret", &client.client_secret),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    assert_eq!(200, response.status().as_u16());
+    let token: TokenResponse = response.json().await.unwrap();
+    assert_eq!("reports:read reports:write", token.scope);
+
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .basic_auth(&client.client_id, Some(&client.client_secret))
+        .form(&[("grant_type", "client_credentials"), ("scope", "admin")])
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    assert_eq!(400, response.status().as_u16());
+    let error: OAuthError = response.json().await.unwrap();
+    assert_eq!("invalid_scope", error.error);
+}
+
+#[tokio::test]
+async fn rotated_secret_replaces_the_old_one() {
+    common::spawn_app().await;
+    let token = admin_token().await;
+    let client = create_client(&token).await;
+
+    let response = Client::new()
+        .post(format!(
+            "http://127.0.0.1:3000/admin/clients/{}/secret",
+            client.client_id
+        ))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request to /admin/clients/{id}/secret");
+    assert_eq!(200, response.status().as_u16());
+    let rotated: ClientCredentials = response.json().await.unwrap();
+    assert_eq!(client.client_id, rotated.client_id);
+    assert_ne!(client.client_secret, rotated.client_secret);
+
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .basic_auth(&client.client_id, Some(&client.client_secret))
+        .form(&[("grant_type", "client_credentials")])
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    assert_eq!(401, response.status().as_u16());
+    let error: OAuthError = response.json().await.unwrap();
+    assert_eq!("invalid_client", error.error);
+
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .basic_auth(&rotated.client_id, Some(&rotated.client_secret))
+        .form(&[("grant_type", "client_credentials")])
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn public_clients_cannot_use_client_credentials() {
+    common::spawn_app().await;
+    let client_id = common::register_client("http://localhost:8080/callback", &["profile"])
+        .await
+        .to_string();
+
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .form(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", &client_id),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    assert_eq!(400, response.status().as_u16());
+    let error: OAuthError = response.json().await.unwrap();
+    assert_eq!("unauthorized_client", error.error);
+}
+
+#[tokio::test]
+async fn only_admins_manage_clients() {
+    common::spawn_app().await;
+    let credentials = common::Credentials {
+        username: format!("user-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (_, token) = common::singup(credentials).await;
+
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/admin/clients")
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .json(&json!({ "name": "Reporting", "confidential": true }))
+        .send()
+        .await
+        .expect("Failed to execute request to /admin/clients");
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn admin_tokens_delegated_to_clients_do_not_manage_clients() {
+    common::spawn_app().await;
+    let credentials = common::Credentials {
+        username: format!("admin-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, _) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    common::make_admin(&credentials.username).await;
+    let redirect_uri = "http://localhost:8080/callback";
+    let client_id = common::register_client(redirect_uri, &["openid"])
+        .await
+        .to_string();
+    let tokens =
+        common::authorization_code_tokens(&credentials, &client_id, redirect_uri, "openid", "n")
+            .await;
+
+    let response = Client::new()
+        .get("http://127.0.0.1:3000/admin/clients")
+        .bearer_auth(tokens["access_token"].as_str().unwrap())
+        .send()
+        .await
+        .expect("Failed to execute request to /admin/clients");
+    assert_eq!(403, response.status().as_u16());
+}
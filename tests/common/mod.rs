@@ -1,156 +1,317 @@
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use std::{collections::HashMap, time::Duration};
 use validator::Validate;
 
 use authserver::run;
+#[allow(unused_imports)]
+pub use authserver_client::models::User;
+use authserver_client::models::{SignInResponse, TokenRequest};
+use authserver_client::{Client, Error};
 use base64::encode_config;
+use mobc_postgres::tokio_postgres::{self, NoTls};
 use reqwest::header;
+use sha2::{Digest, Sha256};
 use tokio::time::sleep;
 use uuid::Uuid;
 
+pub const BASE_URL: &str = "http://127.0.0.1:3000";
+
 #[allow(dead_code)]
 pub async fn spawn_app() {
+    // Requests are logged at info; only what goes wrong is of interest here.
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "warn,warp=error");
+    }
     let server = run();
-    let _ = tokio::task::spawn(server);
-    sleep(Duration::from_millis(100)).await;
-    println!("100 ms have elapsed");
+    tokio::task::spawn(server);
+    // Startup loads the realms' signing keys, so wait for the port rather than a fixed delay.
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect("127.0.0.1:3000")
+            .await
+            .is_ok()
+        {
+            return;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("server did not start listening on 127.0.0.1:3000");
 }
+/// A client of the default realm, with the user agent the browser app sends.
 #[allow(dead_code)]
-pub async fn singup(credentials: Credentials) -> (u16, String) {
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        "WWW-Authenticate",
-        header::HeaderValue::from_static("Basic realm=AuthServer"),
-    );
-    let credentials_inline = format!("{}:{}", credentials.username, credentials.password);
-    let encoded_credentials: &str = &encode_config(credentials_inline, base64::STANDARD);
-    let auth_value = format!("Basic {}", encoded_credentials);
-    let from_str = header::HeaderValue::from_str(&auth_value).unwrap();
-    headers.insert(header::AUTHORIZATION, from_str);
-
-    let mut params = HashMap::new();
-    params.insert("email", "milekium@proton.com");
-
-    let client = reqwest::Client::builder()
+pub fn client() -> Client {
+    let http = reqwest::Client::builder()
         .user_agent("vue/v3")
-        .default_headers(headers)
         .build()
         .expect("build request should pass");
+    Client::new(BASE_URL).with_http_client(http)
+}
 
-    let response = client
-        .post("http://127.0.0.1:3000/signup")
-        .form(&params)
-        .send()
-        .await
-        .expect("Failed to execute request to /signup");
+/// The status and body of a call: the body serialized, or the error message.
+fn answer<T: Serialize>(result: Result<T, Error>) -> (u16, String) {
+    match result {
+        Ok(body) => (200, serde_json::to_string(&body).unwrap()),
+        Err(e) => (e.status().expect("an answer"), e.to_string()),
+    }
+}
 
-    (
-        response.status().as_u16(),
-        response.text().await.expect("text extraction fail"),
+/// The token strings are the bodies themselves.
+fn token_answer(result: Result<String, Error>) -> (u16, String) {
+    match result {
+        Ok(token) => (200, token),
+        Err(e) => answer::<()>(Err(e)),
+    }
+}
+
+/// The token of a sign-in's JSON answer, or the body as is when it is not
+/// one, such as an error page.
+#[allow(dead_code)]
+pub fn access_token(body: String) -> String {
+    match serde_json::from_str::<SignInResponse>(&body) {
+        Ok(signed_in) => signed_in.access_token,
+        Err(_) => body,
+    }
+}
+
+#[allow(dead_code)]
+pub async fn singup(credentials: Credentials) -> (u16, String) {
+    token_answer(
+        client()
+            .signup(
+                &credentials.username,
+                &credentials.password,
+                "milekium@proton.com",
+            )
+            .await,
     )
 }
 #[allow(dead_code)]
 pub async fn login(credentials: Credentials) -> (u16, String) {
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        "WWW-Authenticate",
-        header::HeaderValue::from_static("Basic realm=AuthServer"),
-    );
-    let credentials_inline = format!("{}:{}", credentials.username, credentials.password);
-    let encoded_credentials: &str = &encode_config(credentials_inline, base64::STANDARD);
-    let auth_value = format!("Basic {}", encoded_credentials);
-    let from_str = header::HeaderValue::from_str(&auth_value).unwrap();
-    headers.insert(header::AUTHORIZATION, from_str);
+    token_answer(
+        client()
+            .login(&credentials.username, &credentials.password)
+            .await,
+    )
+}
+#[allow(dead_code)]
+pub async fn delete(token: String) -> (u16, String) {
+    match client().with_token(&token).delete_me().await {
+        Ok(id) => (200, id.to_string()),
+        Err(e) => answer::<()>(Err(e)),
+    }
+}
 
-    let client = reqwest::Client::builder()
-        .user_agent("vue/v3")
-        .default_headers(headers)
-        .build()
-        .expect("build request should pass");
+#[allow(dead_code)]
+pub async fn me(token: String) -> (u16, String) {
+    answer(client().with_token(&token).me().await)
+}
 
-    let response = client
-        .post("http://127.0.0.1:3000/login")
-        .send()
+async fn db_client() -> tokio_postgres::Client {
+    dotenv::dotenv().ok();
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let (client, connection) = tokio_postgres::connect(&database_url, NoTls)
         .await
-        .expect("Failed to execute request to /login");
+        .expect("Failed to connect to the database");
+    tokio::spawn(connection);
+    client
+}
 
-    (
-        response.status().as_u16(),
-        response.text().await.expect("text extraction fail"),
-    )
+/// Registers an OAuth client in the default realm directly in the database.
+#[allow(dead_code)]
+pub async fn register_client(redirect_uri: &str, scopes: &[&str]) -> Uuid {
+    let client = db_client().await;
+    let scopes: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+    let row = client
+        .query_one(
+            "insert into oauth_clients (realm, name, redirect_uris, scopes) values ('AuthServer', 'Test App', $1, $2) returning id",
+            &[&vec![redirect_uri.to_string()], &scopes],
+        )
+        .await
+        .expect("Failed to register client");
+    row.get(0)
 }
+
+/// Grants the admin role to a user of the default realm.
 #[allow(dead_code)]
-pub async fn delete(token: String) -> (u16, String) {
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        "WWW-Authenticate",
-        header::HeaderValue::from_static("Basic realm=AuthServer"),
-    );
+pub async fn make_admin(username: &str) {
+    db_client()
+        .await
+        .execute(
+            "update users set roles = '{admin}' where realm = 'AuthServer' and username = $1",
+            &[&username],
+        )
+        .await
+        .expect("Failed to grant the admin role");
+}
 
-    let encoded_credentials: &str = &encode_config(token, base64::STANDARD);
-    let auth_value = format!("Basic {}", encoded_credentials);
-    let from_str = header::HeaderValue::from_str(&auth_value).unwrap();
-    headers.insert(header::AUTHORIZATION, from_str);
+/// Gives a user of the default realm an email address they have verified.
+#[allow(dead_code)]
+pub async fn verify_email(username: &str, email: &str) {
+    db_client()
+        .await
+        .execute(
+            "update users set email = $1, email_verified = true where realm = 'AuthServer' and username = $2",
+            &[&email, &username],
+        )
+        .await
+        .expect("Failed to verify the email");
+}
 
-    let client = reqwest::Client::builder()
-        .user_agent("vue/v3")
-        .default_headers(headers)
-        .build()
-        .expect("build request should pass");
+/// Suspends a user of the default realm, as an administrator would.
+#[allow(dead_code)]
+pub async fn suspend(username: &str) {
+    db_client()
+        .await
+        .execute(
+            "update users set active = false where realm = 'AuthServer' and username = $1",
+            &[&username],
+        )
+        .await
+        .expect("Failed to suspend the user");
+}
 
-    let response = client
-        .delete("http://127.0.0.1:3000/me")
-        .send()
+/// Records an AuthnRequest of the default realm to `provider`, as
+/// `/saml/{provider}/login` would, for a canned response to answer.
+#[allow(dead_code)]
+pub async fn saml_request(request_id: &str, provider: &str) {
+    db_client()
         .await
-        .expect("Failed to execute request to /delete");
+        .execute(
+            "insert into saml_requests (request_id, realm, provider, expires_at) values ($1, 'AuthServer', $2, now() + interval '10 minutes') \
+             on conflict (request_id) do update set provider = excluded.provider, expires_at = excluded.expires_at",
+            &[&request_id, &provider],
+        )
+        .await
+        .expect("Failed to record the SAML request");
+}
 
+/// Creates a client through the admin API as a fresh administrator,
+/// returning the `{client_id, client_secret}` response.
+#[allow(dead_code)]
+pub async fn admin_client(new_client: serde_json::Value) -> serde_json::Value {
+    let credentials = Credentials {
+        username: format!("admin-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let admin = client();
+    admin
+        .signup(
+            &credentials.username,
+            &credentials.password,
+            "milekium@proton.com",
+        )
+        .await
+        .expect("Failed to sign up");
+    make_admin(&credentials.username).await;
+
+    let new_client = serde_json::from_value(new_client).expect("new client");
+    let credentials = admin
+        .create_client(&new_client)
+        .await
+        .expect("Failed to execute request to /admin/clients");
+    serde_json::to_value(credentials).unwrap()
+}
+
+/// Creates a confidential client, returning its `(client_id, client_secret)`.
+#[allow(dead_code)]
+pub async fn confidential_client(scopes: &[&str], grant_types: &[&str]) -> (String, String) {
+    let response = admin_client(serde_json::json!({
+        "name": "Resource Server",
+        "scopes": scopes,
+        "grant_types": grant_types,
+        "confidential": true
+    }))
+    .await;
     (
-        response.status().as_u16(),
-        response.text().await.expect("text extraction fail"),
+        response["client_id"].as_str().unwrap().to_string(),
+        response["client_secret"].as_str().unwrap().to_string(),
     )
 }
 
+/// Returns a PKCE `(code_verifier, code_challenge)` pair using S256.
 #[allow(dead_code)]
-pub async fn me(token: String) -> (u16, String) {
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        "WWW-Authenticate",
-        header::HeaderValue::from_static("Basic realm=AuthServer"),
+pub fn pkce_pair() -> (String, String) {
+    let verifier = format!(
+        "{}{}",
+        Uuid::new_v4().to_simple(),
+        Uuid::new_v4().to_simple()
     );
+    let challenge = encode_config(Sha256::digest(verifier.as_bytes()), base64::URL_SAFE_NO_PAD);
+    (verifier, challenge)
+}
 
-    let encoded_credentials: &str = &encode_config(token, base64::STANDARD);
-    let auth_value = format!("Basic {}", encoded_credentials);
-    let from_str = header::HeaderValue::from_str(&auth_value).unwrap();
-    headers.insert(header::AUTHORIZATION, from_str);
+/// The ticket a consent page carries in its form.
+#[allow(dead_code)]
+pub fn consent_ticket(page: &str) -> String {
+    let marker = "name=\"consent_ticket\" value=\"";
+    let start = page.find(marker).expect("consent page") + marker.len();
+    let end = start + page[start..].find('"').unwrap();
+    page[start..end].to_string()
+}
 
-    let client = reqwest::Client::builder()
-        .user_agent("vue/v3")
-        .default_headers(headers)
+/// Runs the authorization code flow for `credentials` up to the token
+/// response, approving the consent screen if shown, and returns it as JSON.
+#[allow(dead_code)]
+pub async fn authorization_code_tokens(
+    credentials: &Credentials,
+    client_id: &str,
+    redirect_uri: &str,
+    scope: &str,
+    nonce: &str,
+) -> serde_json::Value {
+    let browser = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
         .build()
         .expect("build request should pass");
+    let (verifier, challenge) = pkce_pair();
 
-    let response = client
-        .get("http://127.0.0.1:3000/me")
+    let mut form = HashMap::new();
+    form.insert("response_type", "code");
+    form.insert("client_id", client_id);
+    form.insert("redirect_uri", redirect_uri);
+    form.insert("scope", scope);
+    form.insert("nonce", nonce);
+    form.insert("code_challenge", &challenge);
+    form.insert("code_challenge_method", "S256");
+    let mut login = form.clone();
+    login.insert("username", &credentials.username);
+    login.insert("password", &credentials.password);
+    let mut response = browser
+        .post("http://127.0.0.1:3000/authorize")
+        .form(&login)
         .send()
         .await
-        .expect("Failed to execute request to /delete");
+        .expect("Failed to execute request to /authorize");
+    if response.status() == reqwest::StatusCode::OK {
+        let ticket = consent_ticket(&response.text().await.unwrap());
+        form.insert("consent_ticket", &ticket);
+        form.insert("action", "approve");
+        response = browser
+            .post("http://127.0.0.1:3000/authorize/consent")
+            .form(&form)
+            .send()
+            .await
+            .expect("Failed to execute request to /authorize/consent");
+    }
+    let location = response.headers()[header::LOCATION].to_str().unwrap();
+    let code = url::Url::parse(location)
+        .unwrap()
+        .query_pairs()
+        .find(|(k, _)| k == "code")
+        .map(|(_, v)| v.into_owned())
+        .expect("code in redirect");
 
-    (
-        response.status().as_u16(),
-        response.text().await.expect("text extraction fail"),
-    )
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct User {
-    pub id: Uuid,
-    pub username: Option<String>,
-    pub email: String,
-    pub full_name: Option<String>,
-    pub bio: Option<String>,
-    pub image: Option<String>,
-    // pub created_at: NaiveDateTime,
-    // pub updated_at: NaiveDateTime,
+    let tokens = client()
+        .token_grant(&TokenRequest {
+            grant_type: "authorization_code".to_string(),
+            code: Some(code),
+            redirect_uri: Some(redirect_uri.to_string()),
+            client_id: Some(client_id.to_string()),
+            code_verifier: Some(verifier),
+            ..TokenRequest::default()
+        })
+        .await
+        .expect("Failed to execute request to /token");
+    serde_json::to_value(tokens).unwrap()
 }
 
 #[derive(Validate, Debug, Clone)]
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use reqwest::{header, redirect::Policy, Client, StatusCode};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+mod common;
+
+const REDIRECT_URI: &str = "http://localhost:8080/callback";
+
+fn client() -> Client {
+    Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .expect("build client should pass")
+}
+
+/// Signs in on the authorization page, returning the response: a consent
+/// page or a redirect back to the client.
+async fn sign_in(
+    credentials: &common::Credentials,
+    client_id: &str,
+    scope: &str,
+) -> (HashMap<&'static str, String>, reqwest::Response) {
+    let (_, challenge) = common::pkce_pair();
+    let params: HashMap<&str, String> = [
+        ("response_type", "code".to_string()),
+        ("client_id", client_id.to_string()),
+        ("redirect_uri", REDIRECT_URI.to_string()),
+        ("scope", scope.to_string()),
+        ("state", "xyz".to_string()),
+        ("code_challenge", challenge),
+        ("code_challenge_method", "S256".to_string()),
+    ]
+    .into_iter()
+    .collect();
+    let mut form = params.clone();
+    form.insert("username", credentials.username.clone());
+    form.insert("password", credentials.password.clone());
+    let response = client()
+        .post("http://127.0.0.1:3000/authorize")
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request to /authorize");
+    (params, response)
+}
+
+async fn consent(mut params: HashMap<&'static str, String>, page: &str, action: &str) -> String {
+    params.insert("consent_ticket", common::consent_ticket(page));
+    params.insert("action", action.to_string());
+    let response = client()
+        .post("http://127.0.0.1:3000/authorize/consent")
+        .form(&params)
+        .send()
+        .await
+        .expect("Failed to execute request to /authorize/consent");
+    assert_eq!(302, response.status().as_u16());
+    response.headers()[header::LOCATION]
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+async fn user() -> (common::Credentials, String) {
+    let credentials = common::Credentials {
+        username: format!("consent-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, token) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    (credentials, token)
+}
+
+#[tokio::test]
+async fn consent_is_asked_once_per_scope() {
+    common::spawn_app().await;
+    let (credentials, token) = user().await;
+    let client_id = common::register_client(REDIRECT_URI, &["profile", "email"])
+        .await
+        .to_string();
+
+    let (params, response) = sign_in(&credentials, &client_id, "profile").await;
+    assert_eq!(200, response.status().as_u16());
+    let page = response.text().await.unwrap();
+    assert!(page.contains("Test App is requesting access"));
+    assert!(page.contains("<li>profile</li>"));
+    let location = consent(params, &page, "approve").await;
+    assert!(location.contains("code="));
+
+    // Already covered: straight back to the client.
+    let (_, response) = sign_in(&credentials, &client_id, "profile").await;
+    assert_eq!(302, response.status().as_u16());
+
+    // Asking for more shows the screen again.
+    let (params, response) = sign_in(&credentials, &client_id, "profile email").await;
+    assert_eq!(200, response.status().as_u16());
+    let page = response.text().await.unwrap();
+    consent(params, &page, "approve").await;
+
+    let grants: Value = client()
+        .get("http://127.0.0.1:3000/me/grants")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/grants")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(1, grants.as_array().unwrap().len());
+    assert_eq!(client_id, grants[0]["client_id"]);
+    assert_eq!("Test App", grants[0]["client_name"]);
+    assert_eq!(json!(["email", "profile"]), grants[0]["scopes"]);
+}
+
+#[tokio::test]
+async fn denying_consent_redirects_with_access_denied() {
+    common::spawn_app().await;
+    let (credentials, _) = user().await;
+    let client_id = common::register_client(REDIRECT_URI, &["profile"])
+        .await
+        .to_string();
+
+    let (params, response) = sign_in(&credentials, &client_id, "profile").await;
+    let page = response.text().await.unwrap();
+    let location = consent(params, &page, "deny").await;
+    assert!(location.starts_with(REDIRECT_URI));
+    assert!(location.contains("error=access_denied"));
+    assert!(location.contains("state=xyz"));
+    assert!(!location.contains("code="));
+
+    // A forged ticket is not a sign-in.
+    let (mut params, _) = sign_in(&credentials, &client_id, "profile").await;
+    params.insert("consent_ticket", "forged".to_string());
+    params.insert("action", "approve".to_string());
+    let response = client()
+        .post("http://127.0.0.1:3000/authorize/consent")
+        .form(&params)
+        .send()
+        .await
+        .expect("Failed to execute request to /authorize/consent");
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn revoking_a_grant_revokes_its_tokens() {
+    common::spawn_app().await;
+    let (credentials, token) = user().await;
+    let client_id = common::register_client(REDIRECT_URI, &["profile"])
+        .await
+        .to_string();
+    let tokens =
+        common::authorization_code_tokens(&credentials, &client_id, REDIRECT_URI, "profile", "n")
+            .await;
+
+    // Clients cannot manage the grants they were given.
+    let response = client()
+        .get("http://127.0.0.1:3000/me/grants")
+        .bearer_auth(tokens["access_token"].as_str().unwrap())
+        .send()
+        .await
+        .expect("Failed to execute request to /me/grants");
+    assert_eq!(403, response.status().as_u16());
+
+    let response = client()
+        .delete(format!("http://127.0.0.1:3000/me/grants/{}", client_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/grants/{client_id}");
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    let response = client()
+        .post("http://127.0.0.1:3000/token")
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", tokens["refresh_token"].as_str().unwrap()),
+            ("client_id", &client_id),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    assert_eq!(400, response.status().as_u16());
+
+    let resource_server = common::confidential_client(&[], &["client_credentials"]).await;
+    let introspection: Value = client()
+        .post("http://127.0.0.1:3000/introspect")
+        .basic_auth(&resource_server.0, Some(&resource_server.1))
+        .form(&[("token", tokens["access_token"].as_str().unwrap())])
+        .send()
+        .await
+        .expect("Failed to execute request to /introspect")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(false, introspection["active"]);
+
+    // The next sign-in asks again.
+    let (_, response) = sign_in(&credentials, &client_id, "profile").await;
+    assert_eq!(200, response.status().as_u16());
+
+    let response = client()
+        .delete(format!("http://127.0.0.1:3000/me/grants/{}", client_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/grants/{client_id}");
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn first_party_clients_skip_consent() {
+    common::spawn_app().await;
+    let (credentials, _) = user().await;
+    let created = common::admin_client(json!({
+        "name": "Account Console",
+        "redirect_uris": [REDIRECT_URI],
+        "scopes": ["profile"],
+        "first_party": true
+    }))
+    .await;
+
+    let (_, response) = sign_in(
+        &credentials,
+        created["client_id"].as_str().unwrap(),
+        "profile",
+    )
+    .await;
+    assert_eq!(302, response.status().as_u16());
+}
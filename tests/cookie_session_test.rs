@@ -0,0 +1,187 @@
+use reqwest::header::{COOKIE, SET_COOKIE};
+use reqwest::{Client, Response};
+use uuid::Uuid;
+
+mod common;
+
+/// Serves the default realm from a realms file turning cookie sessions on.
+async fn spawn_app() {
+    let path = std::env::temp_dir().join("cookie_session_realms.toml");
+    std::fs::write(
+        &path,
+        format!(
+            r#"
+[[realms]]
+name = "AuthServer"
+jwt_secret = "{}"
+jwt_signing_key = "{}"
+
+[realms.cookie_session]
+cookie_name = "app_session"
+same_site = "Strict"
+"#,
+            std::env::var("JWT_SECRET").unwrap(),
+            std::env::var("JWT_SIGNING_KEY").unwrap(),
+        ),
+    )
+    .unwrap();
+    std::env::set_var("REALMS_FILE", &path);
+    common::spawn_app().await;
+}
+
+fn set_cookies(response: &Response) -> Vec<String> {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .map(|value| value.to_str().unwrap().to_string())
+        .collect()
+}
+
+/// The value a `Set-Cookie` header gives the cookie `name`.
+fn cookie<'a>(set_cookies: &'a [String], name: &str) -> &'a str {
+    set_cookies
+        .iter()
+        .find_map(|c| c.strip_prefix(&format!("{}=", name)))
+        .and_then(|c| c.split(';').next())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn browsers_sign_in_with_a_cookie_and_prove_their_requests() {
+    spawn_app().await;
+    let credentials = common::Credentials {
+        username: format!("browser-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    assert_eq!(200, common::singup(credentials.clone()).await.0);
+
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/login")
+        .basic_auth(&credentials.username, Some(&credentials.password))
+        .send()
+        .await
+        .expect("Failed to execute request to /login");
+    assert_eq!(200, response.status().as_u16());
+    let set = set_cookies(&response);
+    let session = set.iter().find(|c| c.starts_with("app_session=")).unwrap();
+    assert!(session.contains("; HttpOnly"));
+    assert!(session.contains("; Secure"));
+    assert!(session.contains("; SameSite=Strict"));
+    let csrf_cookie = set
+        .iter()
+        .find(|c| c.starts_with("app_session_csrf="))
+        .unwrap();
+    assert!(!csrf_cookie.contains("HttpOnly"));
+    let secret = cookie(&set, "app_session");
+    let csrf = cookie(&set, "app_session_csrf");
+    let cookies = format!("app_session={}; app_session_csrf={}", secret, csrf);
+
+    let me = Client::new()
+        .get("http://127.0.0.1:3000/me")
+        .header(COOKIE, &cookies)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, me.status().as_u16());
+
+    // State-changing requests must echo the CSRF cookie.
+    let revoke = |csrf: Option<&str>| {
+        let mut request = Client::new()
+            .delete(format!(
+                "http://127.0.0.1:3000/me/sessions/{}",
+                Uuid::new_v4()
+            ))
+            .header(COOKIE, &cookies);
+        if let Some(csrf) = csrf {
+            request = request.header("X-CSRF-Token", csrf);
+        }
+        request.send()
+    };
+    assert_eq!(403, revoke(None).await.unwrap().status().as_u16());
+    assert_eq!(403, revoke(Some("forged")).await.unwrap().status().as_u16());
+    assert_eq!(404, revoke(Some(csrf)).await.unwrap().status().as_u16());
+
+    let logout = Client::new()
+        .post("http://127.0.0.1:3000/logout")
+        .header(COOKIE, &cookies)
+        .header("X-CSRF-Token", csrf)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, logout.status().as_u16());
+    assert_eq!("", cookie(&set_cookies(&logout), "app_session"));
+
+    let me = Client::new()
+        .get("http://127.0.0.1:3000/me")
+        .header(COOKIE, &cookies)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, me.status().as_u16());
+}
+
+#[tokio::test]
+async fn browsers_approve_devices_with_the_page_csrf_token() {
+    spawn_app().await;
+    let credentials = common::Credentials {
+        username: format!("browser-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    assert_eq!(200, common::singup(credentials.clone()).await.0);
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/login")
+        .basic_auth(&credentials.username, Some(&credentials.password))
+        .send()
+        .await
+        .expect("Failed to execute request to /login");
+    let set = set_cookies(&response);
+    let csrf = cookie(&set, "app_session_csrf");
+    let cookies = format!(
+        "app_session={}; app_session_csrf={}",
+        cookie(&set, "app_session"),
+        csrf
+    );
+
+    let client = common::admin_client(serde_json::json!({
+        "name": "TV",
+        "scopes": ["profile"],
+        "grant_types": ["urn:ietf:params:oauth:grant-type:device_code"]
+    }))
+    .await;
+    let authorization: serde_json::Value = Client::new()
+        .post("http://127.0.0.1:3000/device_authorization")
+        .form(&[("client_id", client["client_id"].as_str().unwrap())])
+        .send()
+        .await
+        .expect("Failed to execute request to /device_authorization")
+        .json()
+        .await
+        .unwrap();
+    let user_code = authorization["user_code"].as_str().unwrap();
+
+    let page = Client::new()
+        .get(authorization["verification_uri_complete"].as_str().unwrap())
+        .header(COOKIE, &cookies)
+        .send()
+        .await
+        .expect("Failed to execute request to /device")
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains(&format!("name=\"csrf_token\" value=\"{}\"", csrf)));
+
+    let approve = |csrf_token: &str| {
+        Client::new()
+            .post("http://127.0.0.1:3000/device")
+            .header(COOKIE, &cookies)
+            .form(&[
+                ("user_code", user_code),
+                ("action", "approve"),
+                ("csrf_token", csrf_token),
+            ])
+            .send()
+    };
+    assert_eq!(403, approve("forged").await.unwrap().status().as_u16());
+    assert_eq!(200, approve(csrf).await.unwrap().status().as_u16());
+}
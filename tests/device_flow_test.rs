@@ -0,0 +1,193 @@
+use reqwest::Client;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+mod common;
+
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+async fn cli_client() -> String {
+    let response = common::admin_client(json!({
+        "name": "Deploy CLI",
+        "scopes": ["deploy", "profile"],
+        "grant_types": [DEVICE_CODE_GRANT]
+    }))
+    .await;
+    response["client_id"].as_str().unwrap().to_string()
+}
+
+async fn device_authorization(client_id: &str) -> Value {
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/device_authorization")
+        .form(&[("client_id", client_id), ("scope", "deploy")])
+        .send()
+        .await
+        .expect("Failed to execute request to /device_authorization");
+    assert_eq!(200, response.status().as_u16());
+    response
+        .json()
+        .await
+        .expect("device authorization response")
+}
+
+async fn poll(client_id: &str, device_code: &str) -> (u16, Value) {
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .form(&[
+            ("grant_type", DEVICE_CODE_GRANT),
+            ("device_code", device_code),
+            ("client_id", client_id),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    (
+        response.status().as_u16(),
+        response.json().await.expect("token response"),
+    )
+}
+
+async fn decide(token: Option<&str>, user_code: &str, action: &str) -> u16 {
+    let mut request = Client::new()
+        .post("http://127.0.0.1:3000/device")
+        .form(&[("user_code", user_code), ("action", action)]);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request
+        .send()
+        .await
+        .expect("Failed to execute request to /device")
+        .status()
+        .as_u16()
+}
+
+/// A signed-up user's access token.
+async fn user() -> String {
+    let credentials = common::Credentials {
+        username: format!("device-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, token) = common::singup(credentials).await;
+    assert_eq!(200, code);
+    token
+}
+
+#[tokio::test]
+async fn approved_device_receives_tokens() {
+    common::spawn_app().await;
+    let token = user().await;
+    let client_id = cli_client().await;
+    let authorization = device_authorization(&client_id).await;
+    let device_code = authorization["device_code"].as_str().unwrap();
+    let user_code = authorization["user_code"].as_str().unwrap();
+    assert_eq!(9, user_code.len());
+    assert_eq!(5, authorization["interval"]);
+    assert_eq!(
+        "http://127.0.0.1:3000/v1/device",
+        authorization["verification_uri"]
+    );
+
+    let page = Client::new()
+        .get(authorization["verification_uri_complete"].as_str().unwrap())
+        .send()
+        .await
+        .expect("Failed to execute request to /device")
+        .text()
+        .await
+        .unwrap();
+    assert!(page.contains("Deploy CLI is requesting access"));
+    assert!(page.contains(user_code));
+
+    // Only a signed-in user may decide.
+    assert_eq!(401, decide(None, user_code, "approve").await);
+    assert_eq!(401, decide(Some("forged"), user_code, "approve").await);
+    assert_eq!(
+        200,
+        decide(Some(&token), &user_code.to_lowercase(), "approve").await
+    );
+
+    let (code, tokens) = poll(&client_id, device_code).await;
+    assert_eq!(200, code);
+    assert_eq!("Bearer", tokens["token_type"]);
+    assert_eq!("deploy", tokens["scope"]);
+    assert!(tokens["refresh_token"].is_string());
+    let access_token = tokens["access_token"].as_str().unwrap();
+    // Valid, but the client's rather than the user's own.
+    let (code, _) = common::me(access_token.to_string()).await;
+    assert_eq!(403, code);
+
+    let (code, error) = poll(&client_id, device_code).await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_grant", error["error"]);
+}
+
+#[tokio::test]
+async fn polling_before_approval_is_pending_then_slowed_down() {
+    common::spawn_app().await;
+    let client_id = cli_client().await;
+    let authorization = device_authorization(&client_id).await;
+    let device_code = authorization["device_code"].as_str().unwrap();
+
+    let (code, error) = poll(&client_id, device_code).await;
+    assert_eq!(400, code);
+    assert_eq!("authorization_pending", error["error"]);
+    let (code, error) = poll(&client_id, device_code).await;
+    assert_eq!(400, code);
+    assert_eq!("slow_down", error["error"]);
+}
+
+#[tokio::test]
+async fn denied_device_gets_access_denied() {
+    common::spawn_app().await;
+    let token = user().await;
+    let client_id = cli_client().await;
+    let authorization = device_authorization(&client_id).await;
+    let user_code = authorization["user_code"].as_str().unwrap();
+
+    assert_eq!(200, decide(Some(&token), user_code, "deny").await);
+    assert_eq!(400, decide(Some(&token), user_code, "approve").await);
+    let (code, error) = poll(&client_id, authorization["device_code"].as_str().unwrap()).await;
+    assert_eq!(400, code);
+    assert_eq!("access_denied", error["error"]);
+}
+
+#[tokio::test]
+async fn clients_without_the_grant_cannot_start_the_flow() {
+    common::spawn_app().await;
+    let client_id = common::register_client("http://localhost:8080/callback", &["profile"])
+        .await
+        .to_string();
+
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/device_authorization")
+        .form(&[("client_id", &client_id)])
+        .send()
+        .await
+        .expect("Failed to execute request to /device_authorization");
+    assert_eq!(400, response.status().as_u16());
+    let error: Value = response.json().await.unwrap();
+    assert_eq!("unauthorized_client", error["error"]);
+}
+
+#[tokio::test]
+async fn revoking_the_grant_kills_approved_device_codes() {
+    common::spawn_app().await;
+    let token = user().await;
+    let client_id = cli_client().await;
+    let authorization = device_authorization(&client_id).await;
+    let user_code = authorization["user_code"].as_str().unwrap();
+    assert_eq!(200, decide(Some(&token), user_code, "approve").await);
+
+    let response = Client::new()
+        .delete(format!("http://127.0.0.1:3000/me/grants/{}", client_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/grants/{client_id}");
+    assert_eq!(204, response.status().as_u16());
+
+    let (code, error) = poll(&client_id, authorization["device_code"].as_str().unwrap()).await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_grant", error["error"]);
+}
@@ -44,3 +44,31 @@ mod common;
 //         .expect(r#"Failed to execute request."#);
 //     assert_eq!(401, response.status().as_u16());
 // }
+
+#[tokio::test]
+async fn missing_authorization_is_challenged_with_the_realm() {
+    common::spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get("http://127.0.0.1:3000/realms/AuthServer/me")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        "Basic realm=\"AuthServer\"",
+        response.headers()[reqwest::header::WWW_AUTHENTICATE]
+    );
+}
+
+#[tokio::test]
+async fn unknown_realms_are_not_found() {
+    common::spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get("http://127.0.0.1:3000/realms/unknown/me")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(404, response.status().as_u16());
+}
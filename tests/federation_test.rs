@@ -0,0 +1,529 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use rand_core::OsRng;
+use reqwest::{redirect::Policy, Client};
+use rsa::pkcs1::EncodeRsaPrivateKey;
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPrivateKey;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use warp::http::header::{COOKIE, LOCATION, SET_COOKIE};
+use warp::http::StatusCode;
+use warp::Filter;
+
+mod common;
+
+const ISSUER: &str = "http://127.0.0.1:3901";
+const CLIENT_ID: &str = "authserver";
+const CLIENT_SECRET: &str = "upstream-secret";
+
+/// Someone who can sign in at the mock provider, chosen with `login_hint`.
+#[derive(Clone)]
+struct UpstreamUser {
+    sub: String,
+    email: String,
+    email_verified: bool,
+    /// Signs the ID token with a key missing from the provider's JWKS.
+    forged: bool,
+}
+
+struct IssuedCode {
+    user: UpstreamUser,
+    nonce: String,
+    code_challenge: String,
+}
+
+#[derive(Default)]
+struct Provider {
+    users: HashMap<String, UpstreamUser>,
+    codes: HashMap<String, IssuedCode>,
+}
+
+fn provider() -> &'static Mutex<Provider> {
+    static PROVIDER: OnceLock<Mutex<Provider>> = OnceLock::new();
+    PROVIDER.get_or_init(Default::default)
+}
+
+/// The provider's signing key, and one it never published.
+fn keys() -> &'static (RsaPrivateKey, RsaPrivateKey) {
+    static KEYS: OnceLock<(RsaPrivateKey, RsaPrivateKey)> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        (
+            RsaPrivateKey::new(&mut OsRng, 2048).unwrap(),
+            RsaPrivateKey::new(&mut OsRng, 2048).unwrap(),
+        )
+    })
+}
+
+fn base64url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn id_token(code: &IssuedCode) -> String {
+    let (key, forger) = keys();
+    let key = if code.user.forged { forger } else { key };
+    let now = chrono::Utc::now().timestamp();
+    let claims = json!({
+        "iss": ISSUER,
+        "aud": CLIENT_ID,
+        "sub": code.user.sub,
+        "iat": now,
+        "exp": now + 300,
+        "nonce": code.nonce,
+        "email": code.user.email,
+        "email_verified": code.user.email_verified,
+    });
+    let header = Header {
+        kid: Some("mock".to_string()),
+        ..Header::new(Algorithm::RS256)
+    };
+    let der = key.to_pkcs1_der().unwrap();
+    encode(&header, &claims, &EncodingKey::from_rsa_der(der.as_bytes())).unwrap()
+}
+
+/// A minimal OpenID provider: discovery, JWKS, an authorization endpoint
+/// that signs in whoever `login_hint` names, and a token endpoint checking
+/// the client secret and PKCE.
+async fn spawn_provider() {
+    let discovery = warp::path!(".well-known" / "openid-configuration").map(|| {
+        warp::reply::json(&json!({
+            "issuer": ISSUER,
+            "authorization_endpoint": format!("{}/authorize", ISSUER),
+            "token_endpoint": format!("{}/token", ISSUER),
+            "jwks_uri": format!("{}/jwks", ISSUER),
+        }))
+    });
+    let jwks = warp::path!("jwks").map(|| {
+        let key = keys().0.to_public_key();
+        warp::reply::json(&json!({ "keys": [{
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": "mock",
+            "n": base64url(&key.n().to_bytes_be()),
+            "e": base64url(&key.e().to_bytes_be()),
+        }]}))
+    });
+    let authorize = warp::path!("authorize")
+        .and(warp::query::<HashMap<String, String>>())
+        .map(|params: HashMap<String, String>| {
+            assert_eq!(CLIENT_ID, params["client_id"]);
+            assert_eq!("S256", params["code_challenge_method"]);
+            let mut provider = provider().lock().unwrap();
+            let user = provider.users[&params["login_hint"]].clone();
+            let code = Uuid::new_v4().to_string();
+            provider.codes.insert(
+                code.clone(),
+                IssuedCode {
+                    user,
+                    nonce: params["nonce"].clone(),
+                    code_challenge: params["code_challenge"].clone(),
+                },
+            );
+            let mut location = url::Url::parse(&params["redirect_uri"]).unwrap();
+            location
+                .query_pairs_mut()
+                .append_pair("code", &code)
+                .append_pair("state", &params["state"]);
+            warp::reply::with_status(
+                warp::reply::with_header(warp::reply(), LOCATION, location.as_str()),
+                StatusCode::FOUND,
+            )
+        });
+    let token = warp::path!("token")
+        .and(warp::header::<String>("authorization"))
+        .and(warp::body::form::<HashMap<String, String>>())
+        .map(|authorization: String, form: HashMap<String, String>| {
+            let credentials = base64::encode(format!("{}:{}", CLIENT_ID, CLIENT_SECRET));
+            assert_eq!(format!("Basic {}", credentials), authorization);
+            let code = provider().lock().unwrap().codes.remove(&form["code"]);
+            match code {
+                Some(code)
+                    if base64url(&Sha256::digest(form["code_verifier"].as_bytes()))
+                        == code.code_challenge =>
+                {
+                    warp::reply::with_status(
+                        warp::reply::json(&json!({
+                            "access_token": "upstream-access-token",
+                            "token_type": "Bearer",
+                            "id_token": id_token(&code),
+                        })),
+                        StatusCode::OK,
+                    )
+                }
+                _ => warp::reply::with_status(
+                    warp::reply::json(&json!({ "error": "invalid_grant" })),
+                    StatusCode::BAD_REQUEST,
+                ),
+            }
+        });
+
+    keys();
+    let routes = warp::get()
+        .and(discovery.or(jwks).or(authorize))
+        .or(warp::post().and(token));
+    tokio::task::spawn(warp::serve(routes).run(([127, 0, 0, 1], 3901)));
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect("127.0.0.1:3901")
+            .await
+            .is_ok()
+        {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("mock provider did not start listening on 127.0.0.1:3901");
+}
+
+/// Serves the default realm from a realms file declaring the mock provider.
+async fn spawn_app() {
+    spawn_provider().await;
+    let path = std::env::temp_dir().join("federation_realms.toml");
+    std::fs::write(
+        &path,
+        format!(
+            r#"
+[[realms]]
+name = "AuthServer"
+jwt_secret = "{}"
+jwt_signing_key = "{}"
+
+[[realms.identity_providers]]
+name = "corp"
+issuer = "{issuer}"
+client_id = "{client_id}"
+client_secret = "{client_secret}"
+link_by_email = true
+
+[[realms.identity_providers]]
+name = "partner"
+issuer = "{issuer}"
+client_id = "{client_id}"
+client_secret = "{client_secret}"
+"#,
+            std::env::var("JWT_SECRET").unwrap(),
+            std::env::var("JWT_SIGNING_KEY").unwrap(),
+            issuer = ISSUER,
+            client_id = CLIENT_ID,
+            client_secret = CLIENT_SECRET
+        ),
+    )
+    .unwrap();
+    std::env::set_var("REALMS_FILE", &path);
+    common::spawn_app().await;
+}
+
+/// An upstream account, returning the `login_hint` that signs in with it.
+fn upstream_user(email: &str, email_verified: bool, forged: bool) -> String {
+    let login_hint = Uuid::new_v4().to_string();
+    provider().lock().unwrap().users.insert(
+        login_hint.clone(),
+        UpstreamUser {
+            sub: format!("corp-{}", Uuid::new_v4()),
+            email: email.to_string(),
+            email_verified,
+            forged,
+        },
+    );
+    login_hint
+}
+
+async fn local_user() -> common::Credentials {
+    let credentials = common::Credentials {
+        username: format!("user-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, _) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    credentials
+}
+
+fn browser() -> Client {
+    Client::builder().redirect(Policy::none()).build().unwrap()
+}
+
+async fn follow(browser: &Client, url: &str) -> String {
+    let response = browser.get(url).send().await.unwrap();
+    assert_eq!(302, response.status().as_u16());
+    response.headers()[LOCATION].to_str().unwrap().to_string()
+}
+
+/// The `name=value` of the cookie binding a sign-in to the browser.
+fn state_cookie(response: &reqwest::Response) -> String {
+    let set_cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+    assert!(set_cookie.contains("; HttpOnly"));
+    assert!(set_cookie.contains("; Path=/login/"));
+    set_cookie.split(';').next().unwrap().to_string()
+}
+
+/// Goes through the provider, returning the callback URL it redirects to and
+/// the cookie to bring back with it.
+async fn sign_in_upstream(browser: &Client, provider: &str, login_hint: &str) -> (String, String) {
+    let response = browser
+        .get(format!(
+            "http://127.0.0.1:3000/login/{}?login_hint={}",
+            provider, login_hint
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(302, response.status().as_u16());
+    let cookie = state_cookie(&response);
+    let authorize = response.headers()[LOCATION].to_str().unwrap().to_string();
+    assert!(authorize.starts_with(&format!("{}/authorize?", ISSUER)));
+    assert!(authorize.contains("code_challenge="));
+    (follow(browser, &authorize).await, cookie)
+}
+
+async fn callback(browser: &Client, callback: &str, cookie: Option<&str>) -> u16 {
+    let mut request = browser.get(callback);
+    if let Some(cookie) = cookie {
+        request = request.header(COOKIE, cookie);
+    }
+    request.send().await.unwrap().status().as_u16()
+}
+
+async fn federated_login(login_hint: &str) -> (u16, String) {
+    federated_login_at("corp", login_hint).await
+}
+
+async fn federated_login_at(provider: &str, login_hint: &str) -> (u16, String) {
+    let browser = browser();
+    let (callback, cookie) = sign_in_upstream(&browser, provider, login_hint).await;
+    let response = browser
+        .get(&callback)
+        .header(COOKIE, cookie)
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    (status, common::access_token(response.text().await.unwrap()))
+}
+
+async fn username(token: String) -> String {
+    let (code, body) = common::me(token).await;
+    assert_eq!(200, code);
+    let user: serde_json::Value = serde_json::from_str(&body).unwrap();
+    user["username"].as_str().unwrap().to_string()
+}
+
+fn link_ticket(page: &str) -> String {
+    let start = page.find("name=\"link_ticket\" value=\"").unwrap() + 26;
+    let end = start + page[start..].find('"').unwrap();
+    page[start..end].to_string()
+}
+
+async fn link(ticket: &str, credentials: &common::Credentials) -> (u16, String) {
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/login/corp/link")
+        .form(&[
+            ("link_ticket", ticket),
+            ("username", &credentials.username),
+            ("password", &credentials.password),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request to /login/corp/link");
+    let status = response.status().as_u16();
+    (status, common::access_token(response.text().await.unwrap()))
+}
+
+#[tokio::test]
+async fn upstream_accounts_are_linked_by_verified_email() {
+    spawn_app().await;
+    let local = local_user().await;
+    let email = format!("{}@corp.example", Uuid::new_v4());
+    common::verify_email(&local.username, &email).await;
+
+    let (code, token) = federated_login(&upstream_user(&email, true, false)).await;
+    assert_eq!(200, code);
+    assert_eq!(local.username, username(token).await);
+
+    // An address the provider has not verified proves nothing.
+    let (code, page) = federated_login(&upstream_user(&email, false, false)).await;
+    assert_eq!(200, code);
+    assert!(page.contains("name=\"link_ticket\""));
+
+    // Nor does one verified by a provider not trusted to link by email.
+    let (code, page) = federated_login_at("partner", &upstream_user(&email, true, false)).await;
+    assert_eq!(200, code);
+    assert!(page.contains("name=\"link_ticket\""));
+}
+
+#[tokio::test]
+async fn upstream_accounts_can_be_linked_by_signing_in() {
+    spawn_app().await;
+    let local = local_user().await;
+    let login_hint = upstream_user("someone@corp.example", false, false);
+
+    let (code, page) = federated_login(&login_hint).await;
+    assert_eq!(200, code);
+    let ticket = link_ticket(&page);
+
+    let wrong = common::Credentials {
+        username: local.username.clone(),
+        password: "wrong".to_string(),
+    };
+    let (code, page) = link(&ticket, &wrong).await;
+    assert_eq!(401, code);
+    assert!(page.contains("Invalid username or password."));
+
+    let (code, token) = link(&ticket, &local).await;
+    assert_eq!(200, code);
+    assert_eq!(local.username, username(token).await);
+
+    // Linked: the next sign-in goes straight through.
+    let (code, token) = federated_login(&login_hint).await;
+    assert_eq!(200, code);
+    assert_eq!(local.username, username(token).await);
+
+    let (code, _) = link("not-a-ticket", &local).await;
+    assert_eq!(400, code);
+}
+
+#[tokio::test]
+async fn forged_and_replayed_responses_are_refused() {
+    spawn_app().await;
+    let (code, _) = federated_login(&upstream_user("mallory@corp.example", true, true)).await;
+    assert_eq!(401, code);
+
+    let browser = browser();
+    let (victim_callback, cookie) = sign_in_upstream(
+        &browser,
+        "corp",
+        &upstream_user("someone@corp.example", false, false),
+    )
+    .await;
+    assert_eq!(
+        200,
+        callback(&browser, &victim_callback, Some(&cookie)).await
+    );
+    assert_eq!(
+        400,
+        callback(&browser, &victim_callback, Some(&cookie)).await
+    );
+
+    // A sign-in only finishes in the browser that started it, so that no one
+    // can slip theirs into someone else's.
+    let (mallory_callback, _) = sign_in_upstream(
+        &browser,
+        "corp",
+        &upstream_user("mallory@corp.example", false, false),
+    )
+    .await;
+    assert_eq!(400, callback(&browser, &mallory_callback, None).await);
+    assert_eq!(
+        400,
+        callback(&browser, &mallory_callback, Some(&cookie)).await
+    );
+
+    let response = browser
+        .get("http://127.0.0.1:3000/login/unknown")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+}
+
+async fn identities(token: &str) -> Vec<serde_json::Value> {
+    let response = Client::new()
+        .get("http://127.0.0.1:3000/me/identities")
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/identities");
+    assert_eq!(200, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+async fn add_identity(token: &str, provider: &str, form: &[(&str, &str)]) -> reqwest::Response {
+    Client::new()
+        .post(format!("http://127.0.0.1:3000/me/identities/{}", provider))
+        .bearer_auth(token)
+        .form(form)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/identities/{provider}")
+}
+
+async fn remove_identity(token: &str, provider: &str) -> u16 {
+    Client::new()
+        .delete(format!("http://127.0.0.1:3000/me/identities/{}", provider))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/identities/{provider}")
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn signed_in_users_link_and_unlink_identities() {
+    spawn_app().await;
+    let local = local_user().await;
+    let (_, token) = common::login(local.clone()).await;
+    let listed = identities(&token).await;
+    assert_eq!(1, listed.len());
+    assert_eq!("password", listed[0]["provider"]);
+    assert_eq!(json!(local.username), listed[0]["subject"]);
+    assert_eq!(409, remove_identity(&token, "password").await);
+
+    // Linking takes the password again.
+    let login_hint = upstream_user("linked@corp.example", false, false);
+    let response = add_identity(
+        &token,
+        "corp",
+        &[("password", "wrong"), ("login_hint", &login_hint)],
+    )
+    .await;
+    assert_eq!(401, response.status().as_u16());
+    let response = add_identity(
+        &token,
+        "corp",
+        &[("password", &local.password), ("login_hint", &login_hint)],
+    )
+    .await;
+    assert_eq!(200, response.status().as_u16());
+    let cookie = state_cookie(&response);
+    let started: serde_json::Value = response.json().await.unwrap();
+    let browser = browser();
+    let callback = follow(&browser, started["authorization_url"].as_str().unwrap()).await;
+    let response = browser
+        .get(&callback)
+        .header(COOKIE, cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains("Account linked"));
+
+    let listed = identities(&token).await;
+    assert_eq!(2, listed.len());
+    assert_eq!("corp", listed[1]["provider"]);
+    assert!(listed[1]["linked_at"].is_string());
+    let (code, upstream_token) = federated_login(&login_hint).await;
+    assert_eq!(200, code);
+    assert_eq!(local.username, username(upstream_token).await);
+
+    // Without the password, the upstream account is all that is left.
+    assert_eq!(204, remove_identity(&token, "password").await);
+    assert_eq!(401, common::login(local.clone()).await.0);
+    assert_eq!(409, remove_identity(&token, "corp").await);
+    assert_eq!(404, remove_identity(&token, "unknown").await);
+
+    // A password-less user who just signed in may set one.
+    let new_password = format!("new-{}", Uuid::new_v4());
+    let response = add_identity(&token, "password", &[("new_password", &new_password)]).await;
+    assert_eq!(204, response.status().as_u16());
+    let renewed = common::Credentials {
+        username: local.username.clone(),
+        password: new_password,
+    };
+    assert_eq!(200, common::login(renewed).await.0);
+    assert_eq!(204, remove_identity(&token, "corp").await);
+    assert_eq!(1, identities(&token).await.len());
+}
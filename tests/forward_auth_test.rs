@@ -0,0 +1,186 @@
+use reqwest::{Client, RequestBuilder, Response};
+use uuid::Uuid;
+
+mod common;
+
+/// Serves the default realm from a realms file with forward-auth rules.
+async fn spawn_app() {
+    let path = std::env::temp_dir().join("forward_auth_realms.toml");
+    std::fs::write(
+        &path,
+        format!(
+            r#"
+[[realms]]
+name = "AuthServer"
+jwt_secret = "{}"
+jwt_signing_key = "{}"
+
+[[realms.forward_auth.rules]]
+path = "/assets/"
+public = true
+
+[[realms.forward_auth.rules]]
+path = "/admin/"
+roles = ["admin"]
+"#,
+            std::env::var("JWT_SECRET").unwrap(),
+            std::env::var("JWT_SIGNING_KEY").unwrap(),
+        ),
+    )
+    .unwrap();
+    std::env::set_var("REALMS_FILE", &path);
+    common::spawn_app().await;
+}
+
+/// Asks about a request as Traefik's ForwardAuth middleware does.
+fn verify(uri: &str) -> RequestBuilder {
+    Client::new()
+        .get("http://127.0.0.1:3000/auth/verify")
+        .header("X-Forwarded-Method", "GET")
+        .header("X-Forwarded-Uri", uri)
+}
+
+fn header<'a>(response: &'a Response, name: &str) -> &'a str {
+    response.headers()[name].to_str().unwrap()
+}
+
+#[tokio::test]
+async fn proxies_learn_who_may_reach_a_path() {
+    spawn_app().await;
+    let username = format!("proxied-{}", Uuid::new_v4());
+    let (code, token) = common::singup(common::Credentials {
+        username: username.clone(),
+        password: "password".to_string(),
+    })
+    .await;
+    assert_eq!(200, code);
+
+    let response = verify("/orders?page=2")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert!(Uuid::parse_str(header(&response, "x-auth-user-id")).is_ok());
+    assert_eq!("", header(&response, "x-auth-roles"));
+    assert!(response.headers().contains_key("x-auth-email"));
+
+    let response = verify("/orders").send().await.unwrap();
+    assert_eq!(401, response.status().as_u16());
+    let response = verify("/orders")
+        .bearer_auth("not-a-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+
+    // nginx auth_request forwards the original URI instead.
+    let response = Client::new()
+        .get("http://127.0.0.1:3000/auth/verify")
+        .header("X-Original-URI", "/assets/app.css")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert!(!response.headers().contains_key("x-auth-user-id"));
+
+    let response = verify("/admin/users")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(403, response.status().as_u16());
+
+    // A fresh token: the last answer about the first one is cached.
+    common::make_admin(&username).await;
+    let (_, admin_token) = common::login(common::Credentials {
+        username,
+        password: "password".to_string(),
+    })
+    .await;
+    let response = verify("/admin/users")
+        .bearer_auth(&admin_token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("admin", header(&response, "x-auth-roles"));
+}
+
+#[tokio::test]
+async fn paths_are_matched_as_the_application_resolves_them() {
+    spawn_app().await;
+    let (code, token) = common::singup(common::Credentials {
+        username: format!("proxied-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    })
+    .await;
+    assert_eq!(200, code);
+
+    // A public prefix does not open what dot segments lead out of it.
+    for uri in [
+        "/assets/../admin/users",
+        "/assets/%2e%2e/admin/users",
+        "//admin/users",
+    ] {
+        let response = verify(uri).send().await.unwrap();
+        assert_eq!(401, response.status().as_u16(), "{}", uri);
+        let response = verify(uri).bearer_auth(&token).send().await.unwrap();
+        assert_eq!(403, response.status().as_u16(), "{}", uri);
+    }
+    let response = verify("/assets/..%2Fadmin/users").send().await.unwrap();
+    assert_eq!(400, response.status().as_u16());
+
+    // Rules cover whole segments.
+    let response = verify("/administrator")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let response = verify("/assets-private/app.css").send().await.unwrap();
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn only_the_users_own_sign_in_gets_through() {
+    spawn_app().await;
+    let credentials = common::Credentials {
+        username: format!("proxied-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, _) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    let redirect_uri = "http://localhost:8080/callback";
+    let client_id = common::register_client(redirect_uri, &["profile"])
+        .await
+        .to_string();
+    let tokens =
+        common::authorization_code_tokens(&credentials, &client_id, redirect_uri, "profile", "n")
+            .await;
+    let response = verify("/orders")
+        .bearer_auth(tokens["access_token"].as_str().unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(403, response.status().as_u16());
+
+    let (client_id, client_secret) =
+        common::confidential_client(&["reports:read"], &["client_credentials"]).await;
+    let tokens: serde_json::Value = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .basic_auth(&client_id, Some(&client_secret))
+        .form(&[("grant_type", "client_credentials")])
+        .send()
+        .await
+        .expect("Failed to execute request to /token")
+        .json()
+        .await
+        .unwrap();
+    let response = verify("/orders")
+        .bearer_auth(tokens["access_token"].as_str().unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(403, response.status().as_u16());
+}
@@ -17,6 +17,8 @@ async fn health_check_works() {
 
 #[tokio::test]
 async fn headers_check_works() {
+    common::spawn_app().await;
+
     let client = reqwest::Client::builder()
         .user_agent("reqwest/v0.8.6")
         .build()
@@ -0,0 +1,257 @@
+use reqwest::Client;
+use serde_json::Value;
+use uuid::Uuid;
+
+mod common;
+
+const REDIRECT_URI: &str = "http://localhost:8080/callback";
+
+async fn introspect(resource_server: &(String, String), token: &str) -> Value {
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/introspect")
+        .basic_auth(&resource_server.0, Some(&resource_server.1))
+        .form(&[("token", token)])
+        .send()
+        .await
+        .expect("Failed to execute request to /introspect");
+    assert_eq!(200, response.status().as_u16());
+    response.json().await.expect("introspection response")
+}
+
+async fn revoke(client_id: &str, token: &str) -> u16 {
+    Client::new()
+        .post("http://127.0.0.1:3000/revoke")
+        .form(&[("token", token), ("client_id", client_id)])
+        .send()
+        .await
+        .expect("Failed to execute request to /revoke")
+        .status()
+        .as_u16()
+}
+
+async fn refresh(client_id: &str, refresh_token: &str) -> (u16, Value) {
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", client_id),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    (
+        response.status().as_u16(),
+        response.json().await.expect("token response"),
+    )
+}
+
+async fn user_tokens() -> (common::Credentials, String, Value) {
+    let credentials = common::Credentials {
+        username: format!("introspect-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, _) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    let client_id = common::register_client(REDIRECT_URI, &["profile"])
+        .await
+        .to_string();
+    let tokens =
+        common::authorization_code_tokens(&credentials, &client_id, REDIRECT_URI, "profile", "n")
+            .await;
+    (credentials, client_id, tokens)
+}
+
+#[tokio::test]
+async fn introspect_and_revoke_client_credentials_token() {
+    common::spawn_app().await;
+    let resource_server = common::confidential_client(&["reports"], &["client_credentials"]).await;
+
+    let token: Value = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .basic_auth(&resource_server.0, Some(&resource_server.1))
+        .form(&[("grant_type", "client_credentials")])
+        .send()
+        .await
+        .expect("Failed to execute request to /token")
+        .json()
+        .await
+        .unwrap();
+    let access_token = token["access_token"].as_str().unwrap();
+
+    let introspection = introspect(&resource_server, access_token).await;
+    assert_eq!(true, introspection["active"]);
+    assert_eq!(resource_server.0, introspection["sub"]);
+    assert_eq!(resource_server.0, introspection["client_id"]);
+    assert_eq!("reports", introspection["scope"]);
+    assert!(introspection["exp"].is_i64());
+
+    let status = Client::new()
+        .post("http://127.0.0.1:3000/revoke")
+        .form(&[
+            ("token", access_token),
+            ("client_id", &resource_server.0),
+            ("client_secret", &resource_server.1),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request to /revoke")
+        .status();
+    assert_eq!(200, status.as_u16());
+
+    let introspection = introspect(&resource_server, access_token).await;
+    assert_eq!(serde_json::json!({ "active": false }), introspection);
+}
+
+#[tokio::test]
+async fn refresh_tokens_rotate_and_revoke_their_access_tokens() {
+    common::spawn_app().await;
+    let resource_server = common::confidential_client(&[], &["client_credentials"]).await;
+    let (_, client_id, tokens) = user_tokens().await;
+    let refresh_token = tokens["refresh_token"].as_str().expect("refresh token");
+
+    let (code, refreshed) = refresh(&client_id, refresh_token).await;
+    assert_eq!(200, code);
+    assert_eq!("profile", refreshed["scope"]);
+    let (code, error) = refresh(&client_id, refresh_token).await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_grant", error["error"]);
+
+    let access_token = refreshed["access_token"].as_str().unwrap();
+    let new_refresh_token = refreshed["refresh_token"].as_str().unwrap();
+    assert_eq!(
+        true,
+        introspect(&resource_server, new_refresh_token).await["active"]
+    );
+    assert_eq!(
+        true,
+        introspect(&resource_server, access_token).await["active"]
+    );
+
+    assert_eq!(200, revoke(&client_id, new_refresh_token).await);
+    assert_eq!(
+        false,
+        introspect(&resource_server, new_refresh_token).await["active"]
+    );
+    assert_eq!(
+        false,
+        introspect(&resource_server, access_token).await["active"]
+    );
+    let (code, _) = refresh(&client_id, new_refresh_token).await;
+    assert_eq!(400, code);
+
+    // Unknown tokens are not an error (RFC 7009 section 2.2).
+    assert_eq!(200, revoke(&client_id, "unknown").await);
+}
+
+#[tokio::test]
+async fn suspended_users_tokens_are_inactive() {
+    common::spawn_app().await;
+    let resource_server = common::confidential_client(&[], &["client_credentials"]).await;
+    let (credentials, client_id, tokens) = user_tokens().await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+
+    let introspection = introspect(&resource_server, access_token).await;
+    assert_eq!(true, introspection["active"]);
+    assert_eq!(client_id, introspection["client_id"]);
+    assert_eq!("profile", introspection["scope"]);
+
+    common::suspend(&credentials.username).await;
+    assert_eq!(
+        false,
+        introspect(&resource_server, access_token).await["active"]
+    );
+    let (code, _) = refresh(&client_id, tokens["refresh_token"].as_str().unwrap()).await;
+    assert_eq!(400, code);
+}
+
+#[tokio::test]
+async fn only_token_owner_may_revoke_and_public_clients_may_not_introspect() {
+    common::spawn_app().await;
+    let (_, client_id, tokens) = user_tokens().await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let other_client = common::register_client(REDIRECT_URI, &["profile"])
+        .await
+        .to_string();
+
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/revoke")
+        .form(&[("token", access_token), ("client_id", &other_client)])
+        .send()
+        .await
+        .expect("Failed to execute request to /revoke");
+    assert_eq!(400, response.status().as_u16());
+    let error: Value = response.json().await.unwrap();
+    assert_eq!("unauthorized_client", error["error"]);
+
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/introspect")
+        .form(&[("token", access_token), ("client_id", &client_id)])
+        .send()
+        .await
+        .expect("Failed to execute request to /introspect");
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn revoked_access_tokens_are_turned_away_everywhere() {
+    common::spawn_app().await;
+    let (_, client_id, tokens) = user_tokens().await;
+    let access_token = tokens["access_token"].as_str().unwrap().to_string();
+    // Not the user's own token, but a valid one.
+    assert_eq!(403, common::me(access_token.clone()).await.0);
+
+    assert_eq!(200, revoke(&client_id, &access_token).await);
+
+    assert_eq!(401, common::me(access_token.clone()).await.0);
+    let response = Client::new()
+        .get("http://127.0.0.1:3000/auth/verify")
+        .bearer_auth(&access_token)
+        .send()
+        .await
+        .expect("Failed to execute request to /auth/verify");
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn tokens_of_revoked_sessions_are_inactive() {
+    common::spawn_app().await;
+    let resource_server = common::confidential_client(&[], &["client_credentials"]).await;
+    let credentials = common::Credentials {
+        username: format!("user-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, laptop) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    let (code, phone) = common::login(credentials).await;
+    assert_eq!(200, code);
+    assert_eq!(true, introspect(&resource_server, &phone).await["active"]);
+
+    let sessions: Value = Client::new()
+        .get("http://127.0.0.1:3000/me/sessions")
+        .bearer_auth(&laptop)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/sessions")
+        .json()
+        .await
+        .unwrap();
+    let other = sessions
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["current"] == false)
+        .unwrap();
+    let response = Client::new()
+        .delete(format!(
+            "http://127.0.0.1:3000/me/sessions/{}",
+            other["id"].as_str().unwrap()
+        ))
+        .bearer_auth(&laptop)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/sessions");
+    assert_eq!(204, response.status().as_u16());
+    assert_eq!(false, introspect(&resource_server, &phone).await["active"]);
+    assert_eq!(true, introspect(&resource_server, &laptop).await["active"]);
+}
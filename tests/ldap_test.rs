@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use bytes::BytesMut;
+use lber::common::TagClass;
+use lber::structure::{StructureTag, PL};
+use reqwest::header::AUTHORIZATION;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+
+mod common;
+
+const LDAP_ADDR: &str = "127.0.0.1:3389";
+const BASE_DN: &str = "dc=corp,dc=example";
+const SERVICE_DN: &str = "cn=service,dc=corp,dc=example";
+const SERVICE_PASSWORD: &str = "service-secret";
+
+const SUCCESS: i64 = 0;
+const INVALID_CREDENTIALS: i64 = 49;
+
+struct Entry {
+    dn: String,
+    password: String,
+    attributes: HashMap<String, String>,
+}
+
+fn directory() -> &'static Mutex<Vec<Entry>> {
+    static DIRECTORY: OnceLock<Mutex<Vec<Entry>>> = OnceLock::new();
+    DIRECTORY.get_or_init(|| {
+        Mutex::new(vec![Entry {
+            dn: SERVICE_DN.to_string(),
+            password: SERVICE_PASSWORD.to_string(),
+            attributes: HashMap::new(),
+        }])
+    })
+}
+
+/// Adds someone to the directory under `ou`, returning their uid.
+fn directory_user(ou: &str, password: &str, mail: &str) -> String {
+    let uid = format!("dir-{}", Uuid::new_v4());
+    directory().lock().unwrap().push(Entry {
+        dn: format!("uid={},ou={},{}", uid, ou, BASE_DN),
+        password: password.to_string(),
+        attributes: HashMap::from([
+            ("objectClass".to_string(), "person".to_string()),
+            ("uid".to_string(), uid.clone()),
+            ("mail".to_string(), mail.to_string()),
+            ("displayName".to_string(), format!("Directory {}", ou)),
+        ]),
+    });
+    uid
+}
+
+fn set_mail(uid: &str, mail: &str) {
+    let mut directory = directory().lock().unwrap();
+    let entry = directory
+        .iter_mut()
+        .find(|e| e.attributes.get("uid").map(String::as_str) == Some(uid))
+        .unwrap();
+    entry
+        .attributes
+        .insert("mail".to_string(), mail.to_string());
+}
+
+fn tag(class: TagClass, id: u64, payload: PL) -> StructureTag {
+    StructureTag { class, id, payload }
+}
+
+fn octets(value: &str) -> StructureTag {
+    tag(TagClass::Universal, 4, PL::P(value.as_bytes().to_vec()))
+}
+
+fn integer(id: u64, value: i64) -> StructureTag {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take(7).take_while(|b| **b == 0).count();
+    tag(TagClass::Universal, id, PL::P(bytes[skip..].to_vec()))
+}
+
+fn parse_integer(tag: &StructureTag) -> i64 {
+    match &tag.payload {
+        PL::P(bytes) => bytes.iter().fold(0, |n, b| (n << 8) | *b as i64),
+        PL::C(_) => panic!("not an integer: {:?}", tag),
+    }
+}
+
+fn string(tag: &StructureTag) -> String {
+    match &tag.payload {
+        PL::P(bytes) => String::from_utf8(bytes.clone()).unwrap(),
+        PL::C(_) => panic!("not a string: {:?}", tag),
+    }
+}
+
+fn children(tag: &StructureTag) -> &[StructureTag] {
+    match &tag.payload {
+        PL::C(children) => children,
+        PL::P(_) => panic!("not constructed: {:?}", tag),
+    }
+}
+
+fn result(op: u64, code: i64) -> StructureTag {
+    tag(
+        TagClass::Application,
+        op,
+        PL::C(vec![integer(10, code), octets(""), octets("")]),
+    )
+}
+
+fn matches(filter: &StructureTag, entry: &Entry) -> bool {
+    let value = |name: &str| {
+        entry
+            .attributes
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    };
+    match (filter.class, filter.id) {
+        // and
+        (TagClass::Context, 0) => children(filter).iter().all(|f| matches(f, entry)),
+        // equalityMatch
+        (TagClass::Context, 3) => {
+            let assertion = children(filter);
+            value(&string(&assertion[0]))
+                .is_some_and(|v| v.eq_ignore_ascii_case(&string(&assertion[1])))
+        }
+        // present
+        (TagClass::Context, 7) => {
+            let name = string(filter);
+            name.eq_ignore_ascii_case("objectClass") || value(&name).is_some()
+        }
+        _ => false,
+    }
+}
+
+/// Answers one LDAP operation; `None` once the client unbinds.
+fn answer(op: &StructureTag, bound: &mut bool) -> Option<Vec<StructureTag>> {
+    let fields = match op.payload {
+        PL::C(ref fields) => fields,
+        // UnbindRequest
+        PL::P(_) => return None,
+    };
+    match op.id {
+        // BindRequest: version, name, simple password.
+        0 => {
+            let (dn, password) = (string(&fields[1]), string(&fields[2]));
+            // An empty password is an anonymous bind, which directories accept.
+            let accepted = password.is_empty()
+                || directory()
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .any(|e| e.dn.eq_ignore_ascii_case(&dn) && e.password == password);
+            *bound = accepted && !password.is_empty();
+            let code = if accepted {
+                SUCCESS
+            } else {
+                INVALID_CREDENTIALS
+            };
+            Some(vec![result(1, code)])
+        }
+        // SearchRequest: base, scope, deref, size limit, time limit, types only, filter, attributes.
+        3 => {
+            // Like Active Directory, only bound clients may search.
+            if !*bound {
+                return Some(vec![result(5, 1)]);
+            }
+            let base = string(&fields[0]).to_lowercase();
+            let subtree = parse_integer(&fields[1]) == 2;
+            let requested: Vec<String> = children(&fields[7]).iter().map(string).collect();
+            let directory = directory().lock().unwrap();
+            let mut replies: Vec<StructureTag> = directory
+                .iter()
+                .filter(|e| {
+                    let dn = e.dn.to_lowercase();
+                    let in_scope = if subtree {
+                        dn.ends_with(&base)
+                    } else {
+                        dn == base
+                    };
+                    in_scope && matches(&fields[6], e)
+                })
+                .map(|e| {
+                    let attributes = e
+                        .attributes
+                        .iter()
+                        .filter(|(name, _)| requested.iter().any(|r| r.eq_ignore_ascii_case(name)))
+                        .map(|(name, value)| {
+                            tag(
+                                TagClass::Universal,
+                                16,
+                                PL::C(vec![
+                                    octets(name),
+                                    tag(TagClass::Universal, 17, PL::C(vec![octets(value)])),
+                                ]),
+                            )
+                        })
+                        .collect();
+                    tag(
+                        TagClass::Application,
+                        4,
+                        PL::C(vec![
+                            octets(&e.dn),
+                            tag(TagClass::Universal, 16, PL::C(attributes)),
+                        ]),
+                    )
+                })
+                .collect();
+            replies.push(result(5, SUCCESS));
+            Some(replies)
+        }
+        op => panic!("unexpected LDAP operation {}", op),
+    }
+}
+
+async fn serve(mut stream: TcpStream) {
+    let mut buffer = Vec::new();
+    let mut bound = false;
+    loop {
+        let (rest, message) = match lber::parse::parse_tag(&buffer) {
+            Ok((rest, message)) => (rest.to_vec(), message),
+            Err(_) => {
+                let mut chunk = [0; 4096];
+                match stream.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                }
+                continue;
+            }
+        };
+        buffer = rest;
+        // LDAPMessage: message id, operation, optional controls.
+        let message = children(&message);
+        let id = parse_integer(&message[0]);
+        let replies = match answer(&message[1], &mut bound) {
+            Some(replies) => replies,
+            None => return,
+        };
+        let mut out = BytesMut::new();
+        for reply in replies {
+            let envelope = tag(TagClass::Universal, 16, PL::C(vec![integer(2, id), reply]));
+            lber::write::encode_into(&mut out, envelope).unwrap();
+        }
+        if stream.write_all(&out).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// A directory stand-in speaking just enough LDAPv3 for simple binds and
+/// searches, on its own thread so it outlives any one test's runtime.
+fn spawn_directory() {
+    static STARTED: OnceLock<()> = OnceLock::new();
+    STARTED.get_or_init(|| {
+        let (ready, listening) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                let listener = TcpListener::bind(LDAP_ADDR).await.unwrap();
+                ready.send(()).unwrap();
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    tokio::spawn(serve(stream));
+                }
+            })
+        });
+        listening.recv_timeout(Duration::from_secs(5)).unwrap();
+    });
+}
+
+/// Serves a default realm searching the directory with a service account,
+/// and a `staff` realm binding users directly.
+async fn spawn_app() {
+    spawn_directory();
+    let path = std::env::temp_dir().join("ldap_realms.toml");
+    std::fs::write(
+        &path,
+        format!(
+            r#"
+[[realms]]
+name = "AuthServer"
+jwt_secret = "{secret}"
+jwt_signing_key = "{key}"
+
+[realms.ldap]
+url = "ldap://{addr}"
+search_dn = "{service_dn}"
+search_password = "{service_password}"
+search_base = "{base}"
+search_filter = "(&(objectClass=person)(uid={{username}}))"
+
+[[realms]]
+name = "staff"
+jwt_secret = "{secret}-staff"
+jwt_signing_key = "{key}-staff"
+
+[realms.ldap]
+url = "ldap://{addr}"
+bind_dn = "uid={{username}},ou=staff,{base}"
+attributes = {{ full_name = "uid" }}
+"#,
+            secret = std::env::var("JWT_SECRET").unwrap(),
+            key = std::env::var("JWT_SIGNING_KEY").unwrap(),
+            addr = LDAP_ADDR,
+            service_dn = SERVICE_DN,
+            service_password = SERVICE_PASSWORD,
+            base = BASE_DN,
+        ),
+    )
+    .unwrap();
+    std::env::set_var("REALMS_FILE", &path);
+    common::spawn_app().await;
+}
+
+async fn login(prefix: &str, username: &str, password: &str) -> (u16, String) {
+    let credentials = base64::encode(format!("{}:{}", username, password));
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:3000{}/login", prefix))
+        .header(AUTHORIZATION, format!("Basic {}", credentials))
+        .send()
+        .await
+        .expect("Failed to execute request to /login");
+    let status = response.status().as_u16();
+    (status, common::access_token(response.text().await.unwrap()))
+}
+
+async fn me(prefix: &str, token: &str) -> serde_json::Value {
+    let response = reqwest::Client::new()
+        .get(format!("http://127.0.0.1:3000{}/me", prefix))
+        .header(AUTHORIZATION, format!("Basic {}", base64::encode(token)))
+        .send()
+        .await
+        .expect("Failed to execute request to /me");
+    assert_eq!(200, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn directory_users_are_provisioned_on_first_login() {
+    spawn_app().await;
+    let uid = directory_user("people", "directory-pass", "jane@corp.example");
+
+    let (code, token) = login("", &uid, "directory-pass").await;
+    assert_eq!(200, code);
+    let user = me("", &token).await;
+    assert_eq!(uid, user["username"]);
+    assert_eq!("jane@corp.example", user["email"]);
+    assert_eq!("Directory people", user["full_name"]);
+
+    // The directory stays the source of truth for the password and profile.
+    set_mail(&uid, "jane.doe@corp.example");
+    let (code, token) = login("", &uid, "directory-pass").await;
+    assert_eq!(200, code);
+    assert_eq!(user["id"], me("", &token).await["id"]);
+    assert_eq!("jane.doe@corp.example", me("", &token).await["email"]);
+
+    let (code, _) = login("", &uid, "wrong").await;
+    assert_eq!(401, code);
+    // The directory would take it for an anonymous bind.
+    let (code, _) = login("", &uid, "").await;
+    assert_eq!(401, code);
+    // Unknown to both the realm and the directory.
+    let (code, _) = login("", &format!("nobody-{}", Uuid::new_v4()), "pass").await;
+    assert_eq!(404, code);
+}
+
+#[tokio::test]
+async fn directory_users_can_bind_directly() {
+    spawn_app().await;
+    let uid = directory_user("staff", "staff-pass", "ops@corp.example");
+
+    let (code, token) = login("/realms/staff", &uid, "staff-pass").await;
+    assert_eq!(200, code);
+    let user = me("/realms/staff", &token).await;
+    assert_eq!("ops@corp.example", user["email"]);
+    assert_eq!(uid, user["full_name"]);
+
+    let (code, _) = login("/realms/staff", &uid, "wrong").await;
+    assert_eq!(401, code);
+    // Only the staff branch of the directory can sign in to the staff realm.
+    let people = directory_user("people", "people-pass", "someone@corp.example");
+    let (code, _) = login("/realms/staff", &people, "people-pass").await;
+    assert_eq!(404, code);
+}
+
+#[tokio::test]
+async fn local_users_keep_their_own_password() {
+    spawn_app().await;
+    let uid = directory_user("people", "directory-pass", "twin@corp.example");
+    let local = common::Credentials {
+        username: uid.clone(),
+        password: "local-pass".to_string(),
+    };
+    let (code, _) = common::singup(local.clone()).await;
+    assert_eq!(200, code);
+
+    let (code, _) = login("", &uid, "local-pass").await;
+    assert_eq!(200, code);
+    let (code, _) = login("", &uid, "directory-pass").await;
+    assert_eq!(401, code);
+}
@@ -0,0 +1,91 @@
+use reqwest::Client;
+use uuid::Uuid;
+
+mod common;
+
+const METRICS_TOKEN: &str = "scraper-token";
+
+async fn spawn_app() {
+    std::env::set_var("METRICS_TOKEN", METRICS_TOKEN);
+    common::spawn_app().await;
+}
+
+async fn scrape() -> String {
+    let response = Client::new()
+        .get(format!("{}/metrics", common::BASE_URL))
+        .bearer_auth(METRICS_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status());
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain; version=0.0.4"));
+    response.text().await.unwrap()
+}
+
+/// The value of the series `series`, labels included.
+fn value(metrics: &str, series: &str) -> Option<f64> {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .map(|value| value.parse().unwrap())
+}
+
+#[tokio::test]
+async fn metrics_count_sign_ins_and_requests() {
+    spawn_app().await;
+    let username = format!("measured-{}", Uuid::new_v4());
+    let credentials = common::Credentials {
+        username: username.clone(),
+        password: "password".to_string(),
+    };
+    let (code, _) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    let (code, _) = common::login(common::Credentials {
+        username,
+        password: "wrong".to_string(),
+    })
+    .await;
+    assert_eq!(401, code);
+    let (code, _) = common::login(credentials).await;
+    assert_eq!(200, code);
+
+    let metrics = scrape().await;
+    let at_least_one = |series: &str| {
+        let value = value(&metrics, series).unwrap_or_else(|| panic!("no {}", series));
+        assert!(value >= 1.0, "{} is {}", series, value);
+    };
+    at_least_one("authserver_signups_total");
+    at_least_one(r#"authserver_logins_total{reason="password",result="success"}"#);
+    at_least_one(r#"authserver_logins_total{reason="invalid_password",result="failure"}"#);
+    at_least_one(r#"authserver_tokens_issued_total{kind="session"}"#);
+    at_least_one(r#"authserver_argon2_duration_seconds_count{operation="hash"}"#);
+    at_least_one(r#"authserver_argon2_duration_seconds_count{operation="verify"}"#);
+    at_least_one(r#"authserver_http_requests_total{method="POST",route="login",status="401"}"#);
+    at_least_one(
+        r#"authserver_http_request_duration_seconds_count{method="POST",route="signup",status="200"}"#,
+    );
+    at_least_one(r#"authserver_db_pool_connections{state="open"}"#);
+    assert!(value(&metrics, "authserver_db_pool_wait_seconds_total").is_some());
+}
+
+#[tokio::test]
+async fn metrics_require_the_token_when_one_is_set() {
+    spawn_app().await;
+    let response = Client::new()
+        .get(format!("{}/metrics", common::BASE_URL))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status());
+
+    let response = Client::new()
+        .get(format!("{}/metrics", common::BASE_URL))
+        .bearer_auth("guess")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status());
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use reqwest::{header, redirect::Policy, Client};
+use serde::Deserialize;
+use uuid::Uuid;
+
+mod common;
+
+const REDIRECT_URI: &str = "http://localhost:8080/callback";
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: String,
+    scope: String,
+}
+
+#[derive(Deserialize)]
+struct OAuthError {
+    error: String,
+}
+
+fn client() -> Client {
+    Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .expect("build client should pass")
+}
+
+fn query_param(location: &str, name: &str) -> Option<String> {
+    url::Url::parse(location)
+        .unwrap()
+        .query_pairs()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+}
+
+#[tokio::test]
+async fn authorization_code_flow_with_pkce() {
+    common::spawn_app().await;
+    let credentials = common::Credentials {
+        username: format!("oauth-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, user_token) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+
+    let client_id = common::register_client(REDIRECT_URI, &["profile", "email"])
+        .await
+        .to_string();
+    let (verifier, challenge) = common::pkce_pair();
+    let params = [
+        ("response_type", "code"),
+        ("client_id", &client_id),
+        ("redirect_uri", REDIRECT_URI),
+        ("scope", "profile"),
+        ("state", "xyz"),
+        ("code_challenge", &challenge),
+        ("code_challenge_method", "S256"),
+    ];
+
+    let response = client()
+        .get("http://127.0.0.1:3000/authorize")
+        .query(&params)
+        .send()
+        .await
+        .expect("Failed to execute request to /authorize");
+    assert_eq!(200, response.status().as_u16());
+    // Other sites may not frame the login page.
+    assert_eq!("DENY", response.headers()["x-frame-options"]);
+    assert_eq!(
+        "frame-ancestors 'none'",
+        response.headers()["content-security-policy"]
+    );
+    assert!(response.text().await.unwrap().contains("Test App"));
+
+    let mut form: HashMap<&str, &str> = params.iter().cloned().collect();
+    form.insert("username", &credentials.username);
+    form.insert("password", &credentials.password);
+    let response = client()
+        .post("http://127.0.0.1:3000/authorize")
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request to /authorize");
+    assert_eq!(200, response.status().as_u16());
+    let ticket = common::consent_ticket(&response.text().await.unwrap());
+
+    let mut form: HashMap<&str, &str> = params.iter().cloned().collect();
+    form.insert("consent_ticket", &ticket);
+    form.insert("action", "approve");
+    let response = client()
+        .post("http://127.0.0.1:3000/authorize/consent")
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request to /authorize/consent");
+    assert_eq!(302, response.status().as_u16());
+    let location = response.headers()[header::LOCATION].to_str().unwrap();
+    assert!(location.starts_with(REDIRECT_URI));
+    assert_eq!(Some("xyz".to_string()), query_param(location, "state"));
+    let code = query_param(location, "code").expect("code in redirect");
+
+    let token_request = [
+        ("grant_type", "authorization_code"),
+        ("code", &code),
+        ("redirect_uri", REDIRECT_URI),
+        ("client_id", &client_id),
+        ("code_verifier", &verifier),
+    ];
+    let response = client()
+        .post("http://127.0.0.1:3000/token")
+        .form(&token_request)
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    assert_eq!(200, response.status().as_u16());
+    let token: TokenResponse = response.json().await.unwrap();
+    assert_eq!("Bearer", token.token_type);
+    assert_eq!("profile", token.scope);
+
+    // The client's token acts within its scope, not as the user's own.
+    let response = client()
+        .get("http://127.0.0.1:3000/me")
+        .bearer_auth(&token.access_token)
+        .send()
+        .await
+        .expect("Failed to execute request to /me");
+    assert_eq!(403, response.status().as_u16());
+
+    // Codes are single use.
+    let response = client()
+        .post("http://127.0.0.1:3000/token")
+        .form(&token_request)
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    assert_eq!(400, response.status().as_u16());
+    let error: OAuthError = response.json().await.unwrap();
+    assert_eq!("invalid_grant", error.error);
+
+    common::delete(user_token).await;
+}
+
+#[tokio::test]
+async fn authorize_requires_pkce() {
+    common::spawn_app().await;
+    let client_id = common::register_client(REDIRECT_URI, &[]).await.to_string();
+
+    let response = client()
+        .get("http://127.0.0.1:3000/authorize")
+        .query(&[
+            ("response_type", "code"),
+            ("client_id", &client_id),
+            ("state", "xyz"),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request to /authorize");
+    assert_eq!(302, response.status().as_u16());
+    let location = response.headers()[header::LOCATION].to_str().unwrap();
+    assert_eq!(
+        Some("invalid_request".to_string()),
+        query_param(location, "error")
+    );
+    assert_eq!(Some("xyz".to_string()), query_param(location, "state"));
+}
+
+#[tokio::test]
+async fn authorize_does_not_redirect_to_unregistered_uris() {
+    common::spawn_app().await;
+    let client_id = common::register_client(REDIRECT_URI, &[]).await.to_string();
+
+    let response = client()
+        .get("http://127.0.0.1:3000/authorize")
+        .query(&[
+            ("response_type", "code"),
+            ("client_id", &client_id),
+            ("redirect_uri", "http://evil.example.com/callback"),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request to /authorize");
+    assert_eq!(400, response.status().as_u16());
+    assert!(response.headers().get(header::LOCATION).is_none());
+}
+
+#[tokio::test]
+async fn client_tokens_do_not_manage_the_account() {
+    common::spawn_app().await;
+    let credentials = common::Credentials {
+        username: format!("delegating-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, user_token) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    let client_id = common::register_client(REDIRECT_URI, &["openid"])
+        .await
+        .to_string();
+    let tokens =
+        common::authorization_code_tokens(&credentials, &client_id, REDIRECT_URI, "openid", "n")
+            .await;
+    let access_token = tokens["access_token"].as_str().unwrap().to_string();
+
+    let (code, _) = common::me(access_token.clone()).await;
+    assert_eq!(403, code);
+    let (code, _) = common::delete(access_token).await;
+    assert_eq!(403, code);
+
+    let (code, _) = common::me(user_token).await;
+    assert_eq!(200, code);
+}
+
+#[tokio::test]
+async fn token_requests_repeat_the_redirect_uri_they_were_given() {
+    common::spawn_app().await;
+    let credentials = common::Credentials {
+        username: format!("oauth-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, user_token) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    let client_id = common::register_client(REDIRECT_URI, &["profile"])
+        .await
+        .to_string();
+    let (verifier, challenge) = common::pkce_pair();
+    let params = [
+        ("response_type", "code"),
+        ("client_id", &client_id),
+        ("redirect_uri", REDIRECT_URI),
+        ("scope", "profile"),
+        ("code_challenge", &challenge),
+        ("code_challenge_method", "S256"),
+    ];
+    let mut form: HashMap<&str, &str> = params.iter().cloned().collect();
+    form.insert("username", &credentials.username);
+    form.insert("password", &credentials.password);
+    let response = client()
+        .post("http://127.0.0.1:3000/authorize")
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request to /authorize");
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("DENY", response.headers()["x-frame-options"]);
+    let ticket = common::consent_ticket(&response.text().await.unwrap());
+    let mut form: HashMap<&str, &str> = params.iter().cloned().collect();
+    form.insert("consent_ticket", &ticket);
+    form.insert("action", "approve");
+    let response = client()
+        .post("http://127.0.0.1:3000/authorize/consent")
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request to /authorize/consent");
+    let location = response.headers()[header::LOCATION].to_str().unwrap();
+    let code = query_param(location, "code").expect("code in redirect");
+
+    let response = client()
+        .post("http://127.0.0.1:3000/token")
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("client_id", &client_id),
+            ("code_verifier", &verifier),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    assert_eq!(400, response.status().as_u16());
+    let error: OAuthError = response.json().await.unwrap();
+    assert_eq!("invalid_grant", error.error);
+
+    common::delete(user_token).await;
+}
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use authserver_verify::{JwksCache, TokenVerifier};
+use serde_json::Value;
+use uuid::Uuid;
+
+mod common;
+
+const REDIRECT_URI: &str = "http://localhost:8080/callback";
+
+async fn get_json(url: &str, token: Option<&str>) -> (u16, Value) {
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.expect("Failed to execute request");
+    (
+        response.status().as_u16(),
+        response.json().await.unwrap_or(Value::Null),
+    )
+}
+
+#[tokio::test]
+async fn discovery_document_describes_the_provider() {
+    common::spawn_app().await;
+
+    let (code, metadata) = get_json(
+        "http://127.0.0.1:3000/.well-known/openid-configuration",
+        None,
+    )
+    .await;
+    assert_eq!(200, code);
+    let issuer = metadata["issuer"].as_str().unwrap();
+    assert_eq!(
+        format!("{}/v1/token", issuer),
+        metadata["token_endpoint"].as_str().unwrap()
+    );
+    assert_eq!(
+        serde_json::json!(["RS256"]),
+        metadata["id_token_signing_alg_values_supported"]
+    );
+
+    let (code, metadata) = get_json(
+        "http://127.0.0.1:3000/realms/AuthServer/.well-known/openid-configuration",
+        None,
+    )
+    .await;
+    assert_eq!(200, code);
+    assert_eq!(issuer, metadata["issuer"].as_str().unwrap());
+}
+
+#[tokio::test]
+async fn id_token_and_userinfo_carry_scoped_claims() {
+    common::spawn_app().await;
+    let credentials = common::Credentials {
+        username: format!("oidc-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, user_token) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    let client_id = common::register_client(REDIRECT_URI, &["openid", "profile", "email"])
+        .await
+        .to_string();
+
+    let tokens = common::authorization_code_tokens(
+        &credentials,
+        &client_id,
+        REDIRECT_URI,
+        "openid email",
+        "n-0S6_WzA2Mj",
+    )
+    .await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let id_token = tokens["id_token"].as_str().expect("id_token");
+
+    let (_, metadata) = get_json(
+        "http://127.0.0.1:3000/.well-known/openid-configuration",
+        None,
+    )
+    .await;
+    // As a relying party would, with the keys the provider publishes.
+    let jwks = JwksCache::new(
+        metadata["jwks_uri"].as_str().unwrap(),
+        Duration::from_secs(300),
+    )
+    .await
+    .unwrap();
+    let verifier = TokenVerifier::from_jwks(jwks.clone())
+        .with_audience(&client_id)
+        .with_issuer(metadata["issuer"].as_str().unwrap());
+    let claims = verifier.verify_as::<Value>(id_token).await.unwrap().claims;
+    // ID tokens are not access tokens.
+    assert!(verifier.verify(id_token).await.is_err());
+    // Access tokens verify with the same keys, without the realm's secret.
+    let access = TokenVerifier::from_jwks(jwks)
+        .verify(access_token)
+        .await
+        .unwrap()
+        .claims;
+    assert_eq!(claims["sub"], access.sub.to_string());
+    assert_eq!(
+        Some(client_id.clone()),
+        access.client_id.map(|id| id.to_string())
+    );
+
+    assert_eq!("n-0S6_WzA2Mj", claims["nonce"]);
+    assert!(claims["auth_time"].as_i64().is_some());
+    assert_eq!("milekium@proton.com", claims["email"]);
+    assert_eq!(false, claims["email_verified"]);
+    assert!(claims.get("preferred_username").is_none());
+
+    let (code, userinfo) = get_json(
+        metadata["userinfo_endpoint"].as_str().unwrap(),
+        Some(access_token),
+    )
+    .await;
+    assert_eq!(200, code);
+    assert_eq!(claims["sub"], userinfo["sub"]);
+    assert_eq!("milekium@proton.com", userinfo["email"]);
+    assert!(userinfo.get("preferred_username").is_none());
+
+    common::delete(user_token).await;
+}
+
+#[tokio::test]
+async fn userinfo_requires_the_openid_scope() {
+    common::spawn_app().await;
+    let credentials = common::Credentials {
+        username: format!("oidc-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (_, token) = common::singup(credentials).await;
+
+    let (code, error) = get_json("http://127.0.0.1:3000/userinfo", Some(&token)).await;
+    assert_eq!(403, code);
+    assert_eq!("insufficient_scope", error["error"]);
+
+    common::delete(token).await;
+}
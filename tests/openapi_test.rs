@@ -0,0 +1,96 @@
+use authserver_client::models::Problem;
+use reqwest::{Client, Method};
+use serde_json::Value;
+
+mod common;
+
+/// The detail of the 404s answered when no route serves a path.
+const ROUTE_MISSED: &str = "No end-point matches the request.";
+
+async fn spawn_app() {
+    std::env::set_var("SWAGGER_UI", "true");
+    common::spawn_app().await;
+}
+
+/// The path of the spec with sample values for its parameters.
+fn sample_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment {
+            "{id}" | "{client_id}" => uuid::Uuid::new_v4().to_string(),
+            s if s.starts_with('{') => "sample".to_string(),
+            s => s.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[tokio::test]
+async fn every_documented_operation_is_served() {
+    spawn_app().await;
+    let client = Client::new();
+    let spec: Value = client
+        .get(format!("{}/openapi.json", common::BASE_URL))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!("3.1.0", spec["openapi"]);
+    let server = spec["servers"][0]["url"].as_str().unwrap();
+    assert_eq!(format!("{}/v1", common::BASE_URL), server);
+
+    let paths = spec["paths"].as_object().unwrap();
+    assert!(paths.contains_key("/scim/v2/Users/{id}"));
+    for (path, item) in paths {
+        for method in item.as_object().unwrap().keys() {
+            let method = Method::from_bytes(method.to_uppercase().as_bytes()).unwrap();
+            let url = format!("{}{}", server, sample_path(path));
+            let response = client
+                .request(method.clone(), &url)
+                .header("user-agent", "openapi-test")
+                .send()
+                .await
+                .unwrap();
+            assert!(!response.headers().contains_key("deprecation"), "{}", url);
+            let status = response.status().as_u16();
+            assert_ne!(405, status, "{} {}", method, path);
+            if status == 404 {
+                let body = response.text().await.unwrap();
+                let detail = serde_json::from_str::<Problem>(&body)
+                    .ok()
+                    .and_then(|problem| problem.detail);
+                assert_ne!(Some(ROUTE_MISSED), detail.as_deref(), "{} {}", method, path);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn undocumented_paths_miss_every_route() {
+    spawn_app().await;
+    let response = Client::new()
+        .get(format!("{}/undocumented", common::BASE_URL))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status());
+    let problem: Problem = response.json().await.unwrap();
+    assert_eq!(Some(ROUTE_MISSED), problem.detail.as_deref());
+}
+
+#[tokio::test]
+async fn swagger_ui_reads_the_spec_next_to_it() {
+    spawn_app().await;
+    let response = Client::new()
+        .get(format!("{}/realms/AuthServer/docs", common::BASE_URL))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status());
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("url: \"openapi.json\""));
+}
@@ -0,0 +1,209 @@
+use reqwest::Client;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+mod common;
+
+async fn admin_token() -> String {
+    let credentials = common::Credentials {
+        username: format!("admin-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, token) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    common::make_admin(&credentials.username).await;
+    token
+}
+
+async fn register(metadata: Value) -> (u16, Value) {
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/register")
+        .json(&metadata)
+        .send()
+        .await
+        .expect("Failed to execute request to /register");
+    (
+        response.status().as_u16(),
+        response.json().await.expect("registration response"),
+    )
+}
+
+async fn client_credentials(client_id: &str, client_secret: &str) -> u16 {
+    Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .basic_auth(client_id, Some(client_secret))
+        .form(&[("grant_type", "client_credentials")])
+        .send()
+        .await
+        .expect("Failed to execute request to /token")
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn registered_clients_wait_for_admin_approval() {
+    common::spawn_app().await;
+    let (code, registration) = register(json!({
+        "client_name": "Partner Sync",
+        "grant_types": ["client_credentials"],
+        "scope": "profile"
+    }))
+    .await;
+    assert_eq!(201, code);
+    assert_eq!("pending", registration["status"]);
+    assert_eq!(
+        "client_secret_basic",
+        registration["token_endpoint_auth_method"]
+    );
+    let client_id = registration["client_id"].as_str().unwrap();
+    let client_secret = registration["client_secret"].as_str().unwrap();
+    assert!(registration["registration_access_token"].is_string());
+    assert_eq!(
+        format!("http://127.0.0.1:3000/v1/register/{}", client_id),
+        registration["registration_client_uri"]
+    );
+
+    assert_eq!(401, client_credentials(client_id, client_secret).await);
+
+    let admin = admin_token().await;
+    let clients: Vec<Value> = Client::new()
+        .get("http://127.0.0.1:3000/admin/clients")
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .expect("Failed to execute request to /admin/clients")
+        .json()
+        .await
+        .unwrap();
+    let listed = clients
+        .iter()
+        .find(|c| c["client_id"] == client_id)
+        .unwrap();
+    assert_eq!("pending", listed["status"]);
+
+    let response = Client::new()
+        .post(format!(
+            "http://127.0.0.1:3000/admin/clients/{}/approve",
+            client_id
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .expect("Failed to execute request to /admin/clients/{id}/approve");
+    assert_eq!(204, response.status().as_u16());
+    assert_eq!(200, client_credentials(client_id, client_secret).await);
+
+    let response = Client::new()
+        .post(format!(
+            "http://127.0.0.1:3000/admin/clients/{}/approve",
+            Uuid::new_v4()
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn registration_validates_client_metadata() {
+    common::spawn_app().await;
+    let (code, error) = register(json!({
+        "client_name": "Partner",
+        "redirect_uris": ["http://partner.example/callback"]
+    }))
+    .await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_redirect_uri", error["error"]);
+
+    let (code, error) = register(json!({
+        "client_name": "Partner",
+        "grant_types": ["client_credentials"],
+        "token_endpoint_auth_method": "none"
+    }))
+    .await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_client_metadata", error["error"]);
+
+    let (code, registration) = register(json!({
+        "client_name": "Partner CLI",
+        "redirect_uris": ["http://127.0.0.1:8400/callback"],
+        "token_endpoint_auth_method": "none"
+    }))
+    .await;
+    assert_eq!(201, code);
+    assert!(registration.get("client_secret").is_none());
+}
+
+#[tokio::test]
+async fn client_configuration_requires_the_registration_token() {
+    common::spawn_app().await;
+    let (_, registration) = register(json!({
+        "client_name": "Partner",
+        "redirect_uris": ["https://partner.example/callback"]
+    }))
+    .await;
+    let uri = registration["registration_client_uri"].as_str().unwrap();
+    let token = registration["registration_access_token"].as_str().unwrap();
+
+    let response = Client::new()
+        .get(uri)
+        .bearer_auth("not-the-token")
+        .send()
+        .await
+        .expect("Failed to execute request to /register/{id}");
+    assert_eq!(401, response.status().as_u16());
+
+    let response = Client::new()
+        .get(uri)
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let configuration: Value = response.json().await.unwrap();
+    assert_eq!("Partner", configuration["client_name"]);
+    assert!(configuration.get("client_secret").is_none());
+
+    let response = Client::new()
+        .put(uri)
+        .bearer_auth(token)
+        .json(&json!({
+            "client_name": "Partner Portal",
+            "redirect_uris": ["https://partner.example/callback"]
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let configuration: Value = response.json().await.unwrap();
+    assert_eq!("Partner Portal", configuration["client_name"]);
+
+    let response = Client::new()
+        .put(uri)
+        .bearer_auth(token)
+        .json(&json!({
+            "client_name": "Partner Portal",
+            "redirect_uris": ["https://partner.example/callback"],
+            "token_endpoint_auth_method": "none"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(400, response.status().as_u16());
+
+    let response = Client::new()
+        .delete(uri)
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, response.status().as_u16());
+    let response = Client::new()
+        .get(uri)
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+}
@@ -0,0 +1,183 @@
+use std::io::Read;
+
+use flate2::read::DeflateDecoder;
+use reqwest::{redirect::Policy, Client};
+use warp::http::header::{CONTENT_TYPE, LOCATION, SET_COOKIE};
+
+mod common;
+
+const SSO_URL: &str = "https://idp.customer.example/saml/sso";
+const ACS_URL: &str = "http://127.0.0.1:3000/saml/customer/acs";
+
+/// Serves the default realm from a realms file declaring the identity
+/// provider that signed the canned responses in `tests/fixtures/saml`.
+async fn spawn_app() {
+    let path = std::env::temp_dir().join("saml_realms.toml");
+    std::fs::write(
+        &path,
+        format!(
+            r#"
+[[realms]]
+name = "AuthServer"
+jwt_secret = "{}"
+jwt_signing_key = "{}"
+
+[realms.cookie_session]
+cookie_name = "saml_session"
+
+[[realms.saml_providers]]
+name = "customer"
+entity_id = "https://idp.customer.example/saml"
+sso_url = "{}"
+certificate = '''
+{}'''
+"#,
+            std::env::var("JWT_SECRET").unwrap(),
+            std::env::var("JWT_SIGNING_KEY").unwrap(),
+            SSO_URL,
+            fixture("idp.crt")
+        ),
+    )
+    .unwrap();
+    std::env::set_var("REALMS_FILE", &path);
+    common::spawn_app().await;
+}
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!(
+        "{}/tests/fixtures/saml/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .unwrap()
+}
+
+/// Posts a canned response to the assertion consumer service, as the
+/// user's browser would, after recording the request it answers. Returns
+/// the status, the token or page and the cookies set.
+async fn post_response(name: &str, request_id: &str) -> (u16, String, Vec<String>) {
+    common::saml_request(request_id, "customer").await;
+    let response = Client::new()
+        .post(ACS_URL)
+        .form(&[("SAMLResponse", base64::encode(fixture(name)))])
+        .send()
+        .await
+        .expect("Failed to execute request to /saml/customer/acs");
+    let status = response.status().as_u16();
+    let cookies = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .map(|value| value.to_str().unwrap().to_string())
+        .collect();
+    (
+        status,
+        common::access_token(response.text().await.unwrap()),
+        cookies,
+    )
+}
+
+async fn user(token: String) -> serde_json::Value {
+    let (code, body) = common::me(token).await;
+    assert_eq!(200, code);
+    serde_json::from_str(&body).unwrap()
+}
+
+#[tokio::test]
+async fn service_provider_metadata_and_requests() {
+    spawn_app().await;
+    let browser = Client::builder().redirect(Policy::none()).build().unwrap();
+
+    let response = browser
+        .get("http://127.0.0.1:3000/saml/customer/metadata")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        "application/samlmetadata+xml",
+        response.headers()[CONTENT_TYPE]
+    );
+    let metadata = response.text().await.unwrap();
+    assert!(metadata.contains("entityID=\"http://127.0.0.1:3000/saml/customer/metadata\""));
+    assert!(metadata.contains(&format!("Location=\"{}\"", ACS_URL)));
+
+    let response = browser
+        .get("http://127.0.0.1:3000/saml/customer/login")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(302, response.status().as_u16());
+    let location = url::Url::parse(response.headers()[LOCATION].to_str().unwrap()).unwrap();
+    assert!(location.as_str().starts_with(SSO_URL));
+    let (_, saml_request) = location
+        .query_pairs()
+        .find(|(name, _)| name == "SAMLRequest")
+        .unwrap();
+    let mut request = String::new();
+    DeflateDecoder::new(base64::decode(saml_request.as_ref()).unwrap().as_slice())
+        .read_to_string(&mut request)
+        .unwrap();
+    assert!(request.starts_with("<samlp:AuthnRequest"));
+    assert!(request.contains(&format!("AssertionConsumerServiceURL=\"{}\"", ACS_URL)));
+    assert!(request.contains("ID=\"_"));
+
+    let response = browser
+        .get("http://127.0.0.1:3000/saml/unknown/login")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn signed_assertions_sign_users_in() {
+    spawn_app().await;
+
+    let (code, token, cookies) =
+        post_response("signed_assertion.xml", "_request-signed-assertion").await;
+    assert_eq!(200, code);
+    // Browsers stay signed in with the realm's session cookie.
+    assert!(cookies.iter().any(|c| c.starts_with("saml_session=")));
+    let jane = user(token).await;
+    assert_eq!("jane@customer.example", jane["username"]);
+    assert_eq!("jane@customer.example", jane["email"]);
+    assert_eq!("Jane Customer", jane["full_name"]);
+
+    // Each request is answered once.
+    let response = Client::new()
+        .post(ACS_URL)
+        .form(&[(
+            "SAMLResponse",
+            base64::encode(fixture("signed_assertion.xml")),
+        )])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(400, response.status().as_u16());
+
+    // The next sign-in finds the same user.
+    let (code, token, _) = post_response("signed_assertion.xml", "_request-signed-assertion").await;
+    assert_eq!(200, code);
+    assert_eq!(jane["id"], user(token).await["id"]);
+
+    let (code, token, _) = post_response("signed_response.xml", "_request-signed-response").await;
+    assert_eq!(200, code);
+    assert_eq!("John Customer", user(token).await["full_name"]);
+}
+
+#[tokio::test]
+async fn forged_and_altered_responses_are_refused() {
+    spawn_app().await;
+    for (name, request_id) in [
+        ("tampered.xml", "_request-tampered"),
+        ("forged.xml", "_request-forged"),
+        ("other_audience.xml", "_request-other-audience"),
+        ("wrapped.xml", "_request-wrapped"),
+        ("unsigned.xml", "_request-unsigned"),
+    ] {
+        let (code, page, _) = post_response(name, request_id).await;
+        assert_eq!(401, code, "{} was accepted", name);
+        assert!(page.contains("could not be verified"));
+    }
+}
@@ -0,0 +1,300 @@
+use reqwest::{header, Client, Method};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+mod common;
+
+const SCIM: &str = "http://127.0.0.1:3000/scim/v2";
+
+/// An access token of a provisioning client, by the client credentials grant.
+async fn provisioning_token(scope: &str) -> String {
+    let (client_id, client_secret) =
+        common::confidential_client(&["scim", "reports:read"], &["client_credentials"]).await;
+    let response: Value = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .basic_auth(client_id, Some(client_secret))
+        .form(&[("grant_type", "client_credentials"), ("scope", scope)])
+        .send()
+        .await
+        .expect("Failed to execute request to /token")
+        .json()
+        .await
+        .unwrap();
+    response["access_token"].as_str().unwrap().to_string()
+}
+
+async fn scim(token: &str, method: Method, path: &str, body: Option<Value>) -> (u16, Value) {
+    let mut request = Client::new()
+        .request(method, format!("{}{}", SCIM, path))
+        .bearer_auth(token);
+    if let Some(body) = body {
+        request = request
+            .header(header::CONTENT_TYPE, "application/scim+json")
+            .body(body.to_string());
+    }
+    let response = request
+        .send()
+        .await
+        .expect("Failed to execute SCIM request");
+    let code = response.status().as_u16();
+    if code != 204 {
+        assert_eq!(
+            "application/scim+json",
+            response.headers()[header::CONTENT_TYPE]
+        );
+    }
+    (code, response.json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn provisioning_clients_manage_users() {
+    common::spawn_app().await;
+    let token = provisioning_token("scim").await;
+    let username = format!("jane-{}@customer.example", Uuid::new_v4());
+
+    let (code, jane) = scim(
+        &token,
+        Method::POST,
+        "/Users",
+        Some(json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"],
+            "userName": username,
+            "name": {"givenName": "Jane", "familyName": "Customer"},
+            "emails": [{"value": "jane@customer.example", "type": "work", "primary": true}],
+            "password": "password",
+            "active": true,
+            "externalId": "00u1"
+        })),
+    )
+    .await;
+    assert_eq!(201, code);
+    assert_eq!(json!(username), jane["userName"]);
+    assert_eq!("Jane Customer", jane["displayName"]);
+    assert_eq!("jane@customer.example", jane["emails"][0]["value"]);
+    assert_eq!(true, jane["active"]);
+    assert!(jane.get("password").is_none());
+    let id = jane["id"].as_str().unwrap().to_string();
+    let credentials = common::Credentials {
+        username: username.clone(),
+        password: "password".to_string(),
+    };
+    assert_eq!(200, common::login(credentials.clone()).await.0);
+
+    let (code, conflict) = scim(
+        &token,
+        Method::POST,
+        "/Users",
+        Some(json!({"userName": username})),
+    )
+    .await;
+    assert_eq!(409, code);
+    assert_eq!("uniqueness", conflict["scimType"]);
+    assert_eq!("409", conflict["status"]);
+
+    // userName comparisons ignore case.
+    let filter = format!("userName eq \"{}\"", username.to_uppercase());
+    let (code, list) = scim(
+        &token,
+        Method::GET,
+        &format!("/Users?filter={}", urlencoding(&filter)),
+        None,
+    )
+    .await;
+    assert_eq!(200, code);
+    assert_eq!(1, list["totalResults"]);
+    assert_eq!(json!(id), list["Resources"][0]["id"]);
+
+    // Identity providers deactivate users, sometimes with a string.
+    let (code, patched) = scim(
+        &token,
+        Method::PATCH,
+        &format!("/Users/{}", id),
+        Some(json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+            "Operations": [
+                {"op": "Replace", "path": "active", "value": "False"},
+                {"op": "replace", "path": "emails[type eq \"work\"].value", "value": "jane@new.example"}
+            ]
+        })),
+    )
+    .await;
+    assert_eq!(200, code);
+    assert_eq!(false, patched["active"]);
+    assert_eq!("jane@new.example", patched["emails"][0]["value"]);
+    assert_eq!(401, common::login(credentials.clone()).await.0);
+
+    let (code, replaced) = scim(
+        &token,
+        Method::PUT,
+        &format!("/Users/{}", id),
+        Some(json!({
+            "userName": username,
+            "displayName": "Jane Doe",
+            "emails": [{"value": "jane@customer.example"}],
+            "active": true
+        })),
+    )
+    .await;
+    assert_eq!(200, code);
+    assert_eq!("Jane Doe", replaced["displayName"]);
+    assert_eq!(200, common::login(credentials.clone()).await.0);
+
+    let (code, _) = scim(&token, Method::DELETE, &format!("/Users/{}", id), None).await;
+    assert_eq!(204, code);
+    let (code, missing) = scim(&token, Method::GET, &format!("/Users/{}", id), None).await;
+    assert_eq!(404, code);
+    assert_eq!("404", missing["status"]);
+}
+
+#[tokio::test]
+async fn deactivated_users_are_signed_out() {
+    common::spawn_app().await;
+    let token = provisioning_token("scim").await;
+    let credentials = common::Credentials {
+        username: format!("leaver-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, session_token) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    let (code, me) = common::me(session_token.clone()).await;
+    assert_eq!(200, code);
+    let id = serde_json::from_str::<Value>(&me).unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (code, _) = scim(
+        &token,
+        Method::PATCH,
+        &format!("/Users/{}", id),
+        Some(json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+            "Operations": [{"op": "replace", "path": "active", "value": false}]
+        })),
+    )
+    .await;
+    assert_eq!(200, code);
+    assert_eq!(401, common::me(session_token.clone()).await.0);
+    let sessions = Client::new()
+        .get("http://127.0.0.1:3000/me/sessions")
+        .bearer_auth(&session_token)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/sessions");
+    assert_eq!(401, sessions.status().as_u16());
+}
+
+#[tokio::test]
+async fn provisioning_clients_manage_groups() {
+    common::spawn_app().await;
+    let token = provisioning_token("scim").await;
+    let mut ids = vec![];
+    for _ in 0..2 {
+        let (code, user) = scim(
+            &token,
+            Method::POST,
+            "/Users",
+            Some(json!({"userName": format!("member-{}", Uuid::new_v4())})),
+        )
+        .await;
+        assert_eq!(201, code);
+        ids.push(user["id"].as_str().unwrap().to_string());
+    }
+    let name = format!("Staff {}", Uuid::new_v4());
+
+    let (code, group) = scim(
+        &token,
+        Method::POST,
+        "/Groups",
+        Some(json!({"displayName": name, "members": [{"value": ids[0]}]})),
+    )
+    .await;
+    assert_eq!(201, code);
+    assert_eq!(json!(ids[0]), group["members"][0]["value"]);
+    let path = format!("/Groups/{}", group["id"].as_str().unwrap());
+
+    let (code, group) = scim(
+        &token,
+        Method::PATCH,
+        &path,
+        Some(json!({"Operations": [
+            {"op": "add", "path": "members", "value": [{"value": ids[1]}]},
+            {"op": "remove", "path": format!("members[value eq \"{}\"]", ids[0])}
+        ]})),
+    )
+    .await;
+    assert_eq!(200, code);
+    assert_eq!(1, group["members"].as_array().unwrap().len());
+    assert_eq!(json!(ids[1]), group["members"][0]["value"]);
+
+    let filter = urlencoding(&format!("displayName eq \"{}\"", name));
+    let (_, list) = scim(
+        &token,
+        Method::GET,
+        &format!("/Groups?filter={}", filter),
+        None,
+    )
+    .await;
+    assert_eq!(1, list["totalResults"]);
+
+    let (code, group) = scim(
+        &token,
+        Method::PUT,
+        &path,
+        Some(json!({"displayName": format!("{} renamed", name), "members": []})),
+    )
+    .await;
+    assert_eq!(200, code);
+    assert_eq!(json!([]), group["members"]);
+
+    let (code, _) = scim(&token, Method::DELETE, &path, None).await;
+    assert_eq!(204, code);
+    let (code, _) = scim(&token, Method::GET, &path, None).await;
+    assert_eq!(404, code);
+}
+
+#[tokio::test]
+async fn only_provisioning_clients_are_let_in() {
+    common::spawn_app().await;
+
+    let response = Client::new()
+        .get(format!("{}/Users", SCIM))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+
+    let (code, error) = scim("not-a-token", Method::GET, "/Users", None).await;
+    assert_eq!(401, code);
+    assert_eq!(
+        json!(["urn:ietf:params:scim:api:messages:2.0:Error"]),
+        error["schemas"]
+    );
+
+    // A user's token, and a client's without the scim scope.
+    let (_, user_token) = common::singup(common::Credentials {
+        username: format!("user-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    })
+    .await;
+    let (code, _) = scim(&user_token, Method::GET, "/Users", None).await;
+    assert_eq!(403, code);
+    let reporting = provisioning_token("reports:read").await;
+    let (code, _) = scim(&reporting, Method::GET, "/Users", None).await;
+    assert_eq!(403, code);
+
+    let token = provisioning_token("scim").await;
+    let (code, error) = scim(
+        &token,
+        Method::GET,
+        &format!("/Users?filter={}", urlencoding("emails co \"@\"")),
+        None,
+    )
+    .await;
+    assert_eq!(400, code);
+    assert_eq!("invalidFilter", error["scimType"]);
+}
+
+fn urlencoding(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
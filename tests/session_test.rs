@@ -0,0 +1,74 @@
+use reqwest::Client;
+use serde_json::Value;
+use uuid::Uuid;
+
+mod common;
+
+async fn sessions(token: &str) -> (u16, Value) {
+    let response = Client::new()
+        .get("http://127.0.0.1:3000/me/sessions")
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/sessions");
+    (
+        response.status().as_u16(),
+        response.json().await.unwrap_or(Value::Null),
+    )
+}
+
+async fn revoke(token: &str, id: &str) -> u16 {
+    Client::new()
+        .delete(format!("http://127.0.0.1:3000/me/sessions/{}", id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request to /me/sessions")
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn users_see_and_revoke_their_sessions() {
+    common::spawn_app().await;
+    let credentials = common::Credentials {
+        username: format!("roaming-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    };
+    let (code, laptop) = common::singup(credentials.clone()).await;
+    assert_eq!(200, code);
+    let phone = common::access_token(
+        Client::builder()
+            .user_agent("phone/1.0")
+            .build()
+            .unwrap()
+            .post("http://127.0.0.1:3000/login")
+            .basic_auth(&credentials.username, Some(&credentials.password))
+            .send()
+            .await
+            .expect("Failed to execute request to /login")
+            .text()
+            .await
+            .unwrap(),
+    );
+
+    let (code, list) = sessions(&laptop).await;
+    assert_eq!(200, code);
+    let list = list.as_array().unwrap();
+    assert_eq!(2, list.len());
+    let current: Vec<_> = list.iter().filter(|s| s["current"] == true).collect();
+    assert_eq!(1, current.len());
+    assert_eq!("vue/v3", current[0]["user_agent"]);
+    assert_eq!("127.0.0.1", current[0]["ip"]);
+    let other = list.iter().find(|s| s["current"] == false).unwrap();
+    assert_eq!("phone/1.0", other["user_agent"]);
+
+    // Signing the phone out takes effect at once.
+    assert_eq!(204, revoke(&laptop, other["id"].as_str().unwrap()).await);
+    assert_eq!(401, common::me(phone.clone()).await.0);
+    assert_eq!(200, common::me(laptop.clone()).await.0);
+    assert_eq!(1, sessions(&laptop).await.1.as_array().unwrap().len());
+
+    assert_eq!(404, revoke(&laptop, &Uuid::new_v4().to_string()).await);
+    assert_eq!(401, sessions(&phone).await.0);
+}
@@ -0,0 +1,256 @@
+use reqwest::Client;
+use serde_json::Value;
+use uuid::Uuid;
+
+mod common;
+
+const TOKEN_EXCHANGE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// Serves the default realm from a realms file declaring the audiences
+/// tokens may be exchanged for.
+async fn spawn_app() {
+    let path = std::env::temp_dir().join("token_exchange_realms.toml");
+    std::fs::write(
+        &path,
+        format!(
+            r#"
+[[realms]]
+name = "AuthServer"
+jwt_secret = "{}"
+jwt_signing_key = "{}"
+
+[[realms.audiences]]
+audience = "orders-service"
+scopes = ["orders:read", "orders:write"]
+
+[[realms.audiences]]
+audience = "billing-service"
+scopes = ["orders:read"]
+clients = ["{}"]
+"#,
+            std::env::var("JWT_SECRET").unwrap(),
+            std::env::var("JWT_SIGNING_KEY").unwrap(),
+            Uuid::nil()
+        ),
+    )
+    .unwrap();
+    std::env::set_var("REALMS_FILE", &path);
+    common::spawn_app().await;
+}
+
+async fn user_token() -> String {
+    let (code, token) = common::singup(common::Credentials {
+        username: format!("user-{}", Uuid::new_v4()),
+        password: "password".to_string(),
+    })
+    .await;
+    assert_eq!(200, code);
+    token
+}
+
+async fn exchanging_client() -> (String, String) {
+    common::confidential_client(
+        &["orders:read", "orders:write", "profile"],
+        &[TOKEN_EXCHANGE],
+    )
+    .await
+}
+
+async fn exchange(
+    client: &(String, String),
+    subject_token: &str,
+    audience: &str,
+    scope: Option<&str>,
+) -> (u16, Value) {
+    let mut form = vec![
+        ("grant_type", TOKEN_EXCHANGE),
+        ("subject_token", subject_token),
+        ("subject_token_type", ACCESS_TOKEN_TYPE),
+        ("audience", audience),
+    ];
+    if let Some(scope) = scope {
+        form.push(("scope", scope));
+    }
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/token")
+        .basic_auth(&client.0, Some(&client.1))
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request to /token");
+    (
+        response.status().as_u16(),
+        response.json().await.expect("token response"),
+    )
+}
+
+fn claims(token: &str) -> Value {
+    let payload = token.split('.').nth(1).unwrap();
+    serde_json::from_slice(&base64::decode_config(payload, base64::URL_SAFE_NO_PAD).unwrap())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn exchanged_tokens_are_narrower_and_audience_restricted() {
+    spawn_app().await;
+    let gateway = exchanging_client().await;
+    let subject_token = user_token().await;
+
+    let (code, response) = exchange(
+        &gateway,
+        &subject_token,
+        "orders-service",
+        Some("orders:read"),
+    )
+    .await;
+    assert_eq!(200, code);
+    assert_eq!(ACCESS_TOKEN_TYPE, response["issued_token_type"]);
+    assert_eq!("Bearer", response["token_type"]);
+    assert_eq!("orders:read", response["scope"]);
+    let exchanged = claims(response["access_token"].as_str().unwrap());
+    assert_eq!(claims(&subject_token)["sub"], exchanged["sub"]);
+    assert_eq!("orders-service", exchanged["aud"]);
+    assert_eq!(gateway.0, exchanged["act"]["sub"]);
+    assert!(exchanged["act"].get("act").is_none());
+
+    let (code, response) =
+        exchange(&gateway, &subject_token, "orders-service", Some("profile")).await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_scope", response["error"]);
+
+    let (code, response) = exchange(&gateway, &subject_token, "payroll-service", None).await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_target", response["error"]);
+    let (code, response) = exchange(&gateway, &subject_token, "billing-service", None).await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_target", response["error"]);
+
+    let (code, response) = exchange(&gateway, "not-a-token", "orders-service", None).await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_grant", response["error"]);
+}
+
+#[tokio::test]
+async fn delegation_chains_carry_every_actor() {
+    spawn_app().await;
+    let gateway = exchanging_client().await;
+    let orders_service = exchanging_client().await;
+
+    let (_, response) = exchange(
+        &gateway,
+        &user_token().await,
+        "orders-service",
+        Some("orders:read"),
+    )
+    .await;
+    let first = response["access_token"].as_str().unwrap().to_string();
+
+    // A token cannot be widened further down the chain.
+    let (code, response) = exchange(
+        &orders_service,
+        &first,
+        "orders-service",
+        Some("orders:write"),
+    )
+    .await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_scope", response["error"]);
+
+    let (code, response) = exchange(&orders_service, &first, "orders-service", None).await;
+    assert_eq!(200, code);
+    assert_eq!("orders:read", response["scope"]);
+    let second = response["access_token"].as_str().unwrap().to_string();
+    let act = &claims(&second)["act"];
+    assert_eq!(orders_service.0, act["sub"]);
+    assert_eq!(gateway.0, act["act"]["sub"]);
+
+    let introspection: Value = Client::new()
+        .post("http://127.0.0.1:3000/introspect")
+        .basic_auth(&gateway.0, Some(&gateway.1))
+        .form(&[("token", &second)])
+        .send()
+        .await
+        .expect("Failed to execute request to /introspect")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(true, introspection["active"]);
+    assert_eq!("orders-service", introspection["aud"]);
+
+    // Revoking a token revokes everything exchanged from it.
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/revoke")
+        .basic_auth(&gateway.0, Some(&gateway.1))
+        .form(&[("token", &first)])
+        .send()
+        .await
+        .expect("Failed to execute request to /revoke");
+    assert_eq!(200, response.status().as_u16());
+    let (code, response) = exchange(&orders_service, &second, "orders-service", None).await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_grant", response["error"]);
+}
+
+#[tokio::test]
+async fn exchanged_tokens_stay_with_their_audience() {
+    spawn_app().await;
+    let gateway = exchanging_client().await;
+    let subject_token = user_token().await;
+
+    let (code, response) = exchange(&gateway, &subject_token, "orders-service", None).await;
+    assert_eq!(200, code);
+    let exchanged = response["access_token"].as_str().unwrap().to_string();
+    assert_eq!(403, common::me(exchanged.clone()).await.0);
+    assert_eq!(403, common::delete(exchanged).await.0);
+    assert_eq!(200, common::me(subject_token.clone()).await.0);
+
+    // Once the user signs out, the session's token is no longer exchanged.
+    let response = Client::new()
+        .post("http://127.0.0.1:3000/logout")
+        .bearer_auth(&subject_token)
+        .send()
+        .await
+        .expect("Failed to execute request to /logout");
+    assert_eq!(204, response.status().as_u16());
+    let (code, response) = exchange(&gateway, &subject_token, "orders-service", None).await;
+    assert_eq!(400, code);
+    assert_eq!("invalid_grant", response["error"]);
+}
+
+#[tokio::test]
+async fn exchanged_tokens_end_with_the_subject_session() {
+    spawn_app().await;
+    let gateway = exchanging_client().await;
+    let subject_token = user_token().await;
+    let (code, response) = exchange(&gateway, &subject_token, "orders-service", None).await;
+    assert_eq!(200, code);
+    let access_token = response["access_token"].as_str().unwrap();
+    let subject = claims(&subject_token);
+    let exchanged = claims(access_token);
+    assert_eq!(subject["sid"], exchanged["sid"]);
+    assert!(exchanged["exp"].as_i64() <= subject["exp"].as_i64());
+
+    let introspect = || async {
+        Client::new()
+            .post("http://127.0.0.1:3000/introspect")
+            .basic_auth(&gateway.0, Some(&gateway.1))
+            .form(&[("token", access_token)])
+            .send()
+            .await
+            .expect("Failed to execute request to /introspect")
+            .json::<Value>()
+            .await
+            .unwrap()
+    };
+    assert_eq!(true, introspect().await["active"]);
+
+    let logout = Client::new()
+        .post("http://127.0.0.1:3000/logout")
+        .bearer_auth(&subject_token)
+        .send()
+        .await
+        .expect("Failed to execute request to /logout");
+    assert_eq!(204, logout.status().as_u16());
+    assert_eq!(false, introspect().await["active"]);
+}
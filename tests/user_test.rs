@@ -12,7 +12,7 @@ async fn test_user() {
     //create new user
     let (code, token) = common::singup(credentials.clone()).await;
     assert_eq!(200, code,);
-    assert!(token.len() > 0);
+    assert!(!token.is_empty());
     println!("Singup success with token: {}", token);
     //Singup
     let (code, token) = common::login(credentials).await;
@@ -30,5 +30,5 @@ async fn test_user() {
     let (code, token) = common::delete(token.clone()).await;
     assert_eq!(200, code);
     println!("Delete User success with token: {}", token);
-    assert!(token.len() > 0);
+    assert!(!token.is_empty());
 }
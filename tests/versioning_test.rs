@@ -0,0 +1,82 @@
+use reqwest::{Client, Response};
+use uuid::Uuid;
+
+mod common;
+
+fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+    response
+        .headers()
+        .get(name)
+        .map(|value| value.to_str().unwrap())
+}
+
+async fn get(path: &str, token: &str) -> Response {
+    Client::new()
+        .get(format!("{}{}", common::BASE_URL, path))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn unprefixed_paths_are_deprecated_aliases_of_v1() {
+    common::spawn_app().await;
+    let username = format!("versioned-{}", Uuid::new_v4());
+    let (code, token) = common::singup(common::Credentials {
+        username: username.clone(),
+        password: "password".to_string(),
+    })
+    .await;
+    assert_eq!(200, code);
+
+    for path in ["/v1/me", "/realms/AuthServer/v1/me"] {
+        let response = get(path, &token).await;
+        assert_eq!(200, response.status(), "{}", path);
+        assert_eq!(None, header(&response, "deprecation"));
+        let user: common::User = response.json().await.unwrap();
+        assert_eq!(Some(username.clone()), user.username);
+    }
+
+    let response = get("/realms/AuthServer/me", &token).await;
+    assert_eq!(200, response.status());
+    assert_eq!(Some("@1792368000"), header(&response, "deprecation"));
+    assert_eq!(
+        Some("Mon, 19 Apr 2027 00:00:00 GMT"),
+        header(&response, "sunset")
+    );
+    assert_eq!(
+        Some("</realms/AuthServer/v1/me>; rel=\"successor-version\""),
+        header(&response, "link")
+    );
+    let user: common::User = response.json().await.unwrap();
+    assert_eq!(Some(username), user.username);
+
+    let response = get("/v1/v1/me", &token).await;
+    assert_eq!(404, response.status());
+}
+
+#[tokio::test]
+async fn discovery_stays_put_and_points_at_v1() {
+    common::spawn_app().await;
+    let response = Client::new()
+        .get(format!(
+            "{}/.well-known/openid-configuration",
+            common::BASE_URL
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status());
+    assert_eq!(None, header(&response, "deprecation"));
+    let metadata: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(common::BASE_URL, metadata["issuer"]);
+    assert_eq!(
+        format!("{}/v1/token", common::BASE_URL),
+        metadata["token_endpoint"]
+    );
+    assert_eq!(
+        format!("{}/.well-known/jwks.json", common::BASE_URL),
+        metadata["jwks_uri"]
+    );
+}